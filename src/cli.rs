@@ -19,6 +19,31 @@ pub struct Cli {
 }
 
 impl Cli {
+    /// Rejects inconsistent option combinations before the server binds.
+    pub fn validate(&self) -> Result<()> {
+        self.port
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Invalid --port: {}", self.port))?;
+
+        if self.role == RedisRole::Slave && self.replicaof.is_none() {
+            return Err(anyhow::anyhow!(
+                "--role slave requires --replicaof \"<host> <port>\""
+            ));
+        }
+
+        if self.replicaof.is_some() {
+            let (master_host, master_port) = self.get_master_info()?;
+            master_port
+                .parse::<u16>()
+                .map_err(|_| anyhow::anyhow!("Invalid port in --replicaof: {}", master_port))?;
+            if master_host == self.host && master_port == self.port {
+                return Err(anyhow::anyhow!("--replicaof cannot point at this server"));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn determine_role(&self) -> RedisRole {
         match self.replicaof {
             Some(_) => RedisRole::Slave,
@@ -40,3 +65,27 @@ impl Cli {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(args: &[&str]) -> Result<()> {
+        Cli::try_parse_from(std::iter::once("redis").chain(args.iter().copied()))?.validate()
+    }
+
+    #[test]
+    fn accepts_consistent_options() {
+        assert!(validate(&[]).is_ok());
+        assert!(validate(&["--port", "6380", "--replicaof", "127.0.0.1 6379"]).is_ok());
+    }
+
+    #[test]
+    fn rejects_inconsistent_options_before_binding() {
+        assert!(validate(&["--port", "redis"]).is_err());
+        assert!(validate(&["--role", "slave"]).is_err());
+        assert!(validate(&["--replicaof", "127.0.0.1"]).is_err());
+        assert!(validate(&["--replicaof", "127.0.0.1 redis"]).is_err());
+        assert!(validate(&["--replicaof", "127.0.0.1 6379"]).is_err());
+    }
+}
@@ -1,13 +1,1026 @@
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use crate::parser::encode_request;
+use crate::utils::format_float;
+
 /// Enum for administrative commands
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
-pub enum AdminCommand {
-    Replicate(String),
-    AddSlave(String),
+pub enum AdminCommand {
+    Replicate(String),
+    AddSlave(String),
+}
+
+/// Enum for runtime configuration commands
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigCommand {
+    Get(String),
+    Set(String, String),
+}
+
+/// Enum for debugging commands
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugCommand {
+    Sleep(Duration),
+    Populate(usize, String, Option<usize>),
+    Internals,
+}
+
+/// Enum for access control commands
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AclCommand {
+    WhoAmI,
+    List,
+    /// The categories, or with one given the commands in it
+    Cat(Option<String>),
+}
+
+/// Enum for OBJECT introspection subcommands, each taking a key
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectCommand {
+    Encoding(String),
+    RefCount(String),
+    IdleTime(String),
+}
+
+/// An expiry directive, normalized to an absolute unix timestamp in milliseconds
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Expiry {
+    At(u64),
+    Persist,
+}
+
+/// Condition under which the EXPIRE family updates a key's expiry.
+/// A key without an expiry counts as having an infinite TTL for GT and LT.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpireCondition {
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+impl ExpireCondition {
+    /// Returns whether an expiry may be replaced by `expiry` under this condition.
+    pub fn allows(&self, current: Option<u64>, expiry: u64) -> bool {
+        match self {
+            ExpireCondition::Nx => current.is_none(),
+            ExpireCondition::Xx => current.is_some(),
+            ExpireCondition::Gt => current.is_some_and(|current| expiry > current),
+            ExpireCondition::Lt => current.is_none_or(|current| expiry < current),
+        }
+    }
+}
+
+impl Display for ExpireCondition {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ExpireCondition::Nx => write!(f, "NX"),
+            ExpireCondition::Xx => write!(f, "XX"),
+            ExpireCondition::Gt => write!(f, "GT"),
+            ExpireCondition::Lt => write!(f, "LT"),
+        }
+    }
+}
+
+/// Condition under which SET writes the value
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SetCondition {
+    /// Only set the key if it does not already exist
+    Nx,
+    /// Only set the key if it already exists
+    Xx,
+}
+
+/// Options accepted by SET
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct SetOptions {
+    /// Absolute expiry in unix milliseconds
+    pub expiry: Option<u64>,
+    pub condition: Option<SetCondition>,
+    /// Keep the existing TTL of the key
+    pub keep_ttl: bool,
+    /// Reply with the previous value of the key
+    pub get: bool,
+}
+
+/// Options accepted by HELLO
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct HelloOptions {
+    /// The protocol version to switch to, none to keep the current one
+    pub protocol: Option<i64>,
+    /// Username and password to authenticate with
+    pub auth: Option<(String, String)>,
+    pub client_name: Option<String>,
+}
+
+/// Comparison a ZADD score must pass against the member's current score to update it
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreComparison {
+    Gt,
+    Lt,
+}
+
+impl ScoreComparison {
+    /// Returns whether a member's score may be replaced by `score` under this comparison.
+    pub fn allows(&self, current: f64, score: f64) -> bool {
+        match self {
+            ScoreComparison::Gt => score > current,
+            ScoreComparison::Lt => score < current,
+        }
+    }
+}
+
+impl Display for ScoreComparison {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ScoreComparison::Gt => write!(f, "GT"),
+            ScoreComparison::Lt => write!(f, "LT"),
+        }
+    }
+}
+
+/// Options accepted by ZADD
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct ZAddOptions {
+    /// Only add new members (NX) or only update existing ones (XX)
+    pub condition: Option<SetCondition>,
+    /// Only update existing members when the new score is greater or less (new members
+    /// are still added)
+    pub comparison: Option<ScoreComparison>,
+    /// Count updated members in the reply as well as added ones
+    pub changed: bool,
+    /// Increment the member's score instead of setting it, replying with the new score
+    pub incr: bool,
+}
+
+impl Display for ZAddOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self.condition {
+            Some(SetCondition::Nx) => write!(f, " NX")?,
+            Some(SetCondition::Xx) => write!(f, " XX")?,
+            None => {}
+        }
+        if let Some(comparison) = self.comparison {
+            write!(f, " {}", comparison)?;
+        }
+        if self.changed {
+            write!(f, " CH")?;
+        }
+        if self.incr {
+            write!(f, " INCR")?;
+        }
+        Ok(())
+    }
+}
+
+/// End of a list that list commands push to or pop from
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    /// Returns the letter list command names start with for this end, as in LPUSH/RPUSH.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ListEnd::Left => "L",
+            ListEnd::Right => "R",
+        }
+    }
+}
+
+impl Display for ListEnd {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ListEnd::Left => write!(f, "LEFT"),
+            ListEnd::Right => write!(f, "RIGHT"),
+        }
+    }
+}
+
+/// End of a sorted set that ZPOPMIN and ZPOPMAX pop from
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreEnd {
+    Min,
+    Max,
+}
+
+impl Display for ScoreEnd {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ScoreEnd::Min => write!(f, "MIN"),
+            ScoreEnd::Max => write!(f, "MAX"),
+        }
+    }
+}
+
+/// Operation combining several sets, as in SINTER, SUNION and SDIFF
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOperation {
+    /// Returns the name shared by the set and sorted set commands, as in SINTER/ZINTER.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SetOperation::Inter => "INTER",
+            SetOperation::Union => "UNION",
+            SetOperation::Diff => "DIFF",
+        }
+    }
+}
+
+impl Display for SetOperation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "S{}", self.name())
+    }
+}
+
+/// How ZUNION and ZINTER combine the scores a member has in each input
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    /// Combines two scores. A sum of opposite infinities is 0, as in Redis.
+    pub fn apply(&self, a: f64, b: f64) -> f64 {
+        match self {
+            Aggregate::Sum if (a + b).is_nan() => 0.0,
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Aggregate::Sum => write!(f, "SUM"),
+            Aggregate::Min => write!(f, "MIN"),
+            Aggregate::Max => write!(f, "MAX"),
+        }
+    }
+}
+
+/// Options accepted by ZUNION, ZINTER and ZDIFF and their STORE forms
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct ZSetOpOptions {
+    /// A factor per input key to multiply its scores by (ZUNION and ZINTER only)
+    pub weights: Option<Vec<f64>>,
+    /// How the scores of a member found in several inputs are combined
+    pub aggregate: Aggregate,
+    /// Reply with each member's score after it (not for the STORE forms)
+    pub with_scores: bool,
+}
+
+impl Display for ZSetOpOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if let Some(weights) = &self.weights {
+            write!(f, " WEIGHTS")?;
+            for weight in weights {
+                write!(f, " {}", format_float(*weight))?;
+            }
+        }
+        if self.aggregate != Aggregate::Sum {
+            write!(f, " AGGREGATE {}", self.aggregate)?;
+        }
+        if self.with_scores {
+            write!(f, " WITHSCORES")?;
+        }
+        Ok(())
+    }
+}
+
+/// Options accepted by LPOS
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct LposOptions {
+    /// Which match to start from, counting back from the tail when negative
+    pub rank: i64,
+    /// Reply with up to this many matching positions (0 for all) instead of just one
+    pub count: Option<usize>,
+    /// Only compare this many elements (0 for the whole list)
+    pub max_len: usize,
+}
+
+impl Default for LposOptions {
+    fn default() -> Self {
+        LposOptions {
+            rank: 1,
+            count: None,
+            max_len: 0,
+        }
+    }
+}
+
+/// Options accepted by the SCAN family of commands
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ScanOptions {
+    /// Only reply with elements matching this glob pattern
+    pub pattern: Option<String>,
+    /// Roughly how many elements to look at per call
+    pub count: usize,
+    /// Reply with hash fields only, leaving out their values (HSCAN only)
+    pub no_values: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            pattern: None,
+            count: 10,
+            no_values: false,
+        }
+    }
+}
+
+impl Display for ScanOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if let Some(pattern) = &self.pattern {
+            write!(f, " MATCH {}", pattern)?;
+        }
+        write!(f, " COUNT {}", self.count)?;
+        if self.no_values {
+            write!(f, " NOVALUES")?;
+        }
+        Ok(())
+    }
+}
+
+/// A score bound of a sorted set range, as in ZRANGEBYSCORE's min and max
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    /// Leave out members scored exactly `value`, written with a `(` prefix
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    /// Returns whether a score is above this bound taken as a minimum.
+    pub fn admits_min(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.value
+        } else {
+            score >= self.value
+        }
+    }
+
+    /// Returns whether a score is below this bound taken as a maximum.
+    pub fn admits_max(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.value
+        } else {
+            score <= self.value
+        }
+    }
+}
+
+impl Display for ScoreBound {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.exclusive {
+            write!(f, "(")?;
+        }
+        write!(f, "{}", format_float(self.value))
+    }
+}
+
+/// The unit the range of BITCOUNT and BITPOS counts in
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+impl Display for BitUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitUnit::Byte => write!(f, "BYTE"),
+            BitUnit::Bit => write!(f, "BIT"),
+        }
+    }
+}
+
+/// Enum for BITOP operations
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl Display for BitOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitOperation::And => write!(f, "AND"),
+            BitOperation::Or => write!(f, "OR"),
+            BitOperation::Xor => write!(f, "XOR"),
+            BitOperation::Not => write!(f, "NOT"),
+        }
+    }
+}
+
+/// The type of a BITFIELD field, e.g. `i16` or `u8`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct BitFieldType {
+    pub signed: bool,
+    /// Width in bits, up to 64 for signed fields and 63 for unsigned ones
+    pub bits: u8,
+}
+
+impl Display for BitFieldType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+}
+
+/// How BITFIELD handles a SET or INCRBY result that doesn't fit its field
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+pub enum BitFieldOverflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+impl Display for BitFieldOverflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitFieldOverflow::Wrap => write!(f, "WRAP"),
+            BitFieldOverflow::Sat => write!(f, "SAT"),
+            BitFieldOverflow::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// A BITFIELD operation, each with the field type and its bit offset
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum BitFieldOperation {
+    Get(BitFieldType, u64),
+    Set(BitFieldType, u64, i64),
+    IncrBy(BitFieldType, u64, i64),
+    /// Sets the overflow behavior for the SET and INCRBY operations after it.
+    Overflow(BitFieldOverflow),
+}
+
+impl Display for BitFieldOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitFieldOperation::Get(field, offset) => write!(f, "GET {} {}", field, offset),
+            BitFieldOperation::Set(field, offset, value) => {
+                write!(f, "SET {} {} {}", field, offset, value)
+            }
+            BitFieldOperation::IncrBy(field, offset, increment) => {
+                write!(f, "INCRBY {} {} {}", field, offset, increment)
+            }
+            BitFieldOperation::Overflow(overflow) => write!(f, "OVERFLOW {}", overflow),
+        }
+    }
+}
+
+/// A member bound of a lexicographic sorted set range, as in ZRANGEBYLEX's min and max
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum LexBound {
+    /// `-`, below every member
+    Min,
+    /// `+`, above every member
+    Max,
+    /// `[member`
+    Inclusive(String),
+    /// `(member`
+    Exclusive(String),
+}
+
+impl LexBound {
+    /// Returns whether a member is above this bound taken as a minimum.
+    pub fn admits_min(&self, member: &str) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(bound) => member >= bound.as_str(),
+            LexBound::Exclusive(bound) => member > bound.as_str(),
+        }
+    }
+
+    /// Returns whether a member is below this bound taken as a maximum.
+    pub fn admits_max(&self, member: &str) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member <= bound.as_str(),
+            LexBound::Exclusive(bound) => member < bound.as_str(),
+        }
+    }
+}
+
+impl Display for LexBound {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            LexBound::Min => write!(f, "-"),
+            LexBound::Max => write!(f, "+"),
+            LexBound::Inclusive(member) => write!(f, "[{}", member),
+            LexBound::Exclusive(member) => write!(f, "({}", member),
+        }
+    }
+}
+
+/// The members a ZRANGE selects: by rank, or between a min and a max score or member.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// Options accepted by ZRANGE
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct ZRangeOptions {
+    /// Order members from the highest score to the lowest
+    pub rev: bool,
+    /// Skip this many members then reply with at most this many (negative for all),
+    /// only allowed with score and lex ranges
+    pub limit: Option<(i64, i64)>,
+    /// Reply with each member's score after it
+    pub with_scores: bool,
+}
+
+/// The longitudes geo commands accept.
+pub const GEO_LONGITUDE_RANGE: (f64, f64) = (-180.0, 180.0);
+/// The latitudes geo commands accept, those the Web Mercator projection covers.
+pub const GEO_LATITUDE_RANGE: (f64, f64) = (-85.05112878, 85.05112878);
+
+/// A distance unit of the geo commands
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+pub enum GeoUnit {
+    #[default]
+    M,
+    Km,
+    Mi,
+    Ft,
+}
+
+impl GeoUnit {
+    /// Returns the length of the unit in meters.
+    pub fn meters(&self) -> f64 {
+        match self {
+            GeoUnit::M => 1.0,
+            GeoUnit::Km => 1000.0,
+            GeoUnit::Mi => 1609.34,
+            GeoUnit::Ft => 0.3048,
+        }
+    }
+}
+
+impl Display for GeoUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeoUnit::M => write!(f, "m"),
+            GeoUnit::Km => write!(f, "km"),
+            GeoUnit::Mi => write!(f, "mi"),
+            GeoUnit::Ft => write!(f, "ft"),
+        }
+    }
+}
+
+/// The center of a GEOSEARCH area
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum GeoOrigin {
+    /// FROMMEMBER, the position of a member of the searched set
+    Member(String),
+    /// FROMLONLAT with the longitude and latitude
+    LonLat(f64, f64),
+}
+
+impl Display for GeoOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeoOrigin::Member(member) => write!(f, "FROMMEMBER {}", member),
+            GeoOrigin::LonLat(longitude, latitude) => write!(
+                f,
+                "FROMLONLAT {} {}",
+                format_float(*longitude),
+                format_float(*latitude)
+            ),
+        }
+    }
+}
+
+/// The shape of a GEOSEARCH area
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum GeoShape {
+    /// BYRADIUS with the radius
+    Radius(f64, GeoUnit),
+    /// BYBOX with the width and the height
+    Box(f64, f64, GeoUnit),
+}
+
+impl GeoShape {
+    pub fn unit(&self) -> GeoUnit {
+        match self {
+            GeoShape::Radius(_, unit) | GeoShape::Box(_, _, unit) => *unit,
+        }
+    }
+}
+
+impl Display for GeoShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeoShape::Radius(radius, unit) => {
+                write!(f, "BYRADIUS {} {}", format_float(*radius), unit)
+            }
+            GeoShape::Box(width, height, unit) => write!(
+                f,
+                "BYBOX {} {} {}",
+                format_float(*width),
+                format_float(*height),
+                unit
+            ),
+        }
+    }
+}
+
+/// Options accepted by GEOSEARCH and GEOSEARCHSTORE
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct GeoSearchOptions {
+    /// Sort the matches by their distance from the center, nearest first unless descending
+    pub descending: Option<bool>,
+    /// Reply with at most this many matches, and with ANY the first ones found rather
+    /// than the nearest
+    pub count: Option<(usize, bool)>,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+}
+
+impl Display for GeoSearchOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.descending {
+            Some(false) => write!(f, " ASC")?,
+            Some(true) => write!(f, " DESC")?,
+            None => {}
+        }
+        if let Some((count, any)) = self.count {
+            write!(f, " COUNT {}", count)?;
+            if any {
+                write!(f, " ANY")?;
+            }
+        }
+        if self.with_coord {
+            write!(f, " WITHCOORD")?;
+        }
+        if self.with_dist {
+            write!(f, " WITHDIST")?;
+        }
+        if self.with_hash {
+            write!(f, " WITHHASH")?;
+        }
+        Ok(())
+    }
+}
+
+/// Options accepted by SORT
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct SortOptions {
+    /// Sort by the values of the keys this pattern names, `*` standing for the element and
+    /// a trailing `->field` reading a hash field. A pattern without `*` skips sorting
+    pub by: Option<String>,
+    /// The offset and count of the sorted elements to keep, all of them if negative
+    pub limit: Option<(i64, i64)>,
+    /// Reply with the values these patterns name for each element instead, `#` being the
+    /// element itself
+    pub get: Vec<String>,
+    pub descending: bool,
+    /// Compare elements as strings rather than as numbers
+    pub alpha: bool,
+    /// Store the result as a list at this key rather than replying with it
+    pub store: Option<String>,
+}
+
+impl Display for SortOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(pattern) = &self.by {
+            write!(f, " BY {}", pattern)?;
+        }
+        if let Some((offset, count)) = self.limit {
+            write!(f, " LIMIT {} {}", offset, count)?;
+        }
+        for pattern in &self.get {
+            write!(f, " GET {}", pattern)?;
+        }
+        if self.descending {
+            write!(f, " DESC")?;
+        }
+        if self.alpha {
+            write!(f, " ALPHA")?;
+        }
+        if let Some(destination) = &self.store {
+            write!(f, " STORE {}", destination)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options accepted by LCS
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct LcsOptions {
+    /// Reply with the length of the match instead of the match itself
+    pub len: bool,
+    /// Reply with the positions of each matching range
+    pub idx: bool,
+    /// Skip ranges shorter than this in IDX replies
+    pub min_match_len: usize,
+    /// Include the length of each range in IDX replies
+    pub with_match_len: bool,
+}
+
+/// A stream entry ID, `<ms>-<seq>`, ordered by its millisecond time then its sequence
+#[derive(
+    Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Parses `<ms>-<seq>`, or just `<ms>` with the sequence number `missing_seq`.
+    pub fn parse(id: &str, missing_seq: u64) -> Option<StreamId> {
+        let (ms, seq) = match id.split_once('-') {
+            Some((ms, seq)) => (ms, seq.parse().ok()?),
+            None => (id, missing_seq),
+        };
+        Some(StreamId {
+            ms: ms.parse().ok()?,
+            seq,
+        })
+    }
+
+    /// Returns the smallest ID after this one, or None for the largest possible ID.
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// Returns the largest ID before this one, or None for 0-0.
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID XADD gives a new entry
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum XAddId {
+    /// `*`, generated from the current time
+    Auto,
+    /// `<ms>-*`, with the next sequence number for that time
+    AutoSequence(u64),
+    Explicit(StreamId),
+}
+
+impl Display for XAddId {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            XAddId::Auto => write!(f, "*"),
+            XAddId::AutoSequence(ms) => write!(f, "{}-*", ms),
+            XAddId::Explicit(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// What a stream is trimmed down to
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum TrimStrategy {
+    /// MAXLEN, keeping at most this many entries
+    MaxLen(usize),
+    /// MINID, dropping entries below this ID
+    MinId(StreamId),
+}
+
+/// How XTRIM, or XADD with a trim option, trims a stream
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    /// `~`, only trim whole chunks of entries, which may leave some past the threshold
+    pub approximate: bool,
+    /// Remove at most this many entries, 0 meaning no limit. Only allowed when approximate
+    pub limit: Option<usize>,
+}
+
+impl Display for StreamTrim {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let operator = if self.approximate { "~" } else { "=" };
+        match self.strategy {
+            TrimStrategy::MaxLen(max_len) => write!(f, " MAXLEN {} {}", operator, max_len)?,
+            TrimStrategy::MinId(min_id) => write!(f, " MINID {} {}", operator, min_id)?,
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options accepted by XADD
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct XAddOptions {
+    /// NOMKSTREAM, don't create a missing stream
+    pub no_mkstream: bool,
+    /// Trim the stream after adding the entry
+    pub trim: Option<StreamTrim>,
+}
+
+impl Display for XAddOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.no_mkstream {
+            write!(f, " NOMKSTREAM")?;
+        }
+        if let Some(trim) = &self.trim {
+            write!(f, "{}", trim)?;
+        }
+        Ok(())
+    }
+}
+
+/// The ID a consumer group has been delivered entries up to, as given to XGROUP CREATE
+/// and SETID
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum GroupStart {
+    /// `$`, the stream's last ID, so only entries added from now on are delivered
+    Last,
+    Id(StreamId),
+}
+
+impl Display for GroupStart {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GroupStart::Last => write!(f, "$"),
+            GroupStart::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// The entries XREADGROUP reads from a stream
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum GroupReadId {
+    /// `>`, entries never delivered to any consumer of the group
+    New,
+    /// The consumer's pending entries after this ID
+    History(StreamId),
+}
+
+impl Display for GroupReadId {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GroupReadId::New => write!(f, ">"),
+            GroupReadId::History(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Enum for XGROUP subcommands, each taking a key and a group name
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum XGroupCommand {
+    /// CREATE with whether to create a missing stream (MKSTREAM).
+    Create(String, String, GroupStart, bool),
+    SetId(String, String, GroupStart),
+    Destroy(String, String),
+    CreateConsumer(String, String, String),
+    DelConsumer(String, String, String),
+}
+
+/// Enum for XINFO subcommands
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum XInfoCommand {
+    /// STREAM with the key and, for the FULL form, how many entries to list, 0 meaning
+    /// all of them.
+    Stream(String, Option<usize>),
+    Groups(String),
+    /// CONSUMERS with the key and the group.
+    Consumers(String, String),
+}
+
+/// Options accepted by XREADGROUP
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct XReadGroupOptions {
+    /// Read at most this many entries per stream
+    pub count: Option<usize>,
+    /// Block for up to this many milliseconds, 0 meaning forever, when no stream has
+    /// new entries
+    pub block: Option<u64>,
+    /// Don't add the entries read to the group's pending entries
+    pub no_ack: bool,
+}
+
+impl Display for XReadGroupOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if let Some(count) = self.count {
+            write!(f, " COUNT {}", count)?;
+        }
+        if let Some(block) = self.block {
+            write!(f, " BLOCK {}", block)?;
+        }
+        if self.no_ack {
+            write!(f, " NOACK")?;
+        }
+        Ok(())
+    }
+}
+
+/// The range of pending entries XPENDING lists in its extended form
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct XPendingRange {
+    /// Only list entries idle for at least this many milliseconds
+    pub idle: Option<u64>,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: usize,
+    /// Only list the entries pending for this consumer
+    pub consumer: Option<String>,
+}
+
+/// Options accepted by XCLAIM
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct XClaimOptions {
+    /// Delivery time the claimed entries are given in unix milliseconds, normalized from
+    /// IDLE or TIME, or the time the command was received
+    pub delivered: u64,
+    /// Set the delivery count instead of incrementing it
+    pub retry_count: Option<u64>,
+    /// Claim entries that aren't pending, as long as they are in the stream
+    pub force: bool,
+    /// Reply with just the IDs, without counting the claim as a delivery
+    pub just_id: bool,
+    /// Move the group's last delivered ID up to this one
+    pub last_id: Option<StreamId>,
+}
+
+impl Display for XClaimOptions {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, " TIME {}", self.delivered)?;
+        if let Some(retry_count) = self.retry_count {
+            write!(f, " RETRYCOUNT {}", retry_count)?;
+        }
+        if self.force {
+            write!(f, " FORCE")?;
+        }
+        if self.just_id {
+            write!(f, " JUSTID")?;
+        }
+        if let Some(last_id) = self.last_id {
+            write!(f, " LASTID {}", last_id)?;
+        }
+        Ok(())
+    }
 }
 
 /// Enum for supported Redis protocol commands
@@ -16,12 +1029,197 @@ pub enum AdminCommand {
 pub enum RedisCommand {
     Ping,
     Pong,
-    Echo(String),
+    Echo(Vec<u8>),
+    Hello(HelloOptions),
     Get(String),
-    Set(String, String, Option<u64>),
-    Info(Option<String>),
+    Set(String, Vec<u8>, SetOptions),
+    SetNx(String, Vec<u8>),
+    GetDel(String),
+    GetEx(String, Option<Expiry>),
+    MSet(Vec<(String, Vec<u8>)>),
+    MSetNx(Vec<(String, Vec<u8>)>),
+    MGet(Vec<String>),
+    Info(Vec<String>),
+    /// LOLWUT with its arguments, which only pick the art Redis draws above its version.
+    Lolwut(Vec<String>),
     Admin(AdminCommand),
     Replconf(Vec<String>),
+    Command(Vec<String>),
+    Config(ConfigCommand),
+    Debug(DebugCommand),
+    Acl(AclCommand),
+    Object(ObjectCommand),
+    Exists(Vec<String>),
+    Touch(Vec<String>),
+    Del(Vec<String>),
+    Unlink(Vec<String>),
+    /// EXPIRE family, normalized to an absolute unix timestamp in milliseconds.
+    Expire(String, u64, Vec<ExpireCondition>),
+    Persist(String),
+    ExpireTime(String),
+    PExpireTime(String),
+    Ttl(String),
+    PTtl(String),
+    /// COPY source destination, with whether an existing destination is replaced.
+    Copy(String, String, bool),
+    Dump(String),
+    /// RESTORE key, absolute expiry in milliseconds, payload, and whether to replace an existing key.
+    Restore(String, Option<u64>, String, bool),
+    IncrBy(String, i64),
+    IncrByFloat(String, f64),
+    Append(String, Vec<u8>),
+    Strlen(String),
+    Lcs(String, String, LcsOptions),
+    GetRange(String, i64, i64),
+    SetRange(String, usize, Vec<u8>),
+    /// SETBIT with the key, the bit offset and the bit value.
+    SetBit(String, u64, bool),
+    GetBit(String, u64),
+    /// BITCOUNT with the key and the inclusive range to count in, if given.
+    BitCount(String, Option<(i64, i64, BitUnit)>),
+    /// BITOP with the operation, the destination and the source keys.
+    BitOp(BitOperation, String, Vec<String>),
+    /// BITPOS with the key, the bit to look for, the start and end of the range if given
+    /// and the range's unit.
+    BitPos(String, bool, Option<i64>, Option<i64>, BitUnit),
+    BitField(String, Vec<BitFieldOperation>),
+    PfAdd(String, Vec<String>),
+    PfCount(Vec<String>),
+    /// PFMERGE with the destination and the source keys.
+    PfMerge(String, Vec<String>),
+    Keys(String),
+    DbSize,
+    Sort(String, SortOptions),
+    /// LPUSH or RPUSH, and with the flag set LPUSHX or RPUSHX, which only push onto an
+    /// existing list.
+    Push(String, ListEnd, Vec<String>, bool),
+    /// LPOP or RPOP, with the number of elements to pop if given.
+    Pop(String, ListEnd, Option<usize>),
+    /// BLPOP or BRPOP, with the keys to pop from and the timeout in seconds (0 blocks forever).
+    BPop(Vec<String>, ListEnd, f64),
+    /// LMOVE with the source, destination and the ends to pop from and push to.
+    /// RPOPLPUSH is parsed as LMOVE source destination RIGHT LEFT.
+    LMove(String, String, ListEnd, ListEnd),
+    /// BLMOVE, as LMOVE followed by the timeout in seconds.
+    BLMove(String, String, ListEnd, ListEnd, f64),
+    LPos(String, String, LposOptions),
+    /// LMPOP with the keys, the end to pop from and the number of elements to pop.
+    LMPop(Vec<String>, ListEnd, usize),
+    /// BLMPOP, as LMPOP followed by the timeout in seconds.
+    BLMPop(Vec<String>, ListEnd, usize, f64),
+    LLen(String),
+    HSet(String, Vec<(String, String)>),
+    HGet(String, String),
+    HDel(String, Vec<String>),
+    HGetAll(String),
+    HMGet(String, Vec<String>),
+    HLen(String),
+    HExists(String, String),
+    /// HRANDFIELD with the key, the count if given and whether to include values.
+    HRandField(String, Option<i64>, bool),
+    HScan(String, u64, ScanOptions),
+    /// The HEXPIRE family, normalized to an absolute unix time in milliseconds like Expire.
+    HExpire(String, u64, Vec<ExpireCondition>, Vec<String>),
+    HPersist(String, Vec<String>),
+    HTtl(String, Vec<String>),
+    HPTtl(String, Vec<String>),
+    SAdd(String, Vec<String>),
+    SRem(String, Vec<String>),
+    SMembers(String),
+    SIsMember(String, String),
+    SMIsMember(String, Vec<String>),
+    /// SMOVE with the source, the destination and the member.
+    SMove(String, String, String),
+    SCard(String),
+    /// SINTER, SUNION or SDIFF over the given keys.
+    SetOp(SetOperation, Vec<String>),
+    /// SINTERSTORE, SUNIONSTORE or SDIFFSTORE with the destination and the source keys.
+    SetOpStore(SetOperation, String, Vec<String>),
+    /// SINTERCARD with the keys and the limit, 0 meaning no limit.
+    SInterCard(Vec<String>, usize),
+    SScan(String, u64, ScanOptions),
+    /// ZADD with the key, its options and the score/member pairs.
+    ZAdd(String, ZAddOptions, Vec<(f64, String)>),
+    ZScore(String, String),
+    ZCard(String),
+    /// ZRANGE with the key, the range and its options. The ZRANGEBYSCORE and ZRANGEBYLEX
+    /// family are parsed into this too.
+    ZRange(String, ZRangeBy, ZRangeOptions),
+    /// ZRANGESTORE with the destination, the source and the range as in ZRange.
+    ZRangeStore(String, String, ZRangeBy, ZRangeOptions),
+    /// ZRANDMEMBER with the key, the count if given and whether to include scores.
+    ZRandMember(String, Option<i64>, bool),
+    ZScan(String, u64, ScanOptions),
+    ZCount(String, ScoreBound, ScoreBound),
+    /// ZINCRBY with the key, the increment and the member.
+    ZIncrBy(String, f64, String),
+    /// ZRANK, or ZREVRANK when reversed, with the key, the member and whether to include
+    /// the score.
+    ZRank(String, String, bool, bool),
+    ZRem(String, Vec<String>),
+    /// ZPOPMIN or ZPOPMAX with the key and the count if given.
+    ZPop(String, ScoreEnd, Option<usize>),
+    /// BZPOPMIN or BZPOPMAX with the keys and the timeout in seconds.
+    BZPop(Vec<String>, ScoreEnd, f64),
+    /// ZINTER, ZUNION or ZDIFF over the given keys.
+    ZSetOp(SetOperation, Vec<String>, ZSetOpOptions),
+    /// ZINTERSTORE, ZUNIONSTORE or ZDIFFSTORE with the destination and the source keys.
+    ZSetOpStore(SetOperation, String, Vec<String>, ZSetOpOptions),
+    ZLexCount(String, LexBound, LexBound),
+    /// GEOADD with the key, its NX, XX and CH options and the longitude/latitude/member
+    /// triples.
+    GeoAdd(String, ZAddOptions, Vec<(f64, f64, String)>),
+    GeoPos(String, Vec<String>),
+    /// GEODIST with the key, the two members and the unit to reply in.
+    GeoDist(String, String, String, GeoUnit),
+    GeoHash(String, Vec<String>),
+    GeoSearch(String, GeoOrigin, GeoShape, GeoSearchOptions),
+    /// GEOSEARCHSTORE with the destination, the source, the search and whether to store
+    /// distances as scores instead of positions.
+    GeoSearchStore(String, String, GeoOrigin, GeoShape, GeoSearchOptions, bool),
+    /// XADD with the key, its options, the ID and the field/value pairs.
+    XAdd(String, XAddOptions, XAddId, Vec<(String, String)>),
+    XTrim(String, StreamTrim),
+    XDel(String, Vec<StreamId>),
+    /// XSETID with the key, the last ID and the ENTRIESADDED and MAXDELETEDID values if
+    /// given.
+    XSetId(String, StreamId, Option<u64>, Option<StreamId>),
+    XLen(String),
+    /// XRANGE, or XREVRANGE when reversed, with the key, the inclusive start and end IDs
+    /// and the count if given.
+    XRange(String, StreamId, StreamId, Option<usize>, bool),
+    XGroup(XGroupCommand),
+    XInfo(XInfoCommand),
+    /// XREADGROUP with the group, the consumer, its options and the ID to read from for
+    /// each stream key.
+    XReadGroup(
+        String,
+        String,
+        XReadGroupOptions,
+        Vec<(String, GroupReadId)>,
+    ),
+    /// XACK with the key, the group and the IDs to acknowledge.
+    XAck(String, String, Vec<StreamId>),
+    /// XPENDING with the key, the group and the range for the extended form.
+    XPending(String, String, Option<XPendingRange>),
+    /// XCLAIM with the key, the group, the consumer, the minimum idle time in
+    /// milliseconds, the IDs and its options.
+    XClaim(String, String, String, u64, Vec<StreamId>, XClaimOptions),
+    /// XAUTOCLAIM with the key, the group, the consumer, the minimum idle time in
+    /// milliseconds, the start ID, the count and whether to reply with just the IDs.
+    XAutoClaim(String, String, String, u64, StreamId, usize, bool),
+    LRange(String, i64, i64),
+    /// LINSERT with the key, whether to insert before the pivot, the pivot and the element.
+    LInsert(String, bool, String, String),
+    LSet(String, i64, String),
+    LRem(String, i64, String),
+    LTrim(String, i64, i64),
+    Type(String),
+    RandomKey,
+    Time,
+    /// FLUSHDB or FLUSHALL, with whether the old contents are freed asynchronously.
+    FlushDb(bool),
+    FlushAll(bool),
     Ok,
 }
 
@@ -30,79 +1228,924 @@ impl Display for RedisCommand {
         match self {
             RedisCommand::Ping => write!(f, "PING"),
             RedisCommand::Pong => write!(f, "PONG"),
-            RedisCommand::Echo(s) => write!(f, "ECHO {}", s),
+            RedisCommand::Echo(s) => write!(f, "ECHO {}", String::from_utf8_lossy(s)),
+            RedisCommand::Hello(options) => {
+                write!(f, "HELLO")?;
+                if let Some(protocol) = options.protocol {
+                    write!(f, " {}", protocol)?;
+                }
+                if let Some((username, password)) = &options.auth {
+                    write!(f, " AUTH {} {}", username, password)?;
+                }
+                if let Some(name) = &options.client_name {
+                    write!(f, " SETNAME {}", name)?;
+                }
+                Ok(())
+            }
             RedisCommand::Get(s) => write!(f, "GET {}", s),
-            RedisCommand::Set(key, value, expiry) => {
-                if let Some(expiry) = expiry {
-                    write!(f, "SET {} {} PX {}", key, value, expiry)
-                } else {
-                    write!(f, "SET {} {}", key, value)
+            RedisCommand::Set(key, value, options) => {
+                write!(f, "SET {} {}", key, String::from_utf8_lossy(value))?;
+                if let Some(expiry) = options.expiry {
+                    write!(f, " PXAT {}", expiry)?;
+                }
+                match options.condition {
+                    Some(SetCondition::Nx) => write!(f, " NX")?,
+                    Some(SetCondition::Xx) => write!(f, " XX")?,
+                    None => {}
+                }
+                if options.keep_ttl {
+                    write!(f, " KEEPTTL")?;
+                }
+                if options.get {
+                    write!(f, " GET")?;
+                }
+                Ok(())
+            }
+            RedisCommand::SetNx(key, value) => {
+                write!(f, "SETNX {} {}", key, String::from_utf8_lossy(value))
+            }
+            RedisCommand::GetDel(key) => write!(f, "GETDEL {}", key),
+            RedisCommand::GetEx(key, None) => write!(f, "GETEX {}", key),
+            RedisCommand::GetEx(key, Some(Expiry::At(timestamp))) => {
+                write!(f, "GETEX {} PXAT {}", key, timestamp)
+            }
+            RedisCommand::GetEx(key, Some(Expiry::Persist)) => write!(f, "GETEX {} PERSIST", key),
+            RedisCommand::Copy(source, destination, replace) => {
+                write!(f, "COPY {} {}", source, destination)?;
+                if *replace {
+                    write!(f, " REPLACE")?;
                 }
+                Ok(())
             }
-            RedisCommand::Info(section) => match section {
-                Some(section) => write!(f, "INFO {}", section),
-                None => write!(f, "INFO"),
+            RedisCommand::Dump(key) => write!(f, "DUMP {}", key),
+            RedisCommand::Restore(key, expiry, payload, replace) => {
+                write!(f, "RESTORE {} {} {}", key, expiry.unwrap_or(0), payload)?;
+                if *replace {
+                    write!(f, " REPLACE")?;
+                }
+                write!(f, " ABSTTL")
+            }
+            RedisCommand::MSet(pairs) => write!(f, "MSET {}", join_pairs(pairs)),
+            RedisCommand::MSetNx(pairs) => write!(f, "MSETNX {}", join_pairs(pairs)),
+            RedisCommand::MGet(keys) => write!(f, "MGET {}", keys.join(" ")),
+            RedisCommand::Info(sections) => match sections.is_empty() {
+                true => write!(f, "INFO"),
+                false => write!(f, "INFO {}", sections.join(" ")),
+            },
+            RedisCommand::Lolwut(args) => match args.is_empty() {
+                true => write!(f, "LOLWUT"),
+                false => write!(f, "LOLWUT {}", args.join(" ")),
             },
             RedisCommand::Admin(command) => match command {
                 AdminCommand::Replicate(data) => write!(f, "REPLICATE {}", data),
                 AdminCommand::AddSlave(data) => write!(f, "ADDSLAVE {}", data),
             },
             RedisCommand::Replconf(data) => write!(f, "REPLCONF {}", data.join(" ")),
+            RedisCommand::Command(args) => write!(f, "COMMAND {}", args.join(" ")),
+            RedisCommand::Config(command) => match command {
+                ConfigCommand::Get(name) => write!(f, "CONFIG GET {}", name),
+                ConfigCommand::Set(name, value) => write!(f, "CONFIG SET {} {}", name, value),
+            },
+            RedisCommand::Debug(command) => match command {
+                DebugCommand::Sleep(duration) => {
+                    write!(f, "DEBUG SLEEP {}", duration.as_secs_f64())
+                }
+                DebugCommand::Populate(count, prefix, size) => match size {
+                    Some(size) => write!(f, "DEBUG POPULATE {} {} {}", count, prefix, size),
+                    None => write!(f, "DEBUG POPULATE {} {}", count, prefix),
+                },
+                DebugCommand::Internals => write!(f, "DEBUG INTERNALS"),
+            },
+            RedisCommand::Acl(command) => match command {
+                AclCommand::WhoAmI => write!(f, "ACL WHOAMI"),
+                AclCommand::List => write!(f, "ACL LIST"),
+                AclCommand::Cat(None) => write!(f, "ACL CAT"),
+                AclCommand::Cat(Some(category)) => write!(f, "ACL CAT {}", category),
+            },
+            RedisCommand::Object(command) => match command {
+                ObjectCommand::Encoding(key) => write!(f, "OBJECT ENCODING {}", key),
+                ObjectCommand::RefCount(key) => write!(f, "OBJECT REFCOUNT {}", key),
+                ObjectCommand::IdleTime(key) => write!(f, "OBJECT IDLETIME {}", key),
+            },
+            RedisCommand::Exists(keys) => write!(f, "EXISTS {}", keys.join(" ")),
+            RedisCommand::Touch(keys) => write!(f, "TOUCH {}", keys.join(" ")),
+            RedisCommand::Del(keys) => write!(f, "DEL {}", keys.join(" ")),
+            RedisCommand::Unlink(keys) => write!(f, "UNLINK {}", keys.join(" ")),
+            RedisCommand::Expire(key, timestamp, conditions) => {
+                write!(f, "PEXPIREAT {} {}", key, timestamp)?;
+                for condition in conditions {
+                    write!(f, " {}", condition)?;
+                }
+                Ok(())
+            }
+            RedisCommand::Persist(key) => write!(f, "PERSIST {}", key),
+            RedisCommand::ExpireTime(key) => write!(f, "EXPIRETIME {}", key),
+            RedisCommand::PExpireTime(key) => write!(f, "PEXPIRETIME {}", key),
+            RedisCommand::Ttl(key) => write!(f, "TTL {}", key),
+            RedisCommand::PTtl(key) => write!(f, "PTTL {}", key),
+            RedisCommand::IncrBy(key, delta) => write!(f, "INCRBY {} {}", key, delta),
+            RedisCommand::IncrByFloat(key, delta) => {
+                write!(f, "INCRBYFLOAT {} {}", key, format_float(*delta))
+            }
+            RedisCommand::Append(key, value) => {
+                write!(f, "APPEND {} {}", key, String::from_utf8_lossy(value))
+            }
+            RedisCommand::Strlen(key) => write!(f, "STRLEN {}", key),
+            RedisCommand::Lcs(key1, key2, options) => {
+                write!(f, "LCS {} {}", key1, key2)?;
+                if options.len {
+                    write!(f, " LEN")?;
+                }
+                if options.idx {
+                    write!(f, " IDX")?;
+                }
+                if options.min_match_len > 0 {
+                    write!(f, " MINMATCHLEN {}", options.min_match_len)?;
+                }
+                if options.with_match_len {
+                    write!(f, " WITHMATCHLEN")?;
+                }
+                Ok(())
+            }
+            RedisCommand::GetRange(key, start, end) => {
+                write!(f, "GETRANGE {} {} {}", key, start, end)
+            }
+            RedisCommand::SetRange(key, offset, value) => {
+                write!(
+                    f,
+                    "SETRANGE {} {} {}",
+                    key,
+                    offset,
+                    String::from_utf8_lossy(value)
+                )
+            }
+            RedisCommand::SetBit(key, offset, value) => {
+                write!(f, "SETBIT {} {} {}", key, offset, *value as u8)
+            }
+            RedisCommand::GetBit(key, offset) => write!(f, "GETBIT {} {}", key, offset),
+            RedisCommand::BitOp(operation, destination, keys) => {
+                write!(f, "BITOP {} {} {}", operation, destination, keys.join(" "))
+            }
+            RedisCommand::PfAdd(key, elements) => {
+                write!(f, "PFADD {}", key)?;
+                for element in elements {
+                    write!(f, " {}", element)?;
+                }
+                Ok(())
+            }
+            RedisCommand::PfCount(keys) => write!(f, "PFCOUNT {}", keys.join(" ")),
+            RedisCommand::PfMerge(destination, keys) => {
+                write!(f, "PFMERGE {}", destination)?;
+                for key in keys {
+                    write!(f, " {}", key)?;
+                }
+                Ok(())
+            }
+            RedisCommand::BitField(key, operations) => {
+                write!(f, "BITFIELD {}", key)?;
+                for operation in operations {
+                    write!(f, " {}", operation)?;
+                }
+                Ok(())
+            }
+            RedisCommand::BitPos(key, bit, start, end, unit) => {
+                write!(f, "BITPOS {} {}", key, *bit as u8)?;
+                if let Some(start) = start {
+                    write!(f, " {}", start)?;
+                }
+                if let Some(end) = end {
+                    write!(f, " {} {}", end, unit)?;
+                }
+                Ok(())
+            }
+            RedisCommand::BitCount(key, range) => {
+                write!(f, "BITCOUNT {}", key)?;
+                if let Some((start, end, unit)) = range {
+                    write!(f, " {} {} {}", start, end, unit)?;
+                }
+                Ok(())
+            }
+            RedisCommand::Keys(pattern) => write!(f, "KEYS {}", pattern),
+            RedisCommand::DbSize => write!(f, "DBSIZE"),
+            RedisCommand::Sort(key, options) => write!(f, "SORT {}{}", key, options),
+            RedisCommand::Push(key, end, values, existing) => {
+                let x = if *existing { "X" } else { "" };
+                write!(f, "{}PUSH{} {} {}", end.prefix(), x, key, values.join(" "))
+            }
+            RedisCommand::Pop(key, end, count) => {
+                write!(f, "{}POP {}", end.prefix(), key)?;
+                if let Some(count) = count {
+                    write!(f, " {}", count)?;
+                }
+                Ok(())
+            }
+            RedisCommand::BPop(keys, end, timeout) => {
+                write!(f, "B{}POP {} {}", end.prefix(), keys.join(" "), timeout)
+            }
+            RedisCommand::LMove(source, destination, from, to) => {
+                write!(f, "LMOVE {} {} {} {}", source, destination, from, to)
+            }
+            RedisCommand::BLMove(source, destination, from, to, timeout) => write!(
+                f,
+                "BLMOVE {} {} {} {} {}",
+                source, destination, from, to, timeout
+            ),
+            RedisCommand::LPos(key, element, options) => {
+                write!(f, "LPOS {} {} RANK {}", key, element, options.rank)?;
+                if let Some(count) = options.count {
+                    write!(f, " COUNT {}", count)?;
+                }
+                write!(f, " MAXLEN {}", options.max_len)
+            }
+            RedisCommand::LMPop(keys, end, count) => write!(
+                f,
+                "LMPOP {} {} {} COUNT {}",
+                keys.len(),
+                keys.join(" "),
+                end,
+                count
+            ),
+            RedisCommand::BLMPop(keys, end, count, timeout) => write!(
+                f,
+                "BLMPOP {} {} {} {} COUNT {}",
+                timeout,
+                keys.len(),
+                keys.join(" "),
+                end,
+                count
+            ),
+            RedisCommand::LLen(key) => write!(f, "LLEN {}", key),
+            RedisCommand::HSet(key, pairs) => write!(f, "HSET {} {}", key, join_pairs(pairs)),
+            RedisCommand::HGet(key, field) => write!(f, "HGET {} {}", key, field),
+            RedisCommand::HDel(key, fields) => write!(f, "HDEL {} {}", key, fields.join(" ")),
+            RedisCommand::HGetAll(key) => write!(f, "HGETALL {}", key),
+            RedisCommand::HMGet(key, fields) => write!(f, "HMGET {} {}", key, fields.join(" ")),
+            RedisCommand::HLen(key) => write!(f, "HLEN {}", key),
+            RedisCommand::HExists(key, field) => write!(f, "HEXISTS {} {}", key, field),
+            RedisCommand::HRandField(key, count, with_values) => {
+                write!(f, "HRANDFIELD {}", key)?;
+                if let Some(count) = count {
+                    write!(f, " {}", count)?;
+                }
+                if *with_values {
+                    write!(f, " WITHVALUES")?;
+                }
+                Ok(())
+            }
+            RedisCommand::HScan(key, cursor, options) => {
+                write!(f, "HSCAN {} {}{}", key, cursor, options)
+            }
+            RedisCommand::HExpire(key, timestamp, conditions, fields) => {
+                write!(f, "HPEXPIREAT {} {}", key, timestamp)?;
+                for condition in conditions {
+                    write!(f, " {}", condition)?;
+                }
+                write!(f, " FIELDS {}", join_fields(fields))
+            }
+            RedisCommand::HPersist(key, fields) => {
+                write!(f, "HPERSIST {} FIELDS {}", key, join_fields(fields))
+            }
+            RedisCommand::SAdd(key, members) => write!(f, "SADD {} {}", key, members.join(" ")),
+            RedisCommand::SRem(key, members) => write!(f, "SREM {} {}", key, members.join(" ")),
+            RedisCommand::SMembers(key) => write!(f, "SMEMBERS {}", key),
+            RedisCommand::SIsMember(key, member) => write!(f, "SISMEMBER {} {}", key, member),
+            RedisCommand::SMIsMember(key, members) => {
+                write!(f, "SMISMEMBER {} {}", key, members.join(" "))
+            }
+            RedisCommand::SMove(source, destination, member) => {
+                write!(f, "SMOVE {} {} {}", source, destination, member)
+            }
+            RedisCommand::SCard(key) => write!(f, "SCARD {}", key),
+            RedisCommand::ZAdd(key, options, pairs) => {
+                write!(f, "ZADD {}{}", key, options)?;
+                for (score, member) in pairs {
+                    write!(f, " {} {}", format_float(*score), member)?;
+                }
+                Ok(())
+            }
+            RedisCommand::ZScore(key, member) => write!(f, "ZSCORE {} {}", key, member),
+            RedisCommand::ZCard(key) => write!(f, "ZCARD {}", key),
+            RedisCommand::ZRange(key, by, options) => {
+                write!(f, "ZRANGE {}", key)?;
+                write_zrange_arguments(f, by, options)
+            }
+            RedisCommand::ZRangeStore(destination, source, by, options) => {
+                write!(f, "ZRANGESTORE {} {}", destination, source)?;
+                write_zrange_arguments(f, by, options)
+            }
+            RedisCommand::ZRandMember(key, count, with_scores) => {
+                write!(f, "ZRANDMEMBER {}", key)?;
+                if let Some(count) = count {
+                    write!(f, " {}", count)?;
+                }
+                if *with_scores {
+                    write!(f, " WITHSCORES")?;
+                }
+                Ok(())
+            }
+            RedisCommand::ZIncrBy(key, increment, member) => {
+                write!(f, "ZINCRBY {} {} {}", key, format_float(*increment), member)
+            }
+            RedisCommand::ZRank(key, member, rev, with_score) => {
+                let name = if *rev { "ZREVRANK" } else { "ZRANK" };
+                write!(f, "{} {} {}", name, key, member)?;
+                if *with_score {
+                    write!(f, " WITHSCORE")?;
+                }
+                Ok(())
+            }
+            RedisCommand::ZPop(key, end, count) => {
+                write!(f, "ZPOP{} {}", end, key)?;
+                if let Some(count) = count {
+                    write!(f, " {}", count)?;
+                }
+                Ok(())
+            }
+            RedisCommand::BZPop(keys, end, timeout) => {
+                write!(f, "BZPOP{} {} {}", end, keys.join(" "), timeout)
+            }
+            RedisCommand::ZSetOp(operation, keys, options) => write!(
+                f,
+                "Z{} {} {}{}",
+                operation.name(),
+                keys.len(),
+                keys.join(" "),
+                options
+            ),
+            RedisCommand::ZSetOpStore(operation, destination, keys, options) => write!(
+                f,
+                "Z{}STORE {} {} {}{}",
+                operation.name(),
+                destination,
+                keys.len(),
+                keys.join(" "),
+                options
+            ),
+            RedisCommand::ZRem(key, members) => write!(f, "ZREM {} {}", key, members.join(" ")),
+            RedisCommand::ZScan(key, cursor, options) => {
+                write!(f, "ZSCAN {} {}{}", key, cursor, options)
+            }
+            RedisCommand::ZCount(key, min, max) => write!(f, "ZCOUNT {} {} {}", key, min, max),
+            RedisCommand::ZLexCount(key, min, max) => {
+                write!(f, "ZLEXCOUNT {} {} {}", key, min, max)
+            }
+            RedisCommand::GeoAdd(key, options, triples) => {
+                write!(f, "GEOADD {}{}", key, options)?;
+                for (longitude, latitude, member) in triples {
+                    write!(
+                        f,
+                        " {} {} {}",
+                        format_float(*longitude),
+                        format_float(*latitude),
+                        member
+                    )?;
+                }
+                Ok(())
+            }
+            RedisCommand::GeoPos(key, members) => {
+                write!(f, "GEOPOS {}", key)?;
+                for member in members {
+                    write!(f, " {}", member)?;
+                }
+                Ok(())
+            }
+            RedisCommand::GeoDist(key, first, second, unit) => {
+                write!(f, "GEODIST {} {} {} {}", key, first, second, unit)
+            }
+            RedisCommand::GeoHash(key, members) => {
+                write!(f, "GEOHASH {}", key)?;
+                for member in members {
+                    write!(f, " {}", member)?;
+                }
+                Ok(())
+            }
+            RedisCommand::GeoSearch(key, origin, shape, options) => {
+                write!(f, "GEOSEARCH {} {} {}{}", key, origin, shape, options)
+            }
+            RedisCommand::GeoSearchStore(
+                destination,
+                source,
+                origin,
+                shape,
+                options,
+                store_dist,
+            ) => {
+                write!(
+                    f,
+                    "GEOSEARCHSTORE {} {} {} {}{}",
+                    destination, source, origin, shape, options
+                )?;
+                if *store_dist {
+                    write!(f, " STOREDIST")?;
+                }
+                Ok(())
+            }
+            RedisCommand::XAdd(key, options, id, fields) => {
+                write!(f, "XADD {}{} {}", key, options, id)?;
+                for (field, value) in fields {
+                    write!(f, " {} {}", field, value)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XLen(key) => write!(f, "XLEN {}", key),
+            RedisCommand::XTrim(key, trim) => write!(f, "XTRIM {}{}", key, trim),
+            RedisCommand::XDel(key, ids) => {
+                write!(f, "XDEL {}", key)?;
+                for id in ids {
+                    write!(f, " {}", id)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XSetId(key, last_id, entries_added, max_deleted_id) => {
+                write!(f, "XSETID {} {}", key, last_id)?;
+                if let Some(entries_added) = entries_added {
+                    write!(f, " ENTRIESADDED {}", entries_added)?;
+                }
+                if let Some(max_deleted_id) = max_deleted_id {
+                    write!(f, " MAXDELETEDID {}", max_deleted_id)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XRange(key, start, end, count, rev) => {
+                if *rev {
+                    write!(f, "XREVRANGE {} {} {}", key, end, start)?;
+                } else {
+                    write!(f, "XRANGE {} {} {}", key, start, end)?;
+                }
+                if let Some(count) = count {
+                    write!(f, " COUNT {}", count)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XGroup(command) => match command {
+                XGroupCommand::Create(key, group, start, mkstream) => {
+                    write!(f, "XGROUP CREATE {} {} {}", key, group, start)?;
+                    if *mkstream {
+                        write!(f, " MKSTREAM")?;
+                    }
+                    Ok(())
+                }
+                XGroupCommand::SetId(key, group, start) => {
+                    write!(f, "XGROUP SETID {} {} {}", key, group, start)
+                }
+                XGroupCommand::Destroy(key, group) => {
+                    write!(f, "XGROUP DESTROY {} {}", key, group)
+                }
+                XGroupCommand::CreateConsumer(key, group, consumer) => {
+                    write!(f, "XGROUP CREATECONSUMER {} {} {}", key, group, consumer)
+                }
+                XGroupCommand::DelConsumer(key, group, consumer) => {
+                    write!(f, "XGROUP DELCONSUMER {} {} {}", key, group, consumer)
+                }
+            },
+            RedisCommand::XInfo(command) => match command {
+                XInfoCommand::Stream(key, None) => write!(f, "XINFO STREAM {}", key),
+                XInfoCommand::Stream(key, Some(count)) => {
+                    write!(f, "XINFO STREAM {} FULL COUNT {}", key, count)
+                }
+                XInfoCommand::Groups(key) => write!(f, "XINFO GROUPS {}", key),
+                XInfoCommand::Consumers(key, group) => {
+                    write!(f, "XINFO CONSUMERS {} {}", key, group)
+                }
+            },
+            RedisCommand::XReadGroup(group, consumer, options, streams) => {
+                write!(
+                    f,
+                    "XREADGROUP GROUP {} {}{} STREAMS",
+                    group, consumer, options
+                )?;
+                for (key, _) in streams {
+                    write!(f, " {}", key)?;
+                }
+                for (_, id) in streams {
+                    write!(f, " {}", id)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XAck(key, group, ids) => {
+                write!(f, "XACK {} {}", key, group)?;
+                for id in ids {
+                    write!(f, " {}", id)?;
+                }
+                Ok(())
+            }
+            RedisCommand::XPending(key, group, range) => {
+                write!(f, "XPENDING {} {}", key, group)?;
+                if let Some(range) = range {
+                    if let Some(idle) = range.idle {
+                        write!(f, " IDLE {}", idle)?;
+                    }
+                    write!(f, " {} {} {}", range.start, range.end, range.count)?;
+                    if let Some(consumer) = &range.consumer {
+                        write!(f, " {}", consumer)?;
+                    }
+                }
+                Ok(())
+            }
+            RedisCommand::XClaim(key, group, consumer, min_idle, ids, options) => {
+                write!(f, "XCLAIM {} {} {} {}", key, group, consumer, min_idle)?;
+                for id in ids {
+                    write!(f, " {}", id)?;
+                }
+                write!(f, "{}", options)
+            }
+            RedisCommand::XAutoClaim(key, group, consumer, min_idle, start, count, just_id) => {
+                write!(
+                    f,
+                    "XAUTOCLAIM {} {} {} {} {} COUNT {}",
+                    key, group, consumer, min_idle, start, count
+                )?;
+                if *just_id {
+                    write!(f, " JUSTID")?;
+                }
+                Ok(())
+            }
+            RedisCommand::SetOp(operation, keys) => write!(f, "{} {}", operation, keys.join(" ")),
+            RedisCommand::SInterCard(keys, limit) => write!(
+                f,
+                "SINTERCARD {} {} LIMIT {}",
+                keys.len(),
+                keys.join(" "),
+                limit
+            ),
+            RedisCommand::SScan(key, cursor, options) => {
+                write!(f, "SSCAN {} {}{}", key, cursor, options)
+            }
+            RedisCommand::SetOpStore(operation, destination, keys) => {
+                write!(f, "{}STORE {} {}", operation, destination, keys.join(" "))
+            }
+            RedisCommand::HTtl(key, fields) => {
+                write!(f, "HTTL {} FIELDS {}", key, join_fields(fields))
+            }
+            RedisCommand::HPTtl(key, fields) => {
+                write!(f, "HPTTL {} FIELDS {}", key, join_fields(fields))
+            }
+            RedisCommand::LRange(key, start, stop) => {
+                write!(f, "LRANGE {} {} {}", key, start, stop)
+            }
+            RedisCommand::LInsert(key, before, pivot, element) => {
+                let position = if *before { "BEFORE" } else { "AFTER" };
+                write!(f, "LINSERT {} {} {} {}", key, position, pivot, element)
+            }
+            RedisCommand::LSet(key, index, element) => {
+                write!(f, "LSET {} {} {}", key, index, element)
+            }
+            RedisCommand::LRem(key, count, element) => {
+                write!(f, "LREM {} {} {}", key, count, element)
+            }
+            RedisCommand::LTrim(key, start, stop) => write!(f, "LTRIM {} {} {}", key, start, stop),
+            RedisCommand::Type(key) => write!(f, "TYPE {}", key),
+            RedisCommand::RandomKey => write!(f, "RANDOMKEY"),
+            RedisCommand::Time => write!(f, "TIME"),
+            RedisCommand::FlushDb(lazy) => write!(f, "FLUSHDB {}", flush_mode(*lazy)),
+            RedisCommand::FlushAll(lazy) => write!(f, "FLUSHALL {}", flush_mode(*lazy)),
             RedisCommand::Ok => write!(f, "OK"),
         }
     }
 }
 
-impl RedisCommand {
-    pub fn is_write_operation(&self) -> bool {
-        matches!(self, RedisCommand::Set(_, _, _))
-    }
+/// Joins key/value pairs into a space separated argument list.
+fn join_pairs(pairs: &[(String, impl AsRef<[u8]>)]) -> String {
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{} {}", key, String::from_utf8_lossy(value.as_ref())))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    pub fn to_resp2(&self) -> String {
-        let command_str = self.to_string();
-        let parts: Vec<&str> = command_str.split_whitespace().collect();
-        let mut resp2 = format!("*{}\r\n", parts.len());
-        for part in parts {
-            resp2.push_str(&format!("${}\r\n{}\r\n", part.len(), part));
-        }
-        resp2
+/// Writes the range and options of ZRANGE and ZRANGESTORE, after the keys.
+fn write_zrange_arguments(
+    f: &mut Formatter,
+    by: &ZRangeBy,
+    options: &ZRangeOptions,
+) -> std::fmt::Result {
+    // Score and lex ranges are held as (min, max) but given as (max, min) with REV
+    match by {
+        ZRangeBy::Rank(start, stop) => write!(f, " {} {}", start, stop)?,
+        ZRangeBy::Score(min, max) if options.rev => write!(f, " {} {} BYSCORE", max, min)?,
+        ZRangeBy::Score(min, max) => write!(f, " {} {} BYSCORE", min, max)?,
+        ZRangeBy::Lex(min, max) if options.rev => write!(f, " {} {} BYLEX", max, min)?,
+        ZRangeBy::Lex(min, max) => write!(f, " {} {} BYLEX", min, max)?,
+    }
+    if options.rev {
+        write!(f, " REV")?;
+    }
+    if let Some((offset, count)) = options.limit {
+        write!(f, " LIMIT {} {}", offset, count)?;
+    }
+    if options.with_scores {
+        write!(f, " WITHSCORES")?;
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RedisCommandResponse {
-    pub message: String,
+/// Formats the `numfields field [field ...]` arguments of the hash field TTL commands.
+fn join_fields(fields: &[String]) -> String {
+    format!("{} {}", fields.len(), fields.join(" "))
 }
 
-impl ToString for RedisCommandResponse {
-    fn to_string(&self) -> String {
-        self.message.clone()
+fn flush_mode(lazy: bool) -> &'static str {
+    if lazy {
+        "ASYNC"
+    } else {
+        "SYNC"
     }
 }
 
-impl RedisCommandResponse {
-    pub fn new(message: String) -> Self {
-        let formatted_message = if message.is_empty() {
-            "$-1\r\n".to_string()
-        } else if message == "OK" {
-            "+OK\r\n".to_string()
-        } else {
-            format!("${}\r\n{}\r\n", message.len(), message)
+impl RedisCommand {
+    /// Returns the request to replicate for a write command received as `raw`.
+    /// Commands with a relative expiry are rewritten to their absolute form so
+    /// replicas expire keys at the same moment as the master, and likewise XCLAIM's
+    /// delivery time. SETEX and PSETEX become SET with PXAT, and XADD is sent with the
+    /// ID its entry was given.
+    pub fn replication_payload(&self, raw: &[Bytes]) -> Bytes {
+        let rewritten = match self {
+            RedisCommand::Set(
+                _,
+                _,
+                SetOptions {
+                    expiry: Some(_), ..
+                },
+            )
+            | RedisCommand::Expire(_, _, _)
+            | RedisCommand::GetEx(_, _)
+            | RedisCommand::Restore(_, _, _, _)
+            | RedisCommand::HExpire(_, _, _, _)
+            | RedisCommand::XAdd(_, _, _, _)
+            | RedisCommand::XClaim(_, _, _, _, _, _) => self.argv(),
+            _ => None,
         };
-        RedisCommandResponse {
-            message: formatted_message,
-        }
+        encode_request(rewritten.as_deref().unwrap_or(raw))
+    }
+
+    /// Returns true for commands that may block the client until a key is ready, which
+    /// the server runs outside the usual request/response path.
+    pub fn is_blocking(&self) -> bool {
+        matches!(
+            self,
+            RedisCommand::BPop(_, _, _)
+                | RedisCommand::BLMove(_, _, _, _, _)
+                | RedisCommand::BLMPop(_, _, _, _)
+                | RedisCommand::BZPop(_, _, _)
+                | RedisCommand::XReadGroup(_, _, XReadGroupOptions { block: Some(_), .. }, _)
+        )
+    }
+
+    pub fn is_write_operation(&self) -> bool {
+        matches!(
+            self,
+            RedisCommand::Set(_, _, _)
+                | RedisCommand::SetNx(_, _)
+                | RedisCommand::GetDel(_)
+                | RedisCommand::GetEx(_, _)
+                | RedisCommand::Copy(_, _, _)
+                | RedisCommand::Restore(_, _, _, _)
+                | RedisCommand::Del(_)
+                | RedisCommand::Unlink(_)
+                | RedisCommand::Push(_, _, _, _)
+                | RedisCommand::Pop(_, _, _)
+                | RedisCommand::BPop(_, _, _)
+                | RedisCommand::LMove(_, _, _, _)
+                | RedisCommand::BLMove(_, _, _, _, _)
+                | RedisCommand::LMPop(_, _, _)
+                | RedisCommand::BLMPop(_, _, _, _)
+                | RedisCommand::LInsert(_, _, _, _)
+                | RedisCommand::LSet(_, _, _)
+                | RedisCommand::LRem(_, _, _)
+                | RedisCommand::LTrim(_, _, _)
+                | RedisCommand::HSet(_, _)
+                | RedisCommand::HDel(_, _)
+                | RedisCommand::HExpire(_, _, _, _)
+                | RedisCommand::HPersist(_, _)
+                | RedisCommand::SAdd(_, _)
+                | RedisCommand::SRem(_, _)
+                | RedisCommand::SMove(_, _, _)
+                | RedisCommand::ZAdd(_, _, _)
+                | RedisCommand::XAdd(_, _, _, _)
+                | RedisCommand::XTrim(_, _)
+                | RedisCommand::XDel(_, _)
+                | RedisCommand::XSetId(_, _, _, _)
+                | RedisCommand::XGroup(_)
+                | RedisCommand::XReadGroup(_, _, _, _)
+                | RedisCommand::XAck(_, _, _)
+                | RedisCommand::XClaim(_, _, _, _, _, _)
+                | RedisCommand::XAutoClaim(_, _, _, _, _, _, _)
+                | RedisCommand::ZIncrBy(_, _, _)
+                | RedisCommand::ZRem(_, _)
+                | RedisCommand::ZPop(_, _, _)
+                | RedisCommand::BZPop(_, _, _)
+                | RedisCommand::ZSetOpStore(_, _, _, _)
+                | RedisCommand::ZRangeStore(_, _, _, _)
+                | RedisCommand::GeoAdd(_, _, _)
+                | RedisCommand::GeoSearchStore(_, _, _, _, _, _)
+                | RedisCommand::Sort(_, SortOptions { store: Some(_), .. })
+                | RedisCommand::SetOpStore(_, _, _)
+                | RedisCommand::MSet(_)
+                | RedisCommand::MSetNx(_)
+                | RedisCommand::FlushDb(_)
+                | RedisCommand::FlushAll(_)
+                | RedisCommand::Expire(_, _, _)
+                | RedisCommand::Persist(_)
+                | RedisCommand::IncrBy(_, _)
+                | RedisCommand::IncrByFloat(_, _)
+                | RedisCommand::Append(_, _)
+                | RedisCommand::SetRange(_, _, _)
+                | RedisCommand::SetBit(_, _, _)
+                | RedisCommand::BitOp(_, _, _)
+                | RedisCommand::BitField(_, _)
+                | RedisCommand::PfAdd(_, _)
+                | RedisCommand::PfMerge(_, _)
+        )
+    }
+
+    /// Returns the argv of a command the server issues itself: a write rewritten for
+    /// replication, the command a blocked client was served with, or a handshake with the
+    /// master. Keys and other names stay single arguments whatever they hold. None for
+    /// commands the server only ever receives.
+    pub fn argv(&self) -> Option<Vec<Bytes>> {
+        // Options are only keywords and numbers, so their display splits into arguments
+        let options = |options: &dyn Display| {
+            options
+                .to_string()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let argv: Vec<String> = match self {
+            RedisCommand::Ping => vec!["PING".to_string()],
+            RedisCommand::Set(key, value, set_options) => {
+                // The value may hold any bytes, so it's kept as it is
+                let mut argv = vec![
+                    Bytes::from_static(b"SET"),
+                    Bytes::from(key.clone()),
+                    Bytes::from(value.clone()),
+                ];
+                if let Some(expiry) = set_options.expiry {
+                    argv.extend([Bytes::from_static(b"PXAT"), expiry.to_string().into()]);
+                }
+                argv.extend(match set_options.condition {
+                    Some(SetCondition::Nx) => Some(Bytes::from_static(b"NX")),
+                    Some(SetCondition::Xx) => Some(Bytes::from_static(b"XX")),
+                    None => None,
+                });
+                if set_options.keep_ttl {
+                    argv.push(Bytes::from_static(b"KEEPTTL"));
+                }
+                if set_options.get {
+                    argv.push(Bytes::from_static(b"GET"));
+                }
+                return Some(argv);
+            }
+            RedisCommand::Replconf(args) => {
+                let mut argv = vec!["REPLCONF".to_string()];
+                argv.extend(args.iter().cloned());
+                argv
+            }
+            RedisCommand::GetEx(key, expiry) => {
+                let mut argv = vec!["GETEX".to_string(), key.clone()];
+                match expiry {
+                    Some(Expiry::At(timestamp)) => {
+                        argv.extend(["PXAT".to_string(), timestamp.to_string()])
+                    }
+                    Some(Expiry::Persist) => argv.push("PERSIST".to_string()),
+                    None => {}
+                }
+                argv
+            }
+            RedisCommand::Restore(key, expiry, payload, replace) => {
+                let mut argv = vec![
+                    "RESTORE".to_string(),
+                    key.clone(),
+                    expiry.unwrap_or(0).to_string(),
+                    payload.clone(),
+                ];
+                if *replace {
+                    argv.push("REPLACE".to_string());
+                }
+                argv.push("ABSTTL".to_string());
+                argv
+            }
+            RedisCommand::Expire(key, timestamp, conditions) => {
+                let mut argv = vec!["PEXPIREAT".to_string(), key.clone(), timestamp.to_string()];
+                argv.extend(conditions.iter().map(ExpireCondition::to_string));
+                argv
+            }
+            RedisCommand::HExpire(key, timestamp, conditions, fields) => {
+                let mut argv = vec!["HPEXPIREAT".to_string(), key.clone(), timestamp.to_string()];
+                argv.extend(conditions.iter().map(ExpireCondition::to_string));
+                argv.extend(["FIELDS".to_string(), fields.len().to_string()]);
+                argv.extend(fields.iter().cloned());
+                argv
+            }
+            RedisCommand::Pop(key, end, count) => {
+                let mut argv = vec![format!("{}POP", end.prefix()), key.clone()];
+                argv.extend(count.map(|count| count.to_string()));
+                argv
+            }
+            RedisCommand::LMove(source, destination, from, to) => vec![
+                "LMOVE".to_string(),
+                source.clone(),
+                destination.clone(),
+                from.to_string(),
+                to.to_string(),
+            ],
+            RedisCommand::ZPop(key, end, count) => {
+                let mut argv = vec![format!("ZPOP{}", end), key.clone()];
+                argv.extend(count.map(|count| count.to_string()));
+                argv
+            }
+            RedisCommand::XAdd(key, add_options, id, fields) => {
+                let mut argv = vec!["XADD".to_string(), key.clone()];
+                argv.extend(options(add_options));
+                argv.push(id.to_string());
+                for (field, value) in fields {
+                    argv.extend([field.clone(), value.clone()]);
+                }
+                argv
+            }
+            RedisCommand::XReadGroup(group, consumer, read_options, streams) => {
+                let mut argv = vec!["XREADGROUP".to_string(), "GROUP".to_string()];
+                argv.extend([group.clone(), consumer.clone()]);
+                argv.extend(options(read_options));
+                argv.push("STREAMS".to_string());
+                argv.extend(streams.iter().map(|(key, _)| key.clone()));
+                argv.extend(streams.iter().map(|(_, id)| id.to_string()));
+                argv
+            }
+            RedisCommand::XClaim(key, group, consumer, min_idle, ids, claim_options) => {
+                let mut argv = vec![
+                    "XCLAIM".to_string(),
+                    key.clone(),
+                    group.clone(),
+                    consumer.clone(),
+                    min_idle.to_string(),
+                ];
+                argv.extend(ids.iter().map(StreamId::to_string));
+                argv.extend(options(claim_options));
+                argv
+            }
+            _ => return None,
+        };
+        Some(argv.into_iter().map(Bytes::from).collect())
     }
+}
 
-    pub fn null() -> Self {
-        RedisCommandResponse {
-            message: "$-1\r\n".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{RedisCommandParser, RespDecoder};
+
+    #[test]
+    fn served_commands_replicate_with_spaces_in_arguments() {
+        for command in [
+            RedisCommand::Pop("my list".to_string(), ListEnd::Left, None),
+            RedisCommand::Pop("my list".to_string(), ListEnd::Right, Some(2)),
+            RedisCommand::LMove(
+                "my list".to_string(),
+                "other list".to_string(),
+                ListEnd::Left,
+                ListEnd::Right,
+            ),
+            RedisCommand::ZPop("my zset".to_string(), ScoreEnd::Min, None),
+        ] {
+            let argv = command.argv().expect("served commands have an argv");
+            assert_eq!(RedisCommandParser::parse_argv(&argv).unwrap(), command);
         }
     }
 
-    pub fn _error(message: String) -> Self {
-        RedisCommandResponse {
-            message: format!("-{}\r\n", message),
+    #[test]
+    fn relative_set_expiries_replicate_as_pxat() {
+        for raw in [
+            &["SET", "key", "value", "EX", "100", "NX"][..],
+            &["SET", "key", "value", "PX", "100000"],
+            &["SETEX", "key", "100", "value"],
+            &["PSETEX", "key", "100000", "value"],
+        ] {
+            let raw: Vec<Bytes> = raw.iter().map(|arg| Bytes::from(arg.to_string())).collect();
+            let command = RedisCommandParser::parse_argv(&raw).unwrap();
+            let RedisCommand::Set(
+                _,
+                _,
+                SetOptions {
+                    expiry: Some(expiry),
+                    ..
+                },
+            ) = command
+            else {
+                panic!("expected a SET with an expiry, got {:?}", command);
+            };
+            let payload = command.replication_payload(&raw);
+            let mut decoder = RespDecoder::default();
+            decoder.feed(&payload);
+            let argv = decoder.next_request(&Default::default()).unwrap().unwrap();
+            let expected = ["SET", "key", "value", "PXAT", &expiry.to_string()]
+                .map(|arg| Bytes::copy_from_slice(arg.as_bytes()));
+            assert_eq!(argv[..5], expected);
+            assert_eq!(RedisCommandParser::parse_argv(&argv).unwrap(), command);
         }
+
+        let raw = vec![Bytes::from("SET"), Bytes::from("key"), Bytes::from("value")];
+        let command = RedisCommandParser::parse_argv(&raw).unwrap();
+        assert_eq!(command.replication_payload(&raw), encode_request(&raw));
     }
 }
@@ -0,0 +1,410 @@
+/// Describes where a command's key arguments live in its argv.
+///
+/// Indexes are into the full argv, so index 0 is the command name itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySpec {
+    /// Keys from `first` to `last` inclusive, every `step` arguments.
+    /// A negative `last` counts back from the end of argv (-1 is the final argument).
+    Range {
+        first: usize,
+        last: isize,
+        step: usize,
+    },
+    /// The first half of the arguments after the STREAMS keyword, searched from `start`
+    /// onwards, as the second half are their IDs.
+    Streams { start: usize },
+    /// A key count at `index`, followed by that many keys.
+    NumKeys { index: usize },
+    /// The argument following any of the given (case-insensitive) keywords,
+    /// searched from `start` onwards.
+    Keyword {
+        keywords: &'static [&'static str],
+        start: usize,
+    },
+}
+
+/// Key spec metadata for a command, along with its arity.
+///
+/// A positive arity is an exact argument count (including the command name),
+/// a negative arity is a minimum.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandKeySpecs {
+    pub arity: isize,
+    pub specs: &'static [KeySpec],
+}
+
+const SINGLE_KEY: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: 1,
+    step: 1,
+}];
+const ALL_KEYS: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: -1,
+    step: 1,
+}];
+const KEY_VALUE_PAIRS: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: -1,
+    step: 2,
+}];
+/// Every argument but the trailing timeout, as in BLPOP.
+const BLOCKING_KEYS: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: -2,
+    step: 1,
+}];
+const COPY_KEYS: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: 2,
+    step: 1,
+}];
+/// The argument following a subcommand, as in OBJECT ENCODING and XGROUP CREATE.
+const SUBCOMMAND_KEY: &[KeySpec] = &[KeySpec::Range {
+    first: 2,
+    last: 2,
+    step: 1,
+}];
+const LCS_KEYS: &[KeySpec] = &[KeySpec::Range {
+    first: 1,
+    last: 2,
+    step: 1,
+}];
+const GEORADIUS_KEYS: &[KeySpec] = &[
+    KeySpec::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    },
+    KeySpec::Keyword {
+        keywords: &["store", "storedist"],
+        start: 6,
+    },
+];
+const GEORADIUSBYMEMBER_KEYS: &[KeySpec] = &[
+    KeySpec::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    },
+    KeySpec::Keyword {
+        keywords: &["store", "storedist"],
+        start: 5,
+    },
+];
+const SORT_KEYS: &[KeySpec] = &[
+    KeySpec::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    },
+    KeySpec::Keyword {
+        keywords: &["store"],
+        start: 2,
+    },
+];
+const NUMKEYS_AT_ONE: &[KeySpec] = &[KeySpec::NumKeys { index: 1 }];
+const NUMKEYS_AT_TWO: &[KeySpec] = &[KeySpec::NumKeys { index: 2 }];
+/// A destination key followed by a key count and that many keys, as in ZUNIONSTORE.
+const DESTINATION_AND_NUMKEYS: &[KeySpec] = &[
+    KeySpec::Range {
+        first: 1,
+        last: 1,
+        step: 1,
+    },
+    KeySpec::NumKeys { index: 2 },
+];
+/// The destination and source keys following the operation, as in BITOP.
+const BITOP_KEYS: &[KeySpec] = &[KeySpec::Range {
+    first: 2,
+    last: -1,
+    step: 1,
+}];
+const XREADGROUP_KEYS: &[KeySpec] = &[KeySpec::Streams { start: 4 }];
+const NO_KEYS: &[KeySpec] = &[];
+
+impl CommandKeySpecs {
+    /// Returns true if the command's arity allows `argc` arguments, counting its name.
+    pub fn accepts(&self, argc: usize) -> bool {
+        let argc = argc as isize;
+        match self.arity {
+            arity if arity > 0 => argc == arity,
+            arity => argc >= -arity,
+        }
+    }
+}
+
+/// Looks up the key spec metadata for a command by name.
+pub fn lookup(command: &str) -> Option<CommandKeySpecs> {
+    let (arity, specs) = match command.to_lowercase().as_str() {
+        "ping" => (-1, NO_KEYS),
+        "echo" => (2, NO_KEYS),
+        "hello" => (-1, NO_KEYS),
+        "info" => (-1, NO_KEYS),
+        "lolwut" => (-1, NO_KEYS),
+        "replconf" => (-1, NO_KEYS),
+        "command" => (-1, NO_KEYS),
+        "config" => (-2, NO_KEYS),
+        "debug" => (-2, NO_KEYS),
+        "acl" => (-2, NO_KEYS),
+        "object" => (-2, SUBCOMMAND_KEY),
+        "get" => (2, SINGLE_KEY),
+        "set" => (-3, SINGLE_KEY),
+        "setnx" => (3, SINGLE_KEY),
+        "getdel" => (2, SINGLE_KEY),
+        "getex" => (-2, SINGLE_KEY),
+        "setex" => (4, SINGLE_KEY),
+        "psetex" => (4, SINGLE_KEY),
+        "exists" => (-2, ALL_KEYS),
+        "touch" => (-2, ALL_KEYS),
+        "unlink" => (-2, ALL_KEYS),
+        "expire" => (-3, SINGLE_KEY),
+        "pexpire" => (-3, SINGLE_KEY),
+        "expireat" => (-3, SINGLE_KEY),
+        "pexpireat" => (-3, SINGLE_KEY),
+        "persist" => (2, SINGLE_KEY),
+        "expiretime" => (2, SINGLE_KEY),
+        "pexpiretime" => (2, SINGLE_KEY),
+        "ttl" => (2, SINGLE_KEY),
+        "pttl" => (2, SINGLE_KEY),
+        "copy" => (-3, COPY_KEYS),
+        "dump" => (2, SINGLE_KEY),
+        "restore" => (-4, SINGLE_KEY),
+        "incr" => (2, SINGLE_KEY),
+        "decr" => (2, SINGLE_KEY),
+        "incrby" => (3, SINGLE_KEY),
+        "decrby" => (3, SINGLE_KEY),
+        "incrbyfloat" => (3, SINGLE_KEY),
+        "append" => (3, SINGLE_KEY),
+        "strlen" => (2, SINGLE_KEY),
+        "lcs" => (-3, LCS_KEYS),
+        "getrange" => (4, SINGLE_KEY),
+        "setrange" => (4, SINGLE_KEY),
+        "setbit" => (4, SINGLE_KEY),
+        "getbit" => (3, SINGLE_KEY),
+        "bitcount" => (-2, SINGLE_KEY),
+        "bitop" => (-4, BITOP_KEYS),
+        "bitpos" => (-3, SINGLE_KEY),
+        "bitfield" => (-2, SINGLE_KEY),
+        "pfadd" => (-2, SINGLE_KEY),
+        "pfcount" => (-2, ALL_KEYS),
+        "pfmerge" => (-2, ALL_KEYS),
+        "keys" => (2, NO_KEYS),
+        "dbsize" => (1, NO_KEYS),
+        "sort" => (-2, SORT_KEYS),
+        "type" => (2, SINGLE_KEY),
+        "randomkey" => (1, NO_KEYS),
+        "time" => (1, NO_KEYS),
+        "flushdb" => (-1, NO_KEYS),
+        "flushall" => (-1, NO_KEYS),
+        "mset" => (-3, KEY_VALUE_PAIRS),
+        "msetnx" => (-3, KEY_VALUE_PAIRS),
+        "mget" => (-2, ALL_KEYS),
+        "del" => (-2, ALL_KEYS),
+        "lpush" => (-3, SINGLE_KEY),
+        "rpush" => (-3, SINGLE_KEY),
+        "lpushx" => (-3, SINGLE_KEY),
+        "rpushx" => (-3, SINGLE_KEY),
+        "lpop" => (-2, SINGLE_KEY),
+        "rpop" => (-2, SINGLE_KEY),
+        "blpop" => (-3, BLOCKING_KEYS),
+        "brpop" => (-3, BLOCKING_KEYS),
+        "lmove" => (5, COPY_KEYS),
+        "rpoplpush" => (3, COPY_KEYS),
+        "blmove" => (6, COPY_KEYS),
+        "lpos" => (-3, SINGLE_KEY),
+        "llen" => (2, SINGLE_KEY),
+        "hset" => (-4, SINGLE_KEY),
+        "hget" => (3, SINGLE_KEY),
+        "hdel" => (-3, SINGLE_KEY),
+        "hgetall" => (2, SINGLE_KEY),
+        "hmget" => (-3, SINGLE_KEY),
+        "hlen" => (2, SINGLE_KEY),
+        "hexists" => (3, SINGLE_KEY),
+        "hrandfield" => (-2, SINGLE_KEY),
+        "hscan" => (-3, SINGLE_KEY),
+        "hexpire" => (-6, SINGLE_KEY),
+        "hpexpire" => (-6, SINGLE_KEY),
+        "hexpireat" => (-6, SINGLE_KEY),
+        "hpexpireat" => (-6, SINGLE_KEY),
+        "hpersist" => (-5, SINGLE_KEY),
+        "httl" => (-5, SINGLE_KEY),
+        "hpttl" => (-5, SINGLE_KEY),
+        "sadd" => (-3, SINGLE_KEY),
+        "srem" => (-3, SINGLE_KEY),
+        "smembers" => (2, SINGLE_KEY),
+        "sismember" => (3, SINGLE_KEY),
+        "smismember" => (-3, SINGLE_KEY),
+        "smove" => (4, COPY_KEYS),
+        "scard" => (2, SINGLE_KEY),
+        "sinter" => (-2, ALL_KEYS),
+        "sunion" => (-2, ALL_KEYS),
+        "sdiff" => (-2, ALL_KEYS),
+        "sintercard" => (-3, NUMKEYS_AT_ONE),
+        "sscan" => (-3, SINGLE_KEY),
+        "sinterstore" => (-3, ALL_KEYS),
+        "sunionstore" => (-3, ALL_KEYS),
+        "sdiffstore" => (-3, ALL_KEYS),
+        "lrange" => (4, SINGLE_KEY),
+        "linsert" => (5, SINGLE_KEY),
+        "lset" => (4, SINGLE_KEY),
+        "lrem" => (4, SINGLE_KEY),
+        "ltrim" => (4, SINGLE_KEY),
+        "zadd" => (-4, SINGLE_KEY),
+        "zscore" => (3, SINGLE_KEY),
+        "zcard" => (2, SINGLE_KEY),
+        "zrange" => (-4, SINGLE_KEY),
+        "zrangebyscore" => (-4, SINGLE_KEY),
+        "zrevrangebyscore" => (-4, SINGLE_KEY),
+        "zrangebylex" => (-4, SINGLE_KEY),
+        "zrevrangebylex" => (-4, SINGLE_KEY),
+        "zrangestore" => (-5, COPY_KEYS),
+        "zrandmember" => (-2, SINGLE_KEY),
+        "zscan" => (-3, SINGLE_KEY),
+        "zcount" => (4, SINGLE_KEY),
+        "zincrby" => (4, SINGLE_KEY),
+        "zrank" => (-3, SINGLE_KEY),
+        "zrevrank" => (-3, SINGLE_KEY),
+        "zrem" => (-3, SINGLE_KEY),
+        "zinter" => (-3, NUMKEYS_AT_ONE),
+        "zunion" => (-3, NUMKEYS_AT_ONE),
+        "zdiff" => (-3, NUMKEYS_AT_ONE),
+        "zinterstore" => (-4, DESTINATION_AND_NUMKEYS),
+        "zunionstore" => (-4, DESTINATION_AND_NUMKEYS),
+        "zdiffstore" => (-4, DESTINATION_AND_NUMKEYS),
+        "zpopmin" => (-2, SINGLE_KEY),
+        "zpopmax" => (-2, SINGLE_KEY),
+        "bzpopmin" => (-3, BLOCKING_KEYS),
+        "bzpopmax" => (-3, BLOCKING_KEYS),
+        "zlexcount" => (4, SINGLE_KEY),
+        "xadd" => (-5, SINGLE_KEY),
+        "xlen" => (2, SINGLE_KEY),
+        "xtrim" => (-4, SINGLE_KEY),
+        "xdel" => (-3, SINGLE_KEY),
+        "xsetid" => (-3, SINGLE_KEY),
+        "xrange" => (-4, SINGLE_KEY),
+        "xrevrange" => (-4, SINGLE_KEY),
+        "xgroup" => (-2, SUBCOMMAND_KEY),
+        "xinfo" => (-2, SUBCOMMAND_KEY),
+        "xreadgroup" => (-7, XREADGROUP_KEYS),
+        "xack" => (-4, SINGLE_KEY),
+        "xpending" => (-3, SINGLE_KEY),
+        "xclaim" => (-6, SINGLE_KEY),
+        "xautoclaim" => (-6, SINGLE_KEY),
+        "geoadd" => (-5, SINGLE_KEY),
+        "geopos" => (-2, SINGLE_KEY),
+        "geodist" => (-4, SINGLE_KEY),
+        "geohash" => (-2, SINGLE_KEY),
+        "geosearch" => (-7, SINGLE_KEY),
+        "geosearchstore" => (-8, COPY_KEYS),
+        "georadius" => (-6, GEORADIUS_KEYS),
+        "georadiusbymember" => (-5, GEORADIUSBYMEMBER_KEYS),
+        "lmpop" => (-4, NUMKEYS_AT_ONE),
+        "zmpop" => (-4, NUMKEYS_AT_ONE),
+        "blmpop" => (-5, NUMKEYS_AT_TWO),
+        _ => return None,
+    };
+    Some(CommandKeySpecs { arity, specs })
+}
+
+/// Extracts the key arguments from a full command argv, as `COMMAND GETKEYS` does.
+pub fn get_keys(argv: &[String]) -> Result<Vec<String>, anyhow::Error> {
+    let name = argv
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("Invalid command specified"))?;
+    let command = lookup(name).ok_or_else(|| anyhow::anyhow!("Invalid command specified"))?;
+
+    if !command.accepts(argv.len()) {
+        anyhow::bail!("Invalid number of arguments specified for command");
+    }
+    let argc = argv.len() as isize;
+    if command.specs.is_empty() {
+        anyhow::bail!("The command has no key arguments");
+    }
+
+    let mut keys = Vec::new();
+    for spec in command.specs {
+        match *spec {
+            KeySpec::Range { first, last, step } => {
+                let last = if last < 0 { argc + last } else { last };
+                let mut index = first as isize;
+                while index <= last && index < argc {
+                    keys.push(argv[index as usize].clone());
+                    index += step as isize;
+                }
+            }
+            KeySpec::NumKeys { index } => {
+                let numkeys = argv
+                    .get(index)
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| anyhow::anyhow!("numkeys should be greater than 0"))?;
+                let first = index + 1;
+                if first + numkeys > argv.len() {
+                    anyhow::bail!("Invalid number of arguments specified for command");
+                }
+                keys.extend_from_slice(&argv[first..first + numkeys]);
+            }
+            KeySpec::Streams { start } => {
+                let position = argv
+                    .iter()
+                    .skip(start)
+                    .position(|arg| arg.eq_ignore_ascii_case("streams"));
+                if let Some(position) = position {
+                    let streams = &argv[start + position + 1..];
+                    if !streams.len().is_multiple_of(2) {
+                        anyhow::bail!("Invalid number of arguments specified for command");
+                    }
+                    keys.extend_from_slice(&streams[..streams.len() / 2]);
+                }
+            }
+            KeySpec::Keyword { keywords, start } => {
+                let position = argv.iter().skip(start).position(|arg| {
+                    keywords
+                        .iter()
+                        .any(|keyword| arg.eq_ignore_ascii_case(keyword))
+                });
+                if let Some(key) = position.and_then(|p| argv.get(start + p + 1)) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(argv: &[&str]) -> Vec<String> {
+        let argv: Vec<String> = argv.iter().map(|argument| argument.to_string()).collect();
+        get_keys(&argv).unwrap()
+    }
+
+    #[test]
+    fn mset_keys_are_every_other_argument() {
+        assert_eq!(
+            keys(&["MSET", "a", "1", "b", "2", "c", "3"]),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn lmpop_keys_follow_numkeys() {
+        assert_eq!(
+            keys(&["LMPOP", "2", "first", "second", "LEFT"]),
+            ["first", "second"]
+        );
+        assert_eq!(
+            keys(&["LMPOP", "1", "first", "LEFT", "COUNT", "2"]),
+            ["first"]
+        );
+    }
+
+    #[test]
+    fn set_has_a_single_key() {
+        assert_eq!(keys(&["SET", "key", "value", "PX", "100"]), ["key"]);
+    }
+}
@@ -2,8 +2,10 @@ use std::sync::Arc;
 
 pub mod cli;
 pub mod command;
+pub mod keyspec;
 pub mod parser;
 pub mod redis;
+pub mod resp;
 pub mod server;
 pub mod utils;
 
@@ -35,6 +37,7 @@ async fn main() -> Result<()> {
     dotenv::dotenv().ok();
 
     let cli = Cli::parse();
+    cli.validate()?;
     let role = cli.determine_role();
     let (master_host, master_port) = cli.get_master_info()?;
 
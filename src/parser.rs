@@ -1,141 +1,898 @@
+use std::iter::Peekable;
+use std::ops::Range;
+use std::time::Duration;
+
 use anyhow::Context;
+use bytes::{Bytes, BytesMut};
 
-use crate::command::{AdminCommand, RedisCommand};
-use crate::utils::millis_to_timestamp_from_now;
+use crate::command::{
+    AclCommand, AdminCommand, Aggregate, BitFieldOperation, BitFieldOverflow, BitFieldType,
+    BitOperation, BitUnit, ConfigCommand, DebugCommand, ExpireCondition, Expiry, GeoOrigin,
+    GeoSearchOptions, GeoShape, GeoUnit, GroupReadId, GroupStart, HelloOptions, LcsOptions,
+    LexBound, ListEnd, LposOptions, ObjectCommand, RedisCommand, ScanOptions, ScoreBound,
+    ScoreComparison, ScoreEnd, SetCondition, SetOperation, SetOptions, SortOptions, StreamId,
+    StreamTrim, TrimStrategy, XAddId, XAddOptions, XClaimOptions, XGroupCommand, XInfoCommand,
+    XPendingRange, XReadGroupOptions, ZAddOptions, ZRangeBy, ZRangeOptions, ZSetOpOptions,
+    GEO_LATITUDE_RANGE, GEO_LONGITUDE_RANGE,
+};
+use crate::keyspec;
+use crate::utils::{millis_to_timestamp_from_now, now_millis, parse_float, parse_score};
 
-pub struct RedisCommandParser;
+/// Default cap on the number of elements in a multibulk request.
+pub const DEFAULT_PROTO_MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+/// Default cap on the length of a bulk string in a request.
+pub const DEFAULT_PROTO_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// Default cap on how much of an inline request, or of the count line of a multibulk
+/// request, is buffered while waiting for its line to end.
+pub const DEFAULT_PROTO_INLINE_MAX_SIZE: usize = 64 * 1024;
 
-impl RedisCommandParser {
-    /// Helper function to extract the next line with a specific prefix
-    fn extract_line<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
-        prefix: char,
-    ) -> Result<&'a str, anyhow::Error> {
-        lines
-            .next()
-            .context("Invalid protocol format")?
-            .strip_prefix(prefix)
-            .ok_or_else(|| anyhow::anyhow!("Expected line to start with '{}'", prefix))
-    }
-
-    /// Parses a Redis command into the RedisCommand enum.
-    pub fn parse(buffer_str: &str) -> Result<RedisCommand, anyhow::Error> {
-        // Remove null characters from the buffer
-        let sanitized_buffer: String = buffer_str.chars().filter(|&c| c != '\0').collect();
-        let mut lines = sanitized_buffer
-            .split("\r\n")
-            .filter(|line| !line.is_empty())
-            .peekable();
+/// Errors in the framing of a request, after which the connection is closed.
+#[derive(Debug, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("Protocol error: invalid multibulk length")]
+    InvalidMultibulkLength,
+    #[error("Protocol error: invalid bulk length")]
+    InvalidBulkLength,
+    #[error("Protocol error: unbalanced quotes in request")]
+    UnbalancedQuotes,
+    #[error("Protocol error: too big inline request")]
+    TooBigInlineRequest,
+    #[error("Protocol error: too big mbulk count string")]
+    TooBigMultibulkCount,
+    #[error("Protocol error: too big bulk count string")]
+    TooBigBulkCount,
+    #[error("Protocol error: expected '{0}', got '{1}'")]
+    UnexpectedByte(char, char),
+    #[error("Protocol error: expected CRLF after bulk string")]
+    UnterminatedBulkString,
+}
 
-        // Peek at the first character to determine the type of the command
-        let first_char = lines
-            .peek()
-            .context("Empty buffer")?
-            .chars()
-            .next()
-            .context("Empty line")?;
+/// Error for a request whose bytes haven't all arrived yet.
+#[derive(Debug, thiserror::Error)]
+#[error("Incomplete request")]
+struct IncompleteRequest;
 
-        match first_char {
-            '*' => Self::parse_array_command(&mut lines),
-            '$' => Self::parse_bulk_string_command(&mut lines),
-            _ => Err(anyhow::anyhow!("Invalid protocol format")),
+/// Reads the parts of a RESP request from raw bytes.
+struct RespReader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+    limits: ProtocolLimits,
+}
+
+impl<'a> RespReader<'a> {
+    fn new(buffer: &'a [u8], limits: ProtocolLimits) -> Self {
+        RespReader {
+            buffer,
+            position: 0,
+            limits,
         }
     }
 
-    fn parse_array_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
-    ) -> Result<RedisCommand, anyhow::Error> {
-        let array_length = Self::parse_array_length(lines)?;
+    fn is_empty(&self) -> bool {
+        self.position >= self.buffer.len()
+    }
+
+    /// Reads a line up to its CRLF, which must start with `prefix`.
+    fn line(&mut self, prefix: u8) -> Result<&'a [u8], anyhow::Error> {
+        let rest = &self.buffer[self.position..];
+        let Some(end) = rest.windows(2).position(|window| window == b"\r\n") else {
+            // Don't wait forever for the end of a line that's already too long
+            if rest.len() > self.limits.max_inline_len {
+                return Err(match prefix {
+                    b'*' => ProtocolError::TooBigMultibulkCount,
+                    _ => ProtocolError::TooBigBulkCount,
+                }
+                .into());
+            }
+            return Err(IncompleteRequest.into());
+        };
+        self.position += end + 2;
+        match rest[..end].split_first() {
+            Some((&first, line)) if first == prefix => Ok(line),
+            _ => Err(ProtocolError::UnexpectedByte(prefix as char, rest[0] as char).into()),
+        }
+    }
+
+    /// Reads the number after a line's prefix, e.g. the length of a bulk string.
+    fn number(&mut self, prefix: u8) -> Result<Option<usize>, anyhow::Error> {
+        let line = self.line(prefix)?;
+        Ok(std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.parse().ok()))
+    }
+
+    /// Reads a bulk string, taking exactly as many bytes as its length says so it may
+    /// hold CRLF, NUL or any other bytes. Returns where its bytes are in the buffer.
+    fn bulk_string(&mut self) -> Result<Range<usize>, anyhow::Error> {
+        let length = self
+            .number(b'$')?
+            .filter(|&length| length <= self.limits.max_bulk_len)
+            .ok_or(ProtocolError::InvalidBulkLength)?;
+        let rest = &self.buffer[self.position..];
+        if rest.len() < length + 2 {
+            return Err(IncompleteRequest.into());
+        }
+        if &rest[length..length + 2] != b"\r\n" {
+            return Err(ProtocolError::UnterminatedBulkString.into());
+        }
+        let start = self.position;
+        self.position += length + 2;
+        Ok(start..start + length)
+    }
+}
+
+/// The kind of range a ZRANGE selects members by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ZRangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Errors in a well-formed request that are reported back to the client,
+/// leaving the connection open.
+#[derive(Debug, thiserror::Error)]
+pub enum CommandError {
+    #[error("syntax error")]
+    Syntax,
+    #[error("unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("Protocol version is not an integer or out of range")]
+    ProtocolVersion,
+    #[error("Syntax error in HELLO option '{0}'")]
+    HelloOption(String),
+    #[error("value is not an integer or out of range")]
+    NotInteger,
+    #[error("increment or decrement would overflow")]
+    Overflow,
+    #[error("value is not a valid float")]
+    NotFloat,
+    #[error("{0} is not valid UTF-8")]
+    InvalidUtf8(String),
+    #[error("DEBUG SLEEP requires a non-negative number of seconds")]
+    InvalidSleep,
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+    #[error("NX and XX, GT or LT options at the same time are not compatible")]
+    IncompatibleExpireConditions,
+    #[error("GT and LT options at the same time are not compatible")]
+    IncompatibleExpireComparisons,
+    #[error("Unsupported option {0}")]
+    UnsupportedOption(String),
+    #[error("unknown subcommand '{0}'. Try {1} HELP.")]
+    UnknownSubcommand(String, &'static str),
+    #[error("Invalid TTL value, must be >= 0")]
+    InvalidTtl,
+    #[error("If you want both the length and indexes, please just use IDX.")]
+    LcsLenAndIdx,
+    #[error("timeout is not a float or out of range")]
+    InvalidTimeout,
+    #[error("timeout is negative")]
+    NegativeTimeout,
+    #[error("timeout is out of range")]
+    TimeoutOutOfRange,
+    #[error("timeout is not an integer or out of range")]
+    TimeoutNotInteger,
+    #[error("RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")]
+    RankZero,
+    #[error("{0} can't be negative")]
+    NegativeOption(&'static str),
+    #[error("numkeys should be greater than 0")]
+    NumKeysNotPositive,
+    #[error("XX and NX options at the same time are not compatible")]
+    IncompatibleZAddConditions,
+    #[error("GT, LT, and/or NX options at the same time are not compatible")]
+    IncompatibleZAddComparisons,
+    #[error("INCR option supports a single increment-element pair")]
+    IncrMultiplePairs,
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error("invalid {0} ID for the interval")]
+    InvalidIntervalId(&'static str),
+    #[error("Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.")]
+    UnbalancedStreams,
+    #[error("The {0} argument must be >= 0.")]
+    NegativeStreamArgument(&'static str),
+    #[error("syntax error, LIMIT cannot be used without the special ~ option")]
+    LimitWithoutApproximation,
+    #[error("syntax error, MAXLEN and MINID options at the same time are not compatible")]
+    IncompatibleTrimStrategies,
+    #[error("entries_added must be positive")]
+    NegativeEntriesAdded,
+    #[error("Invalid {0} argument for {1}")]
+    InvalidClaimArgument(&'static str, &'static str),
+    #[error("COUNT must be > 0")]
+    CountMustBePositive,
+    #[error("min or max is not a float")]
+    NotFloatRange,
+    #[error("min or max not valid string range item")]
+    InvalidLexRange,
+    #[error("syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")]
+    LimitWithoutBy,
+    #[error("syntax error, WITHSCORES not supported in combination with BYLEX")]
+    WithScoresByLex,
+    #[error("at least 1 input key is needed for '{0}' command")]
+    NoInputKeys(String),
+    #[error("weight value is not a float")]
+    WeightNotFloat,
+    #[error("Number of keys can't be greater than number of args")]
+    NumKeysTooLarge,
+    #[error("count should be greater than 0")]
+    CountNotPositive,
+    #[error("Mandatory argument FIELDS is missing or not at the right position")]
+    MissingFields,
+    #[error("Parameter `numFields` should be greater than 0")]
+    NumFieldsNotPositive,
+    #[error("The `numfields` parameter must match the number of arguments")]
+    NumFieldsMismatch,
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("value is out of range, must be positive")]
+    NotPositive,
+    #[error("value is out of range")]
+    OutOfRange,
+    #[error("DB index is out of range")]
+    DbIndexOutOfRange,
+    #[error("offset is out of range")]
+    OffsetOutOfRange,
+    #[error("bit offset is not an integer or out of range")]
+    InvalidBitOffset,
+    #[error("bit is not an integer or out of range")]
+    InvalidBit,
+    #[error("The bit argument must be 1 or 0.")]
+    BitPosInvalidBit,
+    #[error("BITOP NOT must be called with a single source key.")]
+    BitOpNotSingleKey,
+    #[error(
+        "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+    )]
+    InvalidBitFieldType,
+    #[error("Invalid OVERFLOW type specified")]
+    InvalidOverflowType,
+    #[error("invalid longitude,latitude pair {0:.6},{1:.6}")]
+    InvalidLonLat(f64, f64),
+    #[error("unsupported unit provided. please use M, KM, FT, MI")]
+    UnsupportedUnit,
+    #[error("need numeric {0}")]
+    NotNumeric(&'static str),
+    #[error("radius cannot be negative")]
+    NegativeRadius,
+    #[error("height or width cannot be negative")]
+    NegativeBox,
+    #[error("exactly one of FROMMEMBER or FROMLONLAT can be specified for {0}")]
+    GeoSearchOrigin(&'static str),
+    #[error("exactly one of BYRADIUS and BYBOX can be specified for {0}")]
+    GeoSearchShape(&'static str),
+    #[error("the ANY argument requires COUNT argument")]
+    AnyWithoutCount,
+    #[error("GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options")]
+    GeoSearchStoreWith,
+    #[error("string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+}
+
+/// Maximum length of a string value, matching the default `proto-max-bulk-len`.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Limits applied while parsing requests, to protect the server from hostile input.
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolLimits {
+    pub max_multibulk_len: usize,
+    pub max_bulk_len: usize,
+    pub max_inline_len: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_multibulk_len: DEFAULT_PROTO_MAX_MULTIBULK_LEN,
+            max_bulk_len: DEFAULT_PROTO_MAX_BULK_LEN,
+            max_inline_len: DEFAULT_PROTO_INLINE_MAX_SIZE,
+        }
+    }
+}
+
+/// Splits an inline request into its arguments at whitespace, as Redis does. Arguments
+/// may be quoted to hold whitespace: double quotes take the escapes `\n`, `\r`, `\t`,
+/// `\b`, `\a` and `\xHH`, single quotes only `\'`. A closing quote must end its argument.
+fn split_inline_arguments(line: &[u8]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let is_space = |byte: u8| byte.is_ascii_whitespace() || byte == 0;
+    let mut arguments = Vec::new();
+    let mut bytes = line.iter().copied().peekable();
+    loop {
+        while bytes.next_if(|&byte| is_space(byte)).is_some() {}
+        if bytes.peek().is_none() {
+            return Ok(arguments);
+        }
+        let mut argument = Vec::new();
+        let mut quote = None;
+        loop {
+            let Some(byte) = bytes.next() else {
+                match quote {
+                    Some(_) => return Err(ProtocolError::UnbalancedQuotes),
+                    None => break,
+                }
+            };
+            match (quote, byte) {
+                (None, b'"' | b'\'') => quote = Some(byte),
+                (None, byte) if is_space(byte) => break,
+                (None, byte) => argument.push(byte),
+                (Some(closing), byte) if byte == closing => {
+                    if bytes.peek().is_some_and(|&next| !is_space(next)) {
+                        return Err(ProtocolError::UnbalancedQuotes);
+                    }
+                    break;
+                }
+                (Some(b'"'), b'\\') => {
+                    let Some(escaped) = bytes.next() else {
+                        return Err(ProtocolError::UnbalancedQuotes);
+                    };
+                    let hex = |byte: u8| (byte as char).to_digit(16);
+                    match escaped {
+                        b'x' => {
+                            let mut digits = bytes.clone();
+                            match (digits.next().and_then(hex), digits.next().and_then(hex)) {
+                                (Some(high), Some(low)) => {
+                                    argument.push((high * 16 + low) as u8);
+                                    bytes = digits;
+                                }
+                                _ => argument.push(b'x'),
+                            }
+                        }
+                        b'n' => argument.push(b'\n'),
+                        b'r' => argument.push(b'\r'),
+                        b't' => argument.push(b'\t'),
+                        b'b' => argument.push(0x08),
+                        b'a' => argument.push(0x07),
+                        escaped => argument.push(escaped),
+                    }
+                }
+                (Some(b'\''), b'\\') if bytes.peek() == Some(&b'\'') => {
+                    argument.push(b'\'');
+                    bytes.next();
+                }
+                (Some(_), byte) => argument.push(byte),
+            }
+        }
+        arguments.push(argument);
+    }
+}
+
+/// Encodes an argv as an array of bulk strings, the form requests are replicated in.
+pub fn encode_request(argv: &[Bytes]) -> Bytes {
+    let mut request = BytesMut::new();
+    request.extend_from_slice(format!("*{}\r\n", argv.len()).as_bytes());
+    for argument in argv {
+        request.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+        request.extend_from_slice(argument);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.freeze()
+}
+
+/// Accumulates the bytes read from a connection and splits them into requests, so a request
+/// may span any number of reads.
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    buffer: BytesMut,
+    /// How much of a partly received inline request has been searched for its newline.
+    scanned: usize,
+    /// The multibulk request being received, if its bytes haven't all arrived yet.
+    pending: Option<PendingRequest>,
+}
+
+/// How far a request whose bytes haven't all arrived has been read, kept between reads so
+/// the elements already framed aren't parsed again each time more bytes arrive. The request
+/// is either an array of bulk strings, or bulk strings one after another up to the end of
+/// the buffer.
+#[derive(Debug)]
+struct PendingRequest {
+    /// Where the next element starts in the buffer.
+    position: usize,
+    /// Elements still to read, or None for bulk strings running to the end of the buffer.
+    remaining: Option<usize>,
+    /// Where the elements read so far are in the buffer.
+    arguments: Vec<Range<usize>>,
+}
+
+impl PendingRequest {
+    /// Starts a request by reading its array header, if it has one.
+    fn start(reader: &mut RespReader, first: u8) -> Result<Self, anyhow::Error> {
+        let remaining = match first {
+            b'*' => Some(RedisCommandParser::parse_array_length(reader)?),
+            _ => None,
+        };
+        Ok(PendingRequest {
+            position: reader.position,
+            remaining,
+            arguments: Vec::new(),
+        })
+    }
+
+    /// Reads elements until the request is complete. Each element is recorded once it has
+    /// all arrived, so an incomplete request resumes from the element it stopped at.
+    fn read(&mut self, reader: &mut RespReader) -> Result<(), anyhow::Error> {
+        reader.position = self.position;
+        loop {
+            match self.remaining {
+                Some(0) => return Ok(()),
+                None if reader.is_empty() => return Ok(()),
+                _ => {}
+            }
+            self.arguments.push(reader.bulk_string()?);
+            self.position = reader.position;
+            if let Some(remaining) = &mut self.remaining {
+                *remaining -= 1;
+            }
+        }
+    }
+}
+
+impl RespDecoder {
+    /// Appends bytes read from the connection.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Takes the next complete request off the buffer and splits it into its argv, or
+    /// returns None if its bytes haven't all arrived yet. The bytes after it stay buffered.
+    /// Empty requests are skipped. After an error the rest of the buffer can't be framed,
+    /// so the connection should be closed.
+    pub fn next_request(
+        &mut self,
+        limits: &ProtocolLimits,
+    ) -> Result<Option<Vec<Bytes>>, anyhow::Error> {
+        loop {
+            let Some(&first) = self.buffer.first() else {
+                return Ok(None);
+            };
+            if !matches!(first, b'*' | b'$') {
+                let unscanned = &self.buffer[self.scanned..];
+                let Some(end) = unscanned.iter().position(|&byte| byte == b'\n') else {
+                    if self.buffer.len() > limits.max_inline_len {
+                        return Err(ProtocolError::TooBigInlineRequest.into());
+                    }
+                    self.scanned = self.buffer.len();
+                    return Ok(None);
+                };
+                let line = self.buffer.split_to(self.scanned + end + 1);
+                self.scanned = 0;
+                let arguments = split_inline_arguments(&line)?;
+                if arguments.is_empty() {
+                    continue;
+                }
+                return Ok(Some(arguments.into_iter().map(Bytes::from).collect()));
+            }
+            let mut reader = RespReader::new(&self.buffer, *limits);
+            let mut request = match self.pending.take() {
+                Some(request) => request,
+                None => match PendingRequest::start(&mut reader, first) {
+                    Ok(request) => request,
+                    Err(e) if e.is::<IncompleteRequest>() => return Ok(None),
+                    Err(e) => return Err(e),
+                },
+            };
+            match request.read(&mut reader) {
+                Ok(()) => {}
+                Err(e) if e.is::<IncompleteRequest>() => {
+                    self.pending = Some(request);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            }
+            // The arguments share the request's bytes rather than being copied
+            let bytes = self.buffer.split_to(request.position).freeze();
+            if request.arguments.is_empty() {
+                continue;
+            }
+            return Ok(Some(
+                request
+                    .arguments
+                    .into_iter()
+                    .map(|range| bytes.slice(range))
+                    .collect(),
+            ));
+        }
+    }
+}
+
+pub struct RedisCommandParser;
 
-        // Skip the length line for the command itself
-        let _ = Self::extract_line(lines, '$')?;
+impl RedisCommandParser {
+    /// Parses a whole request held in `buffer` into the RedisCommand enum, using the
+    /// default protocol limits.
+    pub fn parse(buffer: &[u8]) -> Result<RedisCommand, anyhow::Error> {
+        let mut decoder = RespDecoder::default();
+        decoder.feed(buffer);
+        let argv = decoder
+            .next_request(&ProtocolLimits::default())?
+            .context("Incomplete request")?;
+        Self::parse_argv(&argv)
+    }
+
+    /// Parses a request's argv into the RedisCommand enum.
+    pub fn parse_argv(argv: &[Bytes]) -> Result<RedisCommand, anyhow::Error> {
+        Self::parse_command(
+            &mut argv.iter().map(|argument| argument.as_ref()),
+            argv.len(),
+        )
+    }
 
-        let command = lines.next().context("Command not found")?.to_lowercase();
+    /// Dispatches on the command name to the command's own parser, which takes the rest of
+    /// the argv and its length counting the name.
+    fn parse_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        // An unknown name is only quoted back in the error, so it can be decoded lossily
+        let name =
+            String::from_utf8_lossy(&Self::parse_bytes_argument(lines, "Command")?).into_owned();
+        let command = name.to_lowercase();
+        if keyspec::lookup(&command).is_some_and(|specs| !specs.accepts(array_length)) {
+            return Err(CommandError::WrongArity(command).into());
+        }
 
         match command.as_str() {
             "ping" => Ok(RedisCommand::Ping),
             "pong" => Ok(RedisCommand::Pong),
             "echo" => Self::handle_echo_command(lines, array_length),
+            "hello" => Self::handle_hello_command(lines, array_length),
             "set" => Self::handle_set_command(lines, array_length),
             "get" => Self::handle_get_command(lines, array_length),
+            "setnx" => Self::handle_setnx_command(lines, array_length),
+            "setex" => Self::handle_setex_command(lines, array_length, "ex"),
+            "psetex" => Self::handle_setex_command(lines, array_length, "px"),
+            "getdel" => Self::handle_getdel_command(lines, array_length),
+            "getex" => Self::handle_getex_command(lines, array_length),
+            "mset" => Self::handle_mset_command(lines, array_length, false),
+            "msetnx" => Self::handle_mset_command(lines, array_length, true),
+            "mget" => Self::handle_mget_command(lines, array_length),
+            "exists" => Self::handle_exists_command(lines, array_length),
+            "touch" => Self::handle_touch_command(lines, array_length),
+            "del" => Self::handle_del_command(lines, array_length),
+            "unlink" => Self::handle_unlink_command(lines, array_length),
+            "expire" => Self::handle_expire_command(lines, array_length, 1000),
+            "pexpire" => Self::handle_expire_command(lines, array_length, 1),
+            "expireat" => Self::handle_expireat_command(lines, array_length, 1000),
+            "pexpireat" => Self::handle_expireat_command(lines, array_length, 1),
+            "copy" => Self::handle_copy_command(lines, array_length),
+            "dump" => Self::handle_dump_command(lines, array_length),
+            "restore" => Self::handle_restore_command(lines, array_length),
+            "expiretime" => {
+                Self::handle_expiretime_command(lines, array_length).map(RedisCommand::ExpireTime)
+            }
+            "pexpiretime" => {
+                Self::handle_expiretime_command(lines, array_length).map(RedisCommand::PExpireTime)
+            }
+            "ttl" => Self::handle_ttl_command(lines, array_length).map(RedisCommand::Ttl),
+            "pttl" => Self::handle_ttl_command(lines, array_length).map(RedisCommand::PTtl),
+            "persist" => Self::handle_persist_command(lines, array_length),
+            "incrbyfloat" => Self::handle_incrbyfloat_command(lines, array_length),
+            "append" => Self::handle_append_command(lines, array_length),
+            "strlen" => Self::handle_strlen_command(lines, array_length),
+            "lcs" => Self::handle_lcs_command(lines, array_length),
+            "getrange" => Self::handle_getrange_command(lines, array_length),
+            "setrange" => Self::handle_setrange_command(lines, array_length),
+            "setbit" => Self::handle_setbit_command(lines, array_length),
+            "getbit" => Self::handle_getbit_command(lines, array_length),
+            "bitcount" => Self::handle_bitcount_command(lines, array_length),
+            "bitop" => Self::handle_bitop_command(lines, array_length),
+            "bitpos" => Self::handle_bitpos_command(lines, array_length),
+            "bitfield" => Self::handle_bitfield_command(lines, array_length),
+            "pfadd" => Self::handle_pfadd_command(lines, array_length),
+            "pfcount" => Self::handle_pfcount_command(lines, array_length),
+            "pfmerge" => Self::handle_pfmerge_command(lines, array_length),
+            "keys" => Self::handle_keys_command(lines, array_length),
+            "dbsize" => Ok(RedisCommand::DbSize),
+            "sort" => Self::handle_sort_command(lines, array_length),
+            "type" => Self::handle_type_command(lines, array_length),
+            "lpush" => Self::handle_push_command(lines, array_length, ListEnd::Left, false),
+            "lpushx" => Self::handle_push_command(lines, array_length, ListEnd::Left, true),
+            "rpush" => Self::handle_push_command(lines, array_length, ListEnd::Right, false),
+            "rpushx" => Self::handle_push_command(lines, array_length, ListEnd::Right, true),
+            "lpop" => Self::handle_pop_command(lines, array_length, ListEnd::Left),
+            "rpop" => Self::handle_pop_command(lines, array_length, ListEnd::Right),
+            "blpop" => Self::handle_bpop_command(lines, array_length, ListEnd::Left),
+            "brpop" => Self::handle_bpop_command(lines, array_length, ListEnd::Right),
+            "lmove" => Self::handle_lmove_command(lines, array_length),
+            "rpoplpush" => Self::handle_rpoplpush_command(lines, array_length),
+            "blmove" => Self::handle_blmove_command(lines, array_length),
+            "lpos" => Self::handle_lpos_command(lines, array_length),
+            "lmpop" => Self::handle_lmpop_command(lines, array_length),
+            "blmpop" => Self::handle_blmpop_command(lines, array_length),
+            "llen" => Self::handle_llen_command(lines, array_length),
+            "hset" => Self::handle_hset_command(lines, array_length),
+            "hget" => Self::handle_hget_command(lines, array_length, false),
+            "hexists" => Self::handle_hget_command(lines, array_length, true),
+            "hdel" => Self::handle_hdel_command(lines, array_length, false),
+            "hmget" => Self::handle_hdel_command(lines, array_length, true),
+            "hgetall" => Self::handle_key_command(lines, array_length, RedisCommand::HGetAll),
+            "hlen" => Self::handle_key_command(lines, array_length, RedisCommand::HLen),
+            "hrandfield" => Self::handle_random_command(lines, array_length, "withvalues")
+                .map(|(key, count, with_values)| RedisCommand::HRandField(key, count, with_values)),
+            "hscan" => Self::handle_hscan_command(lines, array_length),
+            "hexpire" => Self::handle_hexpire_command(lines, array_length, 1000, false),
+            "hpexpire" => Self::handle_hexpire_command(lines, array_length, 1, false),
+            "hexpireat" => Self::handle_hexpire_command(lines, array_length, 1000, true),
+            "hpexpireat" => Self::handle_hexpire_command(lines, array_length, 1, true),
+            "hpersist" => Self::handle_hash_fields_command(lines, array_length)
+                .map(|(key, fields)| RedisCommand::HPersist(key, fields)),
+            "httl" => Self::handle_hash_fields_command(lines, array_length)
+                .map(|(key, fields)| RedisCommand::HTtl(key, fields)),
+            "hpttl" => Self::handle_hash_fields_command(lines, array_length)
+                .map(|(key, fields)| RedisCommand::HPTtl(key, fields)),
+            "sadd" => Self::handle_set_members_command(lines, array_length)
+                .map(|(key, members)| RedisCommand::SAdd(key, members)),
+            "srem" => Self::handle_set_members_command(lines, array_length)
+                .map(|(key, members)| RedisCommand::SRem(key, members)),
+            "smembers" => Self::handle_key_command(lines, array_length, RedisCommand::SMembers),
+            "sismember" => Self::handle_sismember_command(lines, array_length),
+            "smismember" => Self::handle_set_members_command(lines, array_length)
+                .map(|(key, members)| RedisCommand::SMIsMember(key, members)),
+            "smove" => Self::handle_smove_command(lines, array_length),
+            "scard" => Self::handle_key_command(lines, array_length, RedisCommand::SCard),
+            "sinter" => {
+                Self::handle_set_operation_command(lines, array_length, SetOperation::Inter)
+            }
+            "sunion" => {
+                Self::handle_set_operation_command(lines, array_length, SetOperation::Union)
+            }
+            "sdiff" => Self::handle_set_operation_command(lines, array_length, SetOperation::Diff),
+            "sintercard" => Self::handle_sintercard_command(lines, array_length),
+            "sscan" => Self::handle_sscan_command(lines, array_length),
+            "sinterstore" => {
+                Self::handle_set_operation_store_command(lines, array_length, SetOperation::Inter)
+            }
+            "sunionstore" => {
+                Self::handle_set_operation_store_command(lines, array_length, SetOperation::Union)
+            }
+            "sdiffstore" => {
+                Self::handle_set_operation_store_command(lines, array_length, SetOperation::Diff)
+            }
+            "zadd" => Self::handle_zadd_command(lines, array_length),
+            "zscore" => Self::handle_zscore_command(lines, array_length),
+            "zcard" => Self::handle_key_command(lines, array_length, RedisCommand::ZCard),
+            "zrange" => Self::handle_zrange_command(lines, array_length, None),
+            "zrangebyscore" => {
+                Self::handle_zrange_command(lines, array_length, Some((ZRangeKind::Score, false)))
+            }
+            "zrevrangebyscore" => {
+                Self::handle_zrange_command(lines, array_length, Some((ZRangeKind::Score, true)))
+            }
+            "zrangebylex" => {
+                Self::handle_zrange_command(lines, array_length, Some((ZRangeKind::Lex, false)))
+            }
+            "zrevrangebylex" => {
+                Self::handle_zrange_command(lines, array_length, Some((ZRangeKind::Lex, true)))
+            }
+            "zrangestore" => Self::handle_zrangestore_command(lines, array_length),
+            "zrandmember" => Self::handle_random_command(lines, array_length, "withscores").map(
+                |(key, count, with_scores)| RedisCommand::ZRandMember(key, count, with_scores),
+            ),
+            "zscan" => Self::handle_zscan_command(lines, array_length),
+            "zcount" => Self::handle_zcount_command(lines, array_length, false),
+            "zincrby" => Self::handle_zincrby_command(lines, array_length),
+            "zrank" => Self::handle_zrank_command(lines, array_length, false),
+            "zrevrank" => Self::handle_zrank_command(lines, array_length, true),
+            "zpopmin" => Self::handle_zpop_command(lines, array_length, ScoreEnd::Min),
+            "zpopmax" => Self::handle_zpop_command(lines, array_length, ScoreEnd::Max),
+            "bzpopmin" => Self::handle_bzpop_command(lines, array_length, ScoreEnd::Min),
+            "bzpopmax" => Self::handle_bzpop_command(lines, array_length, ScoreEnd::Max),
+            "zinter" => {
+                Self::handle_zsetop_command(lines, array_length, SetOperation::Inter, false)
+            }
+            "zunion" => {
+                Self::handle_zsetop_command(lines, array_length, SetOperation::Union, false)
+            }
+            "zdiff" => Self::handle_zsetop_command(lines, array_length, SetOperation::Diff, false),
+            "zinterstore" => {
+                Self::handle_zsetop_command(lines, array_length, SetOperation::Inter, true)
+            }
+            "zunionstore" => {
+                Self::handle_zsetop_command(lines, array_length, SetOperation::Union, true)
+            }
+            "zdiffstore" => {
+                Self::handle_zsetop_command(lines, array_length, SetOperation::Diff, true)
+            }
+            "zrem" => Self::handle_set_members_command(lines, array_length)
+                .map(|(key, members)| RedisCommand::ZRem(key, members)),
+            "zlexcount" => Self::handle_zcount_command(lines, array_length, true),
+            "geoadd" => Self::handle_geoadd_command(lines, array_length),
+            "geopos" => Self::handle_geo_members_command(lines, array_length, RedisCommand::GeoPos),
+            "geodist" => Self::handle_geodist_command(lines, array_length),
+            "geohash" => {
+                Self::handle_geo_members_command(lines, array_length, RedisCommand::GeoHash)
+            }
+            "geosearch" => Self::handle_geosearch_command(lines, array_length, false),
+            "geosearchstore" => Self::handle_geosearch_command(lines, array_length, true),
+            "xadd" => Self::handle_xadd_command(lines, array_length),
+            "xlen" => Self::handle_key_command(lines, array_length, RedisCommand::XLen),
+            "xtrim" => Self::handle_xtrim_command(lines, array_length),
+            "xdel" => Self::handle_xdel_command(lines, array_length),
+            "xsetid" => Self::handle_xsetid_command(lines, array_length),
+            "xrange" => Self::handle_xrange_command(lines, array_length, false),
+            "xrevrange" => Self::handle_xrange_command(lines, array_length, true),
+            "xgroup" => Self::handle_xgroup_command(lines, array_length),
+            "xinfo" => Self::handle_xinfo_command(lines, array_length),
+            "xreadgroup" => Self::handle_xreadgroup_command(lines, array_length),
+            "xack" => Self::handle_xack_command(lines, array_length),
+            "xpending" => Self::handle_xpending_command(lines, array_length),
+            "xclaim" => Self::handle_xclaim_command(lines, array_length),
+            "xautoclaim" => Self::handle_xautoclaim_command(lines, array_length),
+            "lrange" => Self::handle_lrange_command(lines, array_length),
+            "linsert" => Self::handle_linsert_command(lines, array_length),
+            "lset" => Self::handle_lset_command(lines, array_length),
+            "lrem" => Self::handle_lrem_command(lines, array_length),
+            "ltrim" => Self::handle_ltrim_command(lines, array_length),
+            "randomkey" => Ok(RedisCommand::RandomKey),
+            "time" => Ok(RedisCommand::Time),
+            "flushdb" => Self::handle_flush_mode(lines, array_length).map(RedisCommand::FlushDb),
+            "flushall" => Self::handle_flush_mode(lines, array_length).map(RedisCommand::FlushAll),
+            "incr" | "decr" | "incrby" | "decrby" => {
+                Self::handle_incr_command(lines, array_length, &command)
+            }
             "info" => Self::handle_info_command(lines, array_length),
+            "lolwut" => Self::parse_arguments(lines, array_length - 1).map(RedisCommand::Lolwut),
             "replconf" => Self::handle_replconf_command(lines, array_length),
+            "command" => Self::handle_command_command(lines, array_length),
+            "config" => Self::handle_config_command(lines, array_length),
+            "debug" => Self::handle_debug_command(lines, array_length),
+            "acl" => Self::handle_acl_command(lines, array_length),
+            "object" => Self::handle_object_command(lines, array_length),
             "replicate" | "addslave" => Self::handle_admin_command(lines, array_length),
-            _ => Err(anyhow::anyhow!("Unknown Redis command")),
+            _ => Err(Self::unknown_command(&name, lines).into()),
         }
     }
 
-    fn parse_bulk_string_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
-    ) -> Result<RedisCommand, anyhow::Error> {
-        // Extract the length of the bulk string
-        let length_str = Self::extract_line(lines, '$')?;
-        let _length: usize = length_str.parse().context("Invalid bulk string length")?;
-
-        // Extract the actual command string
-        let command = lines.next().context("Command not found")?;
-
-        match command.to_lowercase().as_str() {
-            "ping" => Ok(RedisCommand::Ping),
-            "pong" => Ok(RedisCommand::Pong),
-            "echo" => Self::handle_echo_command(lines, 2), // Assuming ECHO has 1 argument
-            "set" => Self::handle_set_command(lines, 3),   // Assuming SET has 2 arguments
-            "get" => Self::handle_get_command(lines, 2),   // Assuming GET has 1 argument
-            "info" => Self::handle_info_command(lines, 2), // Assuming INFO has 1 argument
-            "replconf" => Self::handle_replconf_command(lines, 2), // Assuming REPLCONF has 1 argument
-            "replicate" | "addslave" => Self::handle_admin_command(lines, 2), // Assuming admin commands have 1 argument
-            _ => Err(anyhow::anyhow!("Unknown Redis command")),
+    /// Builds the error for an unknown command, quoting its first arguments as Redis does.
+    /// Line breaks are replaced so the error stays on one line.
+    fn unknown_command<'a>(name: &str, lines: impl Iterator<Item = &'a [u8]>) -> CommandError {
+        const QUOTED_LEN: usize = 128;
+        let mut args = String::new();
+        for arg in lines {
+            if args.len() >= QUOTED_LEN {
+                break;
+            }
+            let arg = String::from_utf8_lossy(arg);
+            let arg: String = arg.chars().take(QUOTED_LEN - args.len()).collect();
+            args.push_str(&format!("'{}' ", arg));
         }
+        let name: String = name.chars().take(QUOTED_LEN).collect();
+        let single_line = |text: String| text.replace(['\r', '\n'], " ");
+        CommandError::UnknownCommand(single_line(name), single_line(args))
     }
 
-    fn parse_array_length<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
-    ) -> Result<usize, anyhow::Error> {
-        let array_length_str = Self::extract_line(lines, '*')?;
-        let array_length = array_length_str
-            .parse::<usize>()
-            .context("Invalid array length")?;
+    fn parse_array_length(reader: &mut RespReader) -> Result<usize, anyhow::Error> {
+        let array_length = reader
+            .number(b'*')?
+            .ok_or(ProtocolError::InvalidMultibulkLength)?;
 
-        if array_length < 1 {
-            anyhow::bail!("Command array must have at least one element");
+        // Reject oversized requests before reading any of their elements
+        if array_length > reader.limits.max_multibulk_len {
+            return Err(ProtocolError::InvalidMultibulkLength.into());
         }
 
         Ok(array_length)
     }
 
+    /// Parses an argument taken as text, such as a key or an option. Arguments that aren't
+    /// valid UTF-8 are rejected rather than replaced, since replacing them would corrupt the
+    /// data and make distinct keys collide. Use `parse_bytes_argument` for values kept as
+    /// they are.
     fn parse_argument<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         name: &str,
     ) -> Result<String, anyhow::Error> {
-        Self::extract_line(lines, '$')?;
+        String::from_utf8(Self::parse_bytes_argument(lines, name)?)
+            .map_err(|_| CommandError::InvalidUtf8(name.to_lowercase()).into())
+    }
+
+    /// Parses an argument as raw bytes, for string values which may hold any bytes.
+    fn parse_bytes_argument<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        name: &str,
+    ) -> Result<Vec<u8>, anyhow::Error> {
         lines
             .next()
             .ok_or_else(|| anyhow::anyhow!("{} not found", name))
-            .map(String::from)
+            .map(<[u8]>::to_vec)
     }
 
-    fn parse_expiry<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<u64, anyhow::Error> {
-        Self::extract_line(lines, '$')?;
-        let expiry_str = lines
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("Expiry value not found"))?;
-        let expiry_millis = expiry_str.parse::<u64>().context("Invalid expiry format")?;
-        Ok(millis_to_timestamp_from_now(expiry_millis)?)
+    /// Parses the given number of arguments.
+    fn parse_arguments<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        count: usize,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        (0..count)
+            .map(|_| Self::parse_argument(lines, "Argument"))
+            .collect()
+    }
+
+    /// Parses an expiry option (EX, PX, EXAT, PXAT or PERSIST) and its value into
+    /// an absolute expiry, so every command shares the same unit and offset handling.
+    pub fn parse_expiry_options(
+        command: &str,
+        option: &str,
+        value: Option<&str>,
+    ) -> Result<Expiry, anyhow::Error> {
+        let option = option.to_lowercase();
+        if option == "persist" {
+            return Ok(Expiry::Persist);
+        }
+
+        let value = value
+            .ok_or(CommandError::Syntax)?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if value <= 0 {
+            return Err(CommandError::InvalidExpireTime(command.to_string()).into());
+        }
+        let value = value as u64;
+
+        let timestamp = match option.as_str() {
+            "ex" => millis_to_timestamp_from_now(value.saturating_mul(1000))?,
+            "px" => millis_to_timestamp_from_now(value)?,
+            "exat" => value.saturating_mul(1000),
+            "pxat" => value,
+            _ => anyhow::bail!("Unknown expiry option: {}", option),
+        };
+        Ok(Expiry::At(timestamp))
+    }
+
+    /// Returns true if the argument is one of the expiry options taking a value.
+    fn is_expiry_option(option: &str) -> bool {
+        matches!(
+            option.to_lowercase().as_str(),
+            "ex" | "px" | "exat" | "pxat"
+        )
     }
 
     fn handle_echo_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         array_length: usize,
     ) -> Result<RedisCommand, anyhow::Error> {
         if array_length < 2 {
             anyhow::bail!("ECHO command requires an argument");
         }
-        let argument = Self::parse_argument(lines, "Argument")?;
+        let argument = Self::parse_bytes_argument(lines, "Argument")?;
         Ok(RedisCommand::Echo(argument))
     }
 
+    fn handle_hello_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let args = Self::parse_arguments(lines, array_length - 1)?;
+        let mut args = args.into_iter();
+        let mut options = HelloOptions::default();
+        if let Some(protocol) = args.next() {
+            let protocol = protocol
+                .parse::<i64>()
+                .map_err(|_| CommandError::ProtocolVersion)?;
+            options.protocol = Some(protocol);
+        }
+        while let Some(option) = args.next() {
+            match option.to_lowercase().as_str() {
+                "auth" => match (args.next(), args.next()) {
+                    (Some(username), Some(password)) => options.auth = Some((username, password)),
+                    _ => return Err(CommandError::HelloOption(option).into()),
+                },
+                "setname" => match args.next() {
+                    Some(name) => options.client_name = Some(name),
+                    None => return Err(CommandError::HelloOption(option).into()),
+                },
+                _ => return Err(CommandError::HelloOption(option).into()),
+            }
+        }
+        Ok(RedisCommand::Hello(options))
+    }
+
     fn handle_get_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         array_length: usize,
     ) -> Result<RedisCommand, anyhow::Error> {
         if array_length < 2 {
@@ -145,64 +902,3106 @@ impl RedisCommandParser {
         Ok(RedisCommand::Get(key))
     }
 
-    fn handle_set_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+    fn handle_exists_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("EXISTS command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Exists(keys))
+    }
+
+    fn handle_touch_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("TOUCH command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Touch(keys))
+    }
+
+    fn handle_del_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("DEL command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Del(keys))
+    }
+
+    fn handle_unlink_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("UNLINK command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Unlink(keys))
+    }
+
+    /// Parses EXPIRE and PEXPIRE, whose TTL is given in units of `unit_millis`.
+    fn handle_expire_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         array_length: usize,
+        unit_millis: i64,
     ) -> Result<RedisCommand, anyhow::Error> {
         if array_length < 3 {
-            anyhow::bail!("SET command requires at least two arguments");
+            anyhow::bail!("EXPIRE command requires a key and a TTL");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let ttl = Self::parse_argument(lines, "TTL")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let conditions = Self::parse_expire_conditions(lines, array_length - 3)?;
+        // A non-positive TTL yields a timestamp in the past, which deletes the key
+        let timestamp = (now_millis() as i64).saturating_add(ttl.saturating_mul(unit_millis));
+        Ok(RedisCommand::Expire(
+            key,
+            timestamp.max(0) as u64,
+            conditions,
+        ))
+    }
+
+    /// Parses EXPIREAT and PEXPIREAT, whose unix timestamp is given in units of `unit_millis`.
+    fn handle_expireat_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        unit_millis: i64,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("EXPIREAT command requires a key and a timestamp");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let timestamp = Self::parse_argument(lines, "Timestamp")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let conditions = Self::parse_expire_conditions(lines, array_length - 3)?;
+        // Negative timestamps are in the past, which deletes the key
+        let timestamp = timestamp.saturating_mul(unit_millis).max(0) as u64;
+        Ok(RedisCommand::Expire(key, timestamp, conditions))
+    }
+
+    /// Parses the NX/XX/GT/LT flags of the EXPIRE family.
+    /// NX can't be combined with any other flag, and GT can't be combined with LT.
+    fn parse_expire_conditions<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        count: usize,
+    ) -> Result<Vec<ExpireCondition>, anyhow::Error> {
+        Self::expire_conditions(Self::parse_arguments(lines, count)?)
+    }
+
+    /// Converts already parsed NX/XX/GT/LT flags, see `parse_expire_conditions`.
+    fn expire_conditions(options: Vec<String>) -> Result<Vec<ExpireCondition>, anyhow::Error> {
+        let mut conditions = Vec::new();
+        for option in options {
+            let condition = match option.to_lowercase().as_str() {
+                "nx" => ExpireCondition::Nx,
+                "xx" => ExpireCondition::Xx,
+                "gt" => ExpireCondition::Gt,
+                "lt" => ExpireCondition::Lt,
+                _ => return Err(CommandError::UnsupportedOption(option).into()),
+            };
+            if !conditions.contains(&condition) {
+                conditions.push(condition);
+            }
+        }
+        let has = |condition| conditions.contains(&condition);
+        if has(ExpireCondition::Nx) && conditions.len() > 1 {
+            return Err(CommandError::IncompatibleExpireConditions.into());
+        }
+        if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+            return Err(CommandError::IncompatibleExpireComparisons.into());
+        }
+        Ok(conditions)
+    }
+
+    /// Parses the key of EXPIRETIME and PEXPIRETIME.
+    fn handle_expiretime_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<String, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("EXPIRETIME command requires one key");
+        }
+        Self::parse_argument(lines, "Key")
+    }
+
+    /// Parses the key of TTL and PTTL.
+    fn handle_ttl_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<String, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("TTL command requires one key");
+        }
+        Self::parse_argument(lines, "Key")
+    }
+
+    fn handle_persist_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("PERSIST command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::Persist(key))
+    }
+
+    fn handle_copy_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("COPY command requires a source and a destination");
+        }
+        let source = Self::parse_argument(lines, "Source")?;
+        let destination = Self::parse_argument(lines, "Destination")?;
+
+        let mut replace = false;
+        let mut remaining = array_length - 3;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "replace" => replace = true,
+                // Only database 0 exists
+                "db" if remaining > 0 => {
+                    let db = Self::parse_argument(lines, "Database")?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    remaining -= 1;
+                    if db != 0 {
+                        return Err(CommandError::DbIndexOutOfRange.into());
+                    }
+                }
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::Copy(source, destination, replace))
+    }
+
+    fn handle_dump_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("DUMP command requires one key");
         }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::Dump(key))
+    }
 
+    /// Parses RESTORE, normalizing its TTL to an absolute expiry.
+    /// A TTL of 0 restores the key without an expiry.
+    fn handle_restore_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("RESTORE command requires a key, a TTL and a payload");
+        }
         let key = Self::parse_argument(lines, "Key")?;
-        let value = Self::parse_argument(lines, "Value")?;
+        let ttl = Self::parse_argument(lines, "TTL")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let payload = Self::parse_argument(lines, "Payload")?;
 
-        let expiry = if array_length >= 5 {
-            // Check if the fourth argument is "PX"
-            let px_indicator = Self::parse_argument(lines, "PX Indicator")?;
-            if px_indicator.to_lowercase() == "px" {
-                Some(Self::parse_expiry(lines)?)
-            } else {
-                None
+        let (mut replace, mut absolute) = (false, false);
+        for option in Self::parse_arguments(lines, array_length - 4)? {
+            match option.to_lowercase().as_str() {
+                "replace" => replace = true,
+                "absttl" => absolute = true,
+                _ => return Err(CommandError::Syntax.into()),
             }
+        }
+        if ttl < 0 {
+            return Err(CommandError::InvalidTtl.into());
+        }
+
+        let expiry = match ttl as u64 {
+            0 => None,
+            ttl if absolute => Some(ttl),
+            ttl => Some(millis_to_timestamp_from_now(ttl)?),
+        };
+        Ok(RedisCommand::Restore(key, expiry, payload, replace))
+    }
+
+    /// Parses INCR, DECR, INCRBY and DECRBY into a signed increment.
+    fn handle_incr_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        command: &str,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let takes_amount = command.ends_with("by");
+        if array_length != if takes_amount { 3 } else { 2 } {
+            anyhow::bail!("{} command has the wrong number of arguments", command);
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let amount = if takes_amount {
+            Self::parse_argument(lines, "Increment")?
+                .parse::<i64>()
+                .map_err(|_| CommandError::NotInteger)?
         } else {
-            None
+            1
         };
+        let delta = if command.starts_with("decr") {
+            amount.checked_neg().ok_or(CommandError::Overflow)?
+        } else {
+            amount
+        };
+        Ok(RedisCommand::IncrBy(key, delta))
+    }
 
-        Ok(RedisCommand::Set(key, value, expiry))
+    fn handle_incrbyfloat_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("INCRBYFLOAT command requires a key and an increment");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let increment = Self::parse_argument(lines, "Increment")?;
+        let increment = parse_float(&increment).ok_or(CommandError::NotFloat)?;
+        Ok(RedisCommand::IncrByFloat(key, increment))
     }
 
-    fn handle_info_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+    fn handle_append_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         array_length: usize,
     ) -> Result<RedisCommand, anyhow::Error> {
-        let section = if array_length > 1 {
-            Some(Self::parse_argument(lines, "Section")?)
-        } else {
-            None
+        if array_length != 3 {
+            anyhow::bail!("APPEND command requires a key and a value");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let value = Self::parse_bytes_argument(lines, "Value")?;
+        Ok(RedisCommand::Append(key, value))
+    }
+
+    fn handle_getrange_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("GETRANGE command requires a key, a start and an end");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let start = Self::parse_argument(lines, "Start")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let end = Self::parse_argument(lines, "End")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        Ok(RedisCommand::GetRange(key, start, end))
+    }
+
+    fn handle_setrange_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("SETRANGE command requires a key, an offset and a value");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let offset = Self::parse_argument(lines, "Offset")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let offset = usize::try_from(offset).map_err(|_| CommandError::OffsetOutOfRange)?;
+        let value = Self::parse_bytes_argument(lines, "Value")?;
+        if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+            return Err(CommandError::StringTooLong.into());
+        }
+        Ok(RedisCommand::SetRange(key, offset, value))
+    }
+
+    fn handle_setbit_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("SETBIT command requires a key, an offset and a value");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let offset = Self::parse_bit_offset(&Self::parse_argument(lines, "Offset")?)?;
+        let value = match Self::parse_argument(lines, "Value")?.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Err(CommandError::InvalidBit.into()),
         };
-        Ok(RedisCommand::Info(section))
+        Ok(RedisCommand::SetBit(key, offset, value))
     }
 
-    fn handle_admin_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
-        _array_length: usize,
+    fn handle_getbit_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
     ) -> Result<RedisCommand, anyhow::Error> {
-        let command_type = Self::parse_argument(lines, "Command Type")?;
-        let data = Self::parse_argument(lines, "Data")?;
-        match command_type.as_str() {
-            "replicate" => Ok(RedisCommand::Admin(AdminCommand::Replicate(data))),
-            "addslave" => Ok(RedisCommand::Admin(AdminCommand::AddSlave(data))),
-            _ => Err(anyhow::anyhow!("Unknown admin command")),
+        if array_length != 3 {
+            anyhow::bail!("GETBIT command requires a key and an offset");
         }
+        let key = Self::parse_argument(lines, "Key")?;
+        let offset = Self::parse_bit_offset(&Self::parse_argument(lines, "Offset")?)?;
+        Ok(RedisCommand::GetBit(key, offset))
     }
 
-    fn handle_replconf_command<'a>(
-        lines: &mut impl Iterator<Item = &'a str>,
+    /// Parses the bit offset of SETBIT or GETBIT, which can't reach past the largest string.
+    fn parse_bit_offset(offset: &str) -> Result<u64, CommandError> {
+        offset
+            .parse::<u64>()
+            .ok()
+            .filter(|&offset| offset < (MAX_STRING_LEN as u64) * 8)
+            .ok_or(CommandError::InvalidBitOffset)
+    }
+
+    fn handle_bitcount_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
         array_length: usize,
     ) -> Result<RedisCommand, anyhow::Error> {
-        let args = (0..array_length - 1)
-            .map(|_| Self::parse_argument(lines, "Argument"))
-            .collect::<Result<Vec<String>, anyhow::Error>>()?;
-        Ok(RedisCommand::Replconf(args))
+        if array_length < 2 {
+            anyhow::bail!("BITCOUNT command requires a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let args = Self::parse_arguments(lines, array_length - 2)?;
+        let range = match args.as_slice() {
+            [] => None,
+            [start, end, unit @ ..] if unit.len() <= 1 => {
+                let start = start.parse().map_err(|_| CommandError::NotInteger)?;
+                let end = end.parse().map_err(|_| CommandError::NotInteger)?;
+                let unit = match unit.first().map(|unit| unit.to_lowercase()).as_deref() {
+                    None | Some("byte") => BitUnit::Byte,
+                    Some("bit") => BitUnit::Bit,
+                    _ => return Err(CommandError::Syntax.into()),
+                };
+                Some((start, end, unit))
+            }
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        Ok(RedisCommand::BitCount(key, range))
+    }
+
+    fn handle_bitop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("BITOP command requires an operation, a destination and keys");
+        }
+        let operation = match Self::parse_argument(lines, "Operation")?
+            .to_lowercase()
+            .as_str()
+        {
+            "and" => BitOperation::And,
+            "or" => BitOperation::Or,
+            "xor" => BitOperation::Xor,
+            "not" => BitOperation::Not,
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let keys = Self::parse_arguments(lines, array_length - 3)?;
+        if operation == BitOperation::Not && keys.len() != 1 {
+            return Err(CommandError::BitOpNotSingleKey.into());
+        }
+        Ok(RedisCommand::BitOp(operation, destination, keys))
+    }
+
+    fn handle_bitpos_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if !(3..=6).contains(&array_length) {
+            anyhow::bail!("BITPOS command requires a key and a bit");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let bit = match Self::parse_argument(lines, "Bit")?.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Err(CommandError::BitPosInvalidBit.into()),
+        };
+        let mut args = Self::parse_arguments(lines, array_length - 3)?.into_iter();
+        let mut parse_index = || {
+            args.next()
+                .map(|index| index.parse::<i64>().map_err(|_| CommandError::NotInteger))
+                .transpose()
+        };
+        let start = parse_index()?;
+        let end = parse_index()?;
+        let unit = match args.next().map(|unit| unit.to_lowercase()).as_deref() {
+            None | Some("byte") => BitUnit::Byte,
+            Some("bit") => BitUnit::Bit,
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        Ok(RedisCommand::BitPos(key, bit, start, end, unit))
+    }
+
+    fn handle_bitfield_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("BITFIELD command requires a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?.into_iter();
+        let mut operations = Vec::new();
+        while let Some(operation) = args.next() {
+            let operation = operation.to_lowercase();
+            if operation == "overflow" {
+                let overflow = match args
+                    .next()
+                    .map(|overflow| overflow.to_lowercase())
+                    .as_deref()
+                {
+                    Some("wrap") => BitFieldOverflow::Wrap,
+                    Some("sat") => BitFieldOverflow::Sat,
+                    Some("fail") => BitFieldOverflow::Fail,
+                    Some(_) => return Err(CommandError::InvalidOverflowType.into()),
+                    None => return Err(CommandError::Syntax.into()),
+                };
+                operations.push(BitFieldOperation::Overflow(overflow));
+                continue;
+            }
+            if !matches!(operation.as_str(), "get" | "set" | "incrby") {
+                return Err(CommandError::Syntax.into());
+            }
+            let (Some(field), Some(offset)) = (args.next(), args.next()) else {
+                return Err(CommandError::Syntax.into());
+            };
+            let field = Self::parse_bitfield_type(&field)?;
+            // An offset prefixed with # counts in fields of the type's width
+            let offset = match offset.strip_prefix('#') {
+                Some(index) => index
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|index| index.checked_mul(field.bits as u64))
+                    .filter(|&offset| offset < (MAX_STRING_LEN as u64) * 8)
+                    .ok_or(CommandError::InvalidBitOffset)?,
+                None => Self::parse_bit_offset(&offset)?,
+            };
+            if operation == "get" {
+                operations.push(BitFieldOperation::Get(field, offset));
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or(CommandError::Syntax)?
+                .parse()
+                .map_err(|_| CommandError::NotInteger)?;
+            operations.push(match operation.as_str() {
+                "set" => BitFieldOperation::Set(field, offset, value),
+                _ => BitFieldOperation::IncrBy(field, offset, value),
+            });
+        }
+        Ok(RedisCommand::BitField(key, operations))
+    }
+
+    /// Parses a BITFIELD field type, `i` or `u` followed by its width in bits.
+    fn parse_bitfield_type(field: &str) -> Result<BitFieldType, CommandError> {
+        let signed = match field.chars().next() {
+            Some('i' | 'I') => true,
+            Some('u' | 'U') => false,
+            _ => return Err(CommandError::InvalidBitFieldType),
+        };
+        let max_bits = if signed { 64 } else { 63 };
+        field[1..]
+            .parse::<u8>()
+            .ok()
+            .filter(|bits| (1..=max_bits).contains(bits))
+            .map(|bits| BitFieldType { signed, bits })
+            .ok_or(CommandError::InvalidBitFieldType)
+    }
+
+    fn handle_pfadd_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("PFADD command requires a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let elements = Self::parse_arguments(lines, array_length - 2)?;
+        Ok(RedisCommand::PfAdd(key, elements))
+    }
+
+    fn handle_pfcount_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("PFCOUNT command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::PfCount(keys))
+    }
+
+    fn handle_pfmerge_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("PFMERGE command requires a destination");
+        }
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let keys = Self::parse_arguments(lines, array_length - 2)?;
+        Ok(RedisCommand::PfMerge(destination, keys))
+    }
+
+    fn handle_strlen_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("STRLEN command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::Strlen(key))
+    }
+
+    fn handle_lcs_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("LCS command requires two keys");
+        }
+        let key1 = Self::parse_argument(lines, "Key")?;
+        let key2 = Self::parse_argument(lines, "Key")?;
+
+        let mut options = LcsOptions::default();
+        let mut remaining = array_length - 3;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "len" => options.len = true,
+                "idx" => options.idx = true,
+                "withmatchlen" => options.with_match_len = true,
+                "minmatchlen" if remaining > 0 => {
+                    let min_match_len = Self::parse_argument(lines, "Minimum match length")?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    remaining -= 1;
+                    options.min_match_len = min_match_len.max(0) as usize;
+                }
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        if options.len && options.idx {
+            return Err(CommandError::LcsLenAndIdx.into());
+        }
+        Ok(RedisCommand::Lcs(key1, key2, options))
+    }
+
+    fn handle_keys_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("KEYS command requires one pattern");
+        }
+        let pattern = Self::parse_argument(lines, "Pattern")?;
+        Ok(RedisCommand::Keys(pattern))
+    }
+
+    fn handle_sort_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("SORT command requires a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?.into_iter();
+        let mut options = SortOptions::default();
+        while let Some(option) = args.next() {
+            let mut next = || args.next().ok_or(CommandError::Syntax);
+            match option.to_lowercase().as_str() {
+                "asc" => options.descending = false,
+                "desc" => options.descending = true,
+                "alpha" => options.alpha = true,
+                "by" => options.by = Some(next()?),
+                "limit" => {
+                    let offset = next()?.parse().map_err(|_| CommandError::NotInteger)?;
+                    let count = next()?.parse().map_err(|_| CommandError::NotInteger)?;
+                    options.limit = Some((offset, count));
+                }
+                "get" => options.get.push(next()?),
+                "store" => options.store = Some(next()?),
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::Sort(key, options))
+    }
+
+    fn handle_type_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("TYPE command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::Type(key))
+    }
+
+    fn handle_push_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        end: ListEnd,
+        existing: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!(
+                "{}PUSH{} command requires a key and at least one element",
+                end.prefix(),
+                if existing { "X" } else { "" }
+            );
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let values = Self::parse_arguments(lines, array_length - 2)?;
+        Ok(RedisCommand::Push(key, end, values, existing))
+    }
+
+    fn handle_pop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        end: ListEnd,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if !(2..=3).contains(&array_length) {
+            anyhow::bail!(
+                "{}POP command requires a key and an optional count",
+                end.prefix()
+            );
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let count = if array_length == 3 {
+            Some(Self::parse_count(&Self::parse_argument(lines, "Count")?)?)
+        } else {
+            None
+        };
+        Ok(RedisCommand::Pop(key, end, count))
+    }
+
+    fn handle_bpop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        end: ListEnd,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!(
+                "B{}POP command requires at least one key and a timeout",
+                end.prefix()
+            );
+        }
+        let keys = Self::parse_arguments(lines, array_length - 2)?;
+        let timeout = Self::parse_timeout(&Self::parse_argument(lines, "Timeout")?)?;
+        Ok(RedisCommand::BPop(keys, end, timeout))
+    }
+
+    fn handle_lmove_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 5 {
+            anyhow::bail!("LMOVE command requires a source, a destination and two list ends");
+        }
+        let source = Self::parse_argument(lines, "Source")?;
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let from = Self::parse_list_end(&Self::parse_argument(lines, "Wherefrom")?)?;
+        let to = Self::parse_list_end(&Self::parse_argument(lines, "Whereto")?)?;
+        Ok(RedisCommand::LMove(source, destination, from, to))
+    }
+
+    fn handle_rpoplpush_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("RPOPLPUSH command requires a source and a destination");
+        }
+        let source = Self::parse_argument(lines, "Source")?;
+        let destination = Self::parse_argument(lines, "Destination")?;
+        Ok(RedisCommand::LMove(
+            source,
+            destination,
+            ListEnd::Right,
+            ListEnd::Left,
+        ))
+    }
+
+    fn handle_blmove_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 6 {
+            anyhow::bail!(
+                "BLMOVE command requires a source, a destination, two list ends and a timeout"
+            );
+        }
+        let source = Self::parse_argument(lines, "Source")?;
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let from = Self::parse_list_end(&Self::parse_argument(lines, "Wherefrom")?)?;
+        let to = Self::parse_list_end(&Self::parse_argument(lines, "Whereto")?)?;
+        let timeout = Self::parse_timeout(&Self::parse_argument(lines, "Timeout")?)?;
+        Ok(RedisCommand::BLMove(source, destination, from, to, timeout))
+    }
+
+    fn handle_lmpop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("LMPOP command requires numkeys, at least one key and a list end");
+        }
+        let (keys, end, count) = Self::parse_mpop_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::LMPop(keys, end, count))
+    }
+
+    fn handle_blmpop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 5 {
+            anyhow::bail!(
+                "BLMPOP command requires a timeout, numkeys, at least one key and a list end"
+            );
+        }
+        let timeout = Self::parse_timeout(&Self::parse_argument(lines, "Timeout")?)?;
+        let (keys, end, count) = Self::parse_mpop_arguments(lines, array_length - 2)?;
+        Ok(RedisCommand::BLMPop(keys, end, count, timeout))
+    }
+
+    /// Parses the `numkeys key [key ...] LEFT|RIGHT [COUNT count]` arguments shared by
+    /// LMPOP and BLMPOP, given the number of arguments left.
+    fn parse_mpop_arguments<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        remaining: usize,
+    ) -> Result<(Vec<String>, ListEnd, usize), anyhow::Error> {
+        let num_keys = Self::parse_argument(lines, "Numkeys")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if num_keys <= 0 {
+            return Err(CommandError::NumKeysNotPositive.into());
+        }
+        let num_keys = num_keys as usize;
+        // numkeys, the keys and the list end, optionally followed by COUNT and its value
+        if num_keys + 2 != remaining && num_keys + 4 != remaining {
+            return Err(CommandError::Syntax.into());
+        }
+        let keys = Self::parse_arguments(lines, num_keys)?;
+        let end = Self::parse_list_end(&Self::parse_argument(lines, "Where")?)?;
+        let mut count = 1;
+        if num_keys + 4 == remaining {
+            if !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("count") {
+                return Err(CommandError::Syntax.into());
+            }
+            count = Self::parse_argument(lines, "Count")?
+                .parse::<i64>()
+                .map_err(|_| CommandError::NotInteger)?;
+            if count <= 0 {
+                return Err(CommandError::CountNotPositive.into());
+            }
+        }
+        Ok((keys, end, count as usize))
+    }
+
+    /// Parses a LEFT or RIGHT list end argument.
+    fn parse_list_end(end: &str) -> Result<ListEnd, anyhow::Error> {
+        match end.to_lowercase().as_str() {
+            "left" => Ok(ListEnd::Left),
+            "right" => Ok(ListEnd::Right),
+            _ => Err(CommandError::Syntax.into()),
+        }
+    }
+
+    /// Parses the timeout of a blocking command, in seconds.
+    fn parse_timeout(timeout: &str) -> Result<f64, anyhow::Error> {
+        let timeout = parse_float(timeout)
+            .filter(|timeout| timeout.is_finite())
+            .ok_or(CommandError::InvalidTimeout)?;
+        if timeout < 0.0 {
+            return Err(CommandError::NegativeTimeout.into());
+        }
+        // The server waits with a Duration, so the timeout has to fit in one
+        Duration::try_from_secs_f64(timeout).map_err(|_| CommandError::TimeoutOutOfRange)?;
+        Ok(timeout)
+    }
+
+    /// Parses a non-negative count argument.
+    fn parse_count(count: &str) -> Result<usize, anyhow::Error> {
+        let count = count.parse::<i64>().map_err(|_| CommandError::NotInteger)?;
+        usize::try_from(count).map_err(|_| CommandError::NotPositive.into())
+    }
+
+    fn handle_lpos_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("LPOS command requires a key and an element");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let element = Self::parse_argument(lines, "Element")?;
+
+        let mut options = LposOptions::default();
+        let mut remaining = array_length - 3;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            if remaining == 0 {
+                return Err(CommandError::Syntax.into());
+            }
+            let value = Self::parse_argument(lines, "Option value")?
+                .parse::<i64>()
+                .map_err(|_| CommandError::NotInteger)?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "rank" if value == 0 => return Err(CommandError::RankZero.into()),
+                "rank" => options.rank = value,
+                "count" if value < 0 => return Err(CommandError::NegativeOption("COUNT").into()),
+                "count" => options.count = Some(value as usize),
+                "maxlen" if value < 0 => return Err(CommandError::NegativeOption("MAXLEN").into()),
+                "maxlen" => options.max_len = value as usize,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::LPos(key, element, options))
+    }
+
+    fn handle_hset_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 || !array_length.is_multiple_of(2) {
+            anyhow::bail!("HSET command requires a key and field/value pairs");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let args = Self::parse_arguments(lines, array_length - 2)?;
+        let pairs = args
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Ok(RedisCommand::HSet(key, pairs))
+    }
+
+    /// Parses HGET, or HEXISTS with `exists` set, which both take a key and a field.
+    fn handle_hget_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        exists: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("HGET and HEXISTS commands require a key and a field");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let field = Self::parse_argument(lines, "Field")?;
+        if exists {
+            Ok(RedisCommand::HExists(key, field))
+        } else {
+            Ok(RedisCommand::HGet(key, field))
+        }
+    }
+
+    /// Parses HDEL, or HMGET with `get` set, which both take a key and one or more fields.
+    fn handle_hdel_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        get: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("HDEL and HMGET commands require a key and at least one field");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let fields = Self::parse_arguments(lines, array_length - 2)?;
+        if get {
+            Ok(RedisCommand::HMGet(key, fields))
+        } else {
+            Ok(RedisCommand::HDel(key, fields))
+        }
+    }
+
+    /// Parses HEXPIRE and HPEXPIRE, or HEXPIREAT and HPEXPIREAT with `absolute` set,
+    /// whose time is given in units of `unit_millis`.
+    fn handle_hexpire_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        unit_millis: i64,
+        absolute: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 6 {
+            anyhow::bail!("HEXPIRE command requires a key, a time and the FIELDS argument");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let time = Self::parse_argument(lines, "Time")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if time < 0 {
+            let command = if absolute { "hexpireat" } else { "hexpire" };
+            return Err(CommandError::InvalidExpireTime(command.to_string()).into());
+        }
+        let time = time.saturating_mul(unit_millis);
+        let timestamp = if absolute {
+            time
+        } else {
+            (now_millis() as i64).saturating_add(time)
+        };
+
+        // The NX/XX/GT/LT flags come before FIELDS
+        let mut remaining = array_length - 3;
+        let mut flags = Vec::new();
+        loop {
+            let argument = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            if argument.eq_ignore_ascii_case("fields") {
+                break;
+            }
+            if remaining == 0 {
+                return Err(CommandError::MissingFields.into());
+            }
+            flags.push(argument);
+        }
+        let conditions = Self::expire_conditions(flags)?;
+        let fields = Self::parse_fields(lines, remaining)?;
+        Ok(RedisCommand::HExpire(
+            key,
+            timestamp as u64,
+            conditions,
+            fields,
+        ))
+    }
+
+    /// Parses a key followed by `FIELDS numfields field [field ...]`, as HPERSIST and HTTL take.
+    fn handle_hash_fields_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<(String, Vec<String>), anyhow::Error> {
+        if array_length < 5 {
+            anyhow::bail!("Command requires a key and the FIELDS argument");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        if !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("fields") {
+            return Err(CommandError::MissingFields.into());
+        }
+        let fields = Self::parse_fields(lines, array_length - 3)?;
+        Ok((key, fields))
+    }
+
+    /// Parses the `numfields field [field ...]` following a FIELDS keyword, given the number
+    /// of arguments left.
+    fn parse_fields<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        remaining: usize,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let num_fields = Self::parse_argument(lines, "Numfields")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if num_fields <= 0 {
+            return Err(CommandError::NumFieldsNotPositive.into());
+        }
+        if num_fields as usize != remaining - 1 {
+            return Err(CommandError::NumFieldsMismatch.into());
+        }
+        Self::parse_arguments(lines, remaining - 1)
+    }
+
+    /// Parses HRANDFIELD or ZRANDMEMBER: a key, then an optional count which may be
+    /// followed by `option` (WITHVALUES or WITHSCORES).
+    fn handle_random_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        option: &str,
+    ) -> Result<(String, Option<i64>, bool), anyhow::Error> {
+        if !(2..=4).contains(&array_length) {
+            anyhow::bail!("Command requires a key and an optional count");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let count = match array_length {
+            2 => None,
+            _ => Some(
+                Self::parse_argument(lines, "Count")?
+                    .parse::<i64>()
+                    .map_err(|_| CommandError::NotInteger)?,
+            ),
+        };
+        let with_option = array_length == 4;
+        if with_option && !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case(option) {
+            return Err(CommandError::Syntax.into());
+        }
+        // As in Redis, a negative count must fit once negated, and with the option its
+        // reply of pairs must too.
+        let min_count = if with_option {
+            -i64::MAX / 2
+        } else {
+            -i64::MAX
+        };
+        if count.is_some_and(|count| count < min_count) {
+            return Err(CommandError::OutOfRange.into());
+        }
+        Ok((key, count, with_option))
+    }
+
+    fn handle_hscan_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("HSCAN command requires a key and a cursor");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let cursor = Self::parse_cursor(&Self::parse_argument(lines, "Cursor")?)?;
+        let options = Self::parse_scan_options(lines, array_length - 3, true)?;
+        Ok(RedisCommand::HScan(key, cursor, options))
+    }
+
+    fn handle_zscan_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("ZSCAN command requires a key and a cursor");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let cursor = Self::parse_cursor(&Self::parse_argument(lines, "Cursor")?)?;
+        let options = Self::parse_scan_options(lines, array_length - 3, false)?;
+        Ok(RedisCommand::ZScan(key, cursor, options))
+    }
+
+    fn parse_cursor(cursor: &str) -> Result<u64, anyhow::Error> {
+        cursor
+            .parse::<u64>()
+            .map_err(|_| CommandError::InvalidCursor.into())
+    }
+
+    /// Parses the MATCH, COUNT and (if `allow_no_values` is set) NOVALUES options of a
+    /// SCAN family command, given the number of arguments left.
+    fn parse_scan_options<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        mut remaining: usize,
+        allow_no_values: bool,
+    ) -> Result<ScanOptions, anyhow::Error> {
+        let mut options = ScanOptions::default();
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "match" if remaining > 0 => {
+                    options.pattern = Some(Self::parse_argument(lines, "Pattern")?);
+                    remaining -= 1;
+                }
+                "count" if remaining > 0 => {
+                    let count = Self::parse_argument(lines, "Count")?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    remaining -= 1;
+                    if count < 1 {
+                        return Err(CommandError::Syntax.into());
+                    }
+                    options.count = count as usize;
+                }
+                "novalues" if allow_no_values => options.no_values = true,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Parses SADD, SREM, SMISMEMBER and ZREM, which take a key and one or more members.
+    fn handle_set_members_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<(String, Vec<String>), anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("Command requires a key and at least one member");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let members = Self::parse_arguments(lines, array_length - 2)?;
+        Ok((key, members))
+    }
+
+    fn handle_sismember_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("SISMEMBER command requires a key and a member");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let member = Self::parse_argument(lines, "Member")?;
+        Ok(RedisCommand::SIsMember(key, member))
+    }
+
+    fn handle_smove_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("SMOVE command requires a source, a destination and a member");
+        }
+        let source = Self::parse_argument(lines, "Source")?;
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let member = Self::parse_argument(lines, "Member")?;
+        Ok(RedisCommand::SMove(source, destination, member))
+    }
+
+    fn handle_set_operation_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        operation: SetOperation,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("{} command requires at least one key", operation);
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::SetOp(operation, keys))
+    }
+
+    fn handle_set_operation_store_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        operation: SetOperation,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!(
+                "{}STORE command requires a destination and at least one key",
+                operation
+            );
+        }
+        let destination = Self::parse_argument(lines, "Destination")?;
+        let keys = Self::parse_arguments(lines, array_length - 2)?;
+        Ok(RedisCommand::SetOpStore(operation, destination, keys))
+    }
+
+    /// Parses SINTERCARD: numkeys, the keys and an optional LIMIT, where 0 means no limit.
+    fn handle_sintercard_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("SINTERCARD command requires numkeys and at least one key");
+        }
+        let num_keys = Self::parse_argument(lines, "Numkeys")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if num_keys <= 0 {
+            return Err(CommandError::NumKeysNotPositive.into());
+        }
+        let num_keys = num_keys as usize;
+        let remaining = array_length - 2;
+        if num_keys > remaining {
+            return Err(CommandError::NumKeysTooLarge.into());
+        }
+        // The keys, optionally followed by LIMIT and its value
+        if num_keys != remaining && num_keys + 2 != remaining {
+            return Err(CommandError::Syntax.into());
+        }
+        let keys = Self::parse_arguments(lines, num_keys)?;
+        let mut limit = 0;
+        if num_keys + 2 == remaining {
+            if !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("limit") {
+                return Err(CommandError::Syntax.into());
+            }
+            let value = Self::parse_argument(lines, "Limit")?
+                .parse::<i64>()
+                .map_err(|_| CommandError::NotInteger)?;
+            if value < 0 {
+                return Err(CommandError::NegativeOption("LIMIT").into());
+            }
+            limit = value as usize;
+        }
+        Ok(RedisCommand::SInterCard(keys, limit))
+    }
+
+    fn handle_sscan_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("SSCAN command requires a key and a cursor");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let cursor = Self::parse_cursor(&Self::parse_argument(lines, "Cursor")?)?;
+        let options = Self::parse_scan_options(lines, array_length - 3, false)?;
+        Ok(RedisCommand::SScan(key, cursor, options))
+    }
+
+    fn handle_zadd_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("ZADD command requires a key and score/member pairs");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?
+            .into_iter()
+            .peekable();
+        let mut options = ZAddOptions::default();
+        let (mut nx, mut xx) = (false, false);
+        while let Some(flag) = args.peek() {
+            match flag.to_lowercase().as_str() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "gt" => options.comparison = Some(ScoreComparison::Gt),
+                "lt" => options.comparison = Some(ScoreComparison::Lt),
+                "ch" => options.changed = true,
+                "incr" => options.incr = true,
+                _ => break,
+            }
+            args.next();
+        }
+        let args: Vec<_> = args.collect();
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(CommandError::Syntax.into());
+        }
+        let pairs: Vec<_> = args
+            .chunks(2)
+            .map(|pair| {
+                let score = parse_score(&pair[0]).ok_or(CommandError::NotFloat)?;
+                Ok((score, pair[1].clone()))
+            })
+            .collect::<Result<_, CommandError>>()?;
+        if nx && xx {
+            return Err(CommandError::IncompatibleZAddConditions.into());
+        }
+        options.condition = if nx {
+            Some(SetCondition::Nx)
+        } else if xx {
+            Some(SetCondition::Xx)
+        } else {
+            None
+        };
+        if nx && options.comparison.is_some() {
+            return Err(CommandError::IncompatibleZAddComparisons.into());
+        }
+        if options.incr && pairs.len() > 1 {
+            return Err(CommandError::IncrMultiplePairs.into());
+        }
+        Ok(RedisCommand::ZAdd(key, options, pairs))
+    }
+
+    fn handle_zscore_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("ZSCORE command requires a key and a member");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let member = Self::parse_argument(lines, "Member")?;
+        Ok(RedisCommand::ZScore(key, member))
+    }
+
+    /// Parses ZRANGE, or with `fixed` set one of the ZRANGEBYSCORE and ZRANGEBYLEX family,
+    /// which fix the kind of range and its order rather than taking them as options.
+    fn handle_zrange_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        fixed: Option<(ZRangeKind, bool)>,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("ZRANGE command requires a key, a start and a stop");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let start = Self::parse_argument(lines, "Start")?;
+        let stop = Self::parse_argument(lines, "Stop")?;
+        let (mut kind, rev) = fixed.unwrap_or((ZRangeKind::Rank, false));
+        let mut options = ZRangeOptions {
+            rev,
+            ..Default::default()
+        };
+        let mut remaining = array_length - 4;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "byscore" if fixed.is_none() => kind = ZRangeKind::Score,
+                "bylex" if fixed.is_none() => kind = ZRangeKind::Lex,
+                "rev" if fixed.is_none() => options.rev = true,
+                "withscores" => options.with_scores = true,
+                "limit" if remaining >= 2 => {
+                    let offset = Self::parse_argument(lines, "Offset")?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    let count = Self::parse_argument(lines, "Count")?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    remaining -= 2;
+                    options.limit = Some((offset, count));
+                }
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        if options.limit.is_some() && kind == ZRangeKind::Rank {
+            return Err(CommandError::LimitWithoutBy.into());
+        }
+        if options.with_scores && kind == ZRangeKind::Lex {
+            return Err(CommandError::WithScoresByLex.into());
+        }
+        // Score and lex ranges are given as max then min when reversed
+        let (min, max) = if options.rev && kind != ZRangeKind::Rank {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+        let by = match kind {
+            ZRangeKind::Rank => ZRangeBy::Rank(
+                min.parse::<i64>().map_err(|_| CommandError::NotInteger)?,
+                max.parse::<i64>().map_err(|_| CommandError::NotInteger)?,
+            ),
+            ZRangeKind::Score => ZRangeBy::Score(
+                Self::parse_score_bound(&min)?,
+                Self::parse_score_bound(&max)?,
+            ),
+            ZRangeKind::Lex => {
+                ZRangeBy::Lex(Self::parse_lex_bound(min)?, Self::parse_lex_bound(max)?)
+            }
+        };
+        Ok(RedisCommand::ZRange(key, by, options))
+    }
+
+    fn handle_zincrby_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("ZINCRBY command requires a key, an increment and a member");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let increment = parse_score(&Self::parse_argument(lines, "Increment")?)
+            .ok_or(CommandError::NotFloat)?;
+        let member = Self::parse_argument(lines, "Member")?;
+        Ok(RedisCommand::ZIncrBy(key, increment, member))
+    }
+
+    /// Parses ZRANK, or ZREVRANK with `rev` set.
+    fn handle_zrank_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        rev: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if !(3..=4).contains(&array_length) {
+            anyhow::bail!("ZRANK command requires a key and a member");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let member = Self::parse_argument(lines, "Member")?;
+        let with_score = array_length == 4;
+        if with_score && !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("withscore") {
+            return Err(CommandError::Syntax.into());
+        }
+        Ok(RedisCommand::ZRank(key, member, rev, with_score))
+    }
+
+    fn handle_zpop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        end: ScoreEnd,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if !(2..=3).contains(&array_length) {
+            anyhow::bail!("ZPOP{} command requires a key and an optional count", end);
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let count = if array_length == 3 {
+            Some(Self::parse_count(&Self::parse_argument(lines, "Count")?)?)
+        } else {
+            None
+        };
+        Ok(RedisCommand::ZPop(key, end, count))
+    }
+
+    fn handle_bzpop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        end: ScoreEnd,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!(
+                "BZPOP{} command requires at least one key and a timeout",
+                end
+            );
+        }
+        let keys = Self::parse_arguments(lines, array_length - 2)?;
+        let timeout = Self::parse_timeout(&Self::parse_argument(lines, "Timeout")?)?;
+        Ok(RedisCommand::BZPop(keys, end, timeout))
+    }
+
+    /// Parses ZINTER, ZUNION and ZDIFF, or their STORE forms with `store` set, which take
+    /// a destination first and can't reply WITHSCORES.
+    fn handle_zsetop_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        operation: SetOperation,
+        store: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let name = format!("Z{}{}", operation.name(), if store { "STORE" } else { "" });
+        let min_length = if store { 4 } else { 3 };
+        if array_length < min_length {
+            anyhow::bail!("{} command requires numkeys and at least one key", name);
+        }
+        let destination = if store {
+            Some(Self::parse_argument(lines, "Destination")?)
+        } else {
+            None
+        };
+        let num_keys = Self::parse_argument(lines, "Numkeys")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        if num_keys <= 0 {
+            return Err(CommandError::NoInputKeys(name.to_lowercase()).into());
+        }
+        let num_keys = num_keys as usize;
+        let mut remaining = array_length - min_length + 1;
+        if num_keys > remaining {
+            return Err(CommandError::Syntax.into());
+        }
+        let keys = Self::parse_arguments(lines, num_keys)?;
+        remaining -= num_keys;
+
+        let mut options = ZSetOpOptions::default();
+        let weighted = operation != SetOperation::Diff;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "weights" if weighted && remaining >= num_keys => {
+                    let weights = Self::parse_arguments(lines, num_keys)?
+                        .iter()
+                        .map(|weight| parse_score(weight).ok_or(CommandError::WeightNotFloat))
+                        .collect::<Result<_, _>>()?;
+                    remaining -= num_keys;
+                    options.weights = Some(weights);
+                }
+                "aggregate" if weighted && remaining > 0 => {
+                    remaining -= 1;
+                    options.aggregate = match Self::parse_argument(lines, "Aggregate")?
+                        .to_lowercase()
+                        .as_str()
+                    {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err(CommandError::Syntax.into()),
+                    };
+                }
+                "withscores" if !store => options.with_scores = true,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(match destination {
+            Some(destination) => RedisCommand::ZSetOpStore(operation, destination, keys, options),
+            None => RedisCommand::ZSetOp(operation, keys, options),
+        })
+    }
+
+    /// Parses ZRANGESTORE, which takes a destination followed by the arguments of ZRANGE
+    /// except WITHSCORES.
+    fn handle_zrangestore_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 5 {
+            anyhow::bail!("ZRANGESTORE command requires a destination, a source, a min and a max");
+        }
+        let destination = Self::parse_argument(lines, "Destination")?;
+        match Self::handle_zrange_command(lines, array_length - 1, None)? {
+            RedisCommand::ZRange(_, _, options) if options.with_scores => {
+                Err(CommandError::Syntax.into())
+            }
+            RedisCommand::ZRange(source, by, options) => {
+                Ok(RedisCommand::ZRangeStore(destination, source, by, options))
+            }
+            _ => unreachable!("handle_zrange_command only parses ZRANGE"),
+        }
+    }
+
+    /// Parses ZCOUNT, or ZLEXCOUNT with `lex` set, which both take a key, a min and a max.
+    fn handle_zcount_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        lex: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("ZCOUNT and ZLEXCOUNT commands require a key, a min and a max");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let min = Self::parse_argument(lines, "Min")?;
+        let max = Self::parse_argument(lines, "Max")?;
+        if lex {
+            Ok(RedisCommand::ZLexCount(
+                key,
+                Self::parse_lex_bound(min)?,
+                Self::parse_lex_bound(max)?,
+            ))
+        } else {
+            Ok(RedisCommand::ZCount(
+                key,
+                Self::parse_score_bound(&min)?,
+                Self::parse_score_bound(&max)?,
+            ))
+        }
+    }
+
+    /// Parses a score range bound: a score, optionally prefixed with `(` to exclude it.
+    fn parse_score_bound(bound: &str) -> Result<ScoreBound, anyhow::Error> {
+        let (value, exclusive) = match bound.strip_prefix('(') {
+            Some(value) => (value, true),
+            None => (bound, false),
+        };
+        let value = parse_score(value).ok_or(CommandError::NotFloatRange)?;
+        Ok(ScoreBound { value, exclusive })
+    }
+
+    /// Parses a lex range bound: `-`, `+`, or a member prefixed with `[` or `(`.
+    fn parse_lex_bound(bound: String) -> Result<LexBound, anyhow::Error> {
+        match bound.as_str() {
+            "-" => Ok(LexBound::Min),
+            "+" => Ok(LexBound::Max),
+            _ if bound.starts_with('[') => Ok(LexBound::Inclusive(bound[1..].to_string())),
+            _ if bound.starts_with('(') => Ok(LexBound::Exclusive(bound[1..].to_string())),
+            _ => Err(CommandError::InvalidLexRange.into()),
+        }
+    }
+
+    fn handle_geoadd_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 5 {
+            anyhow::bail!("GEOADD command requires a key and longitude/latitude/member triples");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?
+            .into_iter()
+            .peekable();
+        let mut options = ZAddOptions::default();
+        let (mut nx, mut xx) = (false, false);
+        while let Some(flag) = args.peek() {
+            match flag.to_lowercase().as_str() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "ch" => options.changed = true,
+                _ => break,
+            }
+            args.next();
+        }
+        let args: Vec<_> = args.collect();
+        if args.is_empty() || !args.len().is_multiple_of(3) || (nx && xx) {
+            return Err(CommandError::Syntax.into());
+        }
+        options.condition = if nx {
+            Some(SetCondition::Nx)
+        } else if xx {
+            Some(SetCondition::Xx)
+        } else {
+            None
+        };
+        let triples = args
+            .chunks(3)
+            .map(|triple| {
+                let (longitude, latitude) = Self::parse_lon_lat(&triple[0], &triple[1])?;
+                Ok((longitude, latitude, triple[2].clone()))
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(RedisCommand::GeoAdd(key, options, triples))
+    }
+
+    /// Parses a longitude and a latitude, which must be within the ranges geohashes cover.
+    fn parse_lon_lat(longitude: &str, latitude: &str) -> Result<(f64, f64), anyhow::Error> {
+        let longitude = parse_score(longitude).ok_or(CommandError::NotFloat)?;
+        let latitude = parse_score(latitude).ok_or(CommandError::NotFloat)?;
+        let (min_longitude, max_longitude) = GEO_LONGITUDE_RANGE;
+        let (min_latitude, max_latitude) = GEO_LATITUDE_RANGE;
+        if !(min_longitude..=max_longitude).contains(&longitude)
+            || !(min_latitude..=max_latitude).contains(&latitude)
+        {
+            return Err(CommandError::InvalidLonLat(longitude, latitude).into());
+        }
+        Ok((longitude, latitude))
+    }
+
+    fn parse_geo_unit(unit: &str) -> Result<GeoUnit, anyhow::Error> {
+        match unit.to_lowercase().as_str() {
+            "m" => Ok(GeoUnit::M),
+            "km" => Ok(GeoUnit::Km),
+            "mi" => Ok(GeoUnit::Mi),
+            "ft" => Ok(GeoUnit::Ft),
+            _ => Err(CommandError::UnsupportedUnit.into()),
+        }
+    }
+
+    /// Parses a non-negative distance of BYRADIUS or BYBOX, naming it in the error if it
+    /// isn't a number.
+    fn parse_geo_distance(distance: &str, name: &'static str) -> Result<f64, anyhow::Error> {
+        let distance = parse_float(distance).ok_or(CommandError::NotNumeric(name))?;
+        if distance < 0.0 {
+            return Err(match name {
+                "radius" => CommandError::NegativeRadius,
+                _ => CommandError::NegativeBox,
+            }
+            .into());
+        }
+        Ok(distance)
+    }
+
+    /// Parses GEOPOS or GEOHASH, which take a key and any number of members.
+    fn handle_geo_members_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        command: impl FnOnce(String, Vec<String>) -> RedisCommand,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("GEOPOS and GEOHASH commands require a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let members = Self::parse_arguments(lines, array_length - 2)?;
+        Ok(command(key, members))
+    }
+
+    fn handle_geodist_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if !(4..=5).contains(&array_length) {
+            anyhow::bail!("GEODIST command requires a key, two members and optionally a unit");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let first = Self::parse_argument(lines, "Member")?;
+        let second = Self::parse_argument(lines, "Member")?;
+        let unit = match array_length {
+            5 => Self::parse_geo_unit(&Self::parse_argument(lines, "Unit")?)?,
+            _ => GeoUnit::M,
+        };
+        Ok(RedisCommand::GeoDist(key, first, second, unit))
+    }
+
+    /// Parses GEOSEARCH, or GEOSEARCHSTORE when storing, which takes a destination before
+    /// the source and STOREDIST in place of the WITH options.
+    fn handle_geosearch_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        store: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let name = if store { "GEOSEARCHSTORE" } else { "GEOSEARCH" };
+        let keys = if store { 2 } else { 1 };
+        if array_length < keys + 5 {
+            anyhow::bail!("{} command requires a key, a center and a shape", name);
+        }
+        let destination = match store {
+            true => Some(Self::parse_argument(lines, "Destination")?),
+            false => None,
+        };
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 1 - keys)?.into_iter();
+        let (mut origin, mut shape) = (None, None);
+        let (mut options, mut any, mut store_dist) = (GeoSearchOptions::default(), false, false);
+        while let Some(option) = args.next() {
+            let mut next = || args.next().ok_or(CommandError::Syntax);
+            match option.to_lowercase().as_str() {
+                "frommember" if origin.is_none() => origin = Some(GeoOrigin::Member(next()?)),
+                "fromlonlat" if origin.is_none() => {
+                    let (longitude, latitude) = Self::parse_lon_lat(&next()?, &next()?)?;
+                    origin = Some(GeoOrigin::LonLat(longitude, latitude));
+                }
+                "frommember" | "fromlonlat" => {
+                    return Err(CommandError::GeoSearchOrigin(name).into())
+                }
+                "byradius" if shape.is_none() => {
+                    let radius = Self::parse_geo_distance(&next()?, "radius")?;
+                    shape = Some(GeoShape::Radius(radius, Self::parse_geo_unit(&next()?)?));
+                }
+                "bybox" if shape.is_none() => {
+                    let width = Self::parse_geo_distance(&next()?, "width")?;
+                    let height = Self::parse_geo_distance(&next()?, "height")?;
+                    let unit = Self::parse_geo_unit(&next()?)?;
+                    shape = Some(GeoShape::Box(width, height, unit));
+                }
+                "byradius" | "bybox" => return Err(CommandError::GeoSearchShape(name).into()),
+                "asc" => options.descending = Some(false),
+                "desc" => options.descending = Some(true),
+                "count" => {
+                    let count: i64 = next()?.parse().map_err(|_| CommandError::NotInteger)?;
+                    if count <= 0 {
+                        return Err(CommandError::CountMustBePositive.into());
+                    }
+                    options.count = Some((count as usize, false));
+                }
+                "any" => any = true,
+                "withcoord" if !store => options.with_coord = true,
+                "withdist" if !store => options.with_dist = true,
+                "withhash" if !store => options.with_hash = true,
+                "withcoord" | "withdist" | "withhash" => {
+                    return Err(CommandError::GeoSearchStoreWith.into())
+                }
+                "storedist" if store => store_dist = true,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        let origin = origin.ok_or(CommandError::GeoSearchOrigin(name))?;
+        let shape = shape.ok_or(CommandError::GeoSearchShape(name))?;
+        match (&mut options.count, any) {
+            (Some((_, count_any)), true) => *count_any = true,
+            (None, true) => return Err(CommandError::AnyWithoutCount.into()),
+            _ => {}
+        }
+        Ok(match destination {
+            Some(destination) => {
+                RedisCommand::GeoSearchStore(destination, key, origin, shape, options, store_dist)
+            }
+            None => RedisCommand::GeoSearch(key, origin, shape, options),
+        })
+    }
+
+    fn handle_xadd_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 5 {
+            anyhow::bail!("XADD command requires a key, an ID and field/value pairs");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?
+            .into_iter()
+            .peekable();
+        let mut options = XAddOptions::default();
+        let id = loop {
+            let Some(arg) = args.next() else {
+                anyhow::bail!("XADD command requires an ID");
+            };
+            match arg.to_lowercase().as_str() {
+                "nomkstream" => options.no_mkstream = true,
+                "maxlen" | "minid" => {
+                    if options.trim.is_some() {
+                        return Err(CommandError::IncompatibleTrimStrategies.into());
+                    }
+                    options.trim = Some(Self::parse_stream_trim(&arg, &mut args)?);
+                }
+                _ => break arg,
+            }
+        };
+        let id = match id.split_once('-') {
+            _ if id == "*" => XAddId::Auto,
+            Some((ms, "*")) => {
+                XAddId::AutoSequence(ms.parse().map_err(|_| CommandError::InvalidStreamId)?)
+            }
+            _ => XAddId::Explicit(StreamId::parse(&id, 0).ok_or(CommandError::InvalidStreamId)?),
+        };
+        let args: Vec<_> = args.collect();
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            anyhow::bail!("XADD command requires field/value pairs");
+        }
+        let fields = args
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Ok(RedisCommand::XAdd(key, options, id, fields))
+    }
+
+    fn handle_xtrim_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("XTRIM command requires a key and a trim strategy");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let mut args = Self::parse_arguments(lines, array_length - 2)?
+            .into_iter()
+            .peekable();
+        let strategy = args.next().unwrap_or_default();
+        if !strategy.eq_ignore_ascii_case("maxlen") && !strategy.eq_ignore_ascii_case("minid") {
+            return Err(CommandError::Syntax.into());
+        }
+        let trim = Self::parse_stream_trim(&strategy, &mut args)?;
+        match args.next() {
+            Some(arg)
+                if arg.eq_ignore_ascii_case("maxlen") || arg.eq_ignore_ascii_case("minid") =>
+            {
+                Err(CommandError::IncompatibleTrimStrategies.into())
+            }
+            Some(_) => Err(CommandError::Syntax.into()),
+            None => Ok(RedisCommand::XTrim(key, trim)),
+        }
+    }
+
+    /// Parses the trim option of XTRIM or XADD that follows its MAXLEN or MINID `strategy`:
+    /// an optional `=` or `~`, the threshold and an optional LIMIT.
+    fn parse_stream_trim(
+        strategy: &str,
+        args: &mut Peekable<impl Iterator<Item = String>>,
+    ) -> Result<StreamTrim, anyhow::Error> {
+        let mut threshold = args.next().ok_or(CommandError::Syntax)?;
+        let approximate = threshold == "~";
+        if threshold == "~" || threshold == "=" {
+            threshold = args.next().ok_or(CommandError::Syntax)?;
+        }
+        let strategy = if strategy.eq_ignore_ascii_case("maxlen") {
+            let max_len: i64 = threshold.parse().map_err(|_| CommandError::NotInteger)?;
+            if max_len < 0 {
+                return Err(CommandError::NegativeStreamArgument("MAXLEN").into());
+            }
+            TrimStrategy::MaxLen(max_len as usize)
+        } else {
+            TrimStrategy::MinId(
+                StreamId::parse(&threshold, 0).ok_or(CommandError::InvalidStreamId)?,
+            )
+        };
+        let mut limit = None;
+        if args
+            .next_if(|arg| arg.eq_ignore_ascii_case("limit"))
+            .is_some()
+        {
+            let value: i64 = args
+                .next()
+                .ok_or(CommandError::Syntax)?
+                .parse()
+                .map_err(|_| CommandError::NotInteger)?;
+            if value < 0 {
+                return Err(CommandError::NegativeStreamArgument("LIMIT").into());
+            }
+            if !approximate {
+                return Err(CommandError::LimitWithoutApproximation.into());
+            }
+            limit = Some(value as usize);
+        }
+        Ok(StreamTrim {
+            strategy,
+            approximate,
+            limit,
+        })
+    }
+
+    fn handle_xdel_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("XDEL command requires a key and IDs");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let ids = Self::parse_arguments(lines, array_length - 2)?
+            .iter()
+            .map(|id| StreamId::parse(id, 0).ok_or(CommandError::InvalidStreamId))
+            .collect::<Result<_, _>>()?;
+        Ok(RedisCommand::XDel(key, ids))
+    }
+
+    fn handle_xsetid_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("XSETID command requires a key and an ID");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let parse_id = |id: &str| StreamId::parse(id, 0).ok_or(CommandError::InvalidStreamId);
+        let last_id = parse_id(&Self::parse_argument(lines, "ID")?)?;
+        let mut args = Self::parse_arguments(lines, array_length - 3)?.into_iter();
+        let (mut entries_added, mut max_deleted_id) = (None, None);
+        while let Some(option) = args.next() {
+            let value = args.next().ok_or(CommandError::Syntax)?;
+            match option.to_lowercase().as_str() {
+                "entriesadded" => {
+                    let value: i64 = value.parse().map_err(|_| CommandError::NotInteger)?;
+                    if value < 0 {
+                        return Err(CommandError::NegativeEntriesAdded.into());
+                    }
+                    entries_added = Some(value as u64);
+                }
+                "maxdeletedid" => max_deleted_id = Some(parse_id(&value)?),
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::XSetId(
+            key,
+            last_id,
+            entries_added,
+            max_deleted_id,
+        ))
+    }
+
+    /// Parses XRANGE, or XREVRANGE with `rev` set, which takes the end before the start.
+    fn handle_xrange_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        rev: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 && array_length != 6 {
+            anyhow::bail!("XRANGE and XREVRANGE commands require a key, a start and an end");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let first = Self::parse_argument(lines, "Start")?;
+        let second = Self::parse_argument(lines, "End")?;
+        let (start, end) = if rev {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let start = Self::parse_stream_bound(&start, true)?;
+        let end = Self::parse_stream_bound(&end, false)?;
+        let count = if array_length == 6 {
+            if !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("count") {
+                return Err(CommandError::Syntax.into());
+            }
+            let count: i64 = Self::parse_argument(lines, "Count")?
+                .parse()
+                .map_err(|_| CommandError::NotInteger)?;
+            Some(count.max(0) as usize)
+        } else {
+            None
+        };
+        Ok(RedisCommand::XRange(key, start, end, count, rev))
+    }
+
+    fn handle_xgroup_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("XGROUP command requires a subcommand");
+        }
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        let name = subcommand.to_lowercase();
+        let valid_arity = match name.as_str() {
+            "create" => array_length >= 5,
+            "setid" | "createconsumer" | "delconsumer" => array_length == 5,
+            "destroy" => array_length == 4,
+            _ => return Err(CommandError::UnknownSubcommand(subcommand, "XGROUP").into()),
+        };
+        if !valid_arity {
+            return Err(CommandError::WrongArity(format!("xgroup|{}", name)).into());
+        }
+        if array_length > 6 {
+            return Err(CommandError::Syntax.into());
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let group = Self::parse_argument(lines, "Group")?;
+        let parse_start = |id: String| match id.as_str() {
+            "$" => Ok(GroupStart::Last),
+            _ => StreamId::parse(&id, 0)
+                .map(GroupStart::Id)
+                .ok_or(CommandError::InvalidStreamId),
+        };
+        let command = match name.as_str() {
+            "create" => {
+                let start = parse_start(Self::parse_argument(lines, "ID")?)?;
+                let mkstream = array_length == 6;
+                if mkstream
+                    && !Self::parse_argument(lines, "Option")?.eq_ignore_ascii_case("mkstream")
+                {
+                    return Err(CommandError::Syntax.into());
+                }
+                XGroupCommand::Create(key, group, start, mkstream)
+            }
+            "setid" => {
+                XGroupCommand::SetId(key, group, parse_start(Self::parse_argument(lines, "ID")?)?)
+            }
+            "destroy" => XGroupCommand::Destroy(key, group),
+            "createconsumer" => {
+                XGroupCommand::CreateConsumer(key, group, Self::parse_argument(lines, "Consumer")?)
+            }
+            _ => XGroupCommand::DelConsumer(key, group, Self::parse_argument(lines, "Consumer")?),
+        };
+        Ok(RedisCommand::XGroup(command))
+    }
+
+    fn handle_xinfo_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        let name = subcommand.to_lowercase();
+        let valid_arity = match name.as_str() {
+            "stream" => array_length >= 3,
+            "groups" => array_length == 3,
+            "consumers" => array_length == 4,
+            _ => return Err(CommandError::UnknownSubcommand(subcommand, "XINFO").into()),
+        };
+        if !valid_arity {
+            return Err(CommandError::WrongArity(format!("xinfo|{}", name)).into());
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let command = match name.as_str() {
+            "stream" if array_length == 3 => XInfoCommand::Stream(key, None),
+            "stream" => {
+                let args = Self::parse_arguments(lines, array_length - 3)?;
+                match args.as_slice() {
+                    [full] if full.eq_ignore_ascii_case("full") => {
+                        XInfoCommand::Stream(key, Some(10))
+                    }
+                    [full, keyword, count]
+                        if full.eq_ignore_ascii_case("full")
+                            && keyword.eq_ignore_ascii_case("count") =>
+                    {
+                        let count: i64 = count.parse().map_err(|_| CommandError::NotInteger)?;
+                        let count = if count < 0 { 10 } else { count as usize };
+                        XInfoCommand::Stream(key, Some(count))
+                    }
+                    _ => return Err(CommandError::Syntax.into()),
+                }
+            }
+            "groups" => XInfoCommand::Groups(key),
+            _ => XInfoCommand::Consumers(key, Self::parse_argument(lines, "Group")?),
+        };
+        Ok(RedisCommand::XInfo(command))
+    }
+
+    fn handle_xreadgroup_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 7 {
+            anyhow::bail!("XREADGROUP command requires a group, a consumer and streams");
+        }
+        let mut args = Self::parse_arguments(lines, array_length - 1)?.into_iter();
+        let (Some(keyword), Some(group), Some(consumer)) = (args.next(), args.next(), args.next())
+        else {
+            anyhow::bail!("XREADGROUP command requires a group and a consumer");
+        };
+        if !keyword.eq_ignore_ascii_case("group") {
+            return Err(CommandError::Syntax.into());
+        }
+        let mut options = XReadGroupOptions::default();
+        loop {
+            let option = args.next().ok_or(CommandError::Syntax)?;
+            match option.to_lowercase().as_str() {
+                "streams" => break,
+                "count" => {
+                    let count: i64 = args
+                        .next()
+                        .ok_or(CommandError::Syntax)?
+                        .parse()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    // A count of 0 or less means no limit
+                    options.count = usize::try_from(count).ok().filter(|&count| count > 0);
+                }
+                "block" => {
+                    let block: i64 = args
+                        .next()
+                        .ok_or(CommandError::Syntax)?
+                        .parse()
+                        .map_err(|_| CommandError::TimeoutNotInteger)?;
+                    if block < 0 {
+                        return Err(CommandError::NegativeTimeout.into());
+                    }
+                    options.block = Some(block as u64);
+                }
+                "noack" => options.no_ack = true,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        let args: Vec<_> = args.collect();
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(CommandError::UnbalancedStreams.into());
+        }
+        let (keys, ids) = args.split_at(args.len() / 2);
+        let streams = keys
+            .iter()
+            .zip(ids)
+            .map(|(key, id)| {
+                let id = match id.as_str() {
+                    ">" => GroupReadId::New,
+                    _ => GroupReadId::History(
+                        StreamId::parse(id, 0).ok_or(CommandError::InvalidStreamId)?,
+                    ),
+                };
+                Ok((key.clone(), id))
+            })
+            .collect::<Result<_, CommandError>>()?;
+        Ok(RedisCommand::XReadGroup(group, consumer, options, streams))
+    }
+
+    fn handle_xack_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 4 {
+            anyhow::bail!("XACK command requires a key, a group and IDs");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let group = Self::parse_argument(lines, "Group")?;
+        let ids = Self::parse_arguments(lines, array_length - 3)?
+            .iter()
+            .map(|id| StreamId::parse(id, 0).ok_or(CommandError::InvalidStreamId))
+            .collect::<Result<_, _>>()?;
+        Ok(RedisCommand::XAck(key, group, ids))
+    }
+
+    fn handle_xpending_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("XPENDING command requires a key and a group");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let group = Self::parse_argument(lines, "Group")?;
+        if array_length == 3 {
+            return Ok(RedisCommand::XPending(key, group, None));
+        }
+        let args = Self::parse_arguments(lines, array_length - 3)?;
+        let (idle, args) = match args.split_first() {
+            Some((keyword, rest)) if keyword.eq_ignore_ascii_case("idle") && !rest.is_empty() => {
+                let idle: i64 = rest[0].parse().map_err(|_| CommandError::NotInteger)?;
+                (Some(idle.max(0) as u64), &rest[1..])
+            }
+            _ => (None, &args[..]),
+        };
+        let [start, end, count, consumer @ ..] = args else {
+            return Err(CommandError::Syntax.into());
+        };
+        if consumer.len() > 1 {
+            return Err(CommandError::Syntax.into());
+        }
+        let count: i64 = count.parse().map_err(|_| CommandError::NotInteger)?;
+        let range = XPendingRange {
+            idle,
+            start: Self::parse_stream_bound(start, true)?,
+            end: Self::parse_stream_bound(end, false)?,
+            count: count.max(0) as usize,
+            consumer: consumer.first().cloned(),
+        };
+        Ok(RedisCommand::XPending(key, group, Some(range)))
+    }
+
+    fn handle_xclaim_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 6 {
+            anyhow::bail!(
+                "XCLAIM command requires a key, a group, a consumer, a min idle time and IDs"
+            );
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let group = Self::parse_argument(lines, "Group")?;
+        let consumer = Self::parse_argument(lines, "Consumer")?;
+        let min_idle = Self::parse_claim_argument(
+            &Self::parse_argument(lines, "Min idle time")?,
+            "min-idle-time",
+            "XCLAIM",
+        )?;
+        let mut args = Self::parse_arguments(lines, array_length - 5)?
+            .into_iter()
+            .peekable();
+        let mut ids = Vec::new();
+        while let Some(id) = args.peek().and_then(|id| StreamId::parse(id, 0)) {
+            ids.push(id);
+            args.next();
+        }
+        if ids.is_empty() {
+            return Err(CommandError::InvalidStreamId.into());
+        }
+        let now = now_millis();
+        let mut options = XClaimOptions {
+            delivered: now,
+            retry_count: None,
+            force: false,
+            just_id: false,
+            last_id: None,
+        };
+        while let Some(option) = args.next() {
+            let option = option.to_lowercase();
+            match option.as_str() {
+                "force" => options.force = true,
+                "justid" => options.just_id = true,
+                "idle" | "time" | "retrycount" | "lastid" => {
+                    let value = args.next().ok_or(CommandError::Syntax)?;
+                    match option.as_str() {
+                        "idle" => {
+                            let idle = Self::parse_claim_argument(&value, "IDLE option", "XCLAIM")?;
+                            options.delivered = now.saturating_sub(idle);
+                        }
+                        "time" => {
+                            options.delivered =
+                                Self::parse_claim_argument(&value, "TIME option", "XCLAIM")?;
+                        }
+                        "retrycount" => {
+                            options.retry_count = Some(Self::parse_claim_argument(
+                                &value,
+                                "RETRYCOUNT option",
+                                "XCLAIM",
+                            )?);
+                        }
+                        _ => {
+                            options.last_id = Some(
+                                StreamId::parse(&value, 0).ok_or(CommandError::InvalidStreamId)?,
+                            );
+                        }
+                    }
+                }
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::XClaim(
+            key, group, consumer, min_idle, ids, options,
+        ))
+    }
+
+    fn handle_xautoclaim_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 6 {
+            anyhow::bail!("XAUTOCLAIM command requires a key, a group, a consumer, a min idle time and a start");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let group = Self::parse_argument(lines, "Group")?;
+        let consumer = Self::parse_argument(lines, "Consumer")?;
+        let min_idle = Self::parse_claim_argument(
+            &Self::parse_argument(lines, "Min idle time")?,
+            "min-idle-time",
+            "XAUTOCLAIM",
+        )?;
+        let start = Self::parse_stream_bound(&Self::parse_argument(lines, "Start")?, true)?;
+        let mut args = Self::parse_arguments(lines, array_length - 6)?.into_iter();
+        let (mut count, mut just_id) = (100, false);
+        while let Some(option) = args.next() {
+            match option.to_lowercase().as_str() {
+                "count" => {
+                    let value: i64 = args
+                        .next()
+                        .ok_or(CommandError::Syntax)?
+                        .parse()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    // Ten times the count are scanned, which must not overflow
+                    if !(1..=i64::MAX / 10).contains(&value) {
+                        return Err(CommandError::CountMustBePositive.into());
+                    }
+                    count = value as usize;
+                }
+                "justid" => just_id = true,
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+        Ok(RedisCommand::XAutoClaim(
+            key, group, consumer, min_idle, start, count, just_id,
+        ))
+    }
+
+    /// Parses a time or count argument of XCLAIM or XAUTOCLAIM, where negative values
+    /// count as 0.
+    fn parse_claim_argument(
+        value: &str,
+        argument: &'static str,
+        command: &'static str,
+    ) -> Result<u64, anyhow::Error> {
+        let value: i64 = value
+            .parse()
+            .map_err(|_| CommandError::InvalidClaimArgument(argument, command))?;
+        Ok(value.max(0) as u64)
+    }
+
+    /// Parses a stream range bound: `-`, `+`, or an ID optionally prefixed with `(` to
+    /// exclude it. The bound is returned as the inclusive ID it amounts to, so an ID
+    /// without a sequence number covers the whole millisecond.
+    fn parse_stream_bound(bound: &str, start: bool) -> Result<StreamId, anyhow::Error> {
+        let (id, exclusive) = match bound {
+            "-" => return Ok(StreamId::MIN),
+            "+" => return Ok(StreamId::MAX),
+            _ => match bound.strip_prefix('(') {
+                Some(id) => (id, true),
+                None => (bound, false),
+            },
+        };
+        let missing_seq = if start { 0 } else { u64::MAX };
+        let id = StreamId::parse(id, missing_seq).ok_or(CommandError::InvalidStreamId)?;
+        match (exclusive, start) {
+            (false, _) => Ok(id),
+            (true, true) => id
+                .next()
+                .ok_or_else(|| CommandError::InvalidIntervalId("start").into()),
+            (true, false) => id
+                .prev()
+                .ok_or_else(|| CommandError::InvalidIntervalId("end").into()),
+        }
+    }
+
+    /// Parses a command that only takes a key, such as HLEN or SCARD.
+    fn handle_key_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        command: fn(String) -> RedisCommand,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("Command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(command(key))
+    }
+
+    fn handle_llen_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("LLEN command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::LLen(key))
+    }
+
+    fn handle_lrange_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("LRANGE command requires a key, a start and a stop");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let start = Self::parse_argument(lines, "Start")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let stop = Self::parse_argument(lines, "Stop")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        Ok(RedisCommand::LRange(key, start, stop))
+    }
+
+    fn handle_linsert_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 5 {
+            anyhow::bail!(
+                "LINSERT command requires a key, BEFORE or AFTER, a pivot and an element"
+            );
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let before = match Self::parse_argument(lines, "Position")?
+            .to_lowercase()
+            .as_str()
+        {
+            "before" => true,
+            "after" => false,
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        let pivot = Self::parse_argument(lines, "Pivot")?;
+        let element = Self::parse_argument(lines, "Element")?;
+        Ok(RedisCommand::LInsert(key, before, pivot, element))
+    }
+
+    fn handle_lset_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("LSET command requires a key, an index and an element");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let index = Self::parse_argument(lines, "Index")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let element = Self::parse_argument(lines, "Element")?;
+        Ok(RedisCommand::LSet(key, index, element))
+    }
+
+    fn handle_lrem_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("LREM command requires a key, a count and an element");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let count = Self::parse_argument(lines, "Count")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let element = Self::parse_argument(lines, "Element")?;
+        Ok(RedisCommand::LRem(key, count, element))
+    }
+
+    fn handle_ltrim_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("LTRIM command requires a key, a start and a stop");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let start = Self::parse_argument(lines, "Start")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        let stop = Self::parse_argument(lines, "Stop")?
+            .parse::<i64>()
+            .map_err(|_| CommandError::NotInteger)?;
+        Ok(RedisCommand::LTrim(key, start, stop))
+    }
+
+    /// Parses the optional ASYNC/SYNC argument of FLUSHDB and FLUSHALL,
+    /// returning whether the flush should free memory in the background.
+    fn handle_flush_mode<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<bool, anyhow::Error> {
+        match array_length {
+            1 => Ok(false),
+            2 => match Self::parse_argument(lines, "Mode")?.to_lowercase().as_str() {
+                "async" => Ok(true),
+                "sync" => Ok(false),
+                _ => Err(CommandError::Syntax.into()),
+            },
+            _ => Err(CommandError::Syntax.into()),
+        }
+    }
+
+    fn handle_set_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 {
+            anyhow::bail!("SET command requires at least two arguments");
+        }
+
+        let key = Self::parse_argument(lines, "Key")?;
+        let value = Self::parse_bytes_argument(lines, "Value")?;
+
+        let mut options = SetOptions::default();
+        let mut remaining = array_length - 3;
+        while remaining > 0 {
+            let option = Self::parse_argument(lines, "Option")?;
+            remaining -= 1;
+            match option.to_lowercase().as_str() {
+                "nx" | "xx" if options.condition.is_none() => {
+                    options.condition = Some(if option.eq_ignore_ascii_case("nx") {
+                        SetCondition::Nx
+                    } else {
+                        SetCondition::Xx
+                    });
+                }
+                "keepttl" if !options.keep_ttl && options.expiry.is_none() => {
+                    options.keep_ttl = true;
+                }
+                "get" if !options.get => options.get = true,
+                // Expiry options need a value and may only be given once
+                _ if Self::is_expiry_option(&option)
+                    && remaining > 0
+                    && options.expiry.is_none()
+                    && !options.keep_ttl =>
+                {
+                    let value = Self::parse_argument(lines, "Expiry value")?;
+                    remaining -= 1;
+                    match Self::parse_expiry_options("set", &option, Some(&value))? {
+                        Expiry::At(timestamp) => options.expiry = Some(timestamp),
+                        Expiry::Persist => return Err(CommandError::Syntax.into()),
+                    }
+                }
+                _ => return Err(CommandError::Syntax.into()),
+            }
+        }
+
+        Ok(RedisCommand::Set(key, value, options))
+    }
+
+    fn handle_getdel_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 2 {
+            anyhow::bail!("GETDEL command requires one key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::GetDel(key))
+    }
+
+    fn handle_getex_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("GETEX command requires a key");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let expiry = match array_length {
+            2 => None,
+            3 => {
+                let option = Self::parse_argument(lines, "Option")?;
+                if !option.eq_ignore_ascii_case("persist") {
+                    return Err(CommandError::Syntax.into());
+                }
+                Some(Self::parse_expiry_options("getex", &option, None)?)
+            }
+            4 => {
+                let option = Self::parse_argument(lines, "Option")?;
+                if !Self::is_expiry_option(&option) {
+                    return Err(CommandError::Syntax.into());
+                }
+                let value = Self::parse_argument(lines, "Expiry value")?;
+                Some(Self::parse_expiry_options("getex", &option, Some(&value))?)
+            }
+            _ => return Err(CommandError::Syntax.into()),
+        };
+        Ok(RedisCommand::GetEx(key, expiry))
+    }
+
+    fn handle_setnx_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 3 {
+            anyhow::bail!("SETNX command requires a key and a value");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let value = Self::parse_bytes_argument(lines, "Value")?;
+        Ok(RedisCommand::SetNx(key, value))
+    }
+
+    /// Parses SETEX and PSETEX, whose positional TTL is interpreted as the given expiry option.
+    fn handle_setex_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        option: &str,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length != 4 {
+            anyhow::bail!("SETEX command requires a key, a TTL and a value");
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        let ttl = Self::parse_argument(lines, "TTL")?;
+        let value = Self::parse_bytes_argument(lines, "Value")?;
+        let command = if option == "ex" { "setex" } else { "psetex" };
+        let expiry = match Self::parse_expiry_options(command, option, Some(&ttl))? {
+            Expiry::At(timestamp) => Some(timestamp),
+            Expiry::Persist => None,
+        };
+        let options = SetOptions {
+            expiry,
+            ..SetOptions::default()
+        };
+        Ok(RedisCommand::Set(key, value, options))
+    }
+
+    /// Parses MSET and MSETNX, which take one or more key/value pairs.
+    fn handle_mset_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+        only_if_none_exist: bool,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 3 || array_length.is_multiple_of(2) {
+            anyhow::bail!("MSET command requires key/value pairs");
+        }
+        let pairs = (0..array_length / 2)
+            .map(|_| {
+                let key = Self::parse_argument(lines, "Key")?;
+                let value = Self::parse_bytes_argument(lines, "Value")?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, anyhow::Error>>()?;
+        if only_if_none_exist {
+            Ok(RedisCommand::MSetNx(pairs))
+        } else {
+            Ok(RedisCommand::MSet(pairs))
+        }
+    }
+
+    fn handle_mget_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("MGET command requires at least one key");
+        }
+        let keys = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::MGet(keys))
+    }
+
+    fn handle_info_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let sections = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Info(sections))
+    }
+
+    fn handle_admin_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        _array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let command_type = Self::parse_argument(lines, "Command Type")?;
+        let data = Self::parse_argument(lines, "Data")?;
+        match command_type.as_str() {
+            "replicate" => Ok(RedisCommand::Admin(AdminCommand::Replicate(data))),
+            "addslave" => Ok(RedisCommand::Admin(AdminCommand::AddSlave(data))),
+            _ => Err(anyhow::anyhow!("Unknown admin command")),
+        }
+    }
+
+    fn handle_replconf_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let args = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Replconf(args))
+    }
+
+    fn handle_command_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        let args = Self::parse_arguments(lines, array_length - 1)?;
+        Ok(RedisCommand::Command(args))
+    }
+
+    fn handle_config_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("CONFIG command requires a subcommand");
+        }
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        match subcommand.to_lowercase().as_str() {
+            "get" if array_length == 3 => {
+                let name = Self::parse_argument(lines, "Parameter")?;
+                Ok(RedisCommand::Config(ConfigCommand::Get(name)))
+            }
+            "set" if array_length == 4 => {
+                let name = Self::parse_argument(lines, "Parameter")?;
+                let value = Self::parse_argument(lines, "Value")?;
+                Ok(RedisCommand::Config(ConfigCommand::Set(name, value)))
+            }
+            "get" | "set" => Err(CommandError::WrongArity(format!(
+                "config|{}",
+                subcommand.to_lowercase()
+            ))
+            .into()),
+            _ => Err(CommandError::UnknownSubcommand(subcommand, "CONFIG").into()),
+        }
+    }
+
+    fn handle_debug_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("DEBUG command requires a subcommand");
+        }
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        match subcommand.to_lowercase().as_str() {
+            "sleep" if array_length == 3 => {
+                let seconds = Self::parse_argument(lines, "Seconds")?
+                    .parse::<f64>()
+                    .map_err(|_| CommandError::NotFloat)?;
+                let duration =
+                    Duration::try_from_secs_f64(seconds).map_err(|_| CommandError::InvalidSleep)?;
+                Ok(RedisCommand::Debug(DebugCommand::Sleep(duration)))
+            }
+            "populate" if (3..=5).contains(&array_length) => {
+                let count = Self::parse_argument(lines, "Count")?
+                    .parse::<usize>()
+                    .map_err(|_| CommandError::NotInteger)?;
+                let prefix = if array_length > 3 {
+                    Self::parse_argument(lines, "Prefix")?
+                } else {
+                    "key".to_string()
+                };
+                let size = if array_length > 4 {
+                    let size = Self::parse_argument(lines, "Size")?
+                        .parse::<usize>()
+                        .map_err(|_| CommandError::NotInteger)?;
+                    Some(size)
+                } else {
+                    None
+                };
+                Ok(RedisCommand::Debug(DebugCommand::Populate(
+                    count, prefix, size,
+                )))
+            }
+            "internals" if array_length == 2 => Ok(RedisCommand::Debug(DebugCommand::Internals)),
+            "sleep" | "populate" | "internals" => {
+                Err(CommandError::WrongArity(format!("debug|{}", subcommand.to_lowercase())).into())
+            }
+            _ => Err(CommandError::UnknownSubcommand(subcommand, "DEBUG").into()),
+        }
+    }
+
+    fn handle_acl_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("ACL command requires a subcommand");
+        }
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        let (command, max_length) = match subcommand.to_lowercase().as_str() {
+            "whoami" => (AclCommand::WhoAmI, 2),
+            "list" => (AclCommand::List, 2),
+            "cat" if array_length == 3 => (
+                AclCommand::Cat(Some(Self::parse_argument(lines, "Category")?)),
+                3,
+            ),
+            "cat" => (AclCommand::Cat(None), 3),
+            _ => return Err(CommandError::UnknownSubcommand(subcommand, "ACL").into()),
+        };
+        if array_length > max_length {
+            return Err(
+                CommandError::WrongArity(format!("acl|{}", subcommand.to_lowercase())).into(),
+            );
+        }
+        Ok(RedisCommand::Acl(command))
+    }
+
+    fn handle_object_command<'a>(
+        lines: &mut impl Iterator<Item = &'a [u8]>,
+        array_length: usize,
+    ) -> Result<RedisCommand, anyhow::Error> {
+        if array_length < 2 {
+            anyhow::bail!("OBJECT command requires a subcommand");
+        }
+        let subcommand = Self::parse_argument(lines, "Subcommand")?;
+        let name = subcommand.to_lowercase();
+        let command = match name.as_str() {
+            "encoding" => ObjectCommand::Encoding,
+            "refcount" => ObjectCommand::RefCount,
+            "idletime" => ObjectCommand::IdleTime,
+            _ => return Err(CommandError::UnknownSubcommand(subcommand, "OBJECT").into()),
+        };
+        if array_length != 3 {
+            return Err(CommandError::WrongArity(format!("object|{}", name)).into());
+        }
+        let key = Self::parse_argument(lines, "Key")?;
+        Ok(RedisCommand::Object(command(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a request given as its arguments.
+    fn parse(argv: &[&str]) -> Result<RedisCommand, anyhow::Error> {
+        let argv: Vec<Bytes> = argv
+            .iter()
+            .map(|argument| Bytes::copy_from_slice(argument.as_bytes()))
+            .collect();
+        RedisCommandParser::parse_argv(&argv)
+    }
+
+    /// Returns the CommandError a request fails to parse with.
+    fn command_error(argv: &[&str]) -> CommandError {
+        let error = parse(argv).expect_err("request should fail to parse");
+        match error.downcast::<CommandError>() {
+            Ok(error) => error,
+            Err(error) => panic!("expected a CommandError, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn blocking_timeout_must_fit_a_duration() {
+        assert!(matches!(
+            command_error(&["BLPOP", "key", "1e20"]),
+            CommandError::TimeoutOutOfRange
+        ));
+        assert!(matches!(
+            command_error(&["BLPOP", "key", "-1"]),
+            CommandError::NegativeTimeout
+        ));
+        assert!(matches!(
+            parse(&["BLPOP", "key", "0.5"]).unwrap(),
+            RedisCommand::BPop(_, _, timeout) if timeout == 0.5
+        ));
+    }
+
+    #[test]
+    fn xinfo_checks_subcommand_then_arity() {
+        assert!(matches!(
+            command_error(&["XINFO", "FOO"]),
+            CommandError::UnknownSubcommand(name, "XINFO") if name == "FOO"
+        ));
+        assert!(matches!(
+            command_error(&["XINFO", "STREAM"]),
+            CommandError::WrongArity(name) if name == "xinfo|stream"
+        ));
+        assert!(matches!(
+            command_error(&["XINFO", "GROUPS", "key", "extra"]),
+            CommandError::WrongArity(name) if name == "xinfo|groups"
+        ));
+        assert!(matches!(
+            parse(&["XINFO", "CONSUMERS", "key", "group"]).unwrap(),
+            RedisCommand::XInfo(XInfoCommand::Consumers(key, group)) if key == "key" && group == "group"
+        ));
+    }
+
+    #[test]
+    fn xgroup_and_object_check_subcommand_then_arity() {
+        assert!(matches!(
+            command_error(&["XGROUP", "FOO", "s", "g"]),
+            CommandError::UnknownSubcommand(name, "XGROUP") if name == "FOO"
+        ));
+        assert!(matches!(
+            command_error(&["XGROUP", "CREATE", "s"]),
+            CommandError::WrongArity(name) if name == "xgroup|create"
+        ));
+        assert!(matches!(
+            command_error(&["XGROUP", "DESTROY", "s", "g", "extra"]),
+            CommandError::WrongArity(name) if name == "xgroup|destroy"
+        ));
+        assert!(matches!(
+            command_error(&["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM", "extra"]),
+            CommandError::Syntax
+        ));
+        assert!(matches!(
+            command_error(&["OBJECT", "ENCODING"]),
+            CommandError::WrongArity(name) if name == "object|encoding"
+        ));
+        assert!(matches!(
+            command_error(&["OBJECT", "FOO", "key"]),
+            CommandError::UnknownSubcommand(name, "OBJECT") if name == "FOO"
+        ));
+    }
+
+    #[test]
+    fn random_counts_must_fit_once_negated() {
+        assert!(matches!(
+            command_error(&["HRANDFIELD", "h", "-9223372036854775808"]),
+            CommandError::OutOfRange
+        ));
+        assert!(matches!(
+            command_error(&["HRANDFIELD", "h", "-4611686018427387904", "WITHVALUES"]),
+            CommandError::OutOfRange
+        ));
+        assert!(matches!(
+            command_error(&["ZRANDMEMBER", "z", "-9223372036854775808"]),
+            CommandError::OutOfRange
+        ));
+        assert!(matches!(
+            command_error(&["ZRANDMEMBER", "z", "-4611686018427387904", "WITHSCORES"]),
+            CommandError::OutOfRange
+        ));
+        assert!(matches!(
+            parse(&["HRANDFIELD", "h", "-9223372036854775807"]).unwrap(),
+            RedisCommand::HRandField(_, Some(count), false) if count == -i64::MAX
+        ));
+    }
+
+    #[test]
+    fn binary_values_round_trip_and_text_arguments_must_be_utf8() {
+        let value = b"\x00\xff\r\n\xfe".to_vec();
+        let argv = [
+            Bytes::from_static(b"SET"),
+            Bytes::from_static(b"key"),
+            Bytes::from(value.clone()),
+        ];
+        assert!(matches!(
+            RedisCommandParser::parse_argv(&argv).unwrap(),
+            RedisCommand::Set(key, parsed, _) if key == "key" && parsed == value
+        ));
+
+        let argv = [Bytes::from_static(b"GET"), Bytes::from_static(b"\xff")];
+        let error = RedisCommandParser::parse_argv(&argv).unwrap_err();
+        assert!(matches!(
+            error.downcast::<CommandError>().unwrap(),
+            CommandError::InvalidUtf8(name) if name == "key"
+        ));
+    }
+
+    /// Feeds `bytes` to a decoder one at a time, returning the requests it completes.
+    fn decode_byte_by_byte(bytes: &[u8]) -> Vec<Vec<Bytes>> {
+        let mut decoder = RespDecoder::default();
+        let mut requests = Vec::new();
+        for byte in bytes {
+            decoder.feed(&[*byte]);
+            while let Some(argv) = decoder.next_request(&ProtocolLimits::default()).unwrap() {
+                requests.push(argv);
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn requests_fed_byte_by_byte_are_decoded_once_complete() {
+        let request = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nva\r\nl\r\n";
+        assert_eq!(
+            decode_byte_by_byte(request),
+            [[&b"SET"[..], b"key", b"va\r\nl"]]
+        );
+        assert_eq!(
+            decode_byte_by_byte(b"SET key \"a b\"\r\n"),
+            [[&b"SET"[..], b"key", b"a b"]]
+        );
+
+        // The elements received so far are kept rather than parsed again
+        let mut decoder = RespDecoder::default();
+        decoder.feed(b"*2\r\n$3\r\nGET\r\n$3\r\nk");
+        let limits = ProtocolLimits::default();
+        assert!(decoder.next_request(&limits).unwrap().is_none());
+        let pending = decoder.pending.as_ref().unwrap();
+        assert_eq!((pending.remaining, pending.arguments.len()), (Some(1), 1));
+        decoder.feed(b"ey\r\n");
+        assert_eq!(
+            decoder.next_request(&limits).unwrap().unwrap(),
+            [&b"GET"[..], b"key"]
+        );
+        assert!(decoder.pending.is_none());
+    }
+
+    #[test]
+    fn two_requests_in_one_feed_are_decoded_in_order() {
+        let mut decoder = RespDecoder::default();
+        decoder.feed(b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\nPING\r\n*1\r\n");
+        let limits = ProtocolLimits::default();
+        assert_eq!(
+            decoder.next_request(&limits).unwrap().unwrap(),
+            [&b"PING"[..]]
+        );
+        assert_eq!(
+            decoder.next_request(&limits).unwrap().unwrap(),
+            [&b"ECHO"[..], b"hi"]
+        );
+        assert_eq!(
+            decoder.next_request(&limits).unwrap().unwrap(),
+            [&b"PING"[..]]
+        );
+        assert!(decoder.next_request(&limits).unwrap().is_none());
+        decoder.feed(b"$4\r\nPING\r\n");
+        assert_eq!(
+            decoder.next_request(&limits).unwrap().unwrap(),
+            [&b"PING"[..]]
+        );
+    }
+
+    /// Splits an inline request, with its arguments as strings.
+    fn inline(line: &str) -> Result<Vec<String>, ProtocolError> {
+        Ok(split_inline_arguments(line.as_bytes())?
+            .into_iter()
+            .map(|argument| String::from_utf8(argument).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn inline_arguments_split_at_whitespace_outside_quotes() {
+        assert_eq!(
+            inline("SET  key\tvalue\r\n").unwrap(),
+            ["SET", "key", "value"]
+        );
+        assert_eq!(inline("   \r\n").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            inline("SET key \"hello world\" ''\n").unwrap(),
+            ["SET", "key", "hello world", ""]
+        );
+        assert_eq!(
+            inline("SET 'a \"b\"' \"c 'd'\"").unwrap(),
+            ["SET", "a \"b\"", "c 'd'"]
+        );
+    }
+
+    #[test]
+    fn inline_quotes_take_their_escapes() {
+        assert_eq!(inline(r#"ECHO "a\x41""#).unwrap(), ["ECHO", "aA"]);
+        assert_eq!(
+            inline(r#"ECHO "\n\r\t\"\\\xzz""#).unwrap(),
+            ["ECHO", "\n\r\t\"\\xzz"]
+        );
+        assert_eq!(inline(r#"ECHO 'it\'s'"#).unwrap(), ["ECHO", "it's"]);
+        // Only a quote is escaped in single quotes
+        assert_eq!(inline(r#"ECHO 'a\nb'"#).unwrap(), ["ECHO", r"a\nb"]);
+    }
+
+    #[test]
+    fn unbalanced_inline_quotes_are_rejected() {
+        for line in [
+            "ECHO \"abc",
+            "ECHO 'abc",
+            "ECHO \"abc\\\"",
+            "ECHO 'it''s'",
+            "ECHO \"a\"b",
+        ] {
+            let error = inline(line).unwrap_err();
+            assert!(matches!(error, ProtocolError::UnbalancedQuotes), "{}", line);
+            assert_eq!(
+                error.to_string(),
+                "Protocol error: unbalanced quotes in request"
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_multibulk_header_is_rejected_before_reading_elements() {
+        let mut decoder = RespDecoder::default();
+        decoder.feed(b"*999999999\r\n");
+        let error = decoder
+            .next_request(&ProtocolLimits::default())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast::<ProtocolError>().unwrap(),
+            ProtocolError::InvalidMultibulkLength
+        ));
+    }
+
+    /// Parses an expiry option the way SET does.
+    fn expiry(option: &str, value: Option<&str>) -> Result<Expiry, anyhow::Error> {
+        RedisCommandParser::parse_expiry_options("set", option, value)
+    }
+
+    /// Returns the absolute timestamp of an expiry that sets one.
+    fn timestamp(expiry: Expiry) -> u64 {
+        match expiry {
+            Expiry::At(timestamp) => timestamp,
+            Expiry::Persist => panic!("expected an absolute expiry"),
+        }
+    }
+
+    #[test]
+    fn relative_expiry_options_are_offset_from_now() {
+        let before = now_millis();
+        let ex = timestamp(expiry("EX", Some("10")).unwrap());
+        let px = timestamp(expiry("px", Some("10")).unwrap());
+        let after = now_millis();
+        assert!((before + 10_000..=after + 10_000).contains(&ex));
+        assert!((before + 10..=after + 10).contains(&px));
+    }
+
+    #[test]
+    fn absolute_expiry_options_are_converted_to_milliseconds() {
+        assert_eq!(
+            timestamp(expiry("EXAT", Some("1700000000")).unwrap()),
+            1_700_000_000_000
+        );
+        assert_eq!(
+            timestamp(expiry("pxat", Some("1700000000123")).unwrap()),
+            1_700_000_000_123
+        );
+    }
+
+    #[test]
+    fn persist_takes_no_value() {
+        assert_eq!(expiry("PERSIST", None).unwrap(), Expiry::Persist);
+    }
+
+    #[test]
+    fn invalid_expiry_values_are_rejected() {
+        let error = |option, value| {
+            expiry(option, value)
+                .unwrap_err()
+                .downcast::<CommandError>()
+                .unwrap()
+        };
+        assert!(matches!(error("EX", None), CommandError::Syntax));
+        assert!(matches!(
+            error("PX", Some("soon")),
+            CommandError::NotInteger
+        ));
+        assert!(matches!(
+            error("EXAT", Some("0")),
+            CommandError::InvalidExpireTime(command) if command == "set"
+        ));
+        assert!(matches!(
+            error("PXAT", Some("-5")),
+            CommandError::InvalidExpireTime(_)
+        ));
+    }
+
+    #[test]
+    fn set_rejects_unknown_and_incomplete_options() {
+        assert!(matches!(
+            command_error(&["SET", "key", "value", "FOO"]),
+            CommandError::Syntax
+        ));
+        assert!(matches!(
+            command_error(&["SET", "key", "value", "PX"]),
+            CommandError::Syntax
+        ));
     }
 }
@@ -0,0 +1,247 @@
+/// Name of the built-in user every connection is authenticated as.
+pub const DEFAULT_USER: &str = "default";
+
+/// Command categories reported by ACL CAT.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+/// The categories each command belongs to, as ACL CAT lists them.
+const COMMAND_CATEGORIES: &[(&str, &[&str])] = &[
+    ("ping", &["connection", "fast"]),
+    ("echo", &["connection", "fast"]),
+    ("hello", &["connection", "fast"]),
+    ("info", &["slow", "dangerous"]),
+    ("lolwut", &["read", "fast"]),
+    ("replconf", &["admin", "slow", "dangerous"]),
+    ("command", &["connection", "slow"]),
+    ("config", &["admin", "slow", "dangerous"]),
+    ("debug", &["admin", "slow", "dangerous"]),
+    ("acl", &["slow"]),
+    ("object", &["keyspace", "read", "slow"]),
+    ("get", &["read", "string", "fast"]),
+    ("set", &["write", "string", "slow"]),
+    ("setnx", &["write", "string", "fast"]),
+    ("getdel", &["write", "string", "fast"]),
+    ("getex", &["write", "string", "fast"]),
+    ("setex", &["write", "string", "slow"]),
+    ("psetex", &["write", "string", "slow"]),
+    ("mset", &["write", "string", "slow"]),
+    ("msetnx", &["write", "string", "slow"]),
+    ("mget", &["read", "string", "fast"]),
+    ("exists", &["keyspace", "read", "fast"]),
+    ("touch", &["keyspace", "read", "fast"]),
+    ("del", &["keyspace", "write", "slow"]),
+    ("unlink", &["keyspace", "write", "fast"]),
+    ("expire", &["keyspace", "write", "fast"]),
+    ("pexpire", &["keyspace", "write", "fast"]),
+    ("expireat", &["keyspace", "write", "fast"]),
+    ("pexpireat", &["keyspace", "write", "fast"]),
+    ("persist", &["keyspace", "write", "fast"]),
+    ("expiretime", &["keyspace", "read", "fast"]),
+    ("pexpiretime", &["keyspace", "read", "fast"]),
+    ("ttl", &["keyspace", "read", "fast"]),
+    ("pttl", &["keyspace", "read", "fast"]),
+    ("type", &["keyspace", "read", "fast"]),
+    ("randomkey", &["keyspace", "read", "slow"]),
+    ("keys", &["keyspace", "read", "slow", "dangerous"]),
+    ("dbsize", &["keyspace", "read", "fast"]),
+    ("copy", &["keyspace", "write", "slow"]),
+    ("dump", &["keyspace", "read", "slow"]),
+    ("restore", &["keyspace", "write", "slow", "dangerous"]),
+    ("flushdb", &["keyspace", "write", "slow", "dangerous"]),
+    ("flushall", &["keyspace", "write", "slow", "dangerous"]),
+    ("time", &["fast"]),
+    ("incr", &["write", "string", "fast"]),
+    ("decr", &["write", "string", "fast"]),
+    ("incrby", &["write", "string", "fast"]),
+    ("decrby", &["write", "string", "fast"]),
+    ("incrbyfloat", &["write", "string", "fast"]),
+    ("append", &["write", "string", "fast"]),
+    ("strlen", &["read", "string", "fast"]),
+    ("lcs", &["read", "string", "slow"]),
+    ("getrange", &["read", "string", "slow"]),
+    ("setrange", &["write", "string", "slow"]),
+    ("setbit", &["write", "bitmap", "slow"]),
+    ("getbit", &["read", "bitmap", "fast"]),
+    ("bitcount", &["read", "bitmap", "slow"]),
+    ("bitop", &["write", "bitmap", "slow"]),
+    ("bitpos", &["read", "bitmap", "slow"]),
+    ("bitfield", &["write", "bitmap", "slow"]),
+    ("pfadd", &["write", "hyperloglog", "fast"]),
+    ("pfcount", &["read", "hyperloglog", "slow"]),
+    ("pfmerge", &["write", "hyperloglog", "slow"]),
+    (
+        "sort",
+        &["write", "set", "sortedset", "list", "slow", "dangerous"],
+    ),
+    ("lpush", &["write", "list", "fast"]),
+    ("rpush", &["write", "list", "fast"]),
+    ("lpushx", &["write", "list", "fast"]),
+    ("rpushx", &["write", "list", "fast"]),
+    ("lpop", &["write", "list", "fast"]),
+    ("rpop", &["write", "list", "fast"]),
+    ("blpop", &["write", "list", "slow", "blocking"]),
+    ("brpop", &["write", "list", "slow", "blocking"]),
+    ("lmove", &["write", "list", "slow"]),
+    ("rpoplpush", &["write", "list", "slow"]),
+    ("blmove", &["write", "list", "slow", "blocking"]),
+    ("lmpop", &["write", "list", "slow"]),
+    ("blmpop", &["write", "list", "slow", "blocking"]),
+    ("lpos", &["read", "list", "slow"]),
+    ("llen", &["read", "list", "fast"]),
+    ("lrange", &["read", "list", "slow"]),
+    ("linsert", &["write", "list", "slow"]),
+    ("lset", &["write", "list", "slow"]),
+    ("lrem", &["write", "list", "slow"]),
+    ("ltrim", &["write", "list", "slow"]),
+    ("hset", &["write", "hash", "fast"]),
+    ("hget", &["read", "hash", "fast"]),
+    ("hdel", &["write", "hash", "fast"]),
+    ("hgetall", &["read", "hash", "slow"]),
+    ("hmget", &["read", "hash", "fast"]),
+    ("hlen", &["read", "hash", "fast"]),
+    ("hexists", &["read", "hash", "fast"]),
+    ("hrandfield", &["read", "hash", "slow"]),
+    ("hscan", &["read", "hash", "slow"]),
+    ("hexpire", &["write", "hash", "fast"]),
+    ("hpexpire", &["write", "hash", "fast"]),
+    ("hexpireat", &["write", "hash", "fast"]),
+    ("hpexpireat", &["write", "hash", "fast"]),
+    ("hpersist", &["write", "hash", "fast"]),
+    ("httl", &["read", "hash", "fast"]),
+    ("hpttl", &["read", "hash", "fast"]),
+    ("sadd", &["write", "set", "fast"]),
+    ("srem", &["write", "set", "fast"]),
+    ("smembers", &["read", "set", "slow"]),
+    ("sismember", &["read", "set", "fast"]),
+    ("smismember", &["read", "set", "fast"]),
+    ("smove", &["write", "set", "fast"]),
+    ("scard", &["read", "set", "fast"]),
+    ("sinter", &["read", "set", "slow"]),
+    ("sunion", &["read", "set", "slow"]),
+    ("sdiff", &["read", "set", "slow"]),
+    ("sintercard", &["read", "set", "slow"]),
+    ("sscan", &["read", "set", "slow"]),
+    ("sinterstore", &["write", "set", "slow"]),
+    ("sunionstore", &["write", "set", "slow"]),
+    ("sdiffstore", &["write", "set", "slow"]),
+    ("zadd", &["write", "sortedset", "fast"]),
+    ("zscore", &["read", "sortedset", "fast"]),
+    ("zcard", &["read", "sortedset", "fast"]),
+    ("zrange", &["read", "sortedset", "slow"]),
+    ("zrangebyscore", &["read", "sortedset", "slow"]),
+    ("zrevrangebyscore", &["read", "sortedset", "slow"]),
+    ("zrangebylex", &["read", "sortedset", "slow"]),
+    ("zrevrangebylex", &["read", "sortedset", "slow"]),
+    ("zrangestore", &["write", "sortedset", "slow"]),
+    ("zrandmember", &["read", "sortedset", "slow"]),
+    ("zscan", &["read", "sortedset", "slow"]),
+    ("zcount", &["read", "sortedset", "fast"]),
+    ("zincrby", &["write", "sortedset", "fast"]),
+    ("zrank", &["read", "sortedset", "fast"]),
+    ("zrevrank", &["read", "sortedset", "fast"]),
+    ("zrem", &["write", "sortedset", "fast"]),
+    ("zinter", &["read", "sortedset", "slow"]),
+    ("zunion", &["read", "sortedset", "slow"]),
+    ("zdiff", &["read", "sortedset", "slow"]),
+    ("zinterstore", &["write", "sortedset", "slow"]),
+    ("zunionstore", &["write", "sortedset", "slow"]),
+    ("zdiffstore", &["write", "sortedset", "slow"]),
+    ("zpopmin", &["write", "sortedset", "fast"]),
+    ("zpopmax", &["write", "sortedset", "fast"]),
+    ("bzpopmin", &["write", "sortedset", "fast", "blocking"]),
+    ("bzpopmax", &["write", "sortedset", "fast", "blocking"]),
+    ("zmpop", &["write", "sortedset", "slow"]),
+    ("zlexcount", &["read", "sortedset", "fast"]),
+    ("geoadd", &["write", "geo", "slow"]),
+    ("geopos", &["read", "geo", "slow"]),
+    ("geodist", &["read", "geo", "slow"]),
+    ("geohash", &["read", "geo", "slow"]),
+    ("geosearch", &["read", "geo", "slow"]),
+    ("geosearchstore", &["write", "geo", "slow"]),
+    ("georadius", &["write", "geo", "slow"]),
+    ("georadiusbymember", &["write", "geo", "slow"]),
+    ("xadd", &["write", "stream", "fast"]),
+    ("xlen", &["read", "stream", "fast"]),
+    ("xtrim", &["write", "stream", "slow"]),
+    ("xdel", &["write", "stream", "fast"]),
+    ("xsetid", &["write", "stream", "fast"]),
+    ("xrange", &["read", "stream", "slow"]),
+    ("xrevrange", &["read", "stream", "slow"]),
+    ("xgroup", &["write", "stream", "slow"]),
+    ("xinfo", &["read", "stream", "slow"]),
+    ("xreadgroup", &["write", "stream", "slow", "blocking"]),
+    ("xack", &["write", "stream", "fast"]),
+    ("xpending", &["read", "stream", "slow"]),
+    ("xclaim", &["write", "stream", "fast"]),
+    ("xautoclaim", &["write", "stream", "fast"]),
+];
+
+/// Returns the commands in a category, none for a category that isn't known.
+pub fn category_commands(category: &str) -> Vec<&'static str> {
+    let category = category.to_lowercase();
+    COMMAND_CATEGORIES
+        .iter()
+        .filter(|(_, categories)| categories.contains(&category.as_str()))
+        .map(|(command, _)| *command)
+        .collect()
+}
+
+/// Rule line of the default user. There is no password support, so it is always `nopass`.
+pub const DEFAULT_USER_RULES: &str = "user default on nopass sanitize-payload ~* &* +@all";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_category_is_listed_by_acl_cat() {
+        for (command, categories) in COMMAND_CATEGORIES {
+            for category in *categories {
+                assert!(CATEGORIES.contains(category), "{} in {}", command, category);
+            }
+            let command = crate::keyspec::lookup(command);
+            assert!(command.is_some());
+        }
+    }
+
+    #[test]
+    fn categories_list_their_commands() {
+        let blocking = category_commands("BLOCKING");
+        assert_eq!(
+            blocking,
+            [
+                "blpop",
+                "brpop",
+                "blmove",
+                "blmpop",
+                "bzpopmin",
+                "bzpopmax",
+                "xreadgroup"
+            ]
+        );
+        assert!(category_commands("pubsub").is_empty());
+        assert!(category_commands("nosuchcategory").is_empty());
+    }
+}
@@ -1,19 +1,60 @@
+use crate::resp::RespValue;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
 };
+use tracing::info;
+
+use crate::{
+    command::{
+        AclCommand, ConfigCommand, DebugCommand, Expiry, LcsOptions, ObjectCommand, RedisCommand,
+        SetCondition,
+    },
+    keyspec,
+    parser::MAX_STRING_LEN,
+    utils::{longest_common_subsequence, now_micros},
+};
+
+use super::{
+    acl::{category_commands, CATEGORIES, DEFAULT_USER, DEFAULT_USER_RULES},
+    blocking::BlockedClients,
+    config::RedisConfig,
+    dump,
+    hyperloglog::InvalidHllError,
+    store::{RedisStore, WrongTypeError},
+    stream::GroupError,
+    types::{RedisInfo, RedisRole},
+};
 
-use crate::command::{RedisCommand, RedisCommandResponse};
+/// Builds the reply for a command that failed at runtime. Errors that carry their own
+/// error code, such as WRONGTYPE, are sent as is, anything else is prefixed with ERR.
+pub fn error_reply(e: anyhow::Error) -> RespValue {
+    if e.is::<WrongTypeError>() || e.is::<GroupError>() || e.is::<InvalidHllError>() {
+        RespValue::Error(e.to_string())
+    } else {
+        RespValue::Error(format!("ERR {}", e))
+    }
+}
 
-use super::{store::RedisStore, types::RedisInfo};
+/// The Redis version reported to clients.
+pub const REDIS_VERSION: &str = "7.2.0";
+/// The most memory DEBUG POPULATE may build keys and values in.
+const MAX_POPULATE_BYTES: usize = 4 * MAX_STRING_LEN;
+/// A rough size of a populated key and its bookkeeping, besides the value's padding.
+const POPULATE_ENTRY_OVERHEAD: usize = 64;
 
 /// A trait for Redis server implementations.
 #[async_trait::async_trait]
 pub trait RedisServer {
-    async fn handle_command(
-        &mut self,
-        command: RedisCommand,
-    ) -> Result<RedisCommandResponse, anyhow::Error>;
+    async fn handle_command(&mut self, command: RedisCommand) -> Result<RespValue, anyhow::Error>;
 }
 
 /// A base struct for common Redis server functionality.
@@ -22,16 +63,33 @@ pub struct BaseServer {
     pub info: RedisInfo,
     pub address: String,
     pub store: RedisStore,
+    pub blocked: BlockedClients,
+    pub config: RedisConfig,
+    pub started_at: Instant,
+    /// Number of open client connections, maintained by the server loop.
+    pub connected_clients: Arc<AtomicUsize>,
 }
 
 impl BaseServer {
+    pub fn new(info: RedisInfo, address: String) -> Self {
+        BaseServer {
+            info,
+            address,
+            store: RedisStore::new(),
+            blocked: BlockedClients::default(),
+            config: RedisConfig::default(),
+            started_at: Instant::now(),
+            connected_clients: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
     pub async fn send_command(
         &self,
         address: &str,
-        command: &str,
+        command: &[u8],
     ) -> Result<String, anyhow::Error> {
         let mut stream = TcpStream::connect(address).await?;
-        stream.write_all(command.as_bytes()).await?;
+        stream.write_all(command).await?;
 
         let mut buffer = [0; 1024];
         let n = stream.read(&mut buffer).await?;
@@ -39,4 +97,876 @@ impl BaseServer {
 
         Ok(response)
     }
+
+    /// Settles the parts of a write that are otherwise decided as it runs: the
+    /// `active-expire-jitter` added to SET's expiry and the ID XADD generates. This is done
+    /// before the command runs, so the request it replicates has the same effect.
+    pub async fn resolve_command(&self, command: &mut RedisCommand) {
+        match command {
+            RedisCommand::Set(_, _, options) => {
+                options.expiry = self.config.apply_expire_jitter(options.expiry);
+            }
+            RedisCommand::XAdd(key, _, id, _) => self.resolve_xadd_id(key, id).await,
+            _ => {}
+        }
+    }
+
+    /// Handles commands whose behaviour is the same on masters and slaves.
+    pub async fn handle_shared_command(
+        &mut self,
+        command: RedisCommand,
+    ) -> Result<RespValue, anyhow::Error> {
+        match command {
+            RedisCommand::Set(key, value, options) => {
+                // SET GET replies with the previous value, so it can only replace a string
+                if options.get && self.store.value_type(&key).await.unwrap_or("string") != "string"
+                {
+                    return Ok(error_reply(WrongTypeError.into()));
+                }
+                let (written, previous) = self
+                    .store
+                    .set_with_condition(
+                        &key,
+                        value,
+                        options.expiry,
+                        options.keep_ttl,
+                        options.condition,
+                    )
+                    .await;
+                Ok(match (options.get, written, previous) {
+                    (true, _, Some(previous)) => RespValue::BulkString(previous),
+                    (true, _, None) | (false, false, _) => RespValue::Null,
+                    (false, true, _) => RespValue::ok(),
+                })
+            }
+            RedisCommand::SetNx(key, value) => {
+                let (written, _) = self
+                    .store
+                    .set_with_condition(&key, value, None, false, Some(SetCondition::Nx))
+                    .await;
+                Ok(RespValue::Integer(written as i64))
+            }
+            RedisCommand::GetDel(key) => match self.store.take(&key).await {
+                Ok(Some(value)) => Ok(RespValue::BulkString(value)),
+                Ok(None) => Ok(RespValue::Null),
+                Err(e) => Ok(error_reply(e)),
+            },
+            RedisCommand::GetEx(key, expiry) => {
+                let value = match self.store.get_bytes(&key).await {
+                    Ok(Some(value)) => value,
+                    Ok(None) => return Ok(RespValue::Null),
+                    Err(e) => return Ok(error_reply(e)),
+                };
+                match expiry {
+                    Some(Expiry::At(timestamp)) => {
+                        self.store.set_expiry(&key, timestamp, &[]).await;
+                    }
+                    Some(Expiry::Persist) => {
+                        self.store.persist(&key).await;
+                    }
+                    None => {}
+                }
+                Ok(RespValue::BulkString(value))
+            }
+            RedisCommand::Copy(source, destination, replace) => {
+                if source == destination {
+                    return Ok(RespValue::Error(
+                        "ERR source and destination objects are the same".to_string(),
+                    ));
+                }
+                let copied = self.store.copy(&source, &destination, replace).await;
+                Ok(RespValue::Integer(copied as i64))
+            }
+            RedisCommand::Dump(key) => match self.store.get_value(&key).await {
+                Some(value) => Ok(RespValue::bulk(&dump::serialize(&value))),
+                None => Ok(RespValue::Null),
+            },
+            RedisCommand::Restore(key, expiry, payload, replace) => {
+                let value = match dump::deserialize(&payload) {
+                    Ok(value) => value,
+                    Err(e) => return Ok(RespValue::Error(format!("ERR {}", e))),
+                };
+                let condition = (!replace).then_some(SetCondition::Nx);
+                let (restored, _) = self
+                    .store
+                    .set_with_condition(&key, value, expiry, false, condition)
+                    .await;
+                if !restored {
+                    return Ok(RespValue::Error(
+                        "BUSYKEY Target key name already exists.".to_string(),
+                    ));
+                }
+                Ok(RespValue::ok())
+            }
+            RedisCommand::MSet(pairs) => {
+                self.store.set_many(&pairs).await;
+                Ok(RespValue::ok())
+            }
+            RedisCommand::MSetNx(pairs) => {
+                let written = self.store.set_many_if_none_exist(&pairs).await;
+                Ok(RespValue::Integer(written as i64))
+            }
+            RedisCommand::MGet(keys) => {
+                let values = self.store.get_many(&keys).await;
+                Ok(RespValue::Array(
+                    values
+                        .into_iter()
+                        .map(|value| value.map_or(RespValue::Null, RespValue::BulkString))
+                        .collect(),
+                ))
+            }
+            RedisCommand::Exists(keys) => {
+                let count = self.store.exists(&keys).await;
+                Ok(RespValue::Integer(count as i64))
+            }
+            RedisCommand::Touch(keys) => {
+                let count = self.store.touch(&keys).await;
+                Ok(RespValue::Integer(count as i64))
+            }
+            RedisCommand::Del(keys) => {
+                let lazy = self.config.lazyfree_lazy_user_del;
+                let count = self.store.delete(&keys, lazy).await;
+                Ok(RespValue::Integer(count as i64))
+            }
+            RedisCommand::Unlink(keys) => {
+                let count = self.store.delete(&keys, true).await;
+                Ok(RespValue::Integer(count as i64))
+            }
+            RedisCommand::Expire(key, expiry, conditions) => {
+                let updated = self.store.set_expiry(&key, expiry, &conditions).await;
+                Ok(RespValue::Integer(updated as i64))
+            }
+            RedisCommand::ExpireTime(key) => Ok(self.expire_time(&key, 1000).await),
+            RedisCommand::PExpireTime(key) => Ok(self.expire_time(&key, 1).await),
+            RedisCommand::Ttl(key) => Ok(self.ttl(&key, 1000).await),
+            RedisCommand::PTtl(key) => Ok(self.ttl(&key, 1).await),
+            RedisCommand::Persist(key) => {
+                let persisted = self.store.persist(&key).await;
+                Ok(RespValue::Integer(persisted as i64))
+            }
+            RedisCommand::IncrBy(key, delta) => match self.store.incr_by(&key, delta).await {
+                Ok(value) => Ok(RespValue::Integer(value)),
+                Err(e) => Ok(error_reply(e)),
+            },
+            RedisCommand::IncrByFloat(key, delta) => {
+                match self.store.incr_by_float(&key, delta).await {
+                    Ok(value) => Ok(RespValue::bulk(&value)),
+                    Err(e) => Ok(error_reply(e)),
+                }
+            }
+            RedisCommand::Append(key, suffix) => {
+                let length = self
+                    .store
+                    .mutate(&key, |value| {
+                        value.extend_from_slice(&suffix);
+                        value.len()
+                    })
+                    .await;
+                Ok(match length {
+                    Ok(length) => RespValue::Integer(length as i64),
+                    Err(e) => error_reply(e),
+                })
+            }
+            RedisCommand::GetRange(key, start, end) => {
+                match self.store.get_range(&key, start, end).await {
+                    Ok(range) => Ok(RespValue::BulkString(range)),
+                    Err(e) => Ok(error_reply(e)),
+                }
+            }
+            RedisCommand::SetRange(key, offset, value) => {
+                match self.store.set_range(&key, offset, &value).await {
+                    Ok(length) => Ok(RespValue::Integer(length as i64)),
+                    Err(e) => Ok(error_reply(e)),
+                }
+            }
+            RedisCommand::SetBit(key, offset, value) => Ok(self.setbit(&key, offset, value).await),
+            RedisCommand::GetBit(key, offset) => Ok(self.getbit(&key, offset).await),
+            RedisCommand::BitCount(key, range) => Ok(self.bitcount(&key, range).await),
+            RedisCommand::BitOp(operation, destination, keys) => {
+                Ok(self.bitop(operation, &destination, &keys).await)
+            }
+            RedisCommand::PfAdd(key, elements) => Ok(self.pfadd(&key, &elements).await),
+            RedisCommand::PfCount(keys) => Ok(self.pfcount(&keys).await),
+            RedisCommand::PfMerge(destination, keys) => Ok(self.pfmerge(&destination, &keys).await),
+            RedisCommand::BitField(key, operations) => Ok(self.bitfield(&key, &operations).await),
+            RedisCommand::BitPos(key, bit, start, end, unit) => {
+                Ok(self.bitpos(&key, bit, start, end, unit).await)
+            }
+            RedisCommand::Lcs(key1, key2, options) => Ok(self.lcs(&key1, &key2, options).await),
+            RedisCommand::Strlen(key) => match self.store.get_bytes(&key).await {
+                Ok(value) => Ok(RespValue::Integer(
+                    value.map_or(0, |value| value.len()) as i64
+                )),
+                Err(e) => Ok(error_reply(e)),
+            },
+            RedisCommand::Type(key) => {
+                let value_type = self.store.value_type(&key).await.unwrap_or("none");
+                Ok(RespValue::SimpleString(value_type.to_string()))
+            }
+            RedisCommand::Time => {
+                let now = now_micros();
+                Ok(RespValue::Array(vec![
+                    RespValue::bulk(&(now / 1_000_000).to_string()),
+                    RespValue::bulk(&(now % 1_000_000).to_string()),
+                ]))
+            }
+            RedisCommand::RandomKey => match self.store.random_key().await {
+                Some(key) => Ok(RespValue::bulk(&key)),
+                None => Ok(RespValue::Null),
+            },
+            RedisCommand::FlushDb(lazy) | RedisCommand::FlushAll(lazy) => {
+                self.store.flush(lazy).await;
+                Ok(RespValue::ok())
+            }
+            RedisCommand::Keys(pattern) => {
+                let keys = self.store.keys(&pattern).await;
+                Ok(RespValue::Array(
+                    keys.iter().map(|key| RespValue::bulk(key)).collect(),
+                ))
+            }
+            RedisCommand::DbSize => Ok(RespValue::Integer(self.store.len().await as i64)),
+            RedisCommand::Sort(key, options) => Ok(self.sort(&key, &options).await),
+            RedisCommand::Push(key, end, values, existing) => {
+                Ok(self.push(&key, end, values, existing).await)
+            }
+            RedisCommand::Pop(key, end, count) => Ok(self.pop(&key, end, count).await),
+            RedisCommand::LMove(source, destination, from, to) => {
+                Ok(self.lmove(&source, &destination, from, to).await)
+            }
+            RedisCommand::LPos(key, element, options) => {
+                Ok(self.lpos(&key, &element, options).await)
+            }
+            RedisCommand::LMPop(keys, end, count) => Ok(match self.mpop(&keys, end, count).await {
+                Some(served) => served.response,
+                None => RespValue::NullArray,
+            }),
+            RedisCommand::LLen(key) => Ok(self.llen(&key).await),
+            RedisCommand::HSet(key, pairs) => Ok(self.hset(&key, pairs).await),
+            RedisCommand::HGet(key, field) => Ok(self.hget(&key, &field).await),
+            RedisCommand::HDel(key, fields) => Ok(self.hdel(&key, &fields).await),
+            RedisCommand::HGetAll(key) => Ok(self.hgetall(&key).await),
+            RedisCommand::HMGet(key, fields) => Ok(self.hmget(&key, &fields).await),
+            RedisCommand::HLen(key) => Ok(self.hlen(&key).await),
+            RedisCommand::HExists(key, field) => Ok(self.hexists(&key, &field).await),
+            RedisCommand::HRandField(key, count, with_values) => {
+                Ok(self.hrandfield(&key, count, with_values).await)
+            }
+            RedisCommand::HScan(key, cursor, options) => {
+                Ok(self.hscan(&key, cursor, options).await)
+            }
+            RedisCommand::HExpire(key, timestamp, conditions, fields) => {
+                Ok(self.hexpire(&key, timestamp, &conditions, &fields).await)
+            }
+            RedisCommand::HPersist(key, fields) => Ok(self.hpersist(&key, &fields).await),
+            RedisCommand::HTtl(key, fields) => Ok(self.httl(&key, &fields, 1000).await),
+            RedisCommand::HPTtl(key, fields) => Ok(self.httl(&key, &fields, 1).await),
+            RedisCommand::SAdd(key, members) => Ok(self.sadd(&key, members).await),
+            RedisCommand::SRem(key, members) => Ok(self.srem(&key, &members).await),
+            RedisCommand::SMembers(key) => Ok(self.smembers(&key).await),
+            RedisCommand::SIsMember(key, member) => Ok(self.sismember(&key, &member).await),
+            RedisCommand::SMIsMember(key, members) => Ok(self.smismember(&key, &members).await),
+            RedisCommand::SMove(source, destination, member) => {
+                Ok(self.smove(&source, &destination, member).await)
+            }
+            RedisCommand::SCard(key) => Ok(self.scard(&key).await),
+            RedisCommand::SetOp(operation, keys) => Ok(self.set_operation(operation, &keys).await),
+            RedisCommand::SInterCard(keys, limit) => Ok(self.sintercard(&keys, limit).await),
+            RedisCommand::SScan(key, cursor, options) => {
+                Ok(self.sscan(&key, cursor, options).await)
+            }
+            RedisCommand::SetOpStore(operation, destination, keys) => Ok(self
+                .set_operation_store(operation, &destination, &keys)
+                .await),
+            RedisCommand::ZAdd(key, options, pairs) => Ok(self.zadd(&key, options, pairs).await),
+            RedisCommand::ZScore(key, member) => Ok(self.zscore(&key, &member).await),
+            RedisCommand::ZCard(key) => Ok(self.zcard(&key).await),
+            RedisCommand::ZRange(key, by, options) => Ok(self.zrange(&key, by, options).await),
+            RedisCommand::ZIncrBy(key, increment, member) => {
+                Ok(self.zincrby(&key, increment, member).await)
+            }
+            RedisCommand::ZRank(key, member, rev, with_score) => {
+                Ok(self.zrank(&key, &member, rev, with_score).await)
+            }
+            RedisCommand::ZPop(key, end, count) => Ok(self.zpop(&key, end, count).await),
+            RedisCommand::ZSetOp(operation, keys, options) => {
+                Ok(self.zset_operation(operation, &keys, options).await)
+            }
+            RedisCommand::ZSetOpStore(operation, destination, keys, options) => Ok(self
+                .zset_operation_store(operation, &destination, &keys, options)
+                .await),
+            RedisCommand::ZRem(key, members) => Ok(self.zrem(&key, &members).await),
+            RedisCommand::ZRangeStore(destination, source, by, options) => {
+                Ok(self.zrangestore(&destination, &source, by, options).await)
+            }
+            RedisCommand::ZRandMember(key, count, with_scores) => {
+                Ok(self.zrandmember(&key, count, with_scores).await)
+            }
+            RedisCommand::ZScan(key, cursor, options) => {
+                Ok(self.zscan(&key, cursor, options).await)
+            }
+            RedisCommand::ZCount(key, min, max) => Ok(self.zcount(&key, min, max).await),
+            RedisCommand::ZLexCount(key, min, max) => Ok(self.zlexcount(&key, &min, &max).await),
+            RedisCommand::GeoAdd(key, options, triples) => {
+                Ok(self.geoadd(&key, options, triples).await)
+            }
+            RedisCommand::GeoPos(key, members) => Ok(self.geopos(&key, &members).await),
+            RedisCommand::GeoDist(key, first, second, unit) => {
+                Ok(self.geodist(&key, &first, &second, unit).await)
+            }
+            RedisCommand::GeoHash(key, members) => Ok(self.geohash(&key, &members).await),
+            RedisCommand::GeoSearch(key, origin, shape, options) => {
+                Ok(self.geosearch(&key, &origin, shape, &options).await)
+            }
+            RedisCommand::GeoSearchStore(
+                destination,
+                source,
+                origin,
+                shape,
+                options,
+                store_dist,
+            ) => Ok(self
+                .geosearchstore(&destination, &source, &origin, shape, &options, store_dist)
+                .await),
+            RedisCommand::XAdd(key, options, id, fields) => {
+                Ok(self.xadd(&key, &options, id, fields).await)
+            }
+            RedisCommand::XTrim(key, trim) => Ok(self.xtrim(&key, &trim).await),
+            RedisCommand::XDel(key, ids) => Ok(self.xdel(&key, &ids).await),
+            RedisCommand::XSetId(key, id, entries_added, max_deleted_id) => {
+                Ok(self.xsetid(&key, id, entries_added, max_deleted_id).await)
+            }
+            RedisCommand::XLen(key) => Ok(self.xlen(&key).await),
+            RedisCommand::XRange(key, start, end, count, rev) => {
+                Ok(self.xrange(&key, start, end, count, rev).await)
+            }
+            RedisCommand::XInfo(command) => Ok(self.xinfo(command).await),
+            RedisCommand::XGroup(command) => Ok(self.xgroup(command).await),
+            RedisCommand::XReadGroup(group, consumer, options, streams) => {
+                Ok(self.xreadgroup(&group, &consumer, &options, &streams).await)
+            }
+            RedisCommand::XAck(key, group, ids) => Ok(self.xack(&key, &group, &ids).await),
+            RedisCommand::XPending(key, group, range) => {
+                Ok(self.xpending(&key, &group, range).await)
+            }
+            RedisCommand::XClaim(key, group, consumer, min_idle, ids, options) => Ok(self
+                .xclaim(&key, &group, &consumer, min_idle, &ids, &options)
+                .await),
+            RedisCommand::XAutoClaim(key, group, consumer, min_idle, start, count, just_id) => {
+                Ok(self
+                    .xautoclaim(&key, &group, &consumer, min_idle, start, count, just_id)
+                    .await)
+            }
+            RedisCommand::LRange(key, start, stop) => Ok(self.lrange(&key, start, stop).await),
+            RedisCommand::LInsert(key, before, pivot, element) => {
+                Ok(self.linsert(&key, before, &pivot, element).await)
+            }
+            RedisCommand::LSet(key, index, element) => Ok(self.lset(&key, index, element).await),
+            RedisCommand::LRem(key, count, element) => Ok(self.lrem(&key, count, &element).await),
+            RedisCommand::LTrim(key, start, stop) => Ok(self.ltrim(&key, start, stop).await),
+            RedisCommand::Info(sections) => Ok(self.info(sections).await),
+            RedisCommand::Lolwut(_) => Ok(RespValue::Verbatim(format!(
+                "Redis ver. {}\n",
+                REDIS_VERSION
+            ))),
+            RedisCommand::Command(args) => Ok(self.command(args)),
+            RedisCommand::Config(command) => Ok(self.config(command)),
+            RedisCommand::Acl(command) => Ok(Self::acl(command)),
+            RedisCommand::Object(command) => Ok(self.object(command).await),
+            RedisCommand::Debug(DebugCommand::Internals) => Ok(self.debug_internals().await),
+            RedisCommand::Debug(DebugCommand::Populate(count, prefix, size)) => {
+                Ok(self.populate(count, &prefix, size).await)
+            }
+            _ => Err(anyhow::anyhow!("Unsupported command: {}", command)),
+        }
+    }
+
+    /// Handles the COMMAND introspection subcommands.
+    fn command(&self, args: Vec<String>) -> RespValue {
+        let Some(subcommand) = args.first() else {
+            return RespValue::Error("ERR COMMAND requires a subcommand".to_string());
+        };
+        match subcommand.to_lowercase().as_str() {
+            "getkeys" => match keyspec::get_keys(&args[1..]) {
+                Ok(keys) => RespValue::Array(keys.iter().map(|key| RespValue::bulk(key)).collect()),
+                Err(e) => RespValue::Error(format!("ERR {}", e)),
+            },
+            _ => RespValue::Error(format!("ERR unknown subcommand '{}'", subcommand)),
+        }
+    }
+
+    /// Handles the CONFIG GET/SET subcommands.
+    fn config(&mut self, command: ConfigCommand) -> RespValue {
+        match command {
+            ConfigCommand::Get(pattern) => RespValue::Map(
+                self.config
+                    .get_matching(&pattern)
+                    .iter()
+                    .map(|(name, value)| (RespValue::bulk(name), RespValue::bulk(value)))
+                    .collect(),
+            ),
+            ConfigCommand::Set(name, value) => match self.config.set(&name, &value) {
+                Ok(()) => RespValue::ok(),
+                Err(e) => RespValue::Error(format!("ERR {}", e)),
+            },
+        }
+    }
+
+    /// Handles the ACL subcommands for the built-in default user.
+    fn acl(command: AclCommand) -> RespValue {
+        match command {
+            AclCommand::WhoAmI => RespValue::bulk(DEFAULT_USER),
+            AclCommand::List => RespValue::Array(vec![RespValue::bulk(DEFAULT_USER_RULES)]),
+            AclCommand::Cat(None) => RespValue::Array(
+                CATEGORIES
+                    .iter()
+                    .map(|category| RespValue::bulk(category))
+                    .collect(),
+            ),
+            AclCommand::Cat(Some(category)) => RespValue::Array(
+                category_commands(&category)
+                    .into_iter()
+                    .map(RespValue::bulk)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Reports how a key's value is represented. Replies with a null if the key doesn't exist.
+    async fn object(&self, command: ObjectCommand) -> RespValue {
+        match command {
+            ObjectCommand::Encoding(key) => match self
+                .store
+                .encoding(&key, self.config.zset_listpack_limits())
+                .await
+            {
+                Some(encoding) => RespValue::bulk(encoding),
+                None => RespValue::Null,
+            },
+            // Values are never shared between keys
+            ObjectCommand::RefCount(key) => match self.store.exists(&[key]).await {
+                0 => RespValue::Null,
+                _ => RespValue::Integer(1),
+            },
+            ObjectCommand::IdleTime(key) => match self.store.idle_time(&key).await {
+                Some(idle) => RespValue::Integer((idle / 1000) as i64),
+                None => RespValue::Null,
+            },
+        }
+    }
+
+    /// Reports internal runtime state for diagnosing stalls, if enabled in the config.
+    async fn debug_internals(&self) -> RespValue {
+        if !self.config.enable_debug_internals {
+            return RespValue::Error(
+                "ERR DEBUG INTERNALS is disabled, enable it with CONFIG SET enable-debug-internals yes"
+                    .to_string(),
+            );
+        }
+        let stats = [
+            format!("store_keys:{}", self.store.len().await),
+            format!(
+                "expirations_heap_len:{}",
+                self.store.expirations_heap_len().await
+            ),
+            format!(
+                "connected_clients:{}",
+                self.connected_clients.load(Ordering::Relaxed)
+            ),
+            format!("blocked_clients:{}", self.blocked.len()),
+        ];
+        RespValue::Verbatim(stats.join("\r\n"))
+    }
+
+    /// Bulk-inserts `count` keys named `<prefix>:<n>` with values `value:<n>`,
+    /// padded with zero bytes or truncated to `size` when given. Refuses to build
+    /// more than `MAX_POPULATE_BYTES` of keys and values.
+    async fn populate(&self, count: usize, prefix: &str, size: Option<usize>) -> RespValue {
+        let entry_size = size.unwrap_or(0).saturating_add(POPULATE_ENTRY_OVERHEAD);
+        if count.saturating_mul(entry_size) > MAX_POPULATE_BYTES {
+            return RespValue::Error("ERR OOM in dictTryExpand".to_string());
+        }
+        let entries = (0..count)
+            .map(|n| {
+                let mut value = format!("value:{}", n).into_bytes();
+                if let Some(size) = size {
+                    let mut padded = vec![0u8; size];
+                    let len = value.len().min(size);
+                    padded[..len].copy_from_slice(&value[..len]);
+                    value = padded;
+                }
+                (format!("{}:{}", prefix, n), value)
+            })
+            .collect();
+        let inserted = self.store.set_many_if_absent(entries).await;
+        info!("DEBUG POPULATE inserted {} keys", inserted);
+        RespValue::ok()
+    }
+
+    /// Replies with the longest common subsequence of two string keys,
+    /// treating missing keys as empty strings.
+    async fn lcs(&self, key1: &str, key2: &str, options: LcsOptions) -> RespValue {
+        let (a, b) = match (
+            self.store.get_bytes(key1).await,
+            self.store.get_bytes(key2).await,
+        ) {
+            (Ok(a), Ok(b)) => (a.unwrap_or_default(), b.unwrap_or_default()),
+            (Err(e), _) | (_, Err(e)) => return error_reply(e),
+        };
+        let table_size = (a.len() + 1)
+            .checked_mul(b.len() + 1)
+            .and_then(|cells| cells.checked_mul(std::mem::size_of::<u32>()));
+        if table_size.is_none_or(|size| size > MAX_STRING_LEN) {
+            return RespValue::Error(
+                "ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len"
+                    .to_string(),
+            );
+        }
+
+        let (subsequence, matches) = longest_common_subsequence(&a, &b);
+        if options.len {
+            return RespValue::Integer(subsequence.len() as i64);
+        }
+        if !options.idx {
+            return RespValue::BulkString(subsequence);
+        }
+
+        let range = |(start, end): (usize, usize)| {
+            RespValue::Array(vec![
+                RespValue::Integer(start as i64),
+                RespValue::Integer(end as i64),
+            ])
+        };
+        let matches = matches
+            .iter()
+            .filter(|m| m.match_len() >= options.min_match_len)
+            .map(|m| {
+                let mut entry = vec![range(m.a), range(m.b)];
+                if options.with_match_len {
+                    entry.push(RespValue::Integer(m.match_len() as i64));
+                }
+                RespValue::Array(entry)
+            })
+            .collect();
+        RespValue::Array(vec![
+            RespValue::bulk("matches"),
+            RespValue::Array(matches),
+            RespValue::bulk("len"),
+            RespValue::Integer(subsequence.len() as i64),
+        ])
+    }
+
+    /// Replies with a key's absolute expiry in units of `unit_millis`,
+    /// -1 if it has no expiry or -2 if it doesn't exist.
+    async fn expire_time(&self, key: &str, unit_millis: u64) -> RespValue {
+        match self.store.expiry(key).await {
+            Some(Some(expiry)) => RespValue::Integer((expiry / unit_millis) as i64),
+            Some(None) => RespValue::Integer(-1),
+            None => RespValue::Integer(-2),
+        }
+    }
+
+    /// Replies with a key's remaining time to live in units of `unit_millis`, rounded
+    /// to the nearest unit, -1 if it has no expiry or -2 if it doesn't exist.
+    async fn ttl(&self, key: &str, unit_millis: u64) -> RespValue {
+        match self.store.ttl(key).await {
+            Some(Some(ttl)) => RespValue::Integer(((ttl + unit_millis / 2) / unit_millis) as i64),
+            Some(None) => RespValue::Integer(-1),
+            None => RespValue::Integer(-2),
+        }
+    }
+
+    /// Builds the INFO reply for the requested sections, or the default sections if none are given.
+    async fn info(&self, sections: Vec<String>) -> RespValue {
+        const ALL_SECTIONS: &[&str] = &[
+            "server",
+            "clients",
+            "memory",
+            "stats",
+            "replication",
+            "keyspace",
+        ];
+
+        let mut requested: Vec<&str> = Vec::new();
+        let sections: Vec<String> = sections.iter().map(|s| s.to_lowercase()).collect();
+        if sections.is_empty() {
+            requested.extend(ALL_SECTIONS);
+        }
+        for section in &sections {
+            match section.as_str() {
+                "default" | "all" | "everything" => requested.extend(ALL_SECTIONS),
+                section => {
+                    if let Some(known) = ALL_SECTIONS.iter().find(|known| **known == section) {
+                        requested.push(known);
+                    }
+                }
+            }
+        }
+
+        let mut rendered = Vec::new();
+        for section in ALL_SECTIONS {
+            if requested.contains(section) {
+                rendered.push(self.info_section(section).await);
+            }
+        }
+        RespValue::Verbatim(rendered.join("\r\n"))
+    }
+
+    /// Renders a single INFO section with its `# Section` header.
+    async fn info_section(&self, section: &str) -> String {
+        let (header, fields) = match section {
+            "server" => (
+                "Server",
+                vec![
+                    format!("redis_version:{}", REDIS_VERSION),
+                    "redis_mode:standalone".to_string(),
+                    format!("process_id:{}", std::process::id()),
+                    format!(
+                        "tcp_port:{}",
+                        self.address.rsplit(':').next().unwrap_or_default()
+                    ),
+                    format!("uptime_in_seconds:{}", self.started_at.elapsed().as_secs()),
+                ],
+            ),
+            "clients" => (
+                "Clients",
+                vec![
+                    format!(
+                        "connected_clients:{}",
+                        self.connected_clients.load(Ordering::Relaxed)
+                    ),
+                    format!("blocked_clients:{}", self.blocked.len()),
+                ],
+            ),
+            "memory" => (
+                "Memory",
+                vec![format!(
+                    "lazyfree_pending_objects:{}",
+                    self.store.lazyfree_pending_objects()
+                )],
+            ),
+            "stats" => (
+                "Stats",
+                vec![format!(
+                    "lazyfreed_objects:{}",
+                    self.store.lazyfreed_objects()
+                )],
+            ),
+            "replication" => {
+                let mut fields = vec![
+                    format!("role:{}", self.info.role),
+                    format!("master_host:{}", self.info.master_host),
+                    format!("master_port:{}", self.info.master_port),
+                ];
+                if self.info.role == RedisRole::Slave {
+                    fields.push(format!(
+                        "slave_read_only:{}",
+                        u8::from(self.config.replica_read_only)
+                    ));
+                }
+                fields.extend([
+                    "master_failover_state:no-failover".to_string(),
+                    format!("master_replid:{}", self.info.master_replid),
+                    format!("master_repl_offset:{}", self.info.master_repl_offset),
+                    format!("second_repl_offset:{}", self.info.second_repl_offset),
+                    // There is no replication backlog, so it is never active
+                    "repl_backlog_active:0".to_string(),
+                    "repl_backlog_first_byte_offset:0".to_string(),
+                ]);
+                ("Replication", fields)
+            }
+            "keyspace" => {
+                let keys = self.store.len().await;
+                let fields = if keys > 0 {
+                    vec![format!(
+                        "db0:keys={},expires={},avg_ttl=0",
+                        keys,
+                        self.store.expires_len().await
+                    )]
+                } else {
+                    vec![]
+                };
+                ("Keyspace", fields)
+            }
+            _ => return String::new(),
+        };
+
+        let mut rendered = format!("# {}\r\n", header);
+        for field in fields {
+            rendered.push_str(&field);
+            rendered.push_str("\r\n");
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::command::XAddId;
+    use crate::parser::{RedisCommandParser, RespDecoder};
+
+    fn server(role: RedisRole) -> BaseServer {
+        BaseServer::new(
+            RedisInfo::new(role, "127.0.0.1", "6379"),
+            "127.0.0.1:6380".to_string(),
+        )
+    }
+
+    /// Returns the text of a bulk or verbatim string reply.
+    fn text(reply: RespValue) -> String {
+        match reply {
+            RespValue::BulkString(bytes) => String::from_utf8(bytes).unwrap(),
+            RespValue::Verbatim(text) => text,
+            reply => panic!("expected a string, got {:?}", reply),
+        }
+    }
+
+    /// Returns the `# Section` headers of an INFO reply.
+    fn headers(info: &str) -> Vec<&str> {
+        info.lines().filter(|line| line.starts_with('#')).collect()
+    }
+
+    #[tokio::test]
+    async fn info_returns_only_the_requested_sections() {
+        let server = server(RedisRole::Master);
+        let sections = vec!["replication".to_string(), "clients".to_string()];
+        let info = text(server.info(sections).await);
+        assert_eq!(headers(&info), ["# Clients", "# Replication"]);
+    }
+
+    /// Waits for the background frees to finish and returns the INFO lazyfree counters.
+    async fn lazyfree_counters(server: &BaseServer) -> (u64, u64) {
+        while server.store.lazyfree_pending_objects() > 0 {
+            tokio::task::yield_now().await;
+        }
+        (
+            server.store.lazyfree_pending_objects(),
+            server.store.lazyfreed_objects(),
+        )
+    }
+
+    #[tokio::test]
+    async fn unlink_and_lazy_del_free_values_in_the_background() {
+        let mut server = server(RedisRole::Master);
+        let list: VecDeque<String> = (0..100_000).map(|n| n.to_string()).collect();
+        for key in ["a", "b", "c"] {
+            server.store.replace_list(key, list.clone()).await;
+        }
+
+        let unlink = RedisCommand::Unlink(vec!["a".to_string(), "missing".to_string()]);
+        let reply = server.handle_shared_command(unlink).await.unwrap();
+        assert_eq!(reply, RespValue::Integer(1));
+        assert_eq!(lazyfree_counters(&server).await, (0, 1));
+
+        let del = RedisCommand::Del(vec!["b".to_string()]);
+        let reply = server.handle_shared_command(del).await.unwrap();
+        assert_eq!(reply, RespValue::Integer(1));
+        assert_eq!(lazyfree_counters(&server).await, (0, 1));
+
+        server.config.set("lazyfree-lazy-user-del", "yes").unwrap();
+        let del = RedisCommand::Del(vec!["c".to_string()]);
+        server.handle_shared_command(del).await.unwrap();
+        assert_eq!(lazyfree_counters(&server).await, (0, 2));
+
+        let info = text(server.info(vec!["stats".to_string()]).await);
+        assert_eq!(info, "# Stats\r\nlazyfreed_objects:2\r\n");
+    }
+
+    #[tokio::test]
+    async fn set_replicates_the_jittered_deadline_it_stores() {
+        let mut server = server(RedisRole::Master);
+        server.config.set("active-expire-jitter", "1000").unwrap();
+        let raw = ["PSETEX", "key", "60000", "value"].map(Bytes::from);
+        let mut command = RedisCommandParser::parse_argv(&raw).unwrap();
+        server.resolve_command(&mut command).await;
+        let payload = command.replication_payload(&raw);
+        server.handle_shared_command(command).await.unwrap();
+
+        let stored = match server.expire_time("key", 1).await {
+            RespValue::Integer(stored) => stored,
+            reply => panic!("expected an expiry, got {:?}", reply),
+        };
+        let pxat = format!(
+            "$4\r\nPXAT\r\n${}\r\n{}\r\n",
+            stored.to_string().len(),
+            stored
+        );
+        assert!(payload.ends_with(pxat.as_bytes()), "{:?}", payload);
+    }
+
+    #[tokio::test]
+    async fn xadd_replicates_the_id_it_generates() {
+        let mut server = server(RedisRole::Master);
+        for raw in [
+            &["XADD", "s", "*", "field", "a value"][..],
+            &["XADD", "s", "MAXLEN", "~", "10", "*", "f", "v"],
+            &["XADD", "missing", "NOMKSTREAM", "*", "f", "v"],
+        ] {
+            let raw: Vec<Bytes> = raw.iter().map(|arg| Bytes::from(*arg)).collect();
+            let mut command = RedisCommandParser::parse_argv(&raw).unwrap();
+            server.resolve_command(&mut command).await;
+            let payload = command.replication_payload(&raw);
+            let reply = server.handle_shared_command(command.clone()).await.unwrap();
+
+            let mut decoder = RespDecoder::default();
+            decoder.feed(&payload);
+            let argv = decoder.next_request(&Default::default()).unwrap().unwrap();
+            let replicated = RedisCommandParser::parse_argv(&argv).unwrap();
+            assert_eq!(replicated, command);
+            match (replicated, reply) {
+                (
+                    RedisCommand::XAdd(_, _, XAddId::Explicit(id), _),
+                    RespValue::BulkString(reply),
+                ) => {
+                    assert_eq!(reply, id.to_string().into_bytes())
+                }
+                (RedisCommand::XAdd(_, _, XAddId::Explicit(_), _), RespValue::Null) => {}
+                (replicated, reply) => panic!("{:?} replied {:?}", replicated, reply),
+            }
+        }
+    }
+
+    #[test]
+    fn acl_whoami_is_the_default_user() {
+        assert_eq!(text(BaseServer::acl(AclCommand::WhoAmI)), "default");
+    }
+
+    #[test]
+    fn acl_cat_lists_the_commands_in_a_category() {
+        let reply = BaseServer::acl(AclCommand::Cat(Some("hyperloglog".to_string())));
+        let commands = ["pfadd", "pfcount", "pfmerge"]
+            .map(RespValue::bulk)
+            .to_vec();
+        assert_eq!(reply, RespValue::Array(commands));
+        let reply = BaseServer::acl(AclCommand::Cat(Some("nosuchcategory".to_string())));
+        assert_eq!(reply, RespValue::Array(vec![]));
+    }
+
+    #[tokio::test]
+    async fn only_replicas_report_slave_read_only() {
+        let replication = vec!["replication".to_string()];
+        let mut replica = server(RedisRole::Slave);
+        let info = text(replica.info(replication.clone()).await);
+        assert!(info.contains("slave_read_only:1\r\n"), "{}", info);
+        assert!(
+            info.contains("master_failover_state:no-failover\r\n"),
+            "{}",
+            info
+        );
+        replica.config.set("replica-read-only", "no").unwrap();
+        let info = text(replica.info(replication.clone()).await);
+        assert!(info.contains("slave_read_only:0\r\n"), "{}", info);
+
+        let info = text(server(RedisRole::Master).info(replication).await);
+        assert!(!info.contains("slave_read_only"), "{}", info);
+        assert!(
+            info.contains("master_failover_state:no-failover\r\n"),
+            "{}",
+            info
+        );
+    }
 }
@@ -0,0 +1,297 @@
+use crate::command::{BitFieldOperation, BitFieldOverflow, BitFieldType, BitOperation, BitUnit};
+use crate::resp::RespValue;
+
+use super::base::{error_reply, BaseServer};
+
+/// Resolves an inclusive range of BITCOUNT or BITPOS, which may count from the end when
+/// negative, against a value of `len` units. Returns None if the range is empty.
+pub(super) fn resolve_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+    (len > 0 && start <= end).then_some((start as usize, end as usize))
+}
+
+/// Returns the bit at `offset`, counting from the most significant bit of the first byte.
+pub(super) fn bit(bytes: &[u8], offset: usize) -> bool {
+    bytes
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets the bit at `offset`, which must be within `bytes`.
+fn set_bit(bytes: &mut [u8], offset: usize, value: bool) {
+    let mask = 0x80 >> (offset % 8);
+    if value {
+        bytes[offset / 8] |= mask;
+    } else {
+        bytes[offset / 8] &= !mask;
+    }
+}
+
+/// Counts the set bits in an inclusive range of bytes or bits.
+fn count_bits(bytes: &[u8], range: Option<(i64, i64, BitUnit)>) -> usize {
+    match range {
+        None => bytes.iter().map(|byte| byte.count_ones() as usize).sum(),
+        Some((start, end, BitUnit::Byte)) => match resolve_range(start, end, bytes.len()) {
+            Some((start, end)) => count_bits(&bytes[start..=end], None),
+            None => 0,
+        },
+        Some((start, end, BitUnit::Bit)) => match resolve_range(start, end, bytes.len() * 8) {
+            Some((start, end)) => (start..=end).filter(|&offset| bit(bytes, offset)).count(),
+            None => 0,
+        },
+    }
+}
+
+/// Applies a BITOP operation byte by byte. Shorter strings are padded with zero bytes to
+/// the length of the longest.
+fn combine(operation: BitOperation, strings: &[&[u8]]) -> Vec<u8> {
+    let len = strings.iter().map(|bytes| bytes.len()).max().unwrap_or(0);
+    (0..len)
+        .map(|index| {
+            let mut bytes = strings
+                .iter()
+                .map(|bytes| bytes.get(index).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            match operation {
+                BitOperation::And => bytes.fold(first, |result, byte| result & byte),
+                BitOperation::Or => bytes.fold(first, |result, byte| result | byte),
+                BitOperation::Xor => bytes.fold(first, |result, byte| result ^ byte),
+                BitOperation::Not => !first,
+            }
+        })
+        .collect()
+}
+
+/// Finds the first bit set to `target` in a range of bytes or bits. Without an end, the
+/// value counts as padded with zero bits, so a clear bit is found past its end.
+fn bit_position(
+    bytes: &[u8],
+    target: bool,
+    start: Option<i64>,
+    end: Option<i64>,
+    unit: BitUnit,
+) -> i64 {
+    let len = match unit {
+        BitUnit::Byte => bytes.len(),
+        BitUnit::Bit => bytes.len() * 8,
+    };
+    let Some((start, last)) = resolve_range(start.unwrap_or(0), end.unwrap_or(-1), len) else {
+        return -1;
+    };
+    let (start, last) = match unit {
+        BitUnit::Byte => (start * 8, last * 8 + 7),
+        BitUnit::Bit => (start, last),
+    };
+    match (start..=last).find(|&offset| bit(bytes, offset) == target) {
+        Some(offset) => offset as i64,
+        None if !target && end.is_none() => last as i64 + 1,
+        None => -1,
+    }
+}
+
+/// Reads a BITFIELD field at a bit offset, with bits past the end of the value as zeros.
+fn read_field(bytes: &[u8], offset: usize, field: BitFieldType) -> i64 {
+    let bits = field.bits as u32;
+    let raw = (0..bits as usize).fold(0u64, |raw, index| {
+        raw << 1 | bit(bytes, offset + index) as u64
+    });
+    if field.signed && bits < 64 && raw >> (bits - 1) == 1 {
+        // Sign extend
+        (raw | u64::MAX << bits) as i64
+    } else {
+        raw as i64
+    }
+}
+
+/// Writes the low bits of `value` to a BITFIELD field, which must be within `bytes`.
+fn write_field(bytes: &mut [u8], offset: usize, field: BitFieldType, value: i64) {
+    let bits = field.bits as usize;
+    for index in 0..bits {
+        set_bit(
+            bytes,
+            offset + index,
+            (value as u64) >> (bits - 1 - index) & 1 == 1,
+        );
+    }
+}
+
+/// Fits a value into a BITFIELD field as `overflow` says. Returns None if it doesn't fit
+/// and overflows FAIL.
+fn fit_field(value: i128, field: BitFieldType, overflow: BitFieldOverflow) -> Option<i64> {
+    let bits = field.bits as u32;
+    let (min, max) = if field.signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    };
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        BitFieldOverflow::Fail => None,
+        BitFieldOverflow::Sat => Some(value.clamp(min, max) as i64),
+        BitFieldOverflow::Wrap => {
+            let wrapped = value.rem_euclid(1i128 << bits);
+            Some(if wrapped > max {
+                wrapped - (1i128 << bits)
+            } else {
+                wrapped
+            } as i64)
+        }
+    }
+}
+
+/// Runs BITFIELD operations on a value, first growing it to hold every field written.
+/// Returns the result of each operation but OVERFLOW, None for those that failed.
+fn run_bitfield(bytes: &mut Vec<u8>, operations: &[BitFieldOperation]) -> Vec<Option<i64>> {
+    let len = operations
+        .iter()
+        .filter_map(|operation| match operation {
+            BitFieldOperation::Set(field, offset, _)
+            | BitFieldOperation::IncrBy(field, offset, _) => {
+                Some((*offset as usize + field.bits as usize).div_ceil(8))
+            }
+            _ => None,
+        })
+        .max();
+    if let Some(len) = len.filter(|&len| len > bytes.len()) {
+        bytes.resize(len, 0);
+    }
+    let mut overflow = BitFieldOverflow::default();
+    let mut results = Vec::new();
+    for operation in operations {
+        match *operation {
+            BitFieldOperation::Overflow(next) => overflow = next,
+            BitFieldOperation::Get(field, offset) => {
+                results.push(Some(read_field(bytes, offset as usize, field)));
+            }
+            BitFieldOperation::Set(field, offset, value) => {
+                let previous = read_field(bytes, offset as usize, field);
+                let value = fit_field(value as i128, field, overflow);
+                if let Some(value) = value {
+                    write_field(bytes, offset as usize, field, value);
+                }
+                results.push(value.map(|_| previous));
+            }
+            BitFieldOperation::IncrBy(field, offset, increment) => {
+                let current = read_field(bytes, offset as usize, field);
+                let value = fit_field(current as i128 + increment as i128, field, overflow);
+                if let Some(value) = value {
+                    write_field(bytes, offset as usize, field, value);
+                }
+                results.push(value);
+            }
+        }
+    }
+    results
+}
+
+impl BaseServer {
+    /// Handles SETBIT, growing the value with zero bytes as needed and replying with the
+    /// bit's previous value.
+    pub(super) async fn setbit(&self, key: &str, offset: u64, value: bool) -> RespValue {
+        let offset = offset as usize;
+        let previous = self
+            .store
+            .mutate(key, |bytes| {
+                if bytes.len() <= offset / 8 {
+                    bytes.resize(offset / 8 + 1, 0);
+                }
+                let previous = bit(bytes, offset);
+                set_bit(bytes, offset, value);
+                previous
+            })
+            .await;
+        match previous {
+            Ok(previous) => RespValue::Integer(previous as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn getbit(&self, key: &str, offset: u64) -> RespValue {
+        match self.store.get_bytes(key).await {
+            Ok(bytes) => {
+                let bit = bytes.is_some_and(|bytes| bit(&bytes, offset as usize));
+                RespValue::Integer(bit as i64)
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn bitcount(
+        &self,
+        key: &str,
+        range: Option<(i64, i64, BitUnit)>,
+    ) -> RespValue {
+        match self.store.get_bytes(key).await {
+            Ok(bytes) => {
+                let count = bytes.map_or(0, |bytes| count_bits(&bytes, range));
+                RespValue::Integer(count as i64)
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn bitop(
+        &self,
+        operation: BitOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> RespValue {
+        let stored = self
+            .store
+            .store_combined_strings(destination, keys, |strings| Ok(combine(operation, strings)))
+            .await;
+        match stored {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles BITPOS. A missing key counts as an empty string padded with zero bits.
+    pub(super) async fn bitpos(
+        &self,
+        key: &str,
+        bit: bool,
+        start: Option<i64>,
+        end: Option<i64>,
+        unit: BitUnit,
+    ) -> RespValue {
+        match self.store.get_bytes(key).await {
+            Ok(Some(bytes)) => RespValue::Integer(bit_position(&bytes, bit, start, end, unit)),
+            Ok(None) => RespValue::Integer(if bit { -1 } else { 0 }),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles BITFIELD. Only GET operations leave a missing key missing.
+    pub(super) async fn bitfield(&self, key: &str, operations: &[BitFieldOperation]) -> RespValue {
+        let writes = operations.iter().any(|operation| {
+            matches!(
+                operation,
+                BitFieldOperation::Set(..) | BitFieldOperation::IncrBy(..)
+            )
+        });
+        let results = if writes {
+            self.store
+                .mutate(key, |bytes| run_bitfield(bytes, operations))
+                .await
+        } else {
+            self.store
+                .get_bytes(key)
+                .await
+                .map(|bytes| run_bitfield(&mut bytes.unwrap_or_default(), operations))
+        };
+        match results {
+            Ok(results) => RespValue::Array(
+                results
+                    .into_iter()
+                    .map(|result| result.map_or(RespValue::Null, RespValue::Integer))
+                    .collect(),
+            ),
+            Err(e) => error_reply(e),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::resp::RespValue;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use crate::command::RedisCommand;
+
+use super::{base::BaseServer, slave::READONLY_ERROR, types::RedisRole};
+
+/// The outcome of running a blocking command once its keys are ready.
+#[derive(Debug)]
+pub struct Served {
+    pub response: RespValue,
+    /// The non-blocking command that actually ran, e.g. LPOP for BLPOP. This is what
+    /// gets replicated in place of the blocking command. None if the command failed.
+    pub executed: Option<RedisCommand>,
+}
+
+/// A client blocked until one of its keys can serve its command.
+#[derive(Debug)]
+struct Waiter {
+    command: RedisCommand,
+    keys: Vec<String>,
+    reply: oneshot::Sender<Served>,
+}
+
+#[derive(Debug, Default)]
+struct BlockedState {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    /// Waiter ids per key, in the order the clients blocked.
+    keys: HashMap<String, VecDeque<u64>>,
+}
+
+/// Registry of clients blocked on keys by commands such as BLPOP and BZPOPMIN.
+///
+/// Clients are served in the order they blocked: after every write the server checks the
+/// keys clients are waiting on and runs the command of the longest waiting client first.
+#[derive(Debug, Clone, Default)]
+pub struct BlockedClients {
+    state: Arc<Mutex<BlockedState>>,
+}
+
+/// A client registered as blocked, waiting for its reply.
+#[derive(Debug)]
+pub struct BlockedClient {
+    id: u64,
+    receiver: oneshot::Receiver<Served>,
+    timeout: Option<Duration>,
+    timeout_reply: RespValue,
+}
+
+impl BlockedClients {
+    /// Registers a client as blocked on `keys` until `command` can be served or `timeout`
+    /// seconds pass, with 0 meaning forever.
+    pub fn block(
+        &self,
+        command: RedisCommand,
+        keys: Vec<String>,
+        timeout: f64,
+        timeout_reply: RespValue,
+    ) -> BlockedClient {
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .ok()
+            .filter(|timeout| !timeout.is_zero());
+        let (reply, receiver) = oneshot::channel();
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        for key in &keys {
+            state.keys.entry(key.clone()).or_default().push_back(id);
+        }
+        state.waiters.insert(
+            id,
+            Waiter {
+                command,
+                keys,
+                reply,
+            },
+        );
+        BlockedClient {
+            id,
+            receiver,
+            timeout,
+            timeout_reply,
+        }
+    }
+
+    /// Locks the registry. Its state is consistent between every statement that changes
+    /// it, so a panic while it was held doesn't leave it poisoned for every other client.
+    fn state(&self) -> MutexGuard<'_, BlockedState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Removes a waiter, returning it if it was still blocked.
+    fn unblock(&self, id: u64) -> Option<Waiter> {
+        let mut state = self.state();
+        let waiter = state.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(ids) = state.keys.get_mut(key) {
+                ids.retain(|&waiting| waiting != id);
+                if ids.is_empty() {
+                    state.keys.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+
+    /// Returns the keys clients are blocked on.
+    pub fn keys(&self) -> Vec<String> {
+        self.state().keys.keys().cloned().collect()
+    }
+
+    /// Returns the number of blocked clients.
+    pub fn len(&self) -> usize {
+        self.state().waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the id and command of the longest waiting client blocked on `key`,
+    /// dropping any waiters whose connection has gone away.
+    pub fn first(&self, key: &str) -> Option<(u64, RedisCommand)> {
+        loop {
+            let state = self.state();
+            let id = *state.keys.get(key)?.front()?;
+            let waiter = &state.waiters[&id];
+            if !waiter.reply.is_closed() {
+                return Some((id, waiter.command.clone()));
+            }
+            drop(state);
+            self.unblock(id);
+        }
+    }
+
+    /// Unblocks a client with the outcome of its command.
+    pub fn serve(&self, id: u64, served: Served) {
+        if let Some(waiter) = self.unblock(id) {
+            let _ = waiter.reply.send(served);
+        }
+    }
+}
+
+impl BlockedClient {
+    /// Waits until the client is served or its timeout passes.
+    pub async fn wait(&mut self, blocked: &BlockedClients) -> Served {
+        let served = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut self.receiver)
+                .await
+                .ok()
+                .and_then(Result::ok),
+            None => (&mut self.receiver).await.ok(),
+        };
+        if let Some(served) = served {
+            return served;
+        }
+        // The client may have been served between the timeout firing and unblocking it
+        if blocked.unblock(self.id).is_none() {
+            if let Ok(served) = self.receiver.try_recv() {
+                return served;
+            }
+        }
+        Served {
+            response: self.timeout_reply.clone(),
+            executed: None,
+        }
+    }
+
+    /// Unblocks the client without serving it, e.g. because its connection closed.
+    pub fn cancel(self, blocked: &BlockedClients) {
+        blocked.unblock(self.id);
+    }
+}
+
+impl BaseServer {
+    /// Runs a blocking command. If it can be served straight away the outcome is returned,
+    /// otherwise the client is registered as blocked and must wait for its reply.
+    pub async fn block(&self, command: RedisCommand) -> Result<Served, BlockedClient> {
+        if self.info.role == RedisRole::Slave && self.config.replica_read_only {
+            return Ok(Served {
+                response: RespValue::Error(READONLY_ERROR.to_string()),
+                executed: None,
+            });
+        }
+        if let Some(served) = self.try_serve(&command).await {
+            return Ok(served);
+        }
+        let (keys, timeout, timeout_reply) = match &command {
+            RedisCommand::BPop(keys, _, timeout) => (keys.clone(), *timeout, RespValue::NullArray),
+            RedisCommand::BLMPop(keys, _, _, timeout) => {
+                (keys.clone(), *timeout, RespValue::NullArray)
+            }
+            RedisCommand::BLMove(source, _, _, _, timeout) => {
+                (vec![source.clone()], *timeout, RespValue::Null)
+            }
+            RedisCommand::BZPop(keys, _, timeout) => (keys.clone(), *timeout, RespValue::NullArray),
+            RedisCommand::XReadGroup(_, _, options, streams) => (
+                streams.iter().map(|(key, _)| key.clone()).collect(),
+                options.block.unwrap_or(0) as f64 / 1000.0,
+                RespValue::NullArray,
+            ),
+            _ => unreachable!("try_serve serves every non-blocking command"),
+        };
+        Err(self.blocked.block(command, keys, timeout, timeout_reply))
+    }
+
+    /// Runs a blocking command if one of its keys is ready, returning None if it would block.
+    async fn try_serve(&self, command: &RedisCommand) -> Option<Served> {
+        match command {
+            RedisCommand::BPop(keys, end, _) => self.bpop(keys, *end).await,
+            RedisCommand::BLMPop(keys, end, count, _) => self.mpop(keys, *end, *count).await,
+            RedisCommand::BLMove(source, destination, from, to, _) => {
+                self.blmove(source, destination, *from, *to).await
+            }
+            RedisCommand::BZPop(keys, end, _) => self.bzpop(keys, *end).await,
+            RedisCommand::XReadGroup(group, consumer, options, streams) => {
+                self.blocking_xreadgroup(group, consumer, options, streams)
+                    .await
+            }
+            command => Some(Served {
+                response: RespValue::Error(format!("ERR {} is not a blocking command", command)),
+                executed: None,
+            }),
+        }
+    }
+
+    /// Serves clients blocked on keys that have become ready, longest waiting first.
+    /// Called after every write, as any write may have filled a key clients wait on.
+    /// Serving a client can fill another key in turn (BLMOVE pushes to its destination),
+    /// so this keeps going until no more clients can be served.
+    pub async fn serve_blocked_clients(&self) {
+        let mut served_any = true;
+        while served_any && !self.blocked.is_empty() {
+            served_any = false;
+            for key in self.blocked.keys() {
+                while let Some((id, command)) = self.blocked.first(&key) {
+                    // A command that fails, e.g. BLMOVE onto a key of the wrong type, is
+                    // answered with its error so it doesn't hold up the clients behind it
+                    match self.try_serve(&command).await {
+                        Some(served) => {
+                            served_any |= served.executed.is_some();
+                            self.blocked.serve(id, served);
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+}
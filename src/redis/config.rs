@@ -0,0 +1,273 @@
+use anyhow::Context;
+use rand::Rng;
+
+use crate::{
+    parser::{
+        ProtocolLimits, DEFAULT_PROTO_INLINE_MAX_SIZE, DEFAULT_PROTO_MAX_BULK_LEN,
+        DEFAULT_PROTO_MAX_MULTIBULK_LEN,
+    },
+    resp::DEFAULT_REPLY_CHUNK_SIZE,
+    utils::glob_match,
+};
+
+use super::store::ListpackLimits;
+
+/// Names of all parameters known to CONFIG GET/SET.
+const PARAMETERS: &[&str] = &[
+    "active-expire-jitter",
+    "enable-debug-internals",
+    "lazyfree-lazy-user-del",
+    "maxmemory",
+    "maxmemory-policy",
+    "proto-inline-max-size",
+    "proto-max-bulk-len",
+    "proto-max-multibulk-len",
+    "replica-read-only",
+    "reply-chunk-size",
+    "zset-max-listpack-entries",
+    "zset-max-listpack-value",
+];
+
+/// Eviction policies accepted by `maxmemory-policy`.
+const MAXMEMORY_POLICIES: &[&str] = &[
+    "noeviction",
+    "allkeys-lru",
+    "allkeys-lfu",
+    "allkeys-random",
+    "volatile-lru",
+    "volatile-lfu",
+    "volatile-random",
+    "volatile-ttl",
+];
+
+/// The latest millisecond timestamp an expiry can hold, which also bounds
+/// `active-expire-jitter`.
+const LATEST_EXPIRY: u64 = i64::MAX as u64;
+
+/// Runtime configuration parameters, readable and writable through CONFIG GET/SET.
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    /// Upper bound in milliseconds of the random jitter added to key expiries.
+    pub active_expire_jitter: u64,
+    /// Whether DEBUG INTERNALS may be used to inspect runtime state.
+    pub enable_debug_internals: bool,
+    /// Whether DEL frees the values it removes in the background, as UNLINK does.
+    pub lazyfree_lazy_user_del: bool,
+    /// Memory limit in bytes. Stored for client compatibility, eviction is not implemented.
+    pub maxmemory: u64,
+    /// Eviction policy used once `maxmemory` is reached.
+    pub maxmemory_policy: String,
+    /// Maximum length of an inline request, or of a multibulk request's count lines.
+    pub proto_inline_max_size: usize,
+    /// Maximum length of a bulk string accepted in a request.
+    pub proto_max_bulk_len: usize,
+    /// Maximum number of elements accepted in a multibulk request.
+    pub proto_max_multibulk_len: usize,
+    /// Whether replicas reject write commands from clients.
+    pub replica_read_only: bool,
+    /// Size in bytes of the segments large replies are written to clients in.
+    pub reply_chunk_size: usize,
+    /// Most members a sorted set may have and still be encoded as a listpack.
+    pub zset_max_listpack_entries: usize,
+    /// Longest member a sorted set may hold and still be encoded as a listpack.
+    pub zset_max_listpack_value: usize,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            active_expire_jitter: 0,
+            enable_debug_internals: false,
+            lazyfree_lazy_user_del: false,
+            maxmemory: 0,
+            maxmemory_policy: "noeviction".to_string(),
+            proto_inline_max_size: DEFAULT_PROTO_INLINE_MAX_SIZE,
+            proto_max_bulk_len: DEFAULT_PROTO_MAX_BULK_LEN,
+            proto_max_multibulk_len: DEFAULT_PROTO_MAX_MULTIBULK_LEN,
+            replica_read_only: true,
+            reply_chunk_size: DEFAULT_REPLY_CHUNK_SIZE,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+        }
+    }
+}
+
+impl RedisConfig {
+    /// Returns the value of a configuration parameter, if it exists.
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name.to_lowercase().as_str() {
+            "active-expire-jitter" => Some(self.active_expire_jitter.to_string()),
+            "enable-debug-internals" => Some(yes_no(self.enable_debug_internals).to_string()),
+            "lazyfree-lazy-user-del" => Some(yes_no(self.lazyfree_lazy_user_del).to_string()),
+            "maxmemory" => Some(self.maxmemory.to_string()),
+            "maxmemory-policy" => Some(self.maxmemory_policy.clone()),
+            "proto-inline-max-size" => Some(self.proto_inline_max_size.to_string()),
+            "proto-max-bulk-len" => Some(self.proto_max_bulk_len.to_string()),
+            "proto-max-multibulk-len" => Some(self.proto_max_multibulk_len.to_string()),
+            "replica-read-only" => Some(yes_no(self.replica_read_only).to_string()),
+            "reply-chunk-size" => Some(self.reply_chunk_size.to_string()),
+            "zset-max-listpack-entries" => Some(self.zset_max_listpack_entries.to_string()),
+            "zset-max-listpack-value" => Some(self.zset_max_listpack_value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns the name/value pairs of all parameters matching a glob pattern.
+    pub fn get_matching(&self, pattern: &str) -> Vec<(String, String)> {
+        let pattern = pattern.to_lowercase();
+        PARAMETERS
+            .iter()
+            .filter(|name| glob_match(&pattern, name))
+            .filter_map(|name| Some((name.to_string(), self.get(name)?)))
+            .collect()
+    }
+
+    /// Sets the value of a configuration parameter.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), anyhow::Error> {
+        match name.to_lowercase().as_str() {
+            "active-expire-jitter" => {
+                let jitter = value
+                    .parse::<u64>()
+                    .context("argument couldn't be parsed into an integer")?;
+                if jitter > LATEST_EXPIRY {
+                    anyhow::bail!("argument must be between 0 and {}", LATEST_EXPIRY);
+                }
+                self.active_expire_jitter = jitter;
+            }
+            "enable-debug-internals" => {
+                self.enable_debug_internals = parse_yes_no(value)?;
+            }
+            "lazyfree-lazy-user-del" => {
+                self.lazyfree_lazy_user_del = parse_yes_no(value)?;
+            }
+            "maxmemory" => {
+                self.maxmemory = value
+                    .parse::<u64>()
+                    .context("argument couldn't be parsed into an integer")?;
+            }
+            "maxmemory-policy" => {
+                let policy = value.to_lowercase();
+                if !MAXMEMORY_POLICIES.contains(&policy.as_str()) {
+                    anyhow::bail!(
+                        "argument(s) must be one of the following: {}",
+                        MAXMEMORY_POLICIES.join(", ")
+                    );
+                }
+                self.maxmemory_policy = policy;
+            }
+            "proto-inline-max-size" => {
+                self.proto_inline_max_size = parse_limit(value)?;
+            }
+            "proto-max-bulk-len" => {
+                self.proto_max_bulk_len = parse_limit(value)?;
+            }
+            "proto-max-multibulk-len" => {
+                self.proto_max_multibulk_len = parse_limit(value)?;
+            }
+            "replica-read-only" => {
+                self.replica_read_only = parse_yes_no(value)?;
+            }
+            "reply-chunk-size" => {
+                self.reply_chunk_size = parse_limit(value)?;
+            }
+            "zset-max-listpack-entries" => {
+                self.zset_max_listpack_entries = value
+                    .parse::<usize>()
+                    .context("argument couldn't be parsed into an integer")?;
+            }
+            "zset-max-listpack-value" => {
+                self.zset_max_listpack_value = value
+                    .parse::<usize>()
+                    .context("argument couldn't be parsed into an integer")?;
+            }
+            _ => anyhow::bail!("Unknown option or number of arguments for CONFIG SET"),
+        }
+        Ok(())
+    }
+
+    /// Returns the limits the parser should enforce on incoming requests.
+    pub fn protocol_limits(&self) -> ProtocolLimits {
+        ProtocolLimits {
+            max_multibulk_len: self.proto_max_multibulk_len,
+            max_bulk_len: self.proto_max_bulk_len,
+            max_inline_len: self.proto_inline_max_size,
+        }
+    }
+
+    /// Returns the limits up to which sorted sets are encoded as listpacks.
+    pub fn zset_listpack_limits(&self) -> ListpackLimits {
+        ListpackLimits {
+            max_entries: self.zset_max_listpack_entries,
+            max_value: self.zset_max_listpack_value,
+        }
+    }
+
+    /// Spreads an expiry timestamp by a random amount up to `active-expire-jitter`,
+    /// so keys set with the same TTL don't all expire in the same millisecond.
+    pub fn apply_expire_jitter(&self, expiry: Option<u64>) -> Option<u64> {
+        match expiry {
+            Some(expiry) if self.active_expire_jitter > 0 => {
+                let jitter = rand::thread_rng().gen_range(0..=self.active_expire_jitter);
+                Some(expiry.saturating_add(jitter).min(LATEST_EXPIRY.max(expiry)))
+            }
+            expiry => expiry,
+        }
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, anyhow::Error> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => anyhow::bail!("argument must be 'yes' or 'no'"),
+    }
+}
+
+/// Parses a size limit, which must be a positive integer.
+fn parse_limit(value: &str) -> Result<usize, anyhow::Error> {
+    let limit = value
+        .parse::<usize>()
+        .context("argument couldn't be parsed into an integer")?;
+    if limit == 0 {
+        anyhow::bail!("argument must be greater than 0");
+    }
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expire_jitter_is_bounded_and_never_overflows() {
+        let mut config = RedisConfig::default();
+        assert!(config
+            .set("active-expire-jitter", "18446744073709551615")
+            .is_err());
+        config
+            .set("active-expire-jitter", &LATEST_EXPIRY.to_string())
+            .unwrap();
+        let expiry = 9223372036854775000;
+        assert!(config.apply_expire_jitter(Some(expiry)).unwrap() <= LATEST_EXPIRY);
+    }
+
+    #[test]
+    fn get_matching_returns_every_matching_parameter() {
+        let config = RedisConfig::default();
+        let names: Vec<String> = config
+            .get_matching("max*")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["maxmemory", "maxmemory-policy"]);
+        assert!(config.get_matching("nothing*").is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+/// Version of the payload format, bumped whenever the layout changes.
+const DUMP_VERSION: u16 = 5;
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::command::{StreamId, XAddId};
+
+use super::store::{Consumer, Hash, PendingEntry, RedisValue, SortedSet, Stream};
+
+/// Type byte for string values.
+const STRING_TYPE: u8 = 0;
+
+/// Type byte for list values, whose elements are each prefixed with their length.
+const LIST_TYPE: u8 = 1;
+
+/// Type byte for hash values. Each field is stored as its length prefixed name and value
+/// followed by its expiry in unix milliseconds, 0 if it has none.
+const HASH_TYPE: u8 = 2;
+
+/// Type byte for set values, whose members are each prefixed with their length.
+const SET_TYPE: u8 = 3;
+
+/// Type byte for sorted set values. Each member is stored as its length prefixed name
+/// followed by its score as a little endian f64.
+const SORTED_SET_TYPE: u8 = 4;
+
+/// Type byte for stream values: the last ID given out, the number of entries, then each
+/// entry as its ID, its number of fields and its length prefixed fields and values.
+/// The consumer groups follow, each as its length prefixed name, its last delivered ID,
+/// its pending entries and its consumers, both prefixed with their number. A pending entry
+/// is its ID, its length prefixed consumer, its delivery time and its delivery count, and
+/// a consumer is its length prefixed name, its seen time and its active time, 0 if none.
+/// IDs are stored as their millisecond time and sequence number, and times in unix
+/// milliseconds, all as little endian u64s. Counts are little endian u32s.
+const STREAM_TYPE: u8 = 5;
+
+/// Length of the version and checksum trailer in bytes.
+const TRAILER_LEN: usize = 2 + 8;
+
+/// Serializes a value into a DUMP payload: a type byte followed by the value,
+/// the format version and a CRC64 of everything before it, hex encoded so the payload
+/// survives commands being replicated in their text form.
+pub fn serialize(value: &RedisValue) -> String {
+    let mut bytes = Vec::new();
+    match value {
+        RedisValue::String(value) => {
+            bytes.push(STRING_TYPE);
+            bytes.extend_from_slice(value);
+        }
+        RedisValue::List(list) => {
+            bytes.push(LIST_TYPE);
+            for element in list {
+                write_chunk(&mut bytes, element.as_bytes());
+            }
+        }
+        RedisValue::Hash(hash) => {
+            bytes.push(HASH_TYPE);
+            for (field, value) in hash.iter() {
+                write_chunk(&mut bytes, field.as_bytes());
+                write_chunk(&mut bytes, value.as_bytes());
+                let expiry = hash.expiry(field).flatten().unwrap_or(0);
+                bytes.extend_from_slice(&expiry.to_le_bytes());
+            }
+        }
+        RedisValue::Set(set) => {
+            bytes.push(SET_TYPE);
+            for member in set {
+                write_chunk(&mut bytes, member.as_bytes());
+            }
+        }
+        RedisValue::SortedSet(set) => {
+            bytes.push(SORTED_SET_TYPE);
+            for (member, score) in set.iter() {
+                write_chunk(&mut bytes, member.as_bytes());
+                bytes.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        RedisValue::Stream(stream) => {
+            bytes.push(STREAM_TYPE);
+            write_stream_id(&mut bytes, stream.last_id());
+            bytes.extend_from_slice(&stream.entries_added().to_le_bytes());
+            write_stream_id(&mut bytes, stream.max_deleted_id());
+            bytes.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+            for (id, fields) in stream.range(StreamId::MIN, StreamId::MAX) {
+                write_stream_id(&mut bytes, *id);
+                bytes.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+                for (field, value) in fields {
+                    write_chunk(&mut bytes, field.as_bytes());
+                    write_chunk(&mut bytes, value.as_bytes());
+                }
+            }
+            for (name, group) in stream.groups() {
+                write_chunk(&mut bytes, name.as_bytes());
+                write_stream_id(&mut bytes, group.last_delivered);
+                let entries_read = group.entries_read.unwrap_or(u64::MAX);
+                bytes.extend_from_slice(&entries_read.to_le_bytes());
+                bytes.extend_from_slice(&(group.pending.len() as u32).to_le_bytes());
+                for (id, entry) in &group.pending {
+                    write_stream_id(&mut bytes, *id);
+                    write_chunk(&mut bytes, entry.consumer.as_bytes());
+                    bytes.extend_from_slice(&entry.delivered.to_le_bytes());
+                    bytes.extend_from_slice(&entry.deliveries.to_le_bytes());
+                }
+                bytes.extend_from_slice(&(group.consumers.len() as u32).to_le_bytes());
+                for (name, consumer) in &group.consumers {
+                    write_chunk(&mut bytes, name.as_bytes());
+                    bytes.extend_from_slice(&consumer.seen.to_le_bytes());
+                    bytes.extend_from_slice(&consumer.active.unwrap_or(0).to_le_bytes());
+                }
+            }
+        }
+    }
+    bytes.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let checksum = crc64(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Deserializes a DUMP payload back into a value, checking its version and checksum.
+pub fn deserialize(payload: &str) -> Result<RedisValue, anyhow::Error> {
+    let bytes = decode_hex(payload)
+        .filter(|bytes| bytes.len() > TRAILER_LEN)
+        .ok_or_else(|| anyhow::anyhow!("DUMP payload version or checksum are wrong"))?;
+
+    let (body, checksum) = bytes.split_at(bytes.len() - 8);
+    let (data, version) = body.split_at(body.len() - 2);
+    let checksum = u64::from_le_bytes(checksum.try_into()?);
+    let version = u16::from_le_bytes(version.try_into()?);
+    if version != DUMP_VERSION || checksum != crc64(body) {
+        anyhow::bail!("DUMP payload version or checksum are wrong");
+    }
+
+    match data.split_first() {
+        Some((&STRING_TYPE, value)) => Ok(RedisValue::String(value.to_vec())),
+        Some((&LIST_TYPE, mut elements)) => {
+            let mut list = VecDeque::new();
+            while !elements.is_empty() {
+                let (element, rest) = read_chunk(elements)?;
+                list.push_back(decode_string(element)?);
+                elements = rest;
+            }
+            Ok(RedisValue::List(list))
+        }
+        Some((&HASH_TYPE, mut pairs)) => {
+            let mut hash = Hash::default();
+            while !pairs.is_empty() {
+                let (field, rest) = read_chunk(pairs)?;
+                let (value, rest) = read_chunk(rest)?;
+                let (expiry, rest) = rest
+                    .split_first_chunk::<8>()
+                    .ok_or_else(|| anyhow::anyhow!("Bad data format"))?;
+                let field = decode_string(field)?;
+                hash.insert(field.clone(), decode_string(value)?);
+                match u64::from_le_bytes(*expiry) {
+                    0 => {}
+                    expiry => hash.set_expiry(&field, expiry),
+                }
+                pairs = rest;
+            }
+            Ok(RedisValue::Hash(hash))
+        }
+        Some((&SET_TYPE, mut members)) => {
+            let mut set = HashSet::new();
+            while !members.is_empty() {
+                let (member, rest) = read_chunk(members)?;
+                set.insert(decode_string(member)?);
+                members = rest;
+            }
+            Ok(RedisValue::Set(set))
+        }
+        Some((&SORTED_SET_TYPE, mut members)) => {
+            let mut set = SortedSet::default();
+            while !members.is_empty() {
+                let (member, rest) = read_chunk(members)?;
+                let (score, rest) = rest
+                    .split_first_chunk::<8>()
+                    .ok_or_else(|| anyhow::anyhow!("Bad data format"))?;
+                let score = f64::from_le_bytes(*score);
+                if score.is_nan() {
+                    anyhow::bail!("Bad data format");
+                }
+                set.insert(decode_string(member)?, score);
+                members = rest;
+            }
+            Ok(RedisValue::SortedSet(set))
+        }
+        Some((&STREAM_TYPE, bytes)) => {
+            let mut stream = Stream::default();
+            let (last_id, bytes) = read_stream_id(bytes)?;
+            let (entries_added, bytes) = read_u64(bytes)?;
+            let (max_deleted_id, bytes) = read_stream_id(bytes)?;
+            let (len, mut bytes) = read_u32(bytes)?;
+            for _ in 0..len {
+                let (id, rest) = read_stream_id(bytes)?;
+                let (len, mut rest) = read_u32(rest)?;
+                let mut fields = Vec::new();
+                for _ in 0..len {
+                    let (field, after_field) = read_chunk(rest)?;
+                    let (value, after_value) = read_chunk(after_field)?;
+                    fields.push((decode_string(field)?, decode_string(value)?));
+                    rest = after_value;
+                }
+                stream
+                    .add(XAddId::Explicit(id), fields)
+                    .map_err(|_| anyhow::anyhow!("Bad data format"))?;
+                bytes = rest;
+            }
+            stream
+                .set_last_id(last_id, Some(entries_added), Some(max_deleted_id))
+                .map_err(|_| anyhow::anyhow!("Bad data format"))?;
+            while !bytes.is_empty() {
+                let (name, rest) = read_chunk(bytes)?;
+                let (last_delivered, rest) = read_stream_id(rest)?;
+                let name = decode_string(name)?;
+                if !stream.create_group(&name, last_delivered) {
+                    anyhow::bail!("Bad data format");
+                }
+                let group = stream
+                    .group_mut(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Bad data format"))?;
+                let (entries_read, rest) = read_u64(rest)?;
+                group.entries_read = (entries_read != u64::MAX).then_some(entries_read);
+                let (len, mut rest) = read_u32(rest)?;
+                for _ in 0..len {
+                    let (id, after_id) = read_stream_id(rest)?;
+                    let (consumer, after_consumer) = read_chunk(after_id)?;
+                    let (delivered, after_delivered) = read_u64(after_consumer)?;
+                    let (deliveries, after_deliveries) = read_u64(after_delivered)?;
+                    let entry = PendingEntry {
+                        consumer: decode_string(consumer)?,
+                        delivered,
+                        deliveries,
+                    };
+                    group.pending.insert(id, entry);
+                    rest = after_deliveries;
+                }
+                let (len, mut rest) = read_u32(rest)?;
+                for _ in 0..len {
+                    let (name, after_name) = read_chunk(rest)?;
+                    let (seen, after_seen) = read_u64(after_name)?;
+                    let (active, after_active) = read_u64(after_seen)?;
+                    let consumer = Consumer {
+                        seen,
+                        active: (active != 0).then_some(active),
+                    };
+                    group.consumers.insert(decode_string(name)?, consumer);
+                    rest = after_active;
+                }
+                bytes = rest;
+            }
+            Ok(RedisValue::Stream(stream))
+        }
+        _ => anyhow::bail!("Bad data format"),
+    }
+}
+
+fn write_stream_id(bytes: &mut Vec<u8>, id: StreamId) {
+    bytes.extend_from_slice(&id.ms.to_le_bytes());
+    bytes.extend_from_slice(&id.seq.to_le_bytes());
+}
+
+/// Reads a stream ID, returning it along with the remaining bytes.
+fn read_stream_id(bytes: &[u8]) -> Result<(StreamId, &[u8]), anyhow::Error> {
+    let (ms, rest) = read_u64(bytes)?;
+    let (seq, rest) = read_u64(rest)?;
+    Ok((StreamId { ms, seq }, rest))
+}
+
+fn read_u32(bytes: &[u8]) -> Result<(u32, &[u8]), anyhow::Error> {
+    let (value, rest) = bytes
+        .split_first_chunk::<4>()
+        .ok_or_else(|| anyhow::anyhow!("Bad data format"))?;
+    Ok((u32::from_le_bytes(*value), rest))
+}
+
+fn read_u64(bytes: &[u8]) -> Result<(u64, &[u8]), anyhow::Error> {
+    let (value, rest) = bytes
+        .split_first_chunk::<8>()
+        .ok_or_else(|| anyhow::anyhow!("Bad data format"))?;
+    Ok((u64::from_le_bytes(*value), rest))
+}
+
+/// Appends a chunk of bytes prefixed with its length.
+fn write_chunk(bytes: &mut Vec<u8>, chunk: &[u8]) {
+    bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(chunk);
+}
+
+/// Reads a length prefixed chunk, returning it along with the remaining bytes.
+fn read_chunk(bytes: &[u8]) -> Result<(&[u8], &[u8]), anyhow::Error> {
+    let bad_format = || anyhow::anyhow!("Bad data format");
+    let (len, rest) = bytes.split_at_checked(4).ok_or_else(bad_format)?;
+    let len = u32::from_le_bytes(len.try_into()?) as usize;
+    rest.split_at_checked(len).ok_or_else(bad_format)
+}
+
+fn decode_string(bytes: &[u8]) -> Result<String, anyhow::Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| anyhow::anyhow!("Bad data format"))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// CRC-64/Jones, the checksum Redis uses for its own DUMP payloads.
+fn crc64(bytes: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    let mut crc = 0u64;
+    for &byte in bytes {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
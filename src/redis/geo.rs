@@ -0,0 +1,342 @@
+use crate::command::{
+    GeoOrigin, GeoSearchOptions, GeoShape, GeoUnit, ZAddOptions, GEO_LATITUDE_RANGE,
+    GEO_LONGITUDE_RANGE,
+};
+use crate::resp::RespValue;
+
+use super::base::{error_reply, BaseServer};
+use super::store::SortedSet;
+
+/// The number of bits each of the longitude and the latitude take in a geohash
+const GEO_STEP: u32 = 26;
+/// The earth's radius in meters, the one Redis computes distances with
+const EARTH_RADIUS: f64 = 6372797.560856;
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+/// The latitudes standard geohash strings cover, wider than those geo commands accept.
+const GEOHASH_LATITUDE_RANGE: (f64, f64) = (-90.0, 90.0);
+
+/// Spreads the low bits of a value out to the even bits of the result.
+fn spread(value: u64) -> u64 {
+    (0..GEO_STEP).fold(0, |bits, index| bits | (value >> index & 1) << (2 * index))
+}
+
+/// Gathers the even bits of a value into the low bits of the result, undoing `spread`.
+fn squash(bits: u64) -> u64 {
+    (0..GEO_STEP).fold(0, |value, index| value | (bits >> (2 * index) & 1) << index)
+}
+
+/// Encodes a position into a 52 bit geohash, the latitude's bits on the even bits and the
+/// longitude's on the odd ones, within the given latitude range.
+fn encode(longitude: f64, latitude: f64, latitude_range: (f64, f64)) -> u64 {
+    let cells = 1u64 << GEO_STEP;
+    let cell = |value: f64, (min, max): (f64, f64)| {
+        ((value - min) / (max - min) * cells as f64).min((cells - 1) as f64) as u64
+    };
+    spread(cell(latitude, latitude_range)) | spread(cell(longitude, GEO_LONGITUDE_RANGE)) << 1
+}
+
+/// Decodes a geohash score into the longitude and latitude of the center of its cell,
+/// with the same arithmetic as Redis so positions match to the last digit.
+fn decode(score: f64) -> (f64, f64) {
+    let bits = score as u64;
+    let cells = (1u64 << GEO_STEP) as f64;
+    let center = |cell: u64, (min, max): (f64, f64)| {
+        let low = min + (cell as f64 / cells) * (max - min);
+        let high = min + ((cell + 1) as f64 / cells) * (max - min);
+        ((low + high) / 2.0).clamp(min, max)
+    };
+    (
+        center(squash(bits >> 1), GEO_LONGITUDE_RANGE),
+        center(squash(bits), GEO_LATITUDE_RANGE),
+    )
+}
+
+/// Returns the 11 character geohash string of a score, re-encoded over the standard
+/// latitude range.
+fn geohash_string(score: f64) -> String {
+    let (longitude, latitude) = decode(score);
+    let bits = encode(longitude, latitude, GEOHASH_LATITUDE_RANGE);
+    (0..11)
+        .map(|index| {
+            // 52 bits only fill 10 characters, the last is always the first letter
+            let digit = match index {
+                10 => 0,
+                _ => bits >> (52 - (index + 1) * 5) & 0x1f,
+            };
+            GEOHASH_ALPHABET[digit as usize] as char
+        })
+        .collect()
+}
+
+/// Returns the great-circle distance in meters between two positions, with the
+/// haversine formula.
+fn distance((longitude1, latitude1): (f64, f64), (longitude2, latitude2): (f64, f64)) -> f64 {
+    let (latitude1, latitude2) = (latitude1.to_radians(), latitude2.to_radians());
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let v = ((longitude2.to_radians() - longitude1.to_radians()) / 2.0).sin();
+    let a = u * u + latitude1.cos() * latitude2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Returns the distance in meters from `center` to `position` if it lies within `shape`.
+fn distance_within(center: (f64, f64), position: (f64, f64), shape: GeoShape) -> Option<f64> {
+    let meters = shape.unit().meters();
+    match shape {
+        GeoShape::Radius(radius, _) => {
+            Some(distance(center, position)).filter(|&distance| distance <= radius * meters)
+        }
+        GeoShape::Box(width, height, _) => {
+            let latitude_distance =
+                EARTH_RADIUS * (position.1.to_radians() - center.1.to_radians()).abs();
+            if latitude_distance > height * meters / 2.0 {
+                return None;
+            }
+            let longitude_distance = distance((center.0, position.1), position);
+            if longitude_distance > width * meters / 2.0 {
+                return None;
+            }
+            Some(distance(center, position))
+        }
+    }
+}
+
+/// Formats a coordinate as Redis does, with up to 17 decimals.
+fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{:.17}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn format_distance(meters: f64, unit: GeoUnit) -> String {
+    format!("{:.4}", meters / unit.meters())
+}
+
+fn position_reply((longitude, latitude): (f64, f64)) -> RespValue {
+    RespValue::Array(vec![
+        RespValue::bulk(&format_coordinate(longitude)),
+        RespValue::bulk(&format_coordinate(latitude)),
+    ])
+}
+
+/// A member found by GEOSEARCH, with its distance from the center in meters.
+struct GeoMatch {
+    member: String,
+    score: f64,
+    distance: f64,
+}
+
+/// Finds the members of a sorted set within a GEOSEARCH area, sorted and cut to COUNT as
+/// the options ask. Without a sort, COUNT sorts nearest first unless it came with ANY.
+fn search(
+    set: &SortedSet,
+    origin: &GeoOrigin,
+    shape: GeoShape,
+    options: &GeoSearchOptions,
+) -> Result<Vec<GeoMatch>, anyhow::Error> {
+    let center = match origin {
+        GeoOrigin::LonLat(longitude, latitude) => (*longitude, *latitude),
+        GeoOrigin::Member(member) => set
+            .score(member)
+            .map(decode)
+            .ok_or_else(|| anyhow::anyhow!("could not decode requested zset member"))?,
+    };
+    let any_limit = options
+        .count
+        .filter(|(_, any)| *any)
+        .map(|(count, _)| count);
+    let mut matches = Vec::new();
+    for (member, score) in set.iter() {
+        if let Some(distance) = distance_within(center, decode(score), shape) {
+            matches.push(GeoMatch {
+                member: member.clone(),
+                score,
+                distance,
+            });
+            if any_limit.is_some_and(|limit| matches.len() >= limit) {
+                break;
+            }
+        }
+    }
+    let descending = match (options.descending, options.count) {
+        (None, Some((_, false))) => Some(false),
+        (descending, _) => descending,
+    };
+    if let Some(descending) = descending {
+        matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        if descending {
+            matches.reverse();
+        }
+    }
+    if let Some((count, _)) = options.count {
+        matches.truncate(count);
+    }
+    Ok(matches)
+}
+
+impl BaseServer {
+    /// Handles GEOADD, which is ZADD with each position encoded into a geohash score.
+    pub(super) async fn geoadd(
+        &self,
+        key: &str,
+        options: ZAddOptions,
+        triples: Vec<(f64, f64, String)>,
+    ) -> RespValue {
+        let pairs = triples
+            .into_iter()
+            .map(|(longitude, latitude, member)| {
+                let score = encode(longitude, latitude, GEO_LATITUDE_RANGE);
+                (score as f64, member)
+            })
+            .collect();
+        self.zadd(key, options, pairs).await
+    }
+
+    /// Handles GEOPOS, replying with a null array for each missing member.
+    pub(super) async fn geopos(&self, key: &str, members: &[String]) -> RespValue {
+        let positions = self
+            .store
+            .sorted_set(key, |set| {
+                members
+                    .iter()
+                    .map(|member| set.score(member).map(decode))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        match positions {
+            Ok(positions) => {
+                let positions = positions.unwrap_or_else(|| vec![None; members.len()]);
+                RespValue::Array(
+                    positions
+                        .into_iter()
+                        .map(|position| position.map_or(RespValue::NullArray, position_reply))
+                        .collect(),
+                )
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles GEODIST, replying with nil if either member is missing.
+    pub(super) async fn geodist(
+        &self,
+        key: &str,
+        first: &str,
+        second: &str,
+        unit: GeoUnit,
+    ) -> RespValue {
+        let positions = self
+            .store
+            .sorted_set(key, |set| Some((set.score(first)?, set.score(second)?)))
+            .await;
+        match positions {
+            Ok(Some(Some((first, second)))) => RespValue::bulk(&format_distance(
+                distance(decode(first), decode(second)),
+                unit,
+            )),
+            Ok(_) => RespValue::Null,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles GEOHASH, replying with nil for each missing member.
+    pub(super) async fn geohash(&self, key: &str, members: &[String]) -> RespValue {
+        let hashes = self
+            .store
+            .sorted_set(key, |set| {
+                members
+                    .iter()
+                    .map(|member| set.score(member).map(geohash_string))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        match hashes {
+            Ok(hashes) => RespValue::Array(
+                hashes
+                    .unwrap_or_else(|| vec![None; members.len()])
+                    .into_iter()
+                    .map(|hash| hash.map_or(RespValue::Null, |hash| RespValue::bulk(&hash)))
+                    .collect(),
+            ),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles GEOSEARCH. Each match is its member alone, or with the WITH options an
+    /// array of the member followed by its distance, its geohash and its position.
+    pub(super) async fn geosearch(
+        &self,
+        key: &str,
+        origin: &GeoOrigin,
+        shape: GeoShape,
+        options: &GeoSearchOptions,
+    ) -> RespValue {
+        let matches = self
+            .store
+            .sorted_set(key, |set| search(set, origin, shape, options))
+            .await;
+        let matches = match matches {
+            Ok(Some(Ok(matches))) => matches,
+            Ok(None) => Vec::new(),
+            Ok(Some(Err(e))) | Err(e) => return error_reply(e),
+        };
+        let with_any = options.with_dist || options.with_hash || options.with_coord;
+        RespValue::Array(
+            matches
+                .into_iter()
+                .map(|found| {
+                    let member = RespValue::bulk(&found.member);
+                    if !with_any {
+                        return member;
+                    }
+                    let mut reply = vec![member];
+                    if options.with_dist {
+                        reply.push(RespValue::bulk(&format_distance(
+                            found.distance,
+                            shape.unit(),
+                        )));
+                    }
+                    if options.with_hash {
+                        reply.push(RespValue::Integer(found.score as i64));
+                    }
+                    if options.with_coord {
+                        reply.push(position_reply(decode(found.score)));
+                    }
+                    RespValue::Array(reply)
+                })
+                .collect(),
+        )
+    }
+
+    /// Handles GEOSEARCHSTORE, storing the matches with their geohash scores, or with
+    /// STOREDIST their distances in the search's unit.
+    pub(super) async fn geosearchstore(
+        &self,
+        destination: &str,
+        source: &str,
+        origin: &GeoOrigin,
+        shape: GeoShape,
+        options: &GeoSearchOptions,
+        store_dist: bool,
+    ) -> RespValue {
+        let stored = self
+            .store
+            .derive_sorted_set(source, destination, |set| {
+                let mut stored = SortedSet::default();
+                for found in search(set, origin, shape, options)? {
+                    let score = match store_dist {
+                        true => found.distance / shape.unit().meters(),
+                        false => found.score,
+                    };
+                    stored.insert(found.member, score);
+                }
+                Ok(stored)
+            })
+            .await;
+        match stored {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::resp::RespValue;
+use rand::seq::IteratorRandom;
+
+use crate::{
+    command::{ExpireCondition, ScanOptions},
+    utils::{choose_with_repeats, glob_match, now_millis, scan},
+};
+
+use super::base::{error_reply, BaseServer};
+
+impl BaseServer {
+    /// Handles HSET, replying with the number of fields that were added rather than updated.
+    pub(super) async fn hset(&self, key: &str, pairs: Vec<(String, String)>) -> RespValue {
+        let added = self
+            .store
+            .hash_mut(key, true, |hash| {
+                pairs
+                    .into_iter()
+                    .map(|(field, value)| hash.insert(field, value).is_none())
+                    .filter(|&added| added)
+                    .count()
+            })
+            .await;
+        match added {
+            Ok(added) => RespValue::Integer(added.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn hget(&self, key: &str, field: &str) -> RespValue {
+        match self.store.hash(key, |hash| hash.get(field).cloned()).await {
+            Ok(Some(Some(value))) => RespValue::bulk(&value),
+            Ok(_) => RespValue::Null,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles HDEL, replying with the number of fields removed. A hash left without
+    /// fields is removed along with its key.
+    pub(super) async fn hdel(&self, key: &str, fields: &[String]) -> RespValue {
+        let removed = self
+            .store
+            .hash_mut(key, false, |hash| {
+                fields
+                    .iter()
+                    .filter(|field| hash.remove(field).is_some())
+                    .count()
+            })
+            .await;
+        match removed {
+            Ok(removed) => RespValue::Integer(removed.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles HGETALL, replying with a flat array of alternating fields and values.
+    pub(super) async fn hgetall(&self, key: &str) -> RespValue {
+        let pairs = self
+            .store
+            .hash(key, |hash| {
+                hash.iter()
+                    .map(|(field, value)| (RespValue::bulk(field), RespValue::bulk(value)))
+                    .collect()
+            })
+            .await;
+        match pairs {
+            Ok(pairs) => RespValue::Map(pairs.unwrap_or_default()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn hmget(&self, key: &str, fields: &[String]) -> RespValue {
+        let values = self
+            .store
+            .hash(key, |hash| {
+                fields
+                    .iter()
+                    .map(|field| hash.get(field).cloned())
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        match values {
+            Ok(values) => RespValue::Array(
+                values
+                    .unwrap_or_else(|| vec![None; fields.len()])
+                    .iter()
+                    .map(|value| match value {
+                        Some(value) => RespValue::bulk(value),
+                        None => RespValue::Null,
+                    })
+                    .collect(),
+            ),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn hlen(&self, key: &str) -> RespValue {
+        match self.store.hash(key, |hash| hash.len()).await {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn hexists(&self, key: &str, field: &str) -> RespValue {
+        match self.store.hash(key, |hash| hash.contains_key(field)).await {
+            Ok(exists) => RespValue::Integer(exists.unwrap_or(false) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles HRANDFIELD. A positive count replies with that many distinct fields (or the
+    /// whole hash if it is smaller), a negative count allows the same field more than once.
+    pub(super) async fn hrandfield(
+        &self,
+        key: &str,
+        count: Option<i64>,
+        with_values: bool,
+    ) -> RespValue {
+        let picked = self
+            .store
+            .hash(key, |hash| {
+                let mut rng = rand::thread_rng();
+                match count {
+                    Some(count) if count >= 0 => hash
+                        .iter()
+                        .choose_multiple(&mut rng, count as usize)
+                        .into_iter()
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .collect(),
+                    Some(count) => {
+                        let pairs: Vec<_> = hash.iter().collect();
+                        choose_with_repeats(&pairs, count.unsigned_abs())
+                            .into_iter()
+                            .map(|(field, value)| (field.clone(), value.clone()))
+                            .collect()
+                    }
+                    None => hash
+                        .iter()
+                        .choose(&mut rng)
+                        .map(|(field, value)| (field.clone(), value.clone()))
+                        .into_iter()
+                        .collect::<Vec<_>>(),
+                }
+            })
+            .await;
+        let picked = match picked {
+            Ok(picked) => picked.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        if count.is_none() {
+            return match picked.first() {
+                Some((field, _)) => RespValue::bulk(field),
+                None => RespValue::Null,
+            };
+        }
+        RespValue::Array(
+            picked
+                .iter()
+                .flat_map(|(field, value)| {
+                    let mut reply = vec![RespValue::bulk(field)];
+                    if with_values {
+                        reply.push(RespValue::bulk(value));
+                    }
+                    reply
+                })
+                .collect(),
+        )
+    }
+
+    /// Handles HSCAN, replying with the next cursor and a flat array of fields and values.
+    pub(super) async fn hscan(&self, key: &str, cursor: u64, options: ScanOptions) -> RespValue {
+        let batch = self
+            .store
+            .hash(key, |hash| {
+                scan(
+                    hash.iter()
+                        .map(|(field, value)| (field.as_str(), (field.clone(), value.clone()))),
+                    cursor,
+                    options.count,
+                )
+            })
+            .await;
+        let (next, pairs) = match batch {
+            Ok(batch) => batch.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        let elements = pairs
+            .iter()
+            .filter(|(field, _)| {
+                options
+                    .pattern
+                    .as_ref()
+                    .is_none_or(|pattern| glob_match(pattern, field))
+            })
+            .flat_map(|(field, value)| {
+                let mut reply = vec![RespValue::bulk(field)];
+                if !options.no_values {
+                    reply.push(RespValue::bulk(value));
+                }
+                reply
+            })
+            .collect();
+        RespValue::Array(vec![
+            RespValue::bulk(&next.to_string()),
+            RespValue::Array(elements),
+        ])
+    }
+
+    /// Handles the HEXPIRE family, replying with a result code per field.
+    pub(super) async fn hexpire(
+        &self,
+        key: &str,
+        timestamp: u64,
+        conditions: &[ExpireCondition],
+        fields: &[String],
+    ) -> RespValue {
+        match self
+            .store
+            .set_field_expiry(key, fields, timestamp, conditions)
+            .await
+        {
+            Ok(results) => integer_array(results),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles HPERSIST, replying per field with -2 if it doesn't exist, -1 if it has no
+    /// expiry and 1 if its expiry was removed.
+    pub(super) async fn hpersist(&self, key: &str, fields: &[String]) -> RespValue {
+        let results = self
+            .store
+            .hash_mut(key, false, |hash| {
+                fields
+                    .iter()
+                    .map(|field| match hash.expiry(field) {
+                        None => -2,
+                        Some(None) => -1,
+                        Some(Some(_)) => {
+                            hash.persist(field);
+                            1
+                        }
+                    })
+                    .collect()
+            })
+            .await;
+        match results {
+            Ok(results) => integer_array(results.unwrap_or_else(|| vec![-2; fields.len()])),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles HTTL and HPTTL, replying per field with -2 if it doesn't exist, -1 if it has
+    /// no expiry, or its remaining time to live in units of `unit_millis`.
+    pub(super) async fn httl(&self, key: &str, fields: &[String], unit_millis: u64) -> RespValue {
+        let now = now_millis();
+        let results = self
+            .store
+            .hash(key, |hash| {
+                fields
+                    .iter()
+                    .map(|field| match hash.expiry(field) {
+                        None => -2,
+                        Some(None) => -1,
+                        Some(Some(expiry)) => {
+                            ((expiry.saturating_sub(now) + unit_millis / 2) / unit_millis) as i64
+                        }
+                    })
+                    .collect()
+            })
+            .await;
+        match results {
+            Ok(results) => integer_array(results.unwrap_or_else(|| vec![-2; fields.len()])),
+            Err(e) => error_reply(e),
+        }
+    }
+}
+
+fn integer_array(values: Vec<i64>) -> RespValue {
+    RespValue::Array(values.into_iter().map(RespValue::Integer).collect())
+}
@@ -0,0 +1,259 @@
+use super::base::{error_reply, BaseServer};
+use crate::resp::RespValue;
+
+/// The number of hash bits picking a register, the rest count the leading run of zeros
+const HLL_P: u32 = 14;
+const HLL_REGISTERS: usize = 1 << HLL_P;
+/// The number of hash bits left once the register is picked
+const HLL_Q: u32 = 64 - HLL_P;
+const HLL_BITS: usize = 6;
+const HLL_HEADER_LEN: usize = 16;
+/// The length of a dense HyperLogLog: the header followed by the 6 bit registers.
+const HLL_DENSE_LEN: usize = HLL_HEADER_LEN + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+const HLL_MAGIC: &[u8] = b"HYLL";
+const HLL_DENSE: u8 = 0;
+/// Flags the cached cardinality in the header as out of date, on its last byte.
+const HLL_CARD_INVALID: u8 = 0x80;
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+/// Error for a HyperLogLog command on a string that isn't one.
+#[derive(Debug, thiserror::Error)]
+#[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+pub struct InvalidHllError;
+
+/// Returns an empty dense HyperLogLog, laid out as Redis does so its DUMP and GET agree.
+fn new_hll() -> Vec<u8> {
+    let mut hll = vec![0; HLL_DENSE_LEN];
+    hll[..4].copy_from_slice(HLL_MAGIC);
+    hll[4] = HLL_DENSE;
+    hll
+}
+
+fn check_hll(bytes: &[u8]) -> Result<(), InvalidHllError> {
+    match bytes.len() == HLL_DENSE_LEN && bytes.starts_with(HLL_MAGIC) && bytes[4] == HLL_DENSE {
+        true => Ok(()),
+        false => Err(InvalidHllError),
+    }
+}
+
+/// Reads the 6 bit register at `index`, which may straddle two bytes.
+fn register(hll: &[u8], index: usize) -> u8 {
+    let bit = index * HLL_BITS;
+    let (byte, shift) = (HLL_HEADER_LEN + bit / 8, bit % 8);
+    let low = hll[byte] >> shift;
+    let high = hll
+        .get(byte + 1)
+        .map_or(0, |next| next.checked_shl(8 - shift as u32).unwrap_or(0));
+    (low | high) & 0x3f
+}
+
+fn set_register(hll: &mut [u8], index: usize, value: u8) {
+    let bit = index * HLL_BITS;
+    let (byte, shift) = (HLL_HEADER_LEN + bit / 8, bit % 8);
+    hll[byte] &= !(0x3f << shift);
+    hll[byte] |= value << shift;
+    if shift > 8 - HLL_BITS {
+        hll[byte + 1] &= !(0x3f >> (8 - shift));
+        hll[byte + 1] |= value >> (8 - shift);
+    }
+}
+
+fn registers(hll: &[u8]) -> Vec<u8> {
+    (0..HLL_REGISTERS)
+        .map(|index| register(hll, index))
+        .collect()
+}
+
+/// Hashes an element into the register it updates and the value it updates it to: the
+/// length of the run of zeros in the rest of the hash, plus one.
+fn hash_element(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash_64a(element, 0xadc83b19);
+    let index = hash as usize & (HLL_REGISTERS - 1);
+    let rest = hash >> HLL_P | 1 << HLL_Q;
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// MurmurHash64A, the hash Redis uses for HyperLogLogs.
+fn murmur_hash_64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (index, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * index);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Estimates the cardinality from the registers with the estimator Redis uses, from Otmar
+/// Ertl's "New cardinality estimation algorithms for HyperLogLog sketches".
+fn estimate(registers: &[u8]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let mut histogram = [0u32; HLL_Q as usize + 2];
+    for &register in registers {
+        histogram[register as usize] += 1;
+    }
+    let mut z = m * tau((m - histogram[HLL_Q as usize + 1] as f64) / m);
+    for count in histogram[1..=HLL_Q as usize].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (HLL_ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Returns the cardinality cached in the header, if it's up to date.
+fn cached_count(hll: &[u8]) -> Option<u64> {
+    let cache = &hll[8..HLL_HEADER_LEN];
+    (cache[7] & HLL_CARD_INVALID == 0).then(|| u64::from_le_bytes(cache.try_into().unwrap()))
+}
+
+fn set_cached_count(hll: &mut [u8], count: u64) {
+    hll[8..HLL_HEADER_LEN].copy_from_slice(&count.to_le_bytes());
+}
+
+/// Adds elements to a HyperLogLog, returning whether any register changed. The cached
+/// cardinality is left for the caller to refresh.
+fn add(hll: &mut [u8], elements: &[String]) -> bool {
+    let mut changed = false;
+    for element in elements {
+        let (index, count) = hash_element(element.as_bytes());
+        if register(hll, index) < count {
+            set_register(hll, index, count);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Merges HyperLogLogs by taking the highest value of each register, with empty strings
+/// standing for missing keys.
+fn merge(hlls: &[&[u8]]) -> Result<Vec<u8>, InvalidHllError> {
+    let mut merged = vec![0; HLL_REGISTERS];
+    for hll in hlls.iter().filter(|hll| !hll.is_empty()) {
+        check_hll(hll)?;
+        for (index, register) in registers(hll).into_iter().enumerate() {
+            merged[index] = merged[index].max(register);
+        }
+    }
+    Ok(merged)
+}
+
+impl BaseServer {
+    /// Handles PFADD, replying with 1 if the key was created or any register changed.
+    pub(super) async fn pfadd(&self, key: &str, elements: &[String]) -> RespValue {
+        let changed = self
+            .store
+            .mutate(key, |bytes| {
+                let created = bytes.is_empty();
+                if created {
+                    *bytes = new_hll();
+                }
+                check_hll(bytes)?;
+                let changed = add(bytes, elements);
+                if changed {
+                    let count = estimate(&registers(bytes));
+                    set_cached_count(bytes, count);
+                }
+                Ok::<_, InvalidHllError>(created || changed)
+            })
+            .await;
+        match changed {
+            Ok(Ok(changed)) => RespValue::Integer(changed as i64),
+            Ok(Err(e)) => error_reply(e.into()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles PFCOUNT, estimating the cardinality of the union of the keys' HyperLogLogs.
+    pub(super) async fn pfcount(&self, keys: &[String]) -> RespValue {
+        let mut hlls = Vec::new();
+        for key in keys {
+            match self.store.get_bytes(key).await {
+                Ok(bytes) => hlls.push(bytes.unwrap_or_default()),
+                Err(e) => return error_reply(e),
+            }
+        }
+        if let [hll] = hlls.as_slice() {
+            if let Some(count) = check_hll(hll).ok().and_then(|_| cached_count(hll)) {
+                return RespValue::Integer(count as i64);
+            }
+        }
+        let hlls: Vec<_> = hlls.iter().map(Vec::as_slice).collect();
+        match merge(&hlls) {
+            Ok(registers) => RespValue::Integer(estimate(&registers) as i64),
+            Err(e) => error_reply(e.into()),
+        }
+    }
+
+    /// Handles PFMERGE, storing the union of the destination's and the sources'
+    /// HyperLogLogs at the destination.
+    pub(super) async fn pfmerge(&self, destination: &str, keys: &[String]) -> RespValue {
+        let keys: Vec<_> = std::iter::once(destination.to_string())
+            .chain(keys.iter().cloned())
+            .collect();
+        let stored = self
+            .store
+            .store_combined_strings(destination, &keys, |hlls| {
+                let mut hll = new_hll();
+                for (index, register) in merge(hlls)?.into_iter().enumerate() {
+                    set_register(&mut hll, index, register);
+                }
+                let count = estimate(&registers(&hll));
+                set_cached_count(&mut hll, count);
+                Ok(hll)
+            })
+            .await;
+        match stored {
+            Ok(_) => RespValue::ok(),
+            Err(e) => error_reply(e),
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use crate::resp::RespValue;
+use std::collections::VecDeque;
+
+use crate::command::{ListEnd, LposOptions, RedisCommand};
+
+use super::{
+    base::{error_reply, BaseServer},
+    blocking::Served,
+};
+
+/// Resolves a possibly negative LRANGE-style index pair against a list of length `len`,
+/// returning the inclusive range to read or None if it selects nothing.
+pub fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Pops up to `count` elements from one end of a list, in the order they were popped.
+fn pop_elements(list: &mut VecDeque<String>, end: ListEnd, count: usize) -> Vec<String> {
+    let count = count.min(list.len());
+    match end {
+        ListEnd::Left => list.drain(..count).collect(),
+        ListEnd::Right => {
+            let mut popped = list.split_off(list.len() - count);
+            popped.make_contiguous().reverse();
+            Vec::from(popped)
+        }
+    }
+}
+
+impl BaseServer {
+    /// Handles LPUSH and RPUSH, replying with the length of the list after the push. With
+    /// `existing`, as for LPUSHX and RPUSHX, a missing list is left alone and replies 0.
+    pub(super) async fn push(
+        &self,
+        key: &str,
+        end: ListEnd,
+        values: Vec<String>,
+        existing: bool,
+    ) -> RespValue {
+        let pushed = self
+            .store
+            .list_mut(key, !existing, |list| {
+                for value in values {
+                    match end {
+                        ListEnd::Left => list.push_front(value),
+                        ListEnd::Right => list.push_back(value),
+                    }
+                }
+                list.len()
+            })
+            .await;
+        match pushed {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles LPOP and RPOP. Without a count a single element is popped and replied as a
+    /// bulk string, with one the popped elements are replied as an array.
+    pub(super) async fn pop(&self, key: &str, end: ListEnd, count: Option<usize>) -> RespValue {
+        let popped = self
+            .store
+            .list_mut(key, false, |list| {
+                pop_elements(list, end, count.unwrap_or(1))
+            })
+            .await;
+        match (popped, count) {
+            (Err(e), _) => error_reply(e),
+            (Ok(None), None) => RespValue::Null,
+            (Ok(None), Some(_)) => RespValue::NullArray,
+            (Ok(Some(popped)), None) => match popped.first() {
+                Some(value) => RespValue::bulk(value),
+                None => RespValue::Null,
+            },
+            (Ok(Some(popped)), Some(_)) => {
+                RespValue::Array(popped.iter().map(|value| RespValue::bulk(value)).collect())
+            }
+        }
+    }
+
+    /// Pops an element from the first non-empty list among `keys`, for BLPOP and BRPOP.
+    /// Returns None if every list is empty, so the client should block.
+    pub(super) async fn bpop(&self, keys: &[String], end: ListEnd) -> Option<Served> {
+        for key in keys {
+            let popped = self
+                .store
+                .list_mut(key, false, |list| match end {
+                    ListEnd::Left => list.pop_front(),
+                    ListEnd::Right => list.pop_back(),
+                })
+                .await;
+            match popped {
+                Ok(Some(Some(value))) => {
+                    return Some(Served {
+                        response: RespValue::Array(vec![
+                            RespValue::bulk(key),
+                            RespValue::bulk(&value),
+                        ]),
+                        executed: Some(RedisCommand::Pop(key.clone(), end, None)),
+                    })
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    return Some(Served {
+                        response: error_reply(e),
+                        executed: None,
+                    })
+                }
+            }
+        }
+        None
+    }
+
+    /// Handles LMOVE and RPOPLPUSH, replying with the moved element.
+    pub(super) async fn lmove(
+        &self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> RespValue {
+        match self.store.list_move(source, destination, from, to).await {
+            Ok(Some(value)) => RespValue::bulk(&value),
+            Ok(None) => RespValue::Null,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Moves an element for BLMOVE. Returns None if the source list is empty,
+    /// so the client should block.
+    pub(super) async fn blmove(
+        &self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Option<Served> {
+        match self.store.list_move(source, destination, from, to).await {
+            Ok(Some(value)) => Some(Served {
+                response: RespValue::bulk(&value),
+                executed: Some(RedisCommand::LMove(
+                    source.to_string(),
+                    destination.to_string(),
+                    from,
+                    to,
+                )),
+            }),
+            Ok(None) => None,
+            Err(e) => Some(Served {
+                response: error_reply(e),
+                executed: None,
+            }),
+        }
+    }
+
+    /// Handles LPOS. Without COUNT the index of the first match is replied, with it an
+    /// array of the matching indexes.
+    pub(super) async fn lpos(&self, key: &str, element: &str, options: LposOptions) -> RespValue {
+        let positions = self
+            .store
+            .list(key, |list| {
+                let limit = match options.max_len {
+                    0 => list.len(),
+                    max_len => max_len.min(list.len()),
+                };
+                let indexes: Box<dyn Iterator<Item = usize>> = if options.rank > 0 {
+                    Box::new(0..limit)
+                } else {
+                    Box::new((list.len() - limit..list.len()).rev())
+                };
+                let wanted = match options.count {
+                    Some(0) => usize::MAX,
+                    Some(count) => count,
+                    None => 1,
+                };
+                indexes
+                    .filter(|&index| list[index] == element)
+                    .skip(options.rank.unsigned_abs() as usize - 1)
+                    .take(wanted)
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        let positions = match positions {
+            Ok(positions) => positions.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        match options.count {
+            Some(_) => RespValue::Array(
+                positions
+                    .iter()
+                    .map(|&index| RespValue::Integer(index as i64))
+                    .collect(),
+            ),
+            None => match positions.first() {
+                Some(&index) => RespValue::Integer(index as i64),
+                None => RespValue::Null,
+            },
+        }
+    }
+
+    /// Pops up to `count` elements from the first non-empty list among `keys`, for LMPOP
+    /// and BLMPOP. Replies with the key and the popped elements, or None if every list
+    /// is empty.
+    pub(super) async fn mpop(&self, keys: &[String], end: ListEnd, count: usize) -> Option<Served> {
+        for key in keys {
+            let popped = self
+                .store
+                .list_mut(key, false, |list| pop_elements(list, end, count))
+                .await;
+            match popped {
+                Ok(Some(popped)) => {
+                    return Some(Served {
+                        response: RespValue::Array(vec![
+                            RespValue::bulk(key),
+                            RespValue::Array(
+                                popped.iter().map(|value| RespValue::bulk(value)).collect(),
+                            ),
+                        ]),
+                        executed: Some(RedisCommand::Pop(key.clone(), end, Some(count))),
+                    })
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    return Some(Served {
+                        response: error_reply(e),
+                        executed: None,
+                    })
+                }
+            }
+        }
+        None
+    }
+
+    pub(super) async fn llen(&self, key: &str) -> RespValue {
+        match self.store.list(key, |list| list.len()).await {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles LRANGE. Negative indexes count back from the end of the list and
+    /// out of range indexes are clamped rather than treated as errors.
+    pub(super) async fn lrange(&self, key: &str, start: i64, stop: i64) -> RespValue {
+        let range = self
+            .store
+            .list(key, |list| match resolve_range(start, stop, list.len()) {
+                Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+                None => Vec::new(),
+            })
+            .await;
+        match range {
+            Ok(values) => RespValue::Array(
+                values
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|value| RespValue::bulk(value))
+                    .collect(),
+            ),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles LINSERT, replying with the new length, -1 if the pivot wasn't found
+    /// or 0 if the key doesn't exist.
+    pub(super) async fn linsert(
+        &self,
+        key: &str,
+        before: bool,
+        pivot: &str,
+        element: String,
+    ) -> RespValue {
+        let inserted = self
+            .store
+            .list_mut(key, false, |list| {
+                let Some(index) = list.iter().position(|value| value == pivot) else {
+                    return -1;
+                };
+                list.insert(if before { index } else { index + 1 }, element);
+                list.len() as i64
+            })
+            .await;
+        match inserted {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0)),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles LSET. Negative indexes count back from the end of the list.
+    pub(super) async fn lset(&self, key: &str, index: i64, element: String) -> RespValue {
+        let set = self
+            .store
+            .list_mut(key, false, |list| {
+                let index = if index < 0 {
+                    index + list.len() as i64
+                } else {
+                    index
+                };
+                let value = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| list.get_mut(index))
+                    .ok_or_else(|| anyhow::anyhow!("index out of range"))?;
+                *value = element;
+                Ok(())
+            })
+            .await;
+        match set {
+            Ok(Some(Ok(()))) => RespValue::ok(),
+            Ok(Some(Err(e))) | Err(e) => error_reply(e),
+            Ok(None) => error_reply(anyhow::anyhow!("no such key")),
+        }
+    }
+
+    /// Handles LREM. A positive count removes that many matches from the head, a negative
+    /// count removes them from the tail and zero removes every match.
+    pub(super) async fn lrem(&self, key: &str, count: i64, element: &str) -> RespValue {
+        let removed = self
+            .store
+            .list_mut(key, false, |list| {
+                let limit = match count {
+                    0 => usize::MAX,
+                    count => count.unsigned_abs() as usize,
+                };
+                let matches: Vec<usize> = if count < 0 {
+                    (0..list.len())
+                        .rev()
+                        .filter(|&i| list[i] == element)
+                        .take(limit)
+                        .collect()
+                } else {
+                    (0..list.len())
+                        .filter(|&i| list[i] == element)
+                        .take(limit)
+                        .collect()
+                };
+                let mut index = 0;
+                list.retain(|_| {
+                    let keep = !matches.contains(&index);
+                    index += 1;
+                    keep
+                });
+                matches.len()
+            })
+            .await;
+        match removed {
+            Ok(count) => RespValue::Integer(count.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles LTRIM, keeping only the elements in the given range. A range that selects
+    /// nothing empties the list, which removes the key.
+    pub(super) async fn ltrim(&self, key: &str, start: i64, stop: i64) -> RespValue {
+        let trimmed = self
+            .store
+            .list_mut(key, false, |list| {
+                match resolve_range(start, stop, list.len()) {
+                    Some((start, stop)) => {
+                        list.truncate(stop + 1);
+                        list.drain(..start);
+                    }
+                    None => list.clear(),
+                }
+            })
+            .await;
+        match trimmed {
+            Ok(_) => RespValue::ok(),
+            Err(e) => error_reply(e),
+        }
+    }
+}
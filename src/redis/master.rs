@@ -1,17 +1,19 @@
+use crate::resp::RespValue;
 use std::time::Duration;
 
 use anyhow::Context;
+use bytes::Bytes;
 use tokio::time::Instant;
 use tracing::{debug, info};
 
 use crate::{
-    command::{AdminCommand, RedisCommand, RedisCommandResponse},
+    command::{AdminCommand, RedisCommand},
+    parser::encode_request,
     utils::now_millis,
 };
 
 use super::{
-    base::{BaseServer, RedisServer},
-    store::RedisStore,
+    base::{error_reply, BaseServer, RedisServer},
     types::{RedisInfo, RedisRole},
 };
 
@@ -27,11 +29,7 @@ impl Master {
     pub fn new(host: &str, port: &str) -> Self {
         let address = format!("{}:{}", host, port);
         Master {
-            base: BaseServer {
-                info: RedisInfo::new(RedisRole::Master, "", ""),
-                address,
-                store: RedisStore::new(),
-            },
+            base: BaseServer::new(RedisInfo::new(RedisRole::Master, "", ""), address),
             slaves: Vec::new(),
         }
     }
@@ -41,17 +39,16 @@ impl Master {
         Ok(())
     }
 
-    pub async fn replicate_to_slaves(&self, command: &str) -> Result<(), anyhow::Error> {
+    pub async fn replicate_to_slaves(&self, command: &[u8]) -> Result<(), anyhow::Error> {
         for slave_address in &self.slaves {
-            let command_to_send = format!(
-                "*2\r\n$9\r\nREPLICATE\r\n${}\r\n{}\r\n",
-                command.len(),
-                command
-            );
+            let command_to_send = encode_request(&[
+                Bytes::from_static(b"REPLICATE"),
+                Bytes::copy_from_slice(command),
+            ]);
 
             if let Err(e) = self
                 .base
-                .send_command(&slave_address, &command_to_send)
+                .send_command(slave_address, &command_to_send)
                 .await
             {
                 debug!(
@@ -111,50 +108,33 @@ impl Master {
 impl RedisServer for Master {
     /// Handles a Redis command.
     /// Parses the command, executes it and returns the response.
-    async fn handle_command(
-        &mut self,
-        command: RedisCommand,
-    ) -> Result<RedisCommandResponse, anyhow::Error> {
+    async fn handle_command(&mut self, command: RedisCommand) -> Result<RespValue, anyhow::Error> {
         info!("Handling command: {:?}", command);
         match command {
-            RedisCommand::Ping => Ok(RedisCommandResponse::new("PONG".to_string())),
-            RedisCommand::Pong => Ok(RedisCommandResponse::new("PING".to_string())),
-            RedisCommand::Echo(s) => Ok(RedisCommandResponse::new(s)),
-            RedisCommand::Get(key) => match self.base.store.get(&key).await {
-                Some(value) => Ok(RedisCommandResponse::new(value)),
-                None => Ok(RedisCommandResponse::null()),
-            },
-            RedisCommand::Info(section) => match section.as_deref() {
-                Some("replication") => {
-                    let info_message = format!(
-                        "role:{}\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_replid:{}\r\nmaster_repl_offset:{}",
-                        self.base.info.role, self.base.info.master_host, self.base.info.master_port, self.base.info.master_replid, self.base.info.master_repl_offset
-                    );
-                    Ok(RedisCommandResponse::new(info_message))
-                }
-                _ => Ok(RedisCommandResponse::_error(
-                    "Unsupported INFO section".to_string(),
-                )),
+            RedisCommand::Ping => Ok(RespValue::SimpleString("PONG".to_string())),
+            RedisCommand::Pong => Ok(RespValue::bulk("PING")),
+            RedisCommand::Echo(s) => Ok(RespValue::BulkString(s)),
+            RedisCommand::Get(key) => match self.base.store.get_bytes(&key).await {
+                Ok(Some(value)) => Ok(RespValue::BulkString(value)),
+                Ok(None) => Ok(RespValue::Null),
+                Err(e) => Ok(error_reply(e)),
             },
-            RedisCommand::Set(key, value, expiry) => {
-                self.base.store.set(&key, &value, expiry).await;
-                Ok(RedisCommandResponse::new("OK".to_string()))
-            }
             RedisCommand::Admin(command) => match command {
                 AdminCommand::Replicate(data) => {
-                    self.replicate_to_slaves(&data).await?;
-                    Ok(RedisCommandResponse::new("OK".to_string()))
+                    self.replicate_to_slaves(data.as_bytes()).await?;
+                    Ok(RespValue::ok())
                 }
                 AdminCommand::AddSlave(data) => {
                     self.add_slave(data).await?;
-                    Ok(RedisCommandResponse::new("OK".to_string()))
+                    Ok(RespValue::ok())
                 }
             },
             RedisCommand::Replconf(data) => {
                 self.replconf(data).await?;
-                Ok(RedisCommandResponse::new("OK".to_string()))
+                Ok(RespValue::ok())
             }
-            RedisCommand::Ok => Ok(RedisCommandResponse::new("OK".to_string())),
+            RedisCommand::Ok => Ok(RespValue::ok()),
+            command => self.base.handle_shared_command(command).await,
         }
     }
 }
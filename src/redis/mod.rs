@@ -1,5 +1,18 @@
+pub mod acl;
 pub mod base;
+pub mod bitmap;
+pub mod blocking;
+pub mod config;
+pub mod dump;
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod list;
 pub mod master;
+pub mod set;
 pub mod slave;
+pub mod sort;
+pub mod sorted_set;
 pub mod store;
+pub mod stream;
 pub mod types;
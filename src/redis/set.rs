@@ -0,0 +1,174 @@
+use crate::resp::RespValue;
+use crate::{
+    command::{ScanOptions, SetOperation},
+    utils::{glob_match, scan},
+};
+
+use super::base::{error_reply, BaseServer};
+
+impl BaseServer {
+    /// Handles SADD, replying with the number of members that weren't already in the set.
+    pub(super) async fn sadd(&self, key: &str, members: Vec<String>) -> RespValue {
+        let added = self
+            .store
+            .set_members_mut(key, true, |set| {
+                members
+                    .into_iter()
+                    .filter(|member| set.insert(member.clone()))
+                    .count()
+            })
+            .await;
+        match added {
+            Ok(added) => RespValue::Integer(added.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SREM, replying with the number of members removed. A set left without
+    /// members is removed along with its key.
+    pub(super) async fn srem(&self, key: &str, members: &[String]) -> RespValue {
+        let removed = self
+            .store
+            .set_members_mut(key, false, |set| {
+                members.iter().filter(|member| set.remove(*member)).count()
+            })
+            .await;
+        match removed {
+            Ok(removed) => RespValue::Integer(removed.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn smembers(&self, key: &str) -> RespValue {
+        let members = self
+            .store
+            .set_members(key, |set| {
+                set.iter().map(|member| RespValue::bulk(member)).collect()
+            })
+            .await;
+        match members {
+            Ok(members) => RespValue::Set(members.unwrap_or_default()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn sismember(&self, key: &str, member: &str) -> RespValue {
+        match self
+            .store
+            .set_members(key, |set| set.contains(member))
+            .await
+        {
+            Ok(exists) => RespValue::Integer(exists.unwrap_or(false) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SMISMEMBER, replying with 1 or 0 per member.
+    pub(super) async fn smismember(&self, key: &str, members: &[String]) -> RespValue {
+        let found = self
+            .store
+            .set_members(key, |set| {
+                members
+                    .iter()
+                    .map(|member| set.contains(member))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        match found {
+            Ok(found) => RespValue::Array(
+                found
+                    .unwrap_or_else(|| vec![false; members.len()])
+                    .into_iter()
+                    .map(|found| RespValue::Integer(found as i64))
+                    .collect(),
+            ),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SMOVE, replying with 1 if the member was moved and 0 if it wasn't in the
+    /// source set.
+    pub(super) async fn smove(&self, source: &str, destination: &str, member: String) -> RespValue {
+        match self.store.set_move(source, destination, member).await {
+            Ok(moved) => RespValue::Integer(moved as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn scard(&self, key: &str) -> RespValue {
+        match self.store.set_members(key, |set| set.len()).await {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SINTER, SUNION and SDIFF, replying with the members of the resulting set.
+    pub(super) async fn set_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+    ) -> RespValue {
+        match self.store.combine_sets(operation, keys).await {
+            Ok(set) => RespValue::Set(set.iter().map(|member| RespValue::bulk(member)).collect()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SINTERSTORE, SUNIONSTORE and SDIFFSTORE, replying with the size of the
+    /// stored set.
+    pub(super) async fn set_operation_store(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> RespValue {
+        match self
+            .store
+            .store_combined_sets(operation, destination, keys)
+            .await
+        {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SINTERCARD, replying with the size of the intersection capped at `limit`.
+    pub(super) async fn sintercard(&self, keys: &[String], limit: usize) -> RespValue {
+        match self.store.intersection_len(keys, limit).await {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles SSCAN, replying with the next cursor and an array of members.
+    pub(super) async fn sscan(&self, key: &str, cursor: u64, options: ScanOptions) -> RespValue {
+        let batch = self
+            .store
+            .set_members(key, |set| {
+                scan(
+                    set.iter().map(|member| (member.as_str(), member.clone())),
+                    cursor,
+                    options.count,
+                )
+            })
+            .await;
+        let (next, members) = match batch {
+            Ok(batch) => batch.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        let members = members
+            .iter()
+            .filter(|member| {
+                options
+                    .pattern
+                    .as_ref()
+                    .is_none_or(|pattern| glob_match(pattern, member))
+            })
+            .map(|member| RespValue::bulk(member))
+            .collect();
+        RespValue::Array(vec![
+            RespValue::bulk(&next.to_string()),
+            RespValue::Array(members),
+        ])
+    }
+}
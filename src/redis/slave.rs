@@ -1,14 +1,18 @@
+use crate::resp::RespValue;
 use anyhow::Context;
 use tracing::{error, info};
 
-use crate::command::{AdminCommand, RedisCommand, RedisCommandResponse};
+use crate::command::{AdminCommand, RedisCommand};
+use crate::parser::encode_request;
 
 use super::{
-    base::{BaseServer, RedisServer},
-    store::RedisStore,
+    base::{error_reply, BaseServer, RedisServer},
     types::{RedisInfo, RedisRole},
 };
 
+/// The reply to a client write while `replica-read-only` is set.
+pub const READONLY_ERROR: &str = "READONLY You can't write against a read only replica.";
+
 /// A Redis slave server implementation.
 #[derive(Debug, Clone)]
 pub struct Slave {
@@ -20,11 +24,10 @@ impl Slave {
     pub fn new(host: &str, port: &str, master_host: &str, master_port: &str) -> Self {
         let address = format!("{}:{}", host, port);
         Slave {
-            base: BaseServer {
-                info: RedisInfo::new(RedisRole::Slave, master_host, master_port),
+            base: BaseServer::new(
+                RedisInfo::new(RedisRole::Slave, master_host, master_port),
                 address,
-                store: RedisStore::new(),
-            },
+            ),
         }
     }
 
@@ -37,17 +40,19 @@ impl Slave {
             "{}:{}",
             self.base.info.master_host, self.base.info.master_port
         );
-        let command_str = command.to_resp2();
+        let argv = command
+            .argv()
+            .with_context(|| format!("{} can't be sent to the master", command))?;
 
-        info!("Sending command to master: {}", command_str);
+        info!("Sending command to master: {}", command);
 
         let response = self
             .base
-            .send_command(&master_address, &command_str)
+            .send_command(&master_address, &encode_request(&argv))
             .await?;
 
-        if response != RedisCommandResponse::new("OK".to_string()).to_string()
-            && response != RedisCommandResponse::new("PONG".to_string()).to_string()
+        if response != RespValue::ok().to_string()
+            && response != RespValue::SimpleString("PONG".to_string()).to_string()
         {
             error!("Failed to send command to master, response: {}", response);
             return Err(anyhow::anyhow!("Failed to send command to master"));
@@ -60,10 +65,10 @@ impl Slave {
     pub async fn handshake(&self) -> Result<(), anyhow::Error> {
         // Send PING command to master
         info!("Sending PING command to master");
-        let response = self.send_command_to_master(RedisCommand::Ping).await?;
+        self.send_command_to_master(RedisCommand::Ping).await?;
 
         // Send the REPLCONF command to the master
-        let response = self.replconf().await?;
+        self.replconf().await?;
 
         Ok(())
     }
@@ -93,60 +98,71 @@ impl Slave {
 impl RedisServer for Slave {
     /// Handles a Redis command.
     /// Parses the command, executes it and returns the response.
-    async fn handle_command(
-        &mut self,
-        command: RedisCommand,
-    ) -> Result<RedisCommandResponse, anyhow::Error> {
+    async fn handle_command(&mut self, command: RedisCommand) -> Result<RespValue, anyhow::Error> {
         info!("Handling command: {:?}", command);
+        if command.is_write_operation() && self.base.config.replica_read_only {
+            return Ok(RespValue::Error(READONLY_ERROR.to_string()));
+        }
         match command {
-            RedisCommand::Ping => Ok(RedisCommandResponse::new("PONG".to_string())),
+            RedisCommand::Ping => Ok(RespValue::SimpleString("PONG".to_string())),
             RedisCommand::Pong => {
                 info!("Slave received PONG command");
                 let replconf_response = self.replconf().await?;
-                Ok(RedisCommandResponse::new(replconf_response))
+                Ok(RespValue::BulkString(replconf_response.into_bytes()))
             }
-            RedisCommand::Echo(s) => Ok(RedisCommandResponse::new(s)),
-            RedisCommand::Get(key) => match self.base.store.get(&key).await {
-                Some(value) => Ok(RedisCommandResponse::new(value)),
-                None => Ok(RedisCommandResponse::null()),
+            RedisCommand::Echo(s) => Ok(RespValue::BulkString(s)),
+            RedisCommand::Get(key) => match self.base.store.get_bytes(&key).await {
+                Ok(Some(value)) => Ok(RespValue::BulkString(value)),
+                Ok(None) => Ok(RespValue::Null),
+                Err(e) => Ok(error_reply(e)),
             },
-            RedisCommand::Info(section) => match section.as_deref() {
-                Some("replication") => {
-                    let info_message = format!(
-                        "role:{}\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_replid:{}\r\nmaster_repl_offset:{}",
-                        self.base.info.role, self.base.info.master_host, self.base.info.master_port, self.base.info.master_replid, self.base.info.master_repl_offset
-                    );
-                    Ok(RedisCommandResponse::new(info_message))
-                }
-                _ => Ok(RedisCommandResponse::_error(
-                    "Unsupported INFO section".to_string(),
-                )),
-            },
-            RedisCommand::Set(key, value, expiry) => {
-                self.base.store.set(&key, &value, expiry).await;
-                Ok(RedisCommandResponse::new("OK".to_string()))
-            }
             RedisCommand::Admin(command) => match command {
                 AdminCommand::Replicate(_) => {
                     // Slaves should not handle replication commands
-                    Ok(RedisCommandResponse::_error(
+                    Ok(RespValue::Error(
                         "Replication command not supported on slave".to_string(),
                     ))
                 }
                 AdminCommand::AddSlave(_) => {
                     // Slaves should not handle adding slaves
-                    Ok(RedisCommandResponse::_error(
+                    Ok(RespValue::Error(
                         "AddSlave command not supported on slave".to_string(),
                     ))
                 }
             },
             RedisCommand::Replconf(_data) => {
                 error!("Slaves do not support REPLCONF");
-                Ok(RedisCommandResponse::_error(
+                Ok(RespValue::Error(
                     "REPLCONF command not supported on slave".to_string(),
                 ))
             }
-            RedisCommand::Ok => Ok(RedisCommandResponse::new("OK".to_string())),
+            RedisCommand::Ok => Ok(RespValue::ok()),
+            command => self.base.handle_shared_command(command).await,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::SetOptions;
+
+    fn set(key: &str) -> RedisCommand {
+        RedisCommand::Set(key.to_string(), b"value".to_vec(), SetOptions::default())
+    }
+
+    #[tokio::test]
+    async fn replica_read_only_decides_whether_writes_are_rejected() {
+        let mut slave = Slave::new("127.0.0.1", "6380", "127.0.0.1", "6379");
+        assert_eq!(
+            slave.handle_command(set("k")).await.unwrap(),
+            RespValue::Error(READONLY_ERROR.to_string())
+        );
+
+        slave.base.config.set("replica-read-only", "no").unwrap();
+        assert_eq!(
+            slave.handle_command(set("k")).await.unwrap(),
+            RespValue::ok()
+        );
+    }
+}
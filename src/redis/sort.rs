@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+
+use crate::command::SortOptions;
+use crate::resp::RespValue;
+use crate::utils::parse_float;
+
+use super::base::{error_reply, BaseServer};
+use super::store::{RedisValue, WrongTypeError};
+
+/// Substitutes `element` for the first `*` of a SORT pattern, returning the key to look up
+/// and the hash field a trailing `->field` names. None if the pattern has no `*`.
+fn pattern_key<'a>(pattern: &'a str, element: &str) -> Option<(String, Option<&'a str>)> {
+    let star = pattern.find('*')?;
+    let rest = &pattern[star + 1..];
+    let (suffix, field) = match rest.find("->") {
+        Some(arrow) if arrow + 2 < rest.len() => (&rest[..arrow], Some(&rest[arrow + 2..])),
+        _ => (rest, None),
+    };
+    Some((format!("{}{}{}", &pattern[..star], element, suffix), field))
+}
+
+/// Resolves the LIMIT of a SORT against `len` elements into the range to keep. A negative
+/// offset starts at the first element and a negative count keeps the rest.
+fn limit_range(limit: Option<(i64, i64)>, len: usize) -> (usize, usize) {
+    let (offset, count) = limit.unwrap_or((0, -1));
+    let start = (offset.max(0) as usize).min(len);
+    let end = match usize::try_from(count) {
+        Ok(count) => start.saturating_add(count).min(len),
+        Err(_) => len,
+    };
+    (start, end)
+}
+
+impl BaseServer {
+    /// Looks up the value a SORT pattern names for `element`: the string at the key it
+    /// names, or a field of the hash there with `->field`. `#` names the element itself.
+    /// None if the pattern has no `*` or the key or field is missing or of another type.
+    async fn sort_lookup(&self, pattern: &str, element: &str) -> Option<String> {
+        if pattern == "#" {
+            return Some(element.to_string());
+        }
+        let (key, field) = pattern_key(pattern, element)?;
+        match field {
+            Some(field) => self
+                .store
+                .hash(&key, |hash| hash.get(field).cloned())
+                .await
+                .ok()
+                .flatten()
+                .flatten(),
+            None => self
+                .store
+                .get_bytes(&key)
+                .await
+                .ok()
+                .flatten()
+                .map(|value| String::from_utf8_lossy(&value).into_owned()),
+        }
+    }
+
+    pub(super) async fn sort(&self, key: &str, options: &SortOptions) -> RespValue {
+        let value = self.store.get_value(key).await;
+        let is_set = matches!(value, Some(RedisValue::Set(_)));
+        let mut elements: Vec<String> = match value {
+            Some(RedisValue::List(list)) => list.into(),
+            Some(RedisValue::Set(set)) => set.into_iter().collect(),
+            Some(RedisValue::SortedSet(set)) => {
+                set.iter().map(|(member, _)| member.clone()).collect()
+            }
+            Some(_) => return error_reply(WrongTypeError.into()),
+            None => Vec::new(),
+        };
+
+        // A BY pattern without `*` skips sorting, except that a set is still sorted by its
+        // members when stored, so the stored list doesn't depend on the set's hash order.
+        let mut by = options.by.as_deref();
+        let mut alpha = options.alpha;
+        let mut sort = by.is_none_or(|pattern| pattern.contains('*'));
+        if !sort && is_set && options.store.is_some() {
+            (sort, by, alpha) = (true, None, true);
+        }
+
+        if sort {
+            let mut weights = Vec::with_capacity(elements.len());
+            for element in &elements {
+                weights.push(match by {
+                    Some(pattern) => self.sort_lookup(pattern, element).await,
+                    None => Some(element.clone()),
+                });
+            }
+            let mut sorted: Vec<(String, SortWeight)> = Vec::with_capacity(elements.len());
+            for (element, weight) in elements.into_iter().zip(weights) {
+                let weight = if alpha {
+                    SortWeight::Text(weight)
+                } else {
+                    match weight.as_deref().map(parse_float) {
+                        Some(None) => {
+                            return RespValue::Error(
+                                "ERR One or more scores can't be converted into double".to_string(),
+                            )
+                        }
+                        Some(Some(score)) => SortWeight::Number(score),
+                        None => SortWeight::Number(0.0),
+                    }
+                };
+                sorted.push((element, weight));
+            }
+            sorted.sort_by(|(a, a_weight), (b, b_weight)| {
+                let order = a_weight.compare(b_weight).then_with(|| a.cmp(b));
+                if options.descending {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+            elements = sorted.into_iter().map(|(element, _)| element).collect();
+        }
+
+        let (start, end) = limit_range(options.limit, elements.len());
+        let mut values = Vec::new();
+        for element in &elements[start..end] {
+            if options.get.is_empty() {
+                values.push(Some(element.clone()));
+            }
+            for pattern in &options.get {
+                values.push(self.sort_lookup(pattern, element).await);
+            }
+        }
+
+        match &options.store {
+            Some(destination) => {
+                let list = values.into_iter().map(Option::unwrap_or_default).collect();
+                RespValue::Integer(self.store.replace_list(destination, list).await as i64)
+            }
+            None => RespValue::Array(
+                values
+                    .into_iter()
+                    .map(|value| {
+                        value.map_or(RespValue::Null, |value| {
+                            RespValue::BulkString(value.into_bytes())
+                        })
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// What SORT orders an element by: a number, or with ALPHA a string, missing ones first.
+enum SortWeight {
+    Number(f64),
+    Text(Option<String>),
+}
+
+impl SortWeight {
+    fn compare(&self, other: &SortWeight) -> Ordering {
+        match (self, other) {
+            (SortWeight::Number(a), SortWeight::Number(b)) => a.total_cmp(b),
+            (SortWeight::Text(a), SortWeight::Text(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_name_a_key_and_an_optional_hash_field() {
+        assert_eq!(
+            pattern_key("weight_*", "a"),
+            Some(("weight_a".to_string(), None))
+        );
+        assert_eq!(
+            pattern_key("user:*->age", "1"),
+            Some(("user:1".to_string(), Some("age")))
+        );
+        assert_eq!(
+            pattern_key("user:*->", "1"),
+            Some(("user:1->".to_string(), None))
+        );
+        assert_eq!(pattern_key("nosort", "a"), None);
+    }
+
+    #[test]
+    fn limits_clamp_to_the_elements() {
+        assert_eq!(limit_range(None, 5), (0, 5));
+        assert_eq!(limit_range(Some((1, 2)), 5), (1, 3));
+        assert_eq!(limit_range(Some((-3, 2)), 5), (0, 2));
+        assert_eq!(limit_range(Some((2, -1)), 5), (2, 5));
+        assert_eq!(limit_range(Some((7, 2)), 5), (5, 5));
+    }
+}
@@ -0,0 +1,459 @@
+use crate::resp::RespValue;
+use rand::seq::IteratorRandom;
+
+use crate::{
+    command::{
+        LexBound, RedisCommand, ScanOptions, ScoreBound, ScoreEnd, SetCondition, SetOperation,
+        ZAddOptions, ZRangeBy, ZRangeOptions, ZSetOpOptions,
+    },
+    utils::{choose_with_repeats, format_float, glob_match, scan},
+};
+
+use super::{
+    base::{error_reply, BaseServer},
+    blocking::Served,
+    list::resolve_range,
+    store::SortedSet,
+};
+
+/// Builds the reply for a run of sorted set members, paired with their scores when
+/// `with_scores` is set.
+fn members_reply(members: &[(String, f64)], with_scores: bool) -> RespValue {
+    if !with_scores {
+        return RespValue::Array(
+            members
+                .iter()
+                .map(|(member, _)| RespValue::bulk(member))
+                .collect(),
+        );
+    }
+    RespValue::Pairs(
+        members
+            .iter()
+            .map(|(member, score)| (RespValue::bulk(member), RespValue::Double(*score)))
+            .collect(),
+    )
+}
+
+/// Selects the members a ZRANGE asks for, in the order it asks for them. Negative ranks
+/// count back from the end, and LIMIT applies after the range is taken in that order.
+fn select_range(set: &SortedSet, by: &ZRangeBy, options: &ZRangeOptions) -> Vec<(String, f64)> {
+    let (members, skip, take): (Box<dyn DoubleEndedIterator<Item = _>>, _, _) = match by {
+        ZRangeBy::Rank(start, stop) => match resolve_range(*start, *stop, set.len()) {
+            Some((start, stop)) => (Box::new(set.iter()), start, stop - start + 1),
+            None => return Vec::new(),
+        },
+        ZRangeBy::Score(min, max) => (Box::new(set.range_by_score(*min, *max)), 0, usize::MAX),
+        ZRangeBy::Lex(min, max) => (Box::new(set.range_by_lex(min, max)), 0, usize::MAX),
+    };
+    let (skip, take) = match options.limit {
+        Some((offset, _)) if offset < 0 => return Vec::new(),
+        Some((offset, count)) => (offset as usize, usize::try_from(count).unwrap_or(take)),
+        None => (skip, take),
+    };
+    let members: Box<dyn Iterator<Item = _>> = if options.rev {
+        Box::new(members.rev())
+    } else {
+        members
+    };
+    members
+        .skip(skip)
+        .take(take)
+        .map(|(member, score)| (member.clone(), score))
+        .collect()
+}
+
+impl BaseServer {
+    /// Handles ZADD, replying with the number of members added, plus those updated under CH.
+    /// With INCR it replies with the member's new score instead, or nil when a condition
+    /// kept the member from being updated.
+    pub(super) async fn zadd(
+        &self,
+        key: &str,
+        options: ZAddOptions,
+        pairs: Vec<(f64, String)>,
+    ) -> RespValue {
+        let outcome = self
+            .store
+            .sorted_set_mut(key, true, |set| {
+                let (mut added, mut changed, mut incremented) = (0, 0, None);
+                for (score, member) in pairs {
+                    let current = set.score(&member);
+                    match (options.condition, current) {
+                        (Some(SetCondition::Nx), Some(_)) | (Some(SetCondition::Xx), None) => {
+                            continue
+                        }
+                        _ => {}
+                    }
+                    let score = match (options.incr, current) {
+                        (true, Some(current)) => current + score,
+                        _ => score,
+                    };
+                    // inf + -inf, which Redis refuses to store
+                    if score.is_nan() {
+                        return None;
+                    }
+                    if let Some(current) = current {
+                        if options
+                            .comparison
+                            .is_some_and(|comparison| !comparison.allows(current, score))
+                        {
+                            continue;
+                        }
+                        if current != score {
+                            changed += 1;
+                        }
+                    } else {
+                        added += 1;
+                    }
+                    set.insert(member, score);
+                    incremented = Some(score);
+                }
+                Some((added, changed, incremented))
+            })
+            .await;
+        match outcome {
+            Ok(Some(None)) => {
+                RespValue::Error("ERR resulting score is not a number (NaN)".to_string())
+            }
+            Ok(outcome) => {
+                let (added, changed, incremented) = outcome.flatten().unwrap_or_default();
+                if options.incr {
+                    match incremented {
+                        Some(score) => RespValue::Double(score),
+                        None => RespValue::Null,
+                    }
+                } else if options.changed {
+                    RespValue::Integer(added + changed)
+                } else {
+                    RespValue::Integer(added)
+                }
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZINCRBY, replying with the member's new score. A missing member starts at 0.
+    pub(super) async fn zincrby(&self, key: &str, increment: f64, member: String) -> RespValue {
+        let score = self
+            .store
+            .sorted_set_mut(key, true, |set| {
+                let score = set.score(&member).unwrap_or(0.0) + increment;
+                // inf + -inf, which Redis refuses to store
+                if score.is_nan() {
+                    return None;
+                }
+                set.insert(member, score);
+                Some(score)
+            })
+            .await;
+        match score {
+            Ok(Some(Some(score))) => RespValue::Double(score),
+            Ok(_) => RespValue::Error("ERR resulting score is not a number (NaN)".to_string()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZREM, replying with the number of members removed. A sorted set left without
+    /// members is removed along with its key.
+    pub(super) async fn zrem(&self, key: &str, members: &[String]) -> RespValue {
+        let removed = self
+            .store
+            .sorted_set_mut(key, false, |set| {
+                members
+                    .iter()
+                    .filter(|member| set.remove(member).is_some())
+                    .count()
+            })
+            .await;
+        match removed {
+            Ok(removed) => RespValue::Integer(removed.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZPOPMIN and ZPOPMAX, replying with the popped members and their scores.
+    pub(super) async fn zpop(&self, key: &str, end: ScoreEnd, count: Option<usize>) -> RespValue {
+        let popped = self
+            .store
+            .sorted_set_mut(key, false, |set| {
+                (0..count.unwrap_or(1))
+                    .map_while(|_| set.pop(end))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        let popped = match popped {
+            Ok(popped) => popped.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        match count {
+            Some(_) => members_reply(&popped, true),
+            // Without a count the member and score are replied flat, as in RESP2
+            None => RespValue::Array(
+                popped
+                    .into_iter()
+                    .flat_map(|(member, score)| {
+                        [RespValue::bulk(&member), RespValue::Double(score)]
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Pops a member from the first non-empty sorted set among `keys`, for BZPOPMIN and
+    /// BZPOPMAX. Returns None if every sorted set is empty, so the client should block.
+    pub(super) async fn bzpop(&self, keys: &[String], end: ScoreEnd) -> Option<Served> {
+        for key in keys {
+            match self
+                .store
+                .sorted_set_mut(key, false, |set| set.pop(end))
+                .await
+            {
+                Ok(Some(Some((member, score)))) => {
+                    return Some(Served {
+                        response: RespValue::Array(vec![
+                            RespValue::bulk(key),
+                            RespValue::bulk(&member),
+                            RespValue::Double(score),
+                        ]),
+                        executed: Some(RedisCommand::ZPop(key.clone(), end, None)),
+                    })
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    return Some(Served {
+                        response: error_reply(e),
+                        executed: None,
+                    })
+                }
+            }
+        }
+        None
+    }
+
+    /// Handles ZRANK and ZREVRANK, replying with the member's rank, followed by its score
+    /// when `with_score` is set.
+    pub(super) async fn zrank(
+        &self,
+        key: &str,
+        member: &str,
+        rev: bool,
+        with_score: bool,
+    ) -> RespValue {
+        let rank = self
+            .store
+            .sorted_set(key, |set| {
+                let rank = set.rank(member)?;
+                let rank = if rev { set.len() - 1 - rank } else { rank };
+                Some((rank, set.score(member)?))
+            })
+            .await;
+        match rank {
+            Ok(Some(Some((rank, score)))) if with_score => RespValue::Array(vec![
+                RespValue::Integer(rank as i64),
+                RespValue::Double(score),
+            ]),
+            Ok(Some(Some((rank, _)))) => RespValue::Integer(rank as i64),
+            Ok(_) if with_score => RespValue::NullArray,
+            Ok(_) => RespValue::Null,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn zscore(&self, key: &str, member: &str) -> RespValue {
+        match self.store.sorted_set(key, |set| set.score(member)).await {
+            Ok(Some(Some(score))) => RespValue::Double(score),
+            Ok(_) => RespValue::Null,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn zcard(&self, key: &str) -> RespValue {
+        match self.store.sorted_set(key, |set| set.len()).await {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZRANGE and the ZRANGEBYSCORE and ZRANGEBYLEX family.
+    pub(super) async fn zrange(
+        &self,
+        key: &str,
+        by: ZRangeBy,
+        options: ZRangeOptions,
+    ) -> RespValue {
+        match self
+            .store
+            .sorted_set(key, |set| select_range(set, &by, &options))
+            .await
+        {
+            Ok(members) => members_reply(&members.unwrap_or_default(), options.with_scores),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZRANGESTORE, replying with the size of the stored sorted set.
+    pub(super) async fn zrangestore(
+        &self,
+        destination: &str,
+        source: &str,
+        by: ZRangeBy,
+        options: ZRangeOptions,
+    ) -> RespValue {
+        let stored = self
+            .store
+            .derive_sorted_set(source, destination, |set| {
+                let mut range = SortedSet::default();
+                for (member, score) in select_range(set, &by, &options) {
+                    range.insert(member, score);
+                }
+                Ok(range)
+            })
+            .await;
+        match stored {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZRANDMEMBER. A positive count replies with that many distinct members (or
+    /// the whole sorted set if it is smaller), a negative count allows repeats.
+    pub(super) async fn zrandmember(
+        &self,
+        key: &str,
+        count: Option<i64>,
+        with_scores: bool,
+    ) -> RespValue {
+        let picked = self
+            .store
+            .sorted_set(key, |set| {
+                let mut rng = rand::thread_rng();
+                let members = set.iter().map(|(member, score)| (member.clone(), score));
+                match count {
+                    Some(count) if count >= 0 => members.choose_multiple(&mut rng, count as usize),
+                    Some(count) => {
+                        choose_with_repeats(&members.collect::<Vec<_>>(), count.unsigned_abs())
+                    }
+                    None => members.choose(&mut rng).into_iter().collect(),
+                }
+            })
+            .await;
+        let picked = match picked {
+            Ok(picked) => picked.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        match count {
+            Some(_) => members_reply(&picked, with_scores),
+            None => match picked.first() {
+                Some((member, _)) => RespValue::bulk(member),
+                None => RespValue::Null,
+            },
+        }
+    }
+
+    pub(super) async fn zcount(&self, key: &str, min: ScoreBound, max: ScoreBound) -> RespValue {
+        match self
+            .store
+            .sorted_set(key, |set| set.range_by_score(min, max).count())
+            .await
+        {
+            Ok(count) => RespValue::Integer(count.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn zlexcount(&self, key: &str, min: &LexBound, max: &LexBound) -> RespValue {
+        match self
+            .store
+            .sorted_set(key, |set| set.range_by_lex(min, max).count())
+            .await
+        {
+            Ok(count) => RespValue::Integer(count.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZINTER, ZUNION and ZDIFF, replying with the resulting members in score order.
+    pub(super) async fn zset_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+        options: ZSetOpOptions,
+    ) -> RespValue {
+        match self
+            .store
+            .combine_sorted_sets(operation, keys, &options)
+            .await
+        {
+            Ok(set) => {
+                let members: Vec<_> = set
+                    .iter()
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect();
+                members_reply(&members, options.with_scores)
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZINTERSTORE, ZUNIONSTORE and ZDIFFSTORE, replying with the size of the
+    /// stored sorted set.
+    pub(super) async fn zset_operation_store(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+        options: ZSetOpOptions,
+    ) -> RespValue {
+        match self
+            .store
+            .store_combined_sorted_sets(operation, destination, keys, &options)
+            .await
+        {
+            Ok(len) => RespValue::Integer(len as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles ZSCAN, replying with the next cursor and a flat array of members and scores.
+    pub(super) async fn zscan(&self, key: &str, cursor: u64, options: ScanOptions) -> RespValue {
+        let batch = self
+            .store
+            .sorted_set(key, |set| {
+                scan(
+                    set.iter()
+                        .map(|(member, score)| (member.as_str(), (member.clone(), score))),
+                    cursor,
+                    options.count,
+                )
+            })
+            .await;
+        let (next, members) = match batch {
+            Ok(batch) => batch.unwrap_or_default(),
+            Err(e) => return error_reply(e),
+        };
+        let members: Vec<_> = members
+            .into_iter()
+            .filter(|(member, _)| {
+                options
+                    .pattern
+                    .as_ref()
+                    .is_none_or(|pattern| glob_match(pattern, member))
+            })
+            .collect();
+        // Unlike other replies with scores, ZSCAN keeps them as flat bulk strings in RESP3
+        let members = members
+            .iter()
+            .flat_map(|(member, score)| {
+                [
+                    RespValue::bulk(member),
+                    RespValue::bulk(&format_float(*score)),
+                ]
+            })
+            .collect();
+        RespValue::Array(vec![
+            RespValue::bulk(&next.to_string()),
+            RespValue::Array(members),
+        ])
+    }
+}
@@ -1,17 +1,1057 @@
 use std::{
     cmp::Reverse,
-    collections::{BTreeMap, BinaryHeap},
-    sync::Arc,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
+
+use rand::Rng;
 use tokio::sync::RwLock;
 use tracing::info;
 
-use crate::utils::now_millis;
+use crate::{
+    command::{
+        ExpireCondition, GroupReadId, LexBound, ListEnd, ScoreBound, ScoreEnd, SetCondition,
+        SetOperation, StreamId, StreamTrim, TrimStrategy, XAddId, XClaimOptions, ZSetOpOptions,
+    },
+    utils::{format_float, glob_match, now_millis, parse_float},
+};
+
+type Entries = BTreeMap<String, Entry>;
+/// Pending expirations as (expiry, key, field). The field is set for hash fields with a TTL.
+type Expirations = BinaryHeap<Reverse<(u64, String, Option<String>)>>;
+
+/// Error for commands run against a key holding a value of another type.
+#[derive(Debug, thiserror::Error)]
+#[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+pub struct WrongTypeError;
+
+/// The sizes up to which a collection is reported with the compact `listpack` encoding,
+/// as set by the `*-max-listpack-*` parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct ListpackLimits {
+    pub max_entries: usize,
+    pub max_value: usize,
+}
+
+/// A value held by a key. Strings are kept as raw bytes, so bit operations can set
+/// bytes that aren't valid UTF-8.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisValue {
+    String(Vec<u8>),
+    List(VecDeque<String>),
+    Hash(Hash),
+    Set(HashSet<String>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl RedisValue {
+    /// Returns the type name reported by TYPE.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisValue::String(_) => "string",
+            RedisValue::List(_) => "list",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Set(_) => "set",
+            RedisValue::SortedSet(_) => "zset",
+            RedisValue::Stream(_) => "stream",
+        }
+    }
+
+    fn as_bytes(&self) -> Result<&Vec<u8>, WrongTypeError> {
+        match self {
+            RedisValue::String(value) => Ok(value),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn as_bytes_mut(&mut self) -> Result<&mut Vec<u8>, WrongTypeError> {
+        match self {
+            RedisValue::String(value) => Ok(value),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    /// Returns a string value as text, with bytes that aren't valid UTF-8 replaced.
+    fn as_string(&self) -> Result<String, WrongTypeError> {
+        Ok(String::from_utf8_lossy(self.as_bytes()?).into_owned())
+    }
+}
+
+impl From<String> for RedisValue {
+    fn from(value: String) -> Self {
+        RedisValue::String(value.into_bytes())
+    }
+}
+
+impl From<Vec<u8>> for RedisValue {
+    fn from(value: Vec<u8>) -> Self {
+        RedisValue::String(value)
+    }
+}
+
+/// A collection type a key can hold, for the typed accessors on the store.
+/// Collections are never stored empty, a key whose collection is emptied is removed.
+trait Collection: Default + Into<RedisValue> {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError>;
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError>;
+    fn is_empty(&self) -> bool;
+    /// Drops any parts of the collection that have expired, e.g. hash fields with a TTL.
+    fn remove_expired(&mut self, _now: u64) {}
+}
+
+impl From<VecDeque<String>> for RedisValue {
+    fn from(list: VecDeque<String>) -> Self {
+        RedisValue::List(list)
+    }
+}
+
+impl Collection for VecDeque<String> {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError> {
+        match value {
+            RedisValue::List(list) => Ok(list),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError> {
+        match value {
+            RedisValue::List(list) => Ok(list),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        VecDeque::is_empty(self)
+    }
+}
+
+impl From<HashSet<String>> for RedisValue {
+    fn from(set: HashSet<String>) -> Self {
+        RedisValue::Set(set)
+    }
+}
+
+impl Collection for HashSet<String> {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError> {
+        match value {
+            RedisValue::Set(set) => Ok(set),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError> {
+        match value {
+            RedisValue::Set(set) => Ok(set),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        HashSet::is_empty(self)
+    }
+}
+
+/// A hash value, along with the expiry of any fields given one by HEXPIRE.
+/// Expired fields are skipped by reads and dropped on the next write to the hash.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: HashMap<String, String>,
+    /// Absolute expiry in unix milliseconds of the fields that have one.
+    expiries: HashMap<String, u64>,
+}
+
+impl Hash {
+    fn is_live(&self, field: &str, now: u64) -> bool {
+        self.expiries.get(field).is_none_or(|&expiry| expiry > now)
+    }
+
+    pub fn get(&self, field: &str) -> Option<&String> {
+        self.fields
+            .get(field)
+            .filter(|_| self.is_live(field, now_millis()))
+    }
+
+    pub fn contains_key(&self, field: &str) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets a field, clearing any expiry it had. Returns the previous value if it was live.
+    pub fn insert(&mut self, field: String, value: String) -> Option<String> {
+        let live = self.is_live(&field, now_millis());
+        self.expiries.remove(&field);
+        self.fields.insert(field, value).filter(|_| live)
+    }
+
+    /// Removes a field, returning its value if it was live.
+    pub fn remove(&mut self, field: &str) -> Option<String> {
+        let live = self.is_live(field, now_millis());
+        self.expiries.remove(field);
+        self.fields.remove(field).filter(|_| live)
+    }
+
+    /// Iterates over the live fields and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        let now = now_millis();
+        self.fields
+            .iter()
+            .filter(move |(field, _)| self.is_live(field, now))
+    }
+
+    /// Returns the number of live fields.
+    pub fn len(&self) -> usize {
+        if self.expiries.is_empty() {
+            self.fields.len()
+        } else {
+            self.iter().count()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the expiry of a live field, or None if the field doesn't exist.
+    /// The inner value is None when the field has no expiry.
+    pub fn expiry(&self, field: &str) -> Option<Option<u64>> {
+        self.contains_key(field)
+            .then(|| self.expiries.get(field).copied())
+    }
+
+    /// Sets the expiry of an existing field.
+    pub fn set_expiry(&mut self, field: &str, expiry: u64) {
+        if self.fields.contains_key(field) {
+            self.expiries.insert(field.to_string(), expiry);
+        }
+    }
+
+    /// Removes the expiry of a field, returning whether it had one.
+    pub fn persist(&mut self, field: &str) -> bool {
+        self.expiries.remove(field).is_some()
+    }
+
+    /// Iterates over the fields that have an expiry, along with it.
+    pub fn expiries(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.expiries.iter().map(|(field, &expiry)| (field, expiry))
+    }
+
+    /// Returns true if every field has expired, so the whole hash is gone.
+    fn is_expired(&self, now: u64) -> bool {
+        !self.fields.is_empty()
+            && self.expiries.len() == self.fields.len()
+            && self.expiries.values().all(|&expiry| expiry <= now)
+    }
+
+    fn remove_expired_fields(&mut self, now: u64) {
+        if self.expiries.is_empty() {
+            return;
+        }
+        let expired: Vec<_> = self
+            .expiries
+            .iter()
+            .filter(|(_, &expiry)| expiry <= now)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in expired {
+            self.expiries.remove(&field);
+            self.fields.remove(&field);
+        }
+    }
+}
+
+impl From<Hash> for RedisValue {
+    fn from(hash: Hash) -> Self {
+        RedisValue::Hash(hash)
+    }
+}
+
+impl Collection for Hash {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError> {
+        match value {
+            RedisValue::Hash(hash) => Ok(hash),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError> {
+        match value {
+            RedisValue::Hash(hash) => Ok(hash),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        Hash::is_empty(self)
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        self.remove_expired_fields(now);
+    }
+}
+
+/// A sorted set score, ordered with `f64::total_cmp` so it can key a BTreeSet.
+/// Scores are never NaN, and -0 is stored as 0 so the two compare equal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A sorted set value. Members are kept both by name, for score lookups, and ordered by
+/// score then name, for range queries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    ordered: BTreeSet<(Score, String)>,
+}
+
+impl SortedSet {
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds a member or updates its score. Returns the previous score if it was a member.
+    pub fn insert(&mut self, member: String, score: f64) -> Option<f64> {
+        let score = score + 0.0;
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.ordered.remove(&(Score(previous), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        previous
+    }
+
+    /// Removes a member, returning its score if it was a member.
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.ordered.remove(&(Score(score), member));
+        Some(score)
+    }
+
+    /// Removes and returns the member with the lowest score, or the highest with `end` Max.
+    pub fn pop(&mut self, end: ScoreEnd) -> Option<(String, f64)> {
+        let (score, member) = match end {
+            ScoreEnd::Min => self.ordered.pop_first()?,
+            ScoreEnd::Max => self.ordered.pop_last()?,
+        };
+        self.scores.remove(&member);
+        Some((member, score.0))
+    }
+
+    /// Returns the 0-based position of a member, ordered from the lowest score.
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(
+            self.ordered
+                .range(..(Score(score), member.to_string()))
+                .count(),
+        )
+    }
+
+    /// Iterates over the members and their scores from the lowest score to the highest,
+    /// with equal scores ordered by member.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&String, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// Iterates in order over the members scored between `min` and `max`.
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> impl DoubleEndedIterator<Item = (&String, f64)> {
+        // Members with the same score are ordered by name and the empty name sorts first,
+        // so (score, "") is the lowest possible entry with that score
+        let lowest = |score: f64| (Score(score + 0.0), String::new());
+        let start = match min.exclusive {
+            true => lowest(min.value.next_up()),
+            false => lowest(min.value),
+        };
+        let end = match max.exclusive {
+            true => Bound::Excluded(lowest(max.value)),
+            false if max.value == f64::INFINITY => Bound::Unbounded,
+            false => Bound::Excluded(lowest(max.value.next_up())),
+        };
+        // BTreeSet::range panics on a start past its end, and there's nothing above +inf
+        let empty = (min.exclusive && min.value == f64::INFINITY)
+            || matches!(&end, Bound::Excluded(end) if start > *end);
+        (!empty)
+            .then(|| self.ordered.range((Bound::Included(start), end)))
+            .into_iter()
+            .flatten()
+            .map(|(score, member)| (member, score.0))
+    }
+
+    /// Iterates in order over the members between `min` and `max`. Lex ranges are meant for
+    /// sets whose members all have the same score, where the order is by member.
+    pub fn range_by_lex<'a>(
+        &'a self,
+        min: &'a LexBound,
+        max: &'a LexBound,
+    ) -> impl DoubleEndedIterator<Item = (&'a String, f64)> {
+        self.iter()
+            .filter(|(member, _)| min.admits_min(member) && max.admits_max(member))
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+impl From<SortedSet> for RedisValue {
+    fn from(set: SortedSet) -> Self {
+        RedisValue::SortedSet(set)
+    }
+}
+
+impl Collection for SortedSet {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError> {
+        match value {
+            RedisValue::SortedSet(set) => Ok(set),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError> {
+        match value {
+            RedisValue::SortedSet(set) => Ok(set),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        SortedSet::is_empty(self)
+    }
+}
+
+/// The fields and values of a stream entry.
+pub type StreamFields = Vec<(String, String)>;
+
+/// The number of entries a node of a real Redis stream holds, which `~` trims work in.
+const STREAM_NODE_ENTRIES: usize = 100;
+
+/// A stream value. Entries are kept ordered by ID, along with the last ID given out,
+/// which new entries must be above even once the entries before them are deleted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamFields>,
+    last_id: StreamId,
+    /// The number of entries ever added, including deleted ones
+    entries_added: u64,
+    /// The highest ID deleted with XDEL
+    max_deleted_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>,
+}
+
+/// A consumer group of a stream, sharing its entries out between its consumers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumerGroup {
+    /// The ID of the last entry delivered to any of the consumers
+    pub last_delivered: StreamId,
+    /// The number of entries ever added that the group has read, if it can be worked out
+    pub entries_read: Option<u64>,
+    /// Entries delivered but not acknowledged yet, by ID
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<String, Consumer>,
+}
+
+/// An entry delivered to a consumer that hasn't acknowledged it yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// Time of the last delivery in unix milliseconds
+    pub delivered: u64,
+    /// Number of times the entry was delivered
+    pub deliveries: u64,
+}
+
+/// A consumer of a group, with the times it was last seen and last read something
+/// in unix milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consumer {
+    pub seen: u64,
+    pub active: Option<u64>,
+}
+
+/// The outcome of an XAUTOCLAIM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoClaim {
+    /// The ID to continue the scan from, or 0-0 once every pending entry was scanned
+    pub next: StreamId,
+    pub claimed: Vec<(StreamId, StreamFields)>,
+    /// Pending entries that were no longer in the stream, which were dropped
+    pub deleted: Vec<StreamId>,
+}
+
+impl PendingEntry {
+    /// Hands the entry over to a consumer, as XCLAIM and XAUTOCLAIM do. The claim counts
+    /// as a delivery unless `deliveries` sets the count or `just_id` is set.
+    fn claim(&mut self, consumer: &str, delivered: u64, deliveries: Option<u64>, just_id: bool) {
+        self.consumer = consumer.to_string();
+        self.delivered = delivered;
+        match deliveries {
+            Some(deliveries) => self.deliveries = deliveries,
+            None if !just_id => self.deliveries += 1,
+            None => {}
+        }
+    }
+
+    /// Returns how long ago the entry was last delivered, in milliseconds.
+    pub fn idle(&self, now: u64) -> u64 {
+        now.saturating_sub(self.delivered)
+    }
+}
+
+impl ConsumerGroup {
+    /// Records that a consumer was seen, creating it if needed, and that it was active if
+    /// it read or claimed anything.
+    fn see_consumer(&mut self, name: &str, now: u64, active: bool) {
+        let consumer = self.consumers.entry(name.to_string()).or_insert(Consumer {
+            seen: now,
+            active: None,
+        });
+        consumer.seen = now;
+        if active {
+            consumer.active = Some(now);
+        }
+    }
+
+    /// Adds a consumer, returning false if the group already has one with that name.
+    pub fn create_consumer(&mut self, name: &str) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen: now_millis(),
+            active: None,
+        };
+        self.consumers.insert(name.to_string(), consumer);
+        true
+    }
+
+    /// Removes a consumer along with its pending entries, returning how many it had.
+    pub fn delete_consumer(&mut self, name: &str) -> usize {
+        if self.consumers.remove(name).is_none() {
+            return 0;
+        }
+        let pending = self.pending.len();
+        self.pending.retain(|_, entry| entry.consumer != name);
+        pending - self.pending.len()
+    }
+
+    /// Acknowledges pending entries, returning how many were pending.
+    pub fn ack(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter()
+            .filter(|id| self.pending.remove(id).is_some())
+            .count()
+    }
+}
+
+impl Stream {
+    /// Adds an entry with the ID XADD asked for, returning the ID it was given.
+    /// Fails if that ID isn't above the last one.
+    pub fn add(&mut self, id: XAddId, fields: StreamFields) -> Result<StreamId, anyhow::Error> {
+        let id = self.next_id(id)?;
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+        Ok(id)
+    }
+
+    /// Returns the ID an entry added with the ID XADD asked for would be given.
+    pub fn next_id(&self, id: XAddId) -> Result<StreamId, anyhow::Error> {
+        let last = self.last_id;
+        Ok(match id {
+            XAddId::Auto => {
+                let ms = now_millis().max(last.ms);
+                if ms == last.ms {
+                    last.next().ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "The stream has exhausted the last possible ID, unable to add more items"
+                        )
+                    })?
+                } else {
+                    StreamId { ms, seq: 0 }
+                }
+            }
+            XAddId::AutoSequence(ms) if ms == last.ms => StreamId {
+                ms,
+                seq: last.seq.checked_add(1).ok_or_else(id_too_small)?,
+            },
+            XAddId::AutoSequence(ms) if ms > last.ms => StreamId { ms, seq: 0 },
+            XAddId::Explicit(StreamId::MIN) => {
+                anyhow::bail!("The ID specified in XADD must be greater than 0-0")
+            }
+            XAddId::Explicit(id) if id > last => id,
+            _ => return Err(id_too_small()),
+        })
+    }
+
+    /// Evicts the oldest entries as XTRIM does, returning how many were evicted. An
+    /// approximate trim only evicts whole nodes' worth of entries, up to its limit.
+    pub fn trim(&mut self, trim: &StreamTrim) -> usize {
+        let (chunk, limit) = match trim.limit {
+            _ if !trim.approximate => (1, None),
+            Some(0) => (STREAM_NODE_ENTRIES, None),
+            Some(limit) => (STREAM_NODE_ENTRIES, Some(limit)),
+            None => (STREAM_NODE_ENTRIES, Some(STREAM_NODE_ENTRIES * 100)),
+        };
+        let mut trimmed = 0;
+        loop {
+            let batch: Vec<StreamId> = self.entries.keys().take(chunk).copied().collect();
+            let Some(&last) = batch.last() else {
+                break;
+            };
+            let evict = match trim.strategy {
+                TrimStrategy::MaxLen(max_len) => self.entries.len() - batch.len() >= max_len,
+                TrimStrategy::MinId(min_id) => last < min_id,
+            };
+            if !evict || limit.is_some_and(|limit| trimmed + batch.len() > limit) {
+                break;
+            }
+            for id in &batch {
+                self.entries.remove(id);
+            }
+            trimmed += batch.len();
+        }
+        trimmed
+    }
+
+    /// Deletes entries by ID as XDEL does, returning how many existed.
+    pub fn delete(&mut self, ids: &[StreamId]) -> usize {
+        let mut deleted = 0;
+        for id in ids {
+            if self.entries.remove(id).is_some() {
+                self.max_deleted_id = self.max_deleted_id.max(*id);
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// Reads entries for a consumer of a group as XREADGROUP does, creating the consumer if
+    /// needed. New entries are marked as delivered to it, and added to its pending entries
+    /// unless `no_ack` is set. Pending entries that have since been deleted are read
+    /// without fields. Returns None if there's no such group.
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        id: GroupReadId,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Option<Vec<(StreamId, Option<StreamFields>)>> {
+        let current = self.groups.get(group)?;
+        let (last_delivered, mut entries_read) = (current.last_delivered, current.entries_read);
+        let now = now_millis();
+        let count = count.unwrap_or(usize::MAX);
+        let new: Vec<_> = match id {
+            GroupReadId::New => self
+                .entries
+                .range((Bound::Excluded(last_delivered), Bound::Unbounded))
+                .take(count)
+                .map(|(id, fields)| (*id, Some(fields.clone())))
+                .collect(),
+            GroupReadId::History(_) => Vec::new(),
+        };
+        for (id, _) in &new {
+            entries_read = match entries_read {
+                Some(read) if !self.has_tombstones_from(*id) => Some(read + 1),
+                _ => self.estimate_entries_read(*id),
+            };
+        }
+        let group = self.groups.get_mut(group)?;
+        let read: Vec<_> = match id {
+            GroupReadId::New => {
+                group.entries_read = entries_read;
+                for (id, _) in &new {
+                    group.last_delivered = *id;
+                    if !no_ack {
+                        let entry = PendingEntry {
+                            consumer: consumer.to_string(),
+                            delivered: now,
+                            deliveries: 1,
+                        };
+                        group.pending.insert(*id, entry);
+                    }
+                }
+                new
+            }
+            GroupReadId::History(after) => group
+                .pending
+                .range_mut((Bound::Excluded(after), Bound::Unbounded))
+                .filter(|(_, entry)| entry.consumer == consumer)
+                .take(count)
+                .map(|(id, entry)| {
+                    let fields = self.entries.get(id).cloned();
+                    if fields.is_some() {
+                        entry.delivered = now;
+                        entry.deliveries += 1;
+                    }
+                    (*id, fields)
+                })
+                .collect(),
+        };
+        group.see_consumer(consumer, now, !read.is_empty());
+        Some(read)
+    }
+
+    /// Claims pending entries idle for at least `min_idle` milliseconds for a consumer of a
+    /// group as XCLAIM does, creating the consumer if needed. Pending entries no longer in
+    /// the stream are dropped rather than claimed. Returns None if there's no such group.
+    pub fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId],
+        options: &XClaimOptions,
+    ) -> Option<Vec<(StreamId, StreamFields)>> {
+        let group = self.groups.get_mut(group)?;
+        let now = now_millis();
+        let mut claimed = Vec::new();
+        for id in ids {
+            let Some(fields) = self.entries.get(id) else {
+                group.pending.remove(id);
+                continue;
+            };
+            if options.force && !group.pending.contains_key(id) {
+                // Never delivered, so it's been idle for as long as can be
+                let entry = PendingEntry {
+                    consumer: consumer.to_string(),
+                    delivered: 0,
+                    deliveries: 0,
+                };
+                group.pending.insert(*id, entry);
+            }
+            let Some(entry) = group.pending.get_mut(id) else {
+                continue;
+            };
+            if entry.idle(now) < min_idle {
+                continue;
+            }
+            let delivered = options.delivered.min(now);
+            entry.claim(consumer, delivered, options.retry_count, options.just_id);
+            claimed.push((*id, fields.clone()));
+        }
+        if let Some(last_id) = options.last_id {
+            group.last_delivered = group.last_delivered.max(last_id);
+        }
+        group.see_consumer(consumer, now, !claimed.is_empty());
+        Some(claimed)
+    }
+
+    /// Scans a group's pending entries from `start`, claiming up to `count` of those idle
+    /// for at least `min_idle` milliseconds for a consumer as XAUTOCLAIM does. At most ten
+    /// times `count` entries are scanned in one go. Returns None if there's no such group.
+    pub fn auto_claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> Option<AutoClaim> {
+        let group = self.groups.get_mut(group)?;
+        let now = now_millis();
+        let attempts = count.saturating_mul(10);
+        let candidates: Vec<_> = group
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(attempts.saturating_add(1))
+            .collect();
+        let (mut claimed, mut deleted, mut scanned) = (Vec::new(), Vec::new(), 0);
+        for id in &candidates {
+            if scanned == attempts || claimed.len() == count {
+                break;
+            }
+            scanned += 1;
+            let Some(entry) = group.pending.get_mut(id) else {
+                continue;
+            };
+            if entry.idle(now) < min_idle {
+                continue;
+            }
+            let Some(fields) = self.entries.get(id) else {
+                group.pending.remove(id);
+                deleted.push(*id);
+                continue;
+            };
+            entry.claim(consumer, now, None, just_id);
+            claimed.push((*id, fields.clone()));
+        }
+        group.see_consumer(consumer, now, !claimed.is_empty());
+        Some(AutoClaim {
+            next: candidates.get(scanned).copied().unwrap_or(StreamId::MIN),
+            claimed,
+            deleted,
+        })
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Iterates over the consumer groups by name.
+    pub fn groups(&self) -> impl Iterator<Item = (&String, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    /// Adds a consumer group that has been delivered the entries up to `last_delivered`.
+    /// Returns false if there's already a group with that name.
+    pub fn create_group(&mut self, name: &str, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        let group = ConsumerGroup {
+            last_delivered,
+            entries_read: self.estimate_entries_read(last_delivered),
+            ..Default::default()
+        };
+        self.groups.insert(name.to_string(), group);
+        true
+    }
+
+    /// Moves a group's last delivered ID as XGROUP SETID does, returning false if there's
+    /// no such group.
+    pub fn set_group_id(&mut self, name: &str, last_delivered: StreamId) -> bool {
+        let entries_read = self.estimate_entries_read(last_delivered);
+        let Some(group) = self.groups.get_mut(name) else {
+            return false;
+        };
+        group.last_delivered = last_delivered;
+        group.entries_read = entries_read;
+        true
+    }
+
+    /// Returns how many entries a group has yet to read, if that can be worked out.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(group.last_delivered) => Some(read),
+            _ => self.estimate_entries_read(group.last_delivered),
+        };
+        entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    /// Works out how many entries ever added come up to `id`, as Redis does to count what
+    /// a group has read. That's only known when `id` is the last ID, or comes before the
+    /// first entry with nothing deleted since.
+    fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let Some((&first, _)) = self.entries.first_key_value() else {
+            return (id <= self.last_id).then_some(self.entries_added);
+        };
+        if id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id
+            || (self.max_deleted_id != StreamId::MIN && self.max_deleted_id >= first)
+        {
+            return None;
+        }
+        let before_first = self.entries_added - self.entries.len() as u64;
+        match id.cmp(&first) {
+            std::cmp::Ordering::Less => Some(before_first),
+            std::cmp::Ordering::Equal => Some(before_first + 1),
+            std::cmp::Ordering::Greater => None,
+        }
+    }
+
+    /// Returns whether entries at or after `id` were deleted, which leaves gaps that
+    /// throw counting what a group has read off.
+    fn has_tombstones_from(&self, id: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && self.max_deleted_id >= id
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Iterates in order over the entries with IDs between `start` and `end` inclusive.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &StreamFields)> {
+        // BTreeMap::range panics on a start past its end
+        (start <= end)
+            .then(|| self.entries.range(start..=end))
+            .into_iter()
+            .flatten()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Returns how many nodes a Redis stream of this length would take up, which XINFO
+    /// reports in place of its radix tree sizes.
+    pub fn node_count(&self) -> usize {
+        self.entries.len().div_ceil(STREAM_NODE_ENTRIES)
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// Sets the last ID given out as XSETID does, along with the number of entries ever
+    /// added and the highest deleted ID if given. Fails if they contradict the entries.
+    pub fn set_last_id(
+        &mut self,
+        id: StreamId,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamId>,
+    ) -> Result<(), anyhow::Error> {
+        if max_deleted_id.is_some_and(|max_deleted_id| id < max_deleted_id) {
+            anyhow::bail!(
+                "The ID specified in XSETID is smaller than the provided max_deleted_entry_id"
+            );
+        }
+        if self
+            .entries
+            .last_key_value()
+            .is_some_and(|(&top, _)| id < top)
+        {
+            anyhow::bail!("The ID specified in XSETID is smaller than the target stream top item");
+        }
+        if entries_added.is_some_and(|entries_added| entries_added < self.entries.len() as u64) {
+            anyhow::bail!(
+                "The entries_added specified in XSETID is smaller than the target stream length"
+            );
+        }
+        self.last_id = id;
+        if let Some(entries_added) = entries_added {
+            self.entries_added = entries_added;
+        }
+        if let Some(max_deleted_id) = max_deleted_id {
+            self.max_deleted_id = max_deleted_id;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn id_too_small() -> anyhow::Error {
+    anyhow::anyhow!("The ID specified in XADD is equal or smaller than the target stream top item")
+}
+
+impl From<Stream> for RedisValue {
+    fn from(stream: Stream) -> Self {
+        RedisValue::Stream(stream)
+    }
+}
+
+impl Collection for Stream {
+    fn of(value: &RedisValue) -> Result<&Self, WrongTypeError> {
+        match value {
+            RedisValue::Stream(stream) => Ok(stream),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    fn of_mut(value: &mut RedisValue) -> Result<&mut Self, WrongTypeError> {
+        match value {
+            RedisValue::Stream(stream) => Ok(stream),
+            _ => Err(WrongTypeError),
+        }
+    }
+
+    /// Unlike other collections a stream keeps its key once its entries are deleted, so
+    /// it only counts as empty if nothing was ever added to it, e.g. after a failed XADD.
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.last_id == StreamId::MIN && self.groups.is_empty()
+    }
+}
+
+/// A stored value along with its expiry and access metadata.
+#[derive(Debug)]
+struct Entry {
+    value: RedisValue,
+    /// Absolute expiry in unix milliseconds.
+    expiry: Option<u64>,
+    /// Last access in unix milliseconds. Atomic so reads can record it under the read lock.
+    accessed: AtomicU64,
+}
+
+impl Entry {
+    fn new(value: impl Into<RedisValue>, expiry: Option<u64>) -> Self {
+        Entry {
+            value: value.into(),
+            expiry,
+            accessed: AtomicU64::new(now_millis()),
+        }
+    }
+
+    fn is_live(&self, now: u64) -> bool {
+        let expired = match &self.value {
+            RedisValue::Hash(hash) => hash.is_expired(now),
+            _ => false,
+        };
+        self.expiry.is_none_or(|expiry| expiry > now) && !expired
+    }
+
+    fn touch(&self, now: u64) {
+        self.accessed.store(now, Ordering::Relaxed);
+    }
+}
+
+/// Counts the values handed to background tasks to be freed, as INFO reports them.
+#[derive(Debug, Default)]
+struct LazyFree {
+    /// Values handed off and not yet freed.
+    pending: AtomicU64,
+    /// Values freed in the background since startup.
+    freed: AtomicU64,
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct RedisStore {
-    store: Arc<RwLock<BTreeMap<String, (String, Option<u64>)>>>,
-    expirations: Arc<RwLock<BinaryHeap<Reverse<(u64, String)>>>>,
+    store: Arc<RwLock<Entries>>,
+    expirations: Arc<RwLock<Expirations>>,
+    lazyfree: Arc<LazyFree>,
 }
 
 impl RedisStore {
@@ -19,29 +1059,966 @@ impl RedisStore {
         RedisStore {
             store: Arc::new(RwLock::new(BTreeMap::new())),
             expirations: Arc::new(RwLock::new(BinaryHeap::new())),
+            lazyfree: Arc::new(LazyFree::default()),
+        }
+    }
+
+    /// Returns the string value of a key as raw bytes, or a WRONGTYPE error if it holds
+    /// another type.
+    pub async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        Ok(match self.get_value(key).await {
+            Some(RedisValue::String(value)) => Some(value),
+            Some(_) => return Err(WrongTypeError.into()),
+            None => None,
+        })
+    }
+
+    /// Returns the value of a key, whatever its type.
+    pub async fn get_value(&self, key: &str) -> Option<RedisValue> {
+        let store = self.store.read().await;
+        let entry = store.get(key)?;
+        let now = now_millis();
+        if !entry.is_live(now) {
+            drop(store);
+            self.remove(key).await;
+            return None;
+        }
+        entry.touch(now);
+        Some(entry.value.clone())
+    }
+
+    /// Atomically reads and rewrites the value of a key under the store write lock.
+    /// `update` receives the current value (None if the key is missing or expired) and
+    /// returns the new value along with a result for the caller. The key's expiry is kept.
+    pub async fn update<T>(
+        &self,
+        key: &str,
+        update: impl FnOnce(Option<&str>) -> Result<(String, T), anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let current = store.get(key).filter(|entry| entry.is_live(now));
+        let expiry = current.and_then(|entry| entry.expiry);
+        let current = match current {
+            Some(entry) => Some(entry.value.as_string()?),
+            None => None,
+        };
+        let (value, result) = update(current.as_deref())?;
+        store.insert(key.to_string(), Entry::new(value, expiry));
+        Ok(result)
+    }
+
+    /// Mutates the bytes of a key's string value in place under the store write lock,
+    /// creating it as an empty string if it is missing or expired. The key's expiry is kept.
+    pub async fn mutate<T>(
+        &self,
+        key: &str,
+        mutate: impl FnOnce(&mut Vec<u8>) -> T,
+    ) -> Result<T, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let entry = store
+            .entry(key.to_string())
+            .or_insert_with(|| Entry::new(Vec::new(), None));
+        if !entry.is_live(now) {
+            *entry = Entry::new(Vec::new(), None);
+        }
+        entry.touch(now);
+        Ok(mutate(entry.value.as_bytes_mut()?))
+    }
+
+    /// Reads the collection stored at a key. Returns None if the key is missing or expired,
+    /// or a WRONGTYPE error if it holds another type.
+    async fn collection<C: Collection, T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&C) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        let Some(entry) = store.get(key).filter(|entry| entry.is_live(now)) else {
+            return Ok(None);
+        };
+        entry.touch(now);
+        Ok(Some(read(C::of(&entry.value)?)))
+    }
+
+    /// Mutates the collection stored at a key under the store write lock. With `create` set
+    /// a missing or expired key starts out as an empty collection, otherwise `mutate` isn't
+    /// called and None is returned. A collection left empty is removed.
+    async fn collection_mut<C: Collection, T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut C) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        if store.get(key).is_some_and(|entry| !entry.is_live(now)) {
+            store.remove(key);
+        }
+        if !store.contains_key(key) {
+            if !create {
+                return Ok(None);
+            }
+            store.insert(key.to_string(), Entry::new(C::default(), None));
+        }
+        let Some(entry) = store.get_mut(key) else {
+            return Ok(None);
+        };
+        entry.touch(now);
+        let collection = C::of_mut(&mut entry.value)?;
+        collection.remove_expired(now);
+        let result = mutate(collection);
+        if collection.is_empty() {
+            store.remove(key);
+        }
+        Ok(Some(result))
+    }
+
+    /// Reads the list stored at a key, see `collection`.
+    pub async fn list<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&VecDeque<String>) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection(key, read).await
+    }
+
+    /// Mutates the list stored at a key, see `collection_mut`.
+    pub async fn list_mut<T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut VecDeque<String>) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection_mut(key, create, mutate).await
+    }
+
+    /// Reads the hash stored at a key, see `collection`.
+    pub async fn hash<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&Hash) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection(key, read).await
+    }
+
+    /// Mutates the hash stored at a key, see `collection_mut`.
+    pub async fn hash_mut<T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut Hash) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection_mut(key, create, mutate).await
+    }
+
+    /// Reads the set stored at a key, see `collection`.
+    pub async fn set_members<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&HashSet<String>) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection(key, read).await
+    }
+
+    /// Mutates the set stored at a key, see `collection_mut`.
+    pub async fn set_members_mut<T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut HashSet<String>) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection_mut(key, create, mutate).await
+    }
+
+    /// Reads the sorted set stored at a key, see `collection`.
+    pub async fn sorted_set<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&SortedSet) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection(key, read).await
+    }
+
+    /// Mutates the sorted set stored at a key, see `collection_mut`.
+    pub async fn sorted_set_mut<T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut SortedSet) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection_mut(key, create, mutate).await
+    }
+
+    /// Reads the stream stored at a key, see `collection`.
+    pub async fn stream<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&Stream) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection(key, read).await
+    }
+
+    /// Mutates the stream stored at a key, see `collection_mut`.
+    pub async fn stream_mut<T>(
+        &self,
+        key: &str,
+        create: bool,
+        mutate: impl FnOnce(&mut Stream) -> T,
+    ) -> Result<Option<T>, anyhow::Error> {
+        self.collection_mut(key, create, mutate).await
+    }
+
+    /// Stores a sorted set derived from the one at `source` at `destination` under a single
+    /// write lock, replacing whatever it held, as ZRANGESTORE does. A missing source counts
+    /// as empty and an empty result removes `destination`, while an error from `derive`
+    /// leaves it alone. Returns the size of the result.
+    pub async fn derive_sorted_set(
+        &self,
+        source: &str,
+        destination: &str,
+        derive: impl FnOnce(&SortedSet) -> Result<SortedSet, anyhow::Error>,
+    ) -> Result<usize, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let set = match store.get(source).filter(|entry| entry.is_live(now)) {
+            Some(entry) => derive(SortedSet::of(&entry.value)?)?,
+            None => SortedSet::default(),
+        };
+        let len = set.len();
+        replace_collection(&mut store, destination, set);
+        Ok(len)
+    }
+
+    /// Combines the sorted sets at `keys` under a single read lock, for ZINTER, ZUNION and
+    /// ZDIFF. Missing keys count as empty and plain sets as members scored 1.
+    pub async fn combine_sorted_sets(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+        options: &ZSetOpOptions,
+    ) -> Result<SortedSet, anyhow::Error> {
+        let store = self.store.read().await;
+        Ok(combine_sorted_sets(
+            &store,
+            operation,
+            keys,
+            options,
+            now_millis(),
+        )?)
+    }
+
+    /// Combines the live strings at `keys`, with missing keys as empty strings, and stores
+    /// the result at `destination` under a single write lock, replacing whatever it held.
+    /// An empty result removes `destination`. Returns the length of the stored string.
+    pub async fn store_combined_strings(
+        &self,
+        destination: &str,
+        keys: &[String],
+        combine: impl FnOnce(&[&[u8]]) -> Result<Vec<u8>, anyhow::Error>,
+    ) -> Result<usize, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let strings = keys
+            .iter()
+            .map(
+                |key| match store.get(key).filter(|entry| entry.is_live(now)) {
+                    Some(entry) => entry.value.as_bytes().map(Vec::as_slice),
+                    None => Ok(&[][..]),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = combine(&strings)?;
+        let len = value.len();
+        if value.is_empty() {
+            store.remove(destination);
+        } else {
+            store.insert(destination.to_string(), Entry::new(value, None));
+        }
+        Ok(len)
+    }
+
+    /// Combines the sorted sets at `keys` and stores the result at `destination` under a
+    /// single write lock, replacing whatever it held. An empty result removes
+    /// `destination`. Returns the size of the stored sorted set.
+    pub async fn store_combined_sorted_sets(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+        options: &ZSetOpOptions,
+    ) -> Result<usize, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let set = combine_sorted_sets(&store, operation, keys, options, now_millis())?;
+        let len = set.len();
+        replace_collection(&mut store, destination, set);
+        Ok(len)
+    }
+
+    /// Moves `member` from the set at `source` to the set at `destination` under a single
+    /// write lock, as SMOVE does. Returns whether the member was in the source set.
+    pub async fn set_move(
+        &self,
+        source: &str,
+        destination: &str,
+        member: String,
+    ) -> Result<bool, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        for key in [source, destination] {
+            if store.get(key).is_some_and(|entry| !entry.is_live(now)) {
+                store.remove(key);
+            }
+        }
+        // Check both types before removing so a WRONGTYPE destination leaves the source as is
+        let Some(entry) = store.get(source) else {
+            return Ok(false);
+        };
+        HashSet::of(&entry.value)?;
+        if let Some(entry) = store.get(destination) {
+            HashSet::of(&entry.value)?;
+        }
+
+        let Some(entry) = store.get_mut(source) else {
+            return Ok(false);
+        };
+        entry.touch(now);
+        let set = HashSet::of_mut(&mut entry.value)?;
+        if !set.remove(&member) {
+            return Ok(false);
+        }
+        if set.is_empty() {
+            store.remove(source);
+        }
+
+        let entry = store
+            .entry(destination.to_string())
+            .or_insert_with(|| Entry::new(HashSet::new(), None));
+        entry.touch(now);
+        HashSet::of_mut(&mut entry.value)?.insert(member);
+        Ok(true)
+    }
+
+    /// Combines the sets at `keys` under a single read lock, treating missing keys as
+    /// empty sets.
+    pub async fn combine_sets(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+    ) -> Result<HashSet<String>, anyhow::Error> {
+        let store = self.store.read().await;
+        Ok(combine_sets(&store, operation, keys, now_millis())?)
+    }
+
+    /// Counts the members of the intersection of the sets at `keys` without building it,
+    /// stopping once `limit` members are found unless it is 0.
+    pub async fn intersection_len(
+        &self,
+        keys: &[String],
+        limit: usize,
+    ) -> Result<usize, anyhow::Error> {
+        let store = self.store.read().await;
+        let empty = HashSet::new();
+        let mut sets = sets_at(&store, keys, &empty, now_millis())?;
+        // Walking the smallest set keeps the number of lookups down
+        sets.sort_by_key(|set| set.len());
+        let Some((smallest, rest)) = sets.split_first() else {
+            return Ok(0);
+        };
+        let members = smallest
+            .iter()
+            .filter(|member| rest.iter().all(|set| set.contains(*member)));
+        Ok(match limit {
+            0 => members.count(),
+            limit => members.take(limit).count(),
+        })
+    }
+
+    /// Combines the sets at `keys` and stores the result at `destination` under a single
+    /// write lock, replacing whatever it held. An empty result removes `destination`.
+    /// Returns the size of the stored set.
+    pub async fn store_combined_sets(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> Result<usize, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let set = combine_sets(&store, operation, keys, now_millis())?;
+        let len = set.len();
+        replace_collection(&mut store, destination, set);
+        Ok(len)
+    }
+
+    /// Stores `list` at `key`, replacing whatever it held, as SORT with STORE does. An empty
+    /// list removes `key`. Returns the length of the stored list.
+    pub async fn replace_list(&self, key: &str, list: VecDeque<String>) -> usize {
+        let mut store = self.store.write().await;
+        let len = list.len();
+        replace_collection(&mut store, key, list);
+        len
+    }
+
+    /// Pops an element from the `from` end of the list at `source` and pushes it onto the
+    /// `to` end of the list at `destination` under a single write lock, as LMOVE does.
+    /// Returns the moved element, or None if the source list doesn't exist.
+    pub async fn list_move(
+        &self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        for key in [source, destination] {
+            if store.get(key).is_some_and(|entry| !entry.is_live(now)) {
+                store.remove(key);
+            }
+        }
+        // Check both types before popping so a WRONGTYPE destination leaves the source as is
+        let Some(entry) = store.get(source) else {
+            return Ok(None);
+        };
+        VecDeque::of(&entry.value)?;
+        if let Some(entry) = store.get(destination) {
+            VecDeque::of(&entry.value)?;
+        }
+
+        let Some(entry) = store.get_mut(source) else {
+            return Ok(None);
+        };
+        entry.touch(now);
+        let list = VecDeque::of_mut(&mut entry.value)?;
+        let value = match from {
+            ListEnd::Left => list.pop_front(),
+            ListEnd::Right => list.pop_back(),
+        };
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        if list.is_empty() {
+            store.remove(source);
+        }
+
+        let entry = store
+            .entry(destination.to_string())
+            .or_insert_with(|| Entry::new(VecDeque::new(), None));
+        entry.touch(now);
+        let list = VecDeque::of_mut(&mut entry.value)?;
+        match to {
+            ListEnd::Left => list.push_front(value.clone()),
+            ListEnd::Right => list.push_back(value.clone()),
+        }
+        Ok(Some(value))
+    }
+
+    /// Returns the bytes of a key's value between `start` and `end` inclusive.
+    /// Negative offsets count back from the end of the string, as in GETRANGE.
+    pub async fn get_range(
+        &self,
+        key: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let Some(bytes) = self.get_bytes(key).await? else {
+            return Ok(Vec::new());
+        };
+        let len = bytes.len() as i64;
+        let start = if start < 0 { len + start } else { start }.max(0);
+        let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+        if len == 0 || start > end {
+            return Ok(Vec::new());
+        }
+        Ok(bytes[start as usize..=end as usize].to_vec())
+    }
+
+    /// Overwrites part of a key's value starting at byte `offset`, padding with zero
+    /// bytes if the value is shorter than the offset. Returns the new length.
+    /// An empty `value` leaves the store untouched, and doesn't create the key.
+    pub async fn set_range(
+        &self,
+        key: &str,
+        offset: usize,
+        value: &[u8],
+    ) -> Result<usize, anyhow::Error> {
+        if value.is_empty() {
+            return Ok(self
+                .get_bytes(key)
+                .await?
+                .map_or(0, |current| current.len()));
         }
+        self.mutate(key, |bytes| {
+            let end = offset + value.len();
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+            bytes[offset..end].copy_from_slice(value);
+            bytes.len()
+        })
+        .await
+    }
+
+    /// Atomically adds `delta` to the integer stored at a key, treating a missing key as 0.
+    pub async fn incr_by(&self, key: &str, delta: i64) -> Result<i64, anyhow::Error> {
+        self.update(key, |current| {
+            let current = match current {
+                Some(value) => value
+                    .parse::<i64>()
+                    .map_err(|_| anyhow::anyhow!("value is not an integer or out of range"))?,
+                None => 0,
+            };
+            let value = current
+                .checked_add(delta)
+                .ok_or_else(|| anyhow::anyhow!("increment or decrement would overflow"))?;
+            Ok((value.to_string(), value))
+        })
+        .await
+    }
+
+    /// Atomically adds `delta` to the float stored at a key, treating a missing key as 0.
+    /// Returns the new value formatted as it is stored.
+    pub async fn incr_by_float(&self, key: &str, delta: f64) -> Result<String, anyhow::Error> {
+        self.update(key, |current| {
+            let current = match current {
+                Some(value) => parse_float(value)
+                    .ok_or_else(|| anyhow::anyhow!("value is not a valid float"))?,
+                None => 0.0,
+            };
+            let value = current + delta;
+            if !value.is_finite() {
+                anyhow::bail!("increment would produce NaN or Infinity");
+            }
+            let value = format_float(value);
+            Ok((value.clone(), value))
+        })
+        .await
+    }
+
+    /// Returns the number of the given keys that exist and have not expired.
+    /// Keys given more than once are counted each time.
+    pub async fn exists(&self, keys: &[String]) -> usize {
+        let store = self.store.read().await;
+        let now = now_millis();
+        keys.iter()
+            .filter(|key| {
+                store
+                    .get(key.as_str())
+                    .is_some_and(|entry| entry.is_live(now))
+            })
+            .count()
+    }
+
+    /// Records an access on each of the given keys that exist.
+    /// Returns the number of keys touched, counting repeated keys each time.
+    pub async fn touch(&self, keys: &[String]) -> usize {
+        let store = self.store.read().await;
+        let now = now_millis();
+        keys.iter()
+            .filter_map(|key| store.get(key.as_str()).filter(|entry| entry.is_live(now)))
+            .map(|entry| entry.touch(now))
+            .count()
+    }
+
+    /// Returns the number of milliseconds since a live key was last accessed.
+    pub async fn idle_time(&self, key: &str) -> Option<u64> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        let entry = store.get(key).filter(|entry| entry.is_live(now))?;
+        Some(now.saturating_sub(entry.accessed.load(Ordering::Relaxed)))
     }
 
-    pub async fn get(&self, key: &str) -> Option<String> {
+    /// Returns the name of the internal encoding Redis would use for a live key's value,
+    /// as reported by OBJECT ENCODING. Sorted sets within `zset_limits` are listpacks.
+    pub async fn encoding(&self, key: &str, zset_limits: ListpackLimits) -> Option<&'static str> {
         let store = self.store.read().await;
-        let value = store.get(key)?;
-        if let Some(expiry) = value.1 {
-            if now_millis() >= expiry {
-                drop(store);
-                self.remove(key).await;
-                return None;
+        let now = now_millis();
+        let entry = store.get(key).filter(|entry| entry.is_live(now))?;
+        Some(match &entry.value {
+            RedisValue::String(value)
+                if value.len() <= 20
+                    && std::str::from_utf8(value)
+                        .is_ok_and(|value| value.parse::<i64>().is_ok()) =>
+            {
+                "int"
+            }
+            RedisValue::String(value) if value.len() <= 44 => "embstr",
+            RedisValue::String(_) => "raw",
+            RedisValue::List(list) if list.len() <= 128 && list.iter().all(|e| e.len() <= 64) => {
+                "listpack"
             }
+            RedisValue::List(_) => "quicklist",
+            RedisValue::Hash(hash)
+                if hash.len() <= 128
+                    && hash
+                        .iter()
+                        .all(|(field, value)| field.len() <= 64 && value.len() <= 64) =>
+            {
+                "listpack"
+            }
+            RedisValue::Hash(_) => "hashtable",
+            RedisValue::Set(set)
+                if set.len() <= 512 && set.iter().all(|member| member.parse::<i64>().is_ok()) =>
+            {
+                "intset"
+            }
+            RedisValue::Set(set) if set.len() <= 128 && set.iter().all(|m| m.len() <= 64) => {
+                "listpack"
+            }
+            RedisValue::Set(_) => "hashtable",
+            RedisValue::SortedSet(set)
+                if set.len() <= zset_limits.max_entries
+                    && set
+                        .iter()
+                        .all(|(member, _)| member.len() <= zset_limits.max_value) =>
+            {
+                "listpack"
+            }
+            RedisValue::SortedSet(_) => "skiplist",
+            RedisValue::Stream(_) => "stream",
+        })
+    }
+
+    /// Returns the type name of a live key, as reported by TYPE.
+    pub async fn value_type(&self, key: &str) -> Option<&'static str> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        store
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.value.type_name())
+    }
+
+    /// Returns a uniformly random live key, or None if there are none.
+    pub async fn random_key(&self) -> Option<String> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        let is_live = |(_, entry): &(&String, &Entry)| entry.is_live(now);
+        let live = store.iter().filter(is_live).count();
+        if live == 0 {
+            return None;
         }
-        Some(value.0.clone())
+        let index = rand::thread_rng().gen_range(0..live);
+        store
+            .iter()
+            .filter(is_live)
+            .nth(index)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Returns all live keys matching a glob pattern, in key order.
+    pub async fn keys(&self, pattern: &str) -> Vec<String> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        store
+            .iter()
+            .filter(|(key, entry)| entry.is_live(now) && glob_match(pattern, key))
+            .map(|(key, _)| key.clone())
+            .collect()
     }
 
     pub async fn set(&self, key: &str, value: &str, expiry: Option<u64>) {
         let mut store = self.store.write().await;
         let mut expirations = self.expirations.write().await;
         if let Some(expiry_time) = expiry {
-            expirations.push(Reverse((expiry_time, key.to_string())));
+            expirations.push(Reverse((expiry_time, key.to_string(), None)));
+        }
+        store.insert(key.to_string(), Entry::new(value.to_string(), expiry));
+    }
+
+    /// Sets a key subject to an optional NX/XX condition, atomically under the write lock,
+    /// replacing any value regardless of its type. Returns whether the value was written
+    /// and the previous value of the key, if it held a string.
+    pub async fn set_with_condition(
+        &self,
+        key: &str,
+        value: impl Into<RedisValue>,
+        expiry: Option<u64>,
+        keep_ttl: bool,
+        condition: Option<SetCondition>,
+    ) -> (bool, Option<Vec<u8>>) {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let previous = store
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| (entry.value.as_bytes().ok().cloned(), entry.expiry));
+        let should_set = match condition {
+            Some(SetCondition::Nx) => previous.is_none(),
+            Some(SetCondition::Xx) => previous.is_some(),
+            None => true,
+        };
+        if !should_set {
+            return (false, previous.and_then(|(value, _)| value));
+        }
+
+        let expiry = if keep_ttl {
+            previous.as_ref().and_then(|(_, expiry)| *expiry)
+        } else {
+            expiry
+        };
+        let value = value.into();
+        self.schedule_expirations(key, expiry, &value).await;
+        store.insert(key.to_string(), Entry::new(value, expiry));
+        (true, previous.and_then(|(value, _)| value))
+    }
+
+    /// Sets several keys under a single lock acquisition, clearing any expiry they had.
+    pub async fn set_many(&self, pairs: &[(String, Vec<u8>)]) {
+        let mut store = self.store.write().await;
+        for (key, value) in pairs {
+            store.insert(key.clone(), Entry::new(value.clone(), None));
+        }
+    }
+
+    /// Sets several keys only if none of them exist, under a single lock acquisition.
+    /// Returns whether the keys were set.
+    pub async fn set_many_if_none_exist(&self, pairs: &[(String, Vec<u8>)]) -> bool {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let any_exist = pairs
+            .iter()
+            .any(|(key, _)| store.get(key).is_some_and(|entry| entry.is_live(now)));
+        if any_exist {
+            return false;
+        }
+        for (key, value) in pairs {
+            store.insert(key.clone(), Entry::new(value.clone(), None));
+        }
+        true
+    }
+
+    /// Returns the values of several keys under a single lock acquisition.
+    /// Keys holding another type than a string are reported as missing.
+    pub async fn get_many(&self, keys: &[String]) -> Vec<Option<Vec<u8>>> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        keys.iter()
+            .map(|key| {
+                let entry = store.get(key).filter(|entry| entry.is_live(now))?;
+                entry.touch(now);
+                entry.value.as_bytes().ok().cloned()
+            })
+            .collect()
+    }
+
+    /// Inserts a batch of entries without expiry under a single lock acquisition,
+    /// skipping keys that already exist. Returns the number of keys inserted.
+    pub async fn set_many_if_absent(&self, entries: Vec<(String, Vec<u8>)>) -> usize {
+        let mut store = self.store.write().await;
+        let mut inserted = 0;
+        for (key, value) in entries {
+            if let std::collections::btree_map::Entry::Vacant(entry) = store.entry(key) {
+                entry.insert(Entry::new(value, None));
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    /// Sets the absolute expiry of an existing key, in unix milliseconds, if all of
+    /// `conditions` allow it. A timestamp in the past deletes the key.
+    /// Returns false if the key doesn't exist or a condition wasn't met.
+    pub async fn set_expiry(&self, key: &str, expiry: u64, conditions: &[ExpireCondition]) -> bool {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let Some(entry) = store.get_mut(key) else {
+            return false;
+        };
+        if !entry.is_live(now) {
+            store.remove(key);
+            return false;
+        }
+        entry.touch(now);
+        if !conditions
+            .iter()
+            .all(|condition| condition.allows(entry.expiry, expiry))
+        {
+            return false;
+        }
+        if expiry <= now {
+            store.remove(key);
+            return true;
+        }
+        entry.expiry = Some(expiry);
+        drop(store);
+        self.expirations
+            .write()
+            .await
+            .push(Reverse((expiry, key.to_string(), None)));
+        true
+    }
+
+    /// Returns the absolute expiry of a live key in milliseconds, or None if the key
+    /// doesn't exist. The inner value is None when the key has no expiry.
+    pub async fn expiry(&self, key: &str) -> Option<Option<u64>> {
+        let store = self.store.read().await;
+        let now = now_millis();
+        store
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.expiry)
+    }
+
+    /// Returns the remaining time to live of a live key in milliseconds, or None if the
+    /// key doesn't exist. The inner value is None when the key has no expiry.
+    pub async fn ttl(&self, key: &str) -> Option<Option<u64>> {
+        let now = now_millis();
+        self.expiry(key)
+            .await
+            .map(|expiry| expiry.map(|expiry| expiry.saturating_sub(now)))
+    }
+
+    /// Queues the expiry of a key, and those of its fields if it holds a hash, on the
+    /// expirations heap.
+    async fn schedule_expirations(&self, key: &str, expiry: Option<u64>, value: &RedisValue) {
+        let mut expirations = self.expirations.write().await;
+        if let Some(expiry) = expiry {
+            expirations.push(Reverse((expiry, key.to_string(), None)));
+        }
+        if let RedisValue::Hash(hash) = value {
+            for (field, expiry) in hash.expiries() {
+                expirations.push(Reverse((expiry, key.to_string(), Some(field.clone()))));
+            }
+        }
+    }
+
+    /// Sets the expiry of hash fields subject to the NX/XX/GT/LT conditions, as HEXPIRE
+    /// does. Replies per field with -2 if it doesn't exist, 0 if a condition wasn't met,
+    /// 1 if the expiry was set and 2 if the field was deleted as the expiry has passed.
+    pub async fn set_field_expiry(
+        &self,
+        key: &str,
+        fields: &[String],
+        expiry: u64,
+        conditions: &[ExpireCondition],
+    ) -> Result<Vec<i64>, anyhow::Error> {
+        let now = now_millis();
+        let results = self
+            .hash_mut(key, false, |hash| {
+                fields
+                    .iter()
+                    .map(|field| {
+                        let Some(current) = hash.expiry(field) else {
+                            return -2;
+                        };
+                        if !conditions
+                            .iter()
+                            .all(|condition| condition.allows(current, expiry))
+                        {
+                            return 0;
+                        }
+                        if expiry <= now {
+                            hash.remove(field);
+                            return 2;
+                        }
+                        hash.set_expiry(field, expiry);
+                        1
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await?
+            .unwrap_or_else(|| vec![-2; fields.len()]);
+        let mut expirations = self.expirations.write().await;
+        for (field, &result) in fields.iter().zip(&results) {
+            if result == 1 {
+                expirations.push(Reverse((expiry, key.to_string(), Some(field.clone()))));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Removes the expiry of a key. Returns false if the key doesn't exist or has no expiry.
+    /// The key's entry in the expirations heap is left behind and skipped once it comes due.
+    pub async fn persist(&self, key: &str) -> bool {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        match store.get_mut(key) {
+            Some(entry) if entry.expiry.is_some() && entry.is_live(now) => {
+                entry.touch(now);
+                entry.expiry = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Copies a key's value and expiry to another key. An existing destination is only
+    /// overwritten when `replace` is set. Returns whether the copy happened.
+    pub async fn copy(&self, source: &str, destination: &str, replace: bool) -> bool {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let Some(entry) = store
+            .get(source)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| Entry::new(entry.value.clone(), entry.expiry))
+        else {
+            return false;
+        };
+        if !replace
+            && store
+                .get(destination)
+                .is_some_and(|entry| entry.is_live(now))
+        {
+            return false;
+        }
+        self.schedule_expirations(destination, entry.expiry, &entry.value)
+            .await;
+        store.insert(destination.to_string(), entry);
+        true
+    }
+
+    /// Removes a key holding a string and returns its value, if it was live.
+    /// A key holding another type is left in place.
+    pub async fn take(&self, key: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let Some(entry) = store.get(key).filter(|entry| entry.is_live(now)) else {
+            return Ok(None);
+        };
+        entry.value.as_bytes()?;
+        Ok(store.remove(key).and_then(|entry| match entry.value {
+            RedisValue::String(value) => Some(value),
+            _ => None,
+        }))
+    }
+
+    /// Removes several keys, returning how many were live. With `lazy` set, the removed
+    /// values are dropped on a background task rather than by the calling client.
+    pub async fn delete(&self, keys: &[String], lazy: bool) -> usize {
+        let mut store = self.store.write().await;
+        let now = now_millis();
+        let removed: Vec<_> = keys.iter().filter_map(|key| store.remove(key)).collect();
+        drop(store);
+        let count = removed.iter().filter(|entry| entry.is_live(now)).count();
+        if lazy {
+            let objects = removed.len() as u64;
+            self.free_later(removed, objects);
         }
-        store.insert(key.to_string(), (value.to_string(), expiry));
+        count
+    }
+
+    /// Drops `values` on a blocking task, counting them as `objects` lazily freed values.
+    fn free_later<T: Send + 'static>(&self, values: T, objects: u64) {
+        let lazyfree = self.lazyfree.clone();
+        lazyfree.pending.fetch_add(objects, Ordering::Relaxed);
+        tokio::task::spawn_blocking(move || {
+            drop(values);
+            lazyfree.pending.fetch_sub(objects, Ordering::Relaxed);
+            lazyfree.freed.fetch_add(objects, Ordering::Relaxed);
+        });
+    }
+
+    /// Returns how many values are waiting to be freed in the background.
+    pub fn lazyfree_pending_objects(&self) -> u64 {
+        self.lazyfree.pending.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many values have been freed in the background since startup.
+    pub fn lazyfreed_objects(&self) -> u64 {
+        self.lazyfree.freed.load(Ordering::Relaxed)
     }
 
     pub async fn remove(&self, key: &str) {
@@ -49,6 +2026,44 @@ impl RedisStore {
         store.remove(key);
     }
 
+    /// Removes every key. With `lazy` set, the old contents are dropped on a
+    /// background task so freeing a large dataset doesn't hold up other clients.
+    pub async fn flush(&self, lazy: bool) {
+        let mut store = self.store.write().await;
+        let mut expirations = self.expirations.write().await;
+        let old = (
+            std::mem::take(&mut *store),
+            std::mem::take(&mut *expirations),
+        );
+        if lazy {
+            let objects = old.0.len() as u64;
+            self.free_later(old, objects);
+        }
+    }
+
+    /// Returns the number of keys in the store, including expired keys not yet cleaned up.
+    pub async fn len(&self) -> usize {
+        self.store.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.store.read().await.is_empty()
+    }
+
+    /// Returns the number of keys that have an expiry set.
+    pub async fn expires_len(&self) -> usize {
+        let store = self.store.read().await;
+        store
+            .values()
+            .filter(|entry| entry.expiry.is_some())
+            .count()
+    }
+
+    /// Returns the number of entries in the expirations heap, including stale ones.
+    pub async fn expirations_heap_len(&self) -> usize {
+        self.expirations.read().await.len()
+    }
+
     pub async fn next_expiration(&self) -> Option<u64> {
         let expirations = self.expirations.read().await;
         expirations.peek().map(|exp| exp.0 .0)
@@ -59,10 +2074,23 @@ impl RedisStore {
         let mut store = self.store.write().await;
         let mut expirations = self.expirations.write().await;
         let now = now_millis();
-        while let Some(Reverse((expiry_time, key))) = expirations.peek() {
+        while let Some(Reverse((expiry_time, key, field))) = expirations.peek() {
             if *expiry_time <= now {
-                info!("Removing expired key: {}", key);
-                store.remove(key);
+                // The heap may hold stale entries for keys whose expiry has since changed,
+                // so only remove keys whose current expiry has actually passed
+                if store.get(key).is_some_and(|entry| !entry.is_live(now)) {
+                    info!("Removing expired key: {}", key);
+                    store.remove(key);
+                } else if let Some(field) = field {
+                    if let Some(RedisValue::Hash(hash)) =
+                        store.get_mut(key).map(|entry| &mut entry.value)
+                    {
+                        if hash.expiry(field).is_none() {
+                            info!("Removing expired field {} of key {}", field, key);
+                            hash.remove_expired_fields(now);
+                        }
+                    }
+                }
                 expirations.pop();
             } else {
                 break;
@@ -70,3 +2098,154 @@ impl RedisStore {
         }
     }
 }
+
+/// Replaces whatever a key held with a collection built by a STORE command, without an
+/// expiry. An empty collection removes the key instead.
+fn replace_collection<C: Collection>(store: &mut Entries, key: &str, collection: C) {
+    if collection.is_empty() {
+        store.remove(key);
+    } else {
+        store.insert(key.to_string(), Entry::new(collection, None));
+    }
+}
+
+/// Returns the live sets at `keys`, with missing keys as empty sets, checking every key
+/// holds a set.
+fn sets_at<'a>(
+    store: &'a Entries,
+    keys: &[String],
+    empty: &'a HashSet<String>,
+    now: u64,
+) -> Result<Vec<&'a HashSet<String>>, WrongTypeError> {
+    keys.iter()
+        .map(
+            |key| match store.get(key).filter(|entry| entry.is_live(now)) {
+                Some(entry) => HashSet::of(&entry.value),
+                None => Ok(empty),
+            },
+        )
+        .collect()
+}
+
+/// Intersects, unions or diffs the live sets at `keys`.
+fn combine_sets(
+    store: &Entries,
+    operation: SetOperation,
+    keys: &[String],
+    now: u64,
+) -> Result<HashSet<String>, WrongTypeError> {
+    let empty = HashSet::new();
+    let sets = sets_at(store, keys, &empty, now)?;
+    let Some((first, rest)) = sets.split_first() else {
+        return Ok(HashSet::new());
+    };
+    Ok(match operation {
+        SetOperation::Inter => first
+            .iter()
+            .filter(|member| rest.iter().all(|set| set.contains(*member)))
+            .cloned()
+            .collect(),
+        SetOperation::Union => sets.iter().flat_map(|set| set.iter().cloned()).collect(),
+        SetOperation::Diff => first
+            .iter()
+            .filter(|member| !rest.iter().any(|set| set.contains(*member)))
+            .cloned()
+            .collect(),
+    })
+}
+
+/// Returns the members and scores of the live sorted sets at `keys`, for ZUNION and the
+/// like. Missing keys are empty and members of plain sets score 1.
+fn scored_members_at<'a>(
+    store: &'a Entries,
+    keys: &[String],
+    now: u64,
+) -> Result<Vec<HashMap<&'a str, f64>>, WrongTypeError> {
+    keys.iter()
+        .map(
+            |key| match store.get(key).filter(|entry| entry.is_live(now)) {
+                None => Ok(HashMap::new()),
+                Some(Entry {
+                    value: RedisValue::Set(set),
+                    ..
+                }) => Ok(set.iter().map(|member| (member.as_str(), 1.0)).collect()),
+                Some(entry) => Ok(SortedSet::of(&entry.value)?
+                    .iter()
+                    .map(|(member, score)| (member.as_str(), score))
+                    .collect()),
+            },
+        )
+        .collect()
+}
+
+/// Intersects, unions or diffs the live sorted sets at `keys`, weighting and aggregating
+/// the scores of members found in several of them as `options` say.
+fn combine_sorted_sets(
+    store: &Entries,
+    operation: SetOperation,
+    keys: &[String],
+    options: &ZSetOpOptions,
+    now: u64,
+) -> Result<SortedSet, WrongTypeError> {
+    let inputs = scored_members_at(store, keys, now)?;
+    let weight = |index: usize| {
+        options
+            .weights
+            .as_ref()
+            .map_or(1.0, |weights| weights[index])
+    };
+    // inf * 0 is NaN, which Redis treats as 0
+    let weighted = |score: f64, index: usize| match score * weight(index) {
+        score if score.is_nan() => 0.0,
+        score => score,
+    };
+    let mut result = SortedSet::default();
+    let Some((first, rest)) = inputs.split_first() else {
+        return Ok(result);
+    };
+    match operation {
+        SetOperation::Union => {
+            let mut scores: HashMap<&str, f64> = HashMap::new();
+            for (index, input) in inputs.iter().enumerate() {
+                for (&member, &score) in input {
+                    let score = weighted(score, index);
+                    scores
+                        .entry(member)
+                        .and_modify(|total| *total = options.aggregate.apply(*total, score))
+                        .or_insert(score);
+                }
+            }
+            for (member, score) in scores {
+                result.insert(member.to_string(), score);
+            }
+        }
+        SetOperation::Inter => {
+            for (&member, &score) in first {
+                let mut total = weighted(score, 0);
+                let mut in_all = true;
+                for (index, input) in rest.iter().enumerate() {
+                    match input.get(member) {
+                        Some(&score) => {
+                            total = options.aggregate.apply(total, weighted(score, index + 1))
+                        }
+                        None => {
+                            in_all = false;
+                            break;
+                        }
+                    }
+                }
+                if in_all {
+                    result.insert(member.to_string(), total);
+                }
+            }
+        }
+        SetOperation::Diff => {
+            for (&member, &score) in first {
+                if !rest.iter().any(|input| input.contains_key(member)) {
+                    result.insert(member.to_string(), score);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
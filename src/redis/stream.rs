@@ -0,0 +1,717 @@
+use crate::resp::RespValue;
+use std::collections::BTreeMap;
+
+use crate::{
+    command::{
+        GroupReadId, GroupStart, RedisCommand, StreamId, StreamTrim, XAddId, XAddOptions,
+        XClaimOptions, XGroupCommand, XInfoCommand, XPendingRange, XReadGroupOptions,
+    },
+    utils::now_millis,
+};
+
+use super::{
+    base::{error_reply, BaseServer},
+    blocking::Served,
+    store::{Consumer, ConsumerGroup, Stream, StreamFields},
+};
+
+/// Errors for consumer group commands, which Redis replies with their own error codes
+/// rather than ERR.
+#[derive(Debug, thiserror::Error)]
+pub enum GroupError {
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    Exists,
+    #[error("NOGROUP No such consumer group '{1}' for key name '{0}'")]
+    NoGroup(String, String),
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}' in XREADGROUP with GROUP option")]
+    NoReadGroup(String, String),
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}'")]
+    NoKeyOrGroup(String, String),
+}
+
+/// Reply for XGROUP subcommands run against a missing key.
+const XGROUP_NO_KEY: &str = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+/// Builds the reply for a stream entry: its ID followed by its fields and values.
+pub(super) fn entry_reply(id: &StreamId, fields: &[(String, String)]) -> RespValue {
+    RespValue::Array(vec![
+        RespValue::bulk(&id.to_string()),
+        RespValue::Array(
+            fields
+                .iter()
+                .flat_map(|(field, value)| [RespValue::bulk(field), RespValue::bulk(value)])
+                .collect(),
+        ),
+    ])
+}
+
+/// Builds the reply for claimed entries, with just their IDs when `just_id` is set.
+fn claimed_reply(claimed: &[(StreamId, StreamFields)], just_id: bool) -> RespValue {
+    RespValue::Array(
+        claimed
+            .iter()
+            .map(|(id, fields)| match just_id {
+                true => RespValue::bulk(&id.to_string()),
+                false => entry_reply(id, fields),
+            })
+            .collect(),
+    )
+}
+
+/// Builds the reply to XINFO STREAM, listing up to `full` entries along with the groups in
+/// detail for the FULL form.
+fn stream_info(stream: &Stream, full: Option<usize>) -> RespValue {
+    let first_id = stream
+        .range(StreamId::MIN, StreamId::MAX)
+        .next()
+        .map_or(StreamId::MIN, |(id, _)| *id);
+    let mut reply = vec![
+        RespValue::bulk("length"),
+        RespValue::Integer(stream.len() as i64),
+        RespValue::bulk("radix-tree-keys"),
+        RespValue::Integer(stream.node_count() as i64),
+        RespValue::bulk("radix-tree-nodes"),
+        RespValue::Integer(stream.node_count() as i64),
+        RespValue::bulk("last-generated-id"),
+        RespValue::bulk(&stream.last_id().to_string()),
+        RespValue::bulk("max-deleted-entry-id"),
+        RespValue::bulk(&stream.max_deleted_id().to_string()),
+        RespValue::bulk("entries-added"),
+        RespValue::Integer(stream.entries_added() as i64),
+        RespValue::bulk("recorded-first-entry-id"),
+        RespValue::bulk(&first_id.to_string()),
+    ];
+    let Some(count) = full else {
+        let entry = |entry: Option<(&StreamId, &StreamFields)>| {
+            entry.map_or(RespValue::Null, |(id, fields)| entry_reply(id, fields))
+        };
+        let mut entries = stream.range(StreamId::MIN, StreamId::MAX);
+        let first = entry(entries.next());
+        let last = entries
+            .next_back()
+            .map_or_else(|| first.clone(), |last| entry(Some(last)));
+        reply.extend([
+            RespValue::bulk("groups"),
+            RespValue::Integer(stream.groups().count() as i64),
+            RespValue::bulk("first-entry"),
+            first,
+            RespValue::bulk("last-entry"),
+            last,
+        ]);
+        return RespValue::Array(reply);
+    };
+    let count = if count == 0 { usize::MAX } else { count };
+    let entries = stream
+        .range(StreamId::MIN, StreamId::MAX)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, fields))
+        .collect();
+    let groups = stream
+        .groups()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, entry)| {
+                    RespValue::Array(vec![
+                        RespValue::bulk(&id.to_string()),
+                        RespValue::bulk(&entry.consumer),
+                        RespValue::Integer(entry.delivered as i64),
+                        RespValue::Integer(entry.deliveries as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending: Vec<_> = group
+                        .pending
+                        .iter()
+                        .filter(|(_, entry)| entry.consumer == *name)
+                        .collect();
+                    RespValue::Array(vec![
+                        RespValue::bulk("name"),
+                        RespValue::bulk(name),
+                        RespValue::bulk("seen-time"),
+                        RespValue::Integer(consumer.seen as i64),
+                        RespValue::bulk("active-time"),
+                        RespValue::Integer(consumer.active.map_or(-1, |at| at as i64)),
+                        RespValue::bulk("pel-count"),
+                        RespValue::Integer(pending.len() as i64),
+                        RespValue::bulk("pending"),
+                        RespValue::Array(
+                            pending
+                                .into_iter()
+                                .take(count)
+                                .map(|(id, entry)| {
+                                    RespValue::Array(vec![
+                                        RespValue::bulk(&id.to_string()),
+                                        RespValue::Integer(entry.delivered as i64),
+                                        RespValue::Integer(entry.deliveries as i64),
+                                    ])
+                                })
+                                .collect(),
+                        ),
+                    ])
+                })
+                .collect();
+            RespValue::Array(vec![
+                RespValue::bulk("name"),
+                RespValue::bulk(name),
+                RespValue::bulk("last-delivered-id"),
+                RespValue::bulk(&group.last_delivered.to_string()),
+                RespValue::bulk("entries-read"),
+                optional_integer(group.entries_read),
+                RespValue::bulk("lag"),
+                optional_integer(stream.lag(group)),
+                RespValue::bulk("pel-count"),
+                RespValue::Integer(group.pending.len() as i64),
+                RespValue::bulk("pending"),
+                RespValue::Array(pending),
+                RespValue::bulk("consumers"),
+                RespValue::Array(consumers),
+            ])
+        })
+        .collect();
+    reply.extend([
+        RespValue::bulk("entries"),
+        RespValue::Array(entries),
+        RespValue::bulk("groups"),
+        RespValue::Array(groups),
+    ]);
+    RespValue::Array(reply)
+}
+
+/// Builds the reply to XINFO GROUPS for one group.
+fn group_info(stream: &Stream, name: &str, group: &ConsumerGroup) -> RespValue {
+    RespValue::Array(vec![
+        RespValue::bulk("name"),
+        RespValue::bulk(name),
+        RespValue::bulk("consumers"),
+        RespValue::Integer(group.consumers.len() as i64),
+        RespValue::bulk("pending"),
+        RespValue::Integer(group.pending.len() as i64),
+        RespValue::bulk("last-delivered-id"),
+        RespValue::bulk(&group.last_delivered.to_string()),
+        RespValue::bulk("entries-read"),
+        optional_integer(group.entries_read),
+        RespValue::bulk("lag"),
+        optional_integer(stream.lag(group)),
+    ])
+}
+
+/// Builds the reply to XINFO CONSUMERS for one consumer of a group.
+fn consumer_info(group: &ConsumerGroup, name: &str, consumer: &Consumer, now: u64) -> RespValue {
+    let pending = group
+        .pending
+        .values()
+        .filter(|entry| entry.consumer == name)
+        .count();
+    let inactive = consumer
+        .active
+        .map_or(-1, |active| now.saturating_sub(active) as i64);
+    RespValue::Array(vec![
+        RespValue::bulk("name"),
+        RespValue::bulk(name),
+        RespValue::bulk("pending"),
+        RespValue::Integer(pending as i64),
+        RespValue::bulk("idle"),
+        RespValue::Integer(now.saturating_sub(consumer.seen) as i64),
+        RespValue::bulk("inactive"),
+        RespValue::Integer(inactive),
+    ])
+}
+
+/// Replies with an integer, or nil for a count that can't be worked out.
+fn optional_integer(value: Option<u64>) -> RespValue {
+    value.map_or(RespValue::Null, |value| RespValue::Integer(value as i64))
+}
+
+/// Builds the summary form of the XPENDING reply: the number of pending entries, the
+/// lowest and highest pending IDs and the number pending for each consumer.
+fn pending_summary(group: &ConsumerGroup) -> RespValue {
+    let (Some((first, _)), Some((last, _))) = (
+        group.pending.first_key_value(),
+        group.pending.last_key_value(),
+    ) else {
+        return RespValue::Array(vec![
+            RespValue::Integer(0),
+            RespValue::Null,
+            RespValue::Null,
+            RespValue::NullArray,
+        ]);
+    };
+    let mut consumers = BTreeMap::new();
+    for entry in group.pending.values() {
+        *consumers.entry(entry.consumer.as_str()).or_insert(0) += 1;
+    }
+    RespValue::Array(vec![
+        RespValue::Integer(group.pending.len() as i64),
+        RespValue::bulk(&first.to_string()),
+        RespValue::bulk(&last.to_string()),
+        RespValue::Array(
+            consumers
+                .into_iter()
+                .map(|(consumer, pending)| {
+                    RespValue::Array(vec![
+                        RespValue::bulk(consumer),
+                        RespValue::bulk(&pending.to_string()),
+                    ])
+                })
+                .collect(),
+        ),
+    ])
+}
+
+impl BaseServer {
+    /// Handles XADD, replying with the ID of the new entry. With NOMKSTREAM a missing
+    /// stream isn't created and the reply is nil.
+    /// Gives an XADD with a generated ID the ID its entry will get, so the request
+    /// replicated for it adds the entry under the same ID. Left alone if XADD would fail.
+    pub(super) async fn resolve_xadd_id(&self, key: &str, id: &mut XAddId) {
+        if matches!(id, XAddId::Explicit(_)) {
+            return;
+        }
+        let next = match self.store.stream(key, |stream| stream.next_id(*id)).await {
+            Ok(Some(next)) => next,
+            Ok(None) => Stream::default().next_id(*id),
+            Err(_) => return,
+        };
+        if let Ok(next) = next {
+            *id = XAddId::Explicit(next);
+        }
+    }
+
+    pub(super) async fn xadd(
+        &self,
+        key: &str,
+        options: &XAddOptions,
+        id: XAddId,
+        fields: Vec<(String, String)>,
+    ) -> RespValue {
+        let add = |stream: &mut Stream| {
+            let id = stream.add(id, fields)?;
+            if let Some(trim) = &options.trim {
+                stream.trim(trim);
+            }
+            Ok(id)
+        };
+        match self.store.stream_mut(key, !options.no_mkstream, add).await {
+            Ok(Some(Ok(id))) => RespValue::bulk(&id.to_string()),
+            Ok(Some(Err(e))) | Err(e) => error_reply(e),
+            Ok(None) => RespValue::Null,
+        }
+    }
+
+    pub(super) async fn xtrim(&self, key: &str, trim: &StreamTrim) -> RespValue {
+        match self
+            .store
+            .stream_mut(key, false, |stream| stream.trim(trim))
+            .await
+        {
+            Ok(trimmed) => RespValue::Integer(trimmed.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn xdel(&self, key: &str, ids: &[StreamId]) -> RespValue {
+        match self
+            .store
+            .stream_mut(key, false, |stream| stream.delete(ids))
+            .await
+        {
+            Ok(deleted) => RespValue::Integer(deleted.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn xsetid(
+        &self,
+        key: &str,
+        id: StreamId,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamId>,
+    ) -> RespValue {
+        let set = |stream: &mut Stream| stream.set_last_id(id, entries_added, max_deleted_id);
+        match self.store.stream_mut(key, false, set).await {
+            Ok(Some(Ok(()))) => RespValue::ok(),
+            Ok(Some(Err(e))) | Err(e) => error_reply(e),
+            Ok(None) => RespValue::Error("ERR no such key".to_string()),
+        }
+    }
+
+    pub(super) async fn xlen(&self, key: &str) -> RespValue {
+        match self.store.stream(key, |stream| stream.len()).await {
+            Ok(len) => RespValue::Integer(len.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles XRANGE and XREVRANGE, replying with up to `count` entries between `start`
+    /// and `end`, from the newest when reversed.
+    pub(super) async fn xrange(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> RespValue {
+        let entries = self
+            .store
+            .stream(key, |stream| {
+                let entries = stream.range(start, end);
+                let entries: Box<dyn Iterator<Item = _>> = if rev {
+                    Box::new(entries.rev())
+                } else {
+                    Box::new(entries)
+                };
+                entries
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|(id, fields)| entry_reply(id, fields))
+                    .collect()
+            })
+            .await;
+        match entries {
+            Ok(entries) => RespValue::Array(entries.unwrap_or_default()),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    pub(super) async fn xgroup(&self, command: XGroupCommand) -> RespValue {
+        let resolve = |start: GroupStart, last_id: StreamId| match start {
+            GroupStart::Last => last_id,
+            GroupStart::Id(id) => id,
+        };
+        let result = match command {
+            XGroupCommand::Create(key, group, start, mkstream) => {
+                self.store
+                    .stream_mut(&key, mkstream, |stream| {
+                        let last_delivered = resolve(start, stream.last_id());
+                        match stream.create_group(&group, last_delivered) {
+                            true => Ok(RespValue::ok()),
+                            false => Err(GroupError::Exists.into()),
+                        }
+                    })
+                    .await
+            }
+            XGroupCommand::SetId(key, group, start) => {
+                self.store
+                    .stream_mut(&key, false, |stream| {
+                        let last_delivered = resolve(start, stream.last_id());
+                        match stream.set_group_id(&group, last_delivered) {
+                            true => Ok(RespValue::ok()),
+                            false => Err(GroupError::NoGroup(key.clone(), group).into()),
+                        }
+                    })
+                    .await
+            }
+            XGroupCommand::Destroy(key, group) => {
+                self.store
+                    .stream_mut(&key, false, |stream| {
+                        Ok(RespValue::Integer(stream.destroy_group(&group) as i64))
+                    })
+                    .await
+            }
+            XGroupCommand::CreateConsumer(key, group, consumer) => {
+                self.store
+                    .stream_mut(&key, false, |stream| match stream.group_mut(&group) {
+                        Some(consumer_group) => Ok(RespValue::Integer(
+                            consumer_group.create_consumer(&consumer) as i64,
+                        )),
+                        None => Err(GroupError::NoGroup(key.clone(), group).into()),
+                    })
+                    .await
+            }
+            XGroupCommand::DelConsumer(key, group, consumer) => {
+                self.store
+                    .stream_mut(&key, false, |stream| match stream.group_mut(&group) {
+                        Some(consumer_group) => Ok(RespValue::Integer(
+                            consumer_group.delete_consumer(&consumer) as i64,
+                        )),
+                        None => Err(GroupError::NoGroup(key.clone(), group).into()),
+                    })
+                    .await
+            }
+        };
+        match result {
+            Ok(Some(Ok(response))) => response,
+            Ok(Some(Err(e))) | Err(e) => error_reply(e),
+            Ok(None) => RespValue::Error(XGROUP_NO_KEY.to_string()),
+        }
+    }
+
+    /// Handles XREADGROUP, replying with the entries read from each stream, or nil if
+    /// there were no new entries in any of them.
+    pub(super) async fn xinfo(&self, command: XInfoCommand) -> RespValue {
+        let now = now_millis();
+        let result = match &command {
+            XInfoCommand::Stream(key, full) => {
+                self.store
+                    .stream(key, |stream| Ok(stream_info(stream, *full)))
+                    .await
+            }
+            XInfoCommand::Groups(key) => {
+                self.store
+                    .stream(key, |stream| {
+                        let groups = stream
+                            .groups()
+                            .map(|(name, group)| group_info(stream, name, group))
+                            .collect();
+                        Ok(RespValue::Array(groups))
+                    })
+                    .await
+            }
+            XInfoCommand::Consumers(key, group) => {
+                self.store
+                    .stream(key, |stream| {
+                        let Some(consumer_group) = stream.group(group) else {
+                            return Err(GroupError::NoGroup(key.clone(), group.clone()).into());
+                        };
+                        let consumers = consumer_group
+                            .consumers
+                            .iter()
+                            .map(|(name, consumer)| {
+                                consumer_info(consumer_group, name, consumer, now)
+                            })
+                            .collect();
+                        Ok(RespValue::Array(consumers))
+                    })
+                    .await
+            }
+        };
+        match result {
+            Ok(Some(Ok(response))) => response,
+            Ok(Some(Err(e))) | Err(e) => error_reply(e),
+            Ok(None) => RespValue::Error("ERR no such key".to_string()),
+        }
+    }
+
+    pub(super) async fn xreadgroup(
+        &self,
+        group: &str,
+        consumer: &str,
+        options: &XReadGroupOptions,
+        streams: &[(String, GroupReadId)],
+    ) -> RespValue {
+        match self.read_groups(group, consumer, options, streams).await {
+            Ok(Some(response)) => response,
+            Ok(None) => RespValue::NullArray,
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles XREADGROUP with BLOCK once the client is blocked, returning None while
+    /// there are still no new entries to read.
+    pub(super) async fn blocking_xreadgroup(
+        &self,
+        group: &str,
+        consumer: &str,
+        options: &XReadGroupOptions,
+        streams: &[(String, GroupReadId)],
+    ) -> Option<Served> {
+        match self.read_groups(group, consumer, options, streams).await {
+            Ok(Some(response)) => {
+                let options = XReadGroupOptions {
+                    block: None,
+                    ..options.clone()
+                };
+                let executed = RedisCommand::XReadGroup(
+                    group.to_string(),
+                    consumer.to_string(),
+                    options,
+                    streams.to_vec(),
+                );
+                Some(Served {
+                    response,
+                    executed: Some(executed),
+                })
+            }
+            Ok(None) => None,
+            Err(e) => Some(Served {
+                response: error_reply(e),
+                executed: None,
+            }),
+        }
+    }
+
+    /// Reads from each stream for XREADGROUP. Streams read from with `>` are left out of
+    /// the reply when they have no new entries, and None is returned if that leaves none.
+    async fn read_groups(
+        &self,
+        group: &str,
+        consumer: &str,
+        options: &XReadGroupOptions,
+        streams: &[(String, GroupReadId)],
+    ) -> Result<Option<RespValue>, anyhow::Error> {
+        for (key, _) in streams {
+            let exists = self
+                .store
+                .stream(key, |stream| stream.group(group).is_some())
+                .await?;
+            if exists != Some(true) {
+                return Err(GroupError::NoReadGroup(key.clone(), group.to_string()).into());
+            }
+        }
+        let mut replies = Vec::new();
+        for (key, id) in streams {
+            let read = self
+                .store
+                .stream_mut(key, false, |stream| {
+                    stream.read_group(group, consumer, *id, options.count, options.no_ack)
+                })
+                .await?
+                .flatten()
+                .unwrap_or_default();
+            if read.is_empty() && *id == GroupReadId::New {
+                continue;
+            }
+            let entries = read
+                .iter()
+                .map(|(id, fields)| match fields {
+                    Some(fields) => entry_reply(id, fields),
+                    // The entry was deleted while it was pending
+                    None => RespValue::Array(vec![
+                        RespValue::bulk(&id.to_string()),
+                        RespValue::NullArray,
+                    ]),
+                })
+                .collect();
+            replies.push(RespValue::Array(vec![
+                RespValue::bulk(key),
+                RespValue::Array(entries),
+            ]));
+        }
+        Ok((!replies.is_empty()).then_some(RespValue::Array(replies)))
+    }
+
+    /// Handles XACK, replying with the number of IDs that were pending in the group.
+    pub(super) async fn xack(&self, key: &str, group: &str, ids: &[StreamId]) -> RespValue {
+        let acked = self
+            .store
+            .stream_mut(key, false, |stream| {
+                stream.group_mut(group).map_or(0, |group| group.ack(ids))
+            })
+            .await;
+        match acked {
+            Ok(acked) => RespValue::Integer(acked.unwrap_or(0) as i64),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles XPENDING, replying with a summary of the group's pending entries, or with
+    /// the pending entries in a range along with their consumer, idle time and delivery
+    /// count in the extended form.
+    pub(super) async fn xpending(
+        &self,
+        key: &str,
+        group: &str,
+        range: Option<XPendingRange>,
+    ) -> RespValue {
+        let now = now_millis();
+        let reply = self
+            .store
+            .stream(key, |stream| {
+                let group = stream.group(group)?;
+                let Some(range) = &range else {
+                    return Some(pending_summary(group));
+                };
+                // BTreeMap::range panics on a start past its end
+                let entries = (range.start <= range.end)
+                    .then(|| group.pending.range(range.start..=range.end))
+                    .into_iter()
+                    .flatten()
+                    .filter(|(_, entry)| range.idle.is_none_or(|idle| entry.idle(now) >= idle))
+                    .filter(|(_, entry)| {
+                        range
+                            .consumer
+                            .as_ref()
+                            .is_none_or(|consumer| entry.consumer == *consumer)
+                    })
+                    .take(range.count)
+                    .map(|(id, entry)| {
+                        RespValue::Array(vec![
+                            RespValue::bulk(&id.to_string()),
+                            RespValue::bulk(&entry.consumer),
+                            RespValue::Integer(entry.idle(now) as i64),
+                            RespValue::Integer(entry.deliveries as i64),
+                        ])
+                    })
+                    .collect();
+                Some(RespValue::Array(entries))
+            })
+            .await;
+        match reply {
+            Ok(Some(Some(reply))) => reply,
+            Ok(_) => {
+                error_reply(GroupError::NoKeyOrGroup(key.to_string(), group.to_string()).into())
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles XCLAIM, replying with the entries claimed, or just their IDs with JUSTID.
+    pub(super) async fn xclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamId],
+        options: &XClaimOptions,
+    ) -> RespValue {
+        let claimed = self
+            .store
+            .stream_mut(key, false, |stream| {
+                stream.claim(group, consumer, min_idle, ids, options)
+            })
+            .await;
+        match claimed {
+            Ok(Some(Some(claimed))) => claimed_reply(&claimed, options.just_id),
+            Ok(_) => {
+                error_reply(GroupError::NoKeyOrGroup(key.to_string(), group.to_string()).into())
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Handles XAUTOCLAIM, replying with the ID to continue from, the entries claimed and
+    /// the IDs of pending entries that were no longer in the stream.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn xautoclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> RespValue {
+        let outcome = self
+            .store
+            .stream_mut(key, false, |stream| {
+                stream.auto_claim(group, consumer, min_idle, start, count, just_id)
+            })
+            .await;
+        match outcome {
+            Ok(Some(Some(outcome))) => RespValue::Array(vec![
+                RespValue::bulk(&outcome.next.to_string()),
+                claimed_reply(&outcome.claimed, just_id),
+                RespValue::Array(
+                    outcome
+                        .deleted
+                        .iter()
+                        .map(|id| RespValue::bulk(&id.to_string()))
+                        .collect(),
+                ),
+            ]),
+            Ok(_) => {
+                error_reply(GroupError::NoKeyOrGroup(key.to_string(), group.to_string()).into())
+            }
+            Err(e) => error_reply(e),
+        }
+    }
+}
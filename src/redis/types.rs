@@ -47,6 +47,8 @@ pub struct RedisInfo {
     pub master_port: String,
     pub master_replid: String,
     pub master_repl_offset: u64,
+    /// Offset up to which `master_replid2` is valid, or -1 when there is no previous replid.
+    pub second_repl_offset: i64,
 }
 
 impl RedisInfo {
@@ -63,6 +65,7 @@ impl RedisInfo {
             master_port: master_port.to_string(),
             master_replid,
             master_repl_offset: 0,
+            second_repl_offset: -1,
         }
     }
 }
@@ -0,0 +1,322 @@
+use std::fmt::{Display, Formatter};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::utils::format_float;
+
+/// The default size of the segments a reply is written in, as Redis's reply buffers.
+pub const DEFAULT_REPLY_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The protocol a connection speaks, RESP2 until it switches with HELLO.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    /// Returns the protocol of a HELLO version, if it's one the server speaks.
+    pub fn from_version(version: i64) -> Option<Self> {
+        match version {
+            2 => Some(Protocol::Resp2),
+            3 => Some(Protocol::Resp3),
+            _ => None,
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+/// A reply to a command, encoded to RESP only when it's written to the client. The RESP3
+/// types fall back to their nearest RESP2 type for connections that haven't switched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    /// A bulk string, as raw bytes since it may hold any bytes.
+    BulkString(Vec<u8>),
+    Array(Vec<RespValue>),
+    /// The null bulk string, the usual reply for a missing value.
+    Null,
+    /// The null array, which RESP2 distinguishes from the null bulk string.
+    NullArray,
+    /// A map, a flat array of its keys and values in RESP2.
+    Map(Vec<(RespValue, RespValue)>),
+    /// Pairs such as members and their scores, an array of two element arrays in RESP3
+    /// and a flat array of both halves in RESP2.
+    Pairs(Vec<(RespValue, RespValue)>),
+    /// A set, an array in RESP2.
+    Set(Vec<RespValue>),
+    /// Plain text meant to be shown as is, such as INFO's report. A verbatim string in
+    /// the `txt` format in RESP3, a bulk string in RESP2.
+    Verbatim(String),
+    /// A double, a bulk string in RESP2.
+    Double(f64),
+    /// A boolean, the integer 1 or 0 in RESP2.
+    Boolean(bool),
+    /// An integer too large for `Integer`, as its decimal digits. A bulk string in RESP2.
+    BigNumber(String),
+    /// An out of band message, an array in RESP2.
+    Push(Vec<RespValue>),
+}
+
+impl RespValue {
+    /// Creates the `+OK` reply.
+    pub fn ok() -> Self {
+        RespValue::SimpleString("OK".to_string())
+    }
+
+    /// Creates a bulk string reply holding text.
+    pub fn bulk(message: &str) -> Self {
+        RespValue::BulkString(message.as_bytes().to_vec())
+    }
+
+    /// Encodes the reply in the given protocol.
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        self.encode_into(&mut encoded, protocol);
+        encoded
+    }
+
+    fn encode_into(&self, encoded: &mut Vec<u8>, protocol: Protocol) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            RespValue::SimpleString(message) => {
+                encoded.extend_from_slice(format!("+{}\r\n", message).as_bytes())
+            }
+            RespValue::Error(message) => {
+                encoded.extend_from_slice(format!("-{}\r\n", message).as_bytes())
+            }
+            RespValue::Integer(value) => {
+                encoded.extend_from_slice(format!(":{}\r\n", value).as_bytes())
+            }
+            RespValue::BulkString(bytes) => {
+                encoded.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                encoded.extend_from_slice(bytes);
+                encoded.extend_from_slice(b"\r\n");
+            }
+            RespValue::Array(elements) => encode_aggregate(encoded, b'*', elements, protocol),
+            RespValue::Null | RespValue::NullArray if resp3 => encoded.extend_from_slice(b"_\r\n"),
+            RespValue::Null => encoded.extend_from_slice(b"$-1\r\n"),
+            RespValue::NullArray => encoded.extend_from_slice(b"*-1\r\n"),
+            RespValue::Map(entries) => {
+                let (prefix, len) = match resp3 {
+                    true => (b'%', entries.len()),
+                    false => (b'*', entries.len() * 2),
+                };
+                encoded.extend_from_slice(format!("{}{}\r\n", prefix as char, len).as_bytes());
+                for (key, value) in entries {
+                    key.encode_into(encoded, protocol);
+                    value.encode_into(encoded, protocol);
+                }
+            }
+            RespValue::Pairs(pairs) => {
+                let len = if resp3 { pairs.len() } else { pairs.len() * 2 };
+                encoded.extend_from_slice(format!("*{}\r\n", len).as_bytes());
+                for (first, second) in pairs {
+                    if resp3 {
+                        encoded.extend_from_slice(b"*2\r\n");
+                    }
+                    first.encode_into(encoded, protocol);
+                    second.encode_into(encoded, protocol);
+                }
+            }
+            RespValue::Set(elements) => {
+                encode_aggregate(encoded, if resp3 { b'~' } else { b'*' }, elements, protocol)
+            }
+            RespValue::Verbatim(text) if resp3 => {
+                encoded.extend_from_slice(format!("={}\r\ntxt:", text.len() + 4).as_bytes());
+                encoded.extend_from_slice(text.as_bytes());
+                encoded.extend_from_slice(b"\r\n");
+            }
+            RespValue::Verbatim(text) => RespValue::bulk(text).encode_into(encoded, protocol),
+            RespValue::Double(value) if resp3 => {
+                let value = match value {
+                    value if value.is_nan() => "nan".to_string(),
+                    value => format_float(*value),
+                };
+                encoded.extend_from_slice(format!(",{}\r\n", value).as_bytes())
+            }
+            RespValue::Double(value) => {
+                RespValue::bulk(&format_float(*value)).encode_into(encoded, protocol)
+            }
+            RespValue::Boolean(value) if resp3 => {
+                encoded.extend_from_slice(if *value { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            RespValue::Boolean(value) => {
+                RespValue::Integer(*value as i64).encode_into(encoded, protocol)
+            }
+            RespValue::BigNumber(digits) if resp3 => {
+                encoded.extend_from_slice(format!("({}\r\n", digits).as_bytes())
+            }
+            RespValue::BigNumber(digits) => RespValue::bulk(digits).encode_into(encoded, protocol),
+            RespValue::Push(elements) => {
+                encode_aggregate(encoded, if resp3 { b'>' } else { b'*' }, elements, protocol)
+            }
+        }
+    }
+}
+
+/// A part of a reply still to be written by `RespValue::write_chunked`.
+enum Pending<'a> {
+    Value(&'a RespValue),
+    Raw(&'static [u8]),
+}
+
+impl RespValue {
+    /// Writes the reply to `writer` in segments of about `chunk_size` bytes. Aggregates are
+    /// encoded element by element and bulk strings larger than a segment are written from
+    /// the reply itself, so a large reply is never encoded into a single buffer.
+    pub async fn write_chunked<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        protocol: Protocol,
+        chunk_size: usize,
+    ) -> std::io::Result<()> {
+        let resp3 = protocol == Protocol::Resp3;
+        let mut buffer = Vec::with_capacity(chunk_size);
+        let mut pending = vec![Pending::Value(self)];
+        while let Some(next) = pending.pop() {
+            let value = match next {
+                Pending::Value(value) => value,
+                Pending::Raw(bytes) => {
+                    buffer.extend_from_slice(bytes);
+                    continue;
+                }
+            };
+            let header = |prefix: u8, len: usize| format!("{}{}\r\n", prefix as char, len);
+            match value {
+                RespValue::Array(elements)
+                | RespValue::Set(elements)
+                | RespValue::Push(elements) => {
+                    let prefix = match value {
+                        RespValue::Set(_) if resp3 => b'~',
+                        RespValue::Push(_) if resp3 => b'>',
+                        _ => b'*',
+                    };
+                    buffer.extend_from_slice(header(prefix, elements.len()).as_bytes());
+                    pending.extend(elements.iter().rev().map(Pending::Value));
+                }
+                RespValue::Map(entries) | RespValue::Pairs(entries) => {
+                    let (prefix, len) = match value {
+                        RespValue::Map(_) if resp3 => (b'%', entries.len()),
+                        RespValue::Pairs(_) if resp3 => (b'*', entries.len()),
+                        _ => (b'*', entries.len() * 2),
+                    };
+                    buffer.extend_from_slice(header(prefix, len).as_bytes());
+                    for (first, second) in entries.iter().rev() {
+                        pending.push(Pending::Value(second));
+                        pending.push(Pending::Value(first));
+                        if resp3 && matches!(value, RespValue::Pairs(_)) {
+                            pending.push(Pending::Raw(b"*2\r\n"));
+                        }
+                    }
+                }
+                RespValue::BulkString(bytes) if bytes.len() > chunk_size => {
+                    buffer.extend_from_slice(header(b'$', bytes.len()).as_bytes());
+                    writer.write_all(&buffer).await?;
+                    buffer.clear();
+                    for chunk in bytes.chunks(chunk_size) {
+                        writer.write_all(chunk).await?;
+                    }
+                    buffer.extend_from_slice(b"\r\n");
+                }
+                value => value.encode_into(&mut buffer, protocol),
+            }
+            if buffer.len() >= chunk_size {
+                writer.write_all(&buffer).await?;
+                buffer.clear();
+            }
+        }
+        writer.write_all(&buffer).await
+    }
+}
+
+/// Encodes an aggregate of elements with its type prefix and length.
+fn encode_aggregate(encoded: &mut Vec<u8>, prefix: u8, elements: &[RespValue], protocol: Protocol) {
+    encoded.extend_from_slice(format!("{}{}\r\n", prefix as char, elements.len()).as_bytes());
+    for element in elements {
+        element.encode_into(encoded, protocol);
+    }
+}
+
+impl Display for RespValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            String::from_utf8_lossy(&self.encode(Protocol::Resp2))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer that keeps each write it's given separately.
+    #[derive(Default)]
+    struct Writes(Vec<Vec<u8>>);
+
+    impl AsyncWrite for Writes {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            bytes: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.0.push(bytes.to_vec());
+            std::task::Poll::Ready(Ok(bytes.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn large_replies_are_written_in_bounded_chunks() {
+        let elements = (0..1000).map(|n| RespValue::bulk(&n.to_string())).collect();
+        let pairs = (0..10)
+            .map(|n| (RespValue::bulk(&n.to_string()), RespValue::Double(n as f64)))
+            .collect();
+        let reply = RespValue::Array(vec![
+            RespValue::Array(elements),
+            RespValue::Pairs(pairs),
+            RespValue::BulkString(vec![b'x'; 1000]),
+        ]);
+        for protocol in [Protocol::Resp2, Protocol::Resp3] {
+            let mut writes = Writes::default();
+            reply
+                .write_chunked(&mut writes, protocol, 256)
+                .await
+                .unwrap();
+            assert!(writes.0.len() > 10);
+            assert!(writes.0.iter().all(|write| write.len() < 2 * 256));
+            assert_eq!(writes.0.concat(), reply.encode(protocol));
+        }
+    }
+
+    #[test]
+    fn verbatim_strings_fall_back_to_bulk_strings_in_resp2() {
+        let text = RespValue::Verbatim("a\r\nb".to_string());
+        assert_eq!(text.encode(Protocol::Resp3), b"=8\r\ntxt:a\r\nb\r\n");
+        assert_eq!(text.encode(Protocol::Resp2), b"$4\r\na\r\nb\r\n");
+    }
+}
@@ -1,14 +1,145 @@
-use crate::redis::{base::RedisServer, slave::Slave};
+use crate::command::{DebugCommand, HelloOptions, RedisCommand};
+use crate::redis::{
+    acl::DEFAULT_USER,
+    base::{error_reply, RedisServer, REDIS_VERSION},
+    blocking::{BlockedClient, BlockedClients, Served},
+    slave::Slave,
+    types::RedisRole,
+};
+use crate::resp::{Protocol, RespValue};
 use anyhow::Result;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpListener,
+    net::{TcpListener, TcpStream},
     sync::Mutex,
 };
 use tracing::{error, info};
 
-use crate::{parser::RedisCommandParser, redis::master::Master};
+use crate::{
+    parser::{encode_request, ProtocolError, RedisCommandParser, RespDecoder},
+    redis::master::Master,
+};
+
+/// The id of the next connection accepted, counting from 1 as Redis does.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// State kept for each client connection.
+struct Connection {
+    id: u64,
+    /// The role of the server the client connected to
+    role: RedisRole,
+    protocol: Protocol,
+}
+
+impl Connection {
+    fn new(role: RedisRole) -> Self {
+        Connection {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            role,
+            protocol: Protocol::default(),
+        }
+    }
+}
+
+/// Handles HELLO, switching protocols before replying so the reply is in the new one.
+/// The default user has no password, so AUTH accepts any password for it. SETNAME is
+/// accepted, though nothing reports client names yet.
+fn hello(options: &HelloOptions, connection: &mut Connection) -> RespValue {
+    let protocol = match options.protocol.map(Protocol::from_version) {
+        Some(Some(protocol)) => protocol,
+        Some(None) => return RespValue::Error("NOPROTO unsupported protocol version".to_string()),
+        None => connection.protocol,
+    };
+    if options
+        .auth
+        .as_ref()
+        .is_some_and(|(username, _)| username != DEFAULT_USER)
+    {
+        return RespValue::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+        );
+    }
+    connection.protocol = protocol;
+    let role = match connection.role {
+        RedisRole::Master => "master",
+        RedisRole::Slave => "replica",
+    };
+    RespValue::Map(vec![
+        (RespValue::bulk("server"), RespValue::bulk("redis")),
+        (RespValue::bulk("version"), RespValue::bulk(REDIS_VERSION)),
+        (
+            RespValue::bulk("proto"),
+            RespValue::Integer(protocol.version()),
+        ),
+        (
+            RespValue::bulk("id"),
+            RespValue::Integer(connection.id as i64),
+        ),
+        (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+        (RespValue::bulk("role"), RespValue::bulk(role)),
+        (RespValue::bulk("modules"), RespValue::Array(Vec::new())),
+    ])
+}
+
+/// Handles commands that only affect the issuing connection, so they can run
+/// without holding the lock on the shared server state.
+async fn handle_connection_command(
+    command: &RedisCommand,
+    connection: &mut Connection,
+) -> Option<RespValue> {
+    match command {
+        RedisCommand::Hello(options) => Some(hello(options, connection)),
+        RedisCommand::Debug(DebugCommand::Sleep(duration)) => {
+            tokio::time::sleep(*duration).await;
+            Some(RespValue::ok())
+        }
+        _ => None,
+    }
+}
+
+/// Waits for a blocked client to be served. Gives up and returns None if the client
+/// disconnects first, so it isn't handed an element it will never read.
+async fn wait_for_blocked_client(
+    stream: &TcpStream,
+    mut client: BlockedClient,
+    blocked_clients: &BlockedClients,
+) -> Option<Served> {
+    let mut probe = [0; 1];
+    tokio::select! {
+        served = client.wait(blocked_clients) => return Some(served),
+        Ok(0) = stream.peek(&mut probe) => {}
+    }
+    client.cancel(blocked_clients);
+    None
+}
+
+/// Replicates the command a blocking command ran, e.g. LPOP for BLPOP, if it ran one.
+async fn replicate_served(redis: &Master, served: &Served) {
+    if let Some(argv) = served.executed.as_ref().and_then(RedisCommand::argv) {
+        if let Err(e) = redis.replicate_to_slaves(&encode_request(&argv)).await {
+            error!("Error replicating to slaves: {:?}", e);
+        }
+    }
+}
+
+/// Replies to a request that failed to parse with its error, so the client isn't left
+/// waiting. Returns true if the error was a protocol error and the connection should be
+/// closed.
+async fn reply_to_parse_error(
+    stream: &mut TcpStream,
+    error: &anyhow::Error,
+    protocol: Protocol,
+) -> bool {
+    let response = RespValue::Error(format!("ERR {}", error));
+    if let Err(e) = stream.write_all(&response.encode(protocol)).await {
+        error!("Error writing response: {:?}", e);
+    }
+    error.is::<ProtocolError>()
+}
 
 pub async fn start_master_server(redis: Arc<Mutex<Master>>) -> Result<()> {
     let listener = TcpListener::bind(&redis.lock().await.base.address).await?;
@@ -20,59 +151,140 @@ pub async fn start_master_server(redis: Arc<Mutex<Master>>) -> Result<()> {
         redis_clone.expiry_worker().await;
     });
 
+    let connected_clients = redis.lock().await.base.connected_clients.clone();
+    let blocked_clients = redis.lock().await.base.blocked.clone();
     loop {
         let (mut stream, _) = listener.accept().await?;
         let redis_clone = redis.clone();
+        let connected_clients = connected_clients.clone();
+        let blocked_clients = blocked_clients.clone();
+        connected_clients.fetch_add(1, Ordering::Relaxed);
 
         tokio::spawn(async move {
             let mut buffer = vec![0; 1024];
-            while let Ok(n) = stream.read(&mut buffer).await {
+            let mut decoder = RespDecoder::default();
+            let mut connection = Connection::new(RedisRole::Master);
+            'connection: while let Ok(n) = stream.read(&mut buffer).await {
                 if n == 0 {
                     break;
                 }
-                let buffer_str = match std::str::from_utf8(&buffer) {
-                    Ok(s) => s,
-                    Err(_) => {
-                        error!("Invalid UTF-8 sequence");
+                decoder.feed(&buffer[..n]);
+
+                let (limits, chunk_size) = {
+                    let config = &redis_clone.lock().await.base.config;
+                    (config.protocol_limits(), config.reply_chunk_size)
+                };
+                // Run every complete request buffered, replying in order
+                loop {
+                    let argv = match decoder.next_request(&limits) {
+                        Ok(Some(argv)) => argv,
+                        Ok(None) => break,
+                        Err(e) => {
+                            // The rest of the buffer can't be framed, so there is no
+                            // request boundary to resume from
+                            error!("Invalid request: {:?}", e);
+                            reply_to_parse_error(&mut stream, &e, connection.protocol).await;
+                            break 'connection;
+                        }
+                    };
+
+                    let mut command = match RedisCommandParser::parse_argv(&argv) {
+                        Ok(cmd) => cmd,
+                        Err(e) => {
+                            error!("Invalid command: {:?}", e);
+                            if reply_to_parse_error(&mut stream, &e, connection.protocol).await {
+                                break 'connection;
+                            }
+                            continue;
+                        }
+                    };
+
+                    if let Some(response) =
+                        handle_connection_command(&command, &mut connection).await
+                    {
+                        if let Err(e) = stream
+                            .write_all(&response.encode(connection.protocol))
+                            .await
+                        {
+                            error!("Error writing response: {:?}", e);
+                        }
                         continue;
                     }
-                }
-                .to_string();
-                buffer.fill(0);
 
-                let command = match RedisCommandParser::parse(&buffer_str) {
-                    Ok(cmd) => cmd,
-                    Err(e) => {
-                        error!("Invalid command: {:?}", e);
+                    if command.is_blocking() {
+                        // The server lock is released while the client waits, so other
+                        // connections can run the command that unblocks it
+                        let redis = redis_clone.lock().await;
+                        let blocked = redis.base.block(command).await;
+                        let served = match blocked {
+                            Ok(served) => {
+                                // Served straight away, it may have filled a key other
+                                // clients wait on, as BLMOVE pushes to its destination
+                                replicate_served(&redis, &served).await;
+                                if served.executed.is_some() {
+                                    redis.base.serve_blocked_clients().await;
+                                }
+                                drop(redis);
+                                served
+                            }
+                            Err(client) => {
+                                drop(redis);
+                                let served = match wait_for_blocked_client(
+                                    &stream,
+                                    client,
+                                    &blocked_clients,
+                                )
+                                .await
+                                {
+                                    Some(served) => served,
+                                    None => break 'connection,
+                                };
+                                replicate_served(&*redis_clone.lock().await, &served).await;
+                                served
+                            }
+                        };
+                        if let Err(e) = served
+                            .response
+                            .write_chunked(&mut stream, connection.protocol, chunk_size)
+                            .await
+                        {
+                            error!("Error writing response: {:?}", e);
+                        }
                         continue;
                     }
-                };
 
-                if command.is_write_operation() {
-                    if let Err(e) = redis_clone
-                        .lock()
-                        .await
-                        .replicate_to_slaves(&buffer_str)
+                    let mut redis = redis_clone.lock().await;
+                    redis.base.resolve_command(&mut command).await;
+                    let payload = command
+                        .is_write_operation()
+                        .then(|| command.replication_payload(&argv));
+                    let response = match redis.handle_command(command).await {
+                        Ok(resp) => resp,
+                        Err(e) => {
+                            error!("Error handling command: {:?}", e);
+                            error_reply(e)
+                        }
+                    };
+                    if let Some(payload) = payload {
+                        // A replica that can't be reached doesn't stop the client from
+                        // getting its reply
+                        if let Err(e) = redis.replicate_to_slaves(&payload).await {
+                            error!("Error replicating to slaves: {:?}", e);
+                        }
+                        redis.base.serve_blocked_clients().await;
+                    }
+                    drop(redis);
+                    info!("Sending response: {:?}", response);
+                    if let Err(e) = response
+                        .write_chunked(&mut stream, connection.protocol, chunk_size)
                         .await
                     {
-                        error!("Error replicating to slaves: {:?}", e);
+                        error!("Error writing response: {:?}", e);
                         continue;
                     }
                 }
-
-                let response = match redis_clone.lock().await.handle_command(command).await {
-                    Ok(resp) => resp,
-                    Err(e) => {
-                        error!("Error handling command: {:?}", e);
-                        continue;
-                    }
-                };
-                info!("Sending response: {:?}", response);
-                if let Err(e) = stream.write_all(response.message.as_bytes()).await {
-                    error!("Error writing response: {:?}", e);
-                    continue;
-                }
             }
+            connected_clients.fetch_sub(1, Ordering::Relaxed);
         });
     }
 }
@@ -87,51 +299,641 @@ pub async fn start_slave_server(redis: Arc<Mutex<Slave>>) -> Result<()> {
     tokio::spawn(async move {
         if let Err(e) = redis_clone.handshake().await {
             error!("Error handshaking with master: {:?}", e);
-            return;
         }
     });
 
+    let connected_clients = redis.lock().await.base.connected_clients.clone();
+    let blocked_clients = redis.lock().await.base.blocked.clone();
     loop {
         let (mut stream, _) = listener.accept().await?;
         let redis_clone = redis.clone();
+        let connected_clients = connected_clients.clone();
+        let blocked_clients = blocked_clients.clone();
+        connected_clients.fetch_add(1, Ordering::Relaxed);
 
         tokio::spawn(async move {
             let mut buffer = vec![0; 1024];
-            while let Ok(n) = stream.read(&mut buffer).await {
+            let mut decoder = RespDecoder::default();
+            let mut connection = Connection::new(RedisRole::Slave);
+            'connection: while let Ok(n) = stream.read(&mut buffer).await {
                 if n == 0 {
                     break;
                 }
-                let buffer_str = match std::str::from_utf8(&buffer[..n]) {
-                    Ok(s) => s,
-                    Err(_) => {
-                        eprintln!("Invalid UTF-8 sequence");
+                decoder.feed(&buffer[..n]);
+
+                let (limits, chunk_size) = {
+                    let config = &redis_clone.lock().await.base.config;
+                    (config.protocol_limits(), config.reply_chunk_size)
+                };
+                // Run every complete request buffered, replying in order
+                loop {
+                    let argv = match decoder.next_request(&limits) {
+                        Ok(Some(argv)) => argv,
+                        Ok(None) => break,
+                        Err(e) => {
+                            // The rest of the buffer can't be framed, so there is no
+                            // request boundary to resume from
+                            error!("Invalid request: {:?}", e);
+                            reply_to_parse_error(&mut stream, &e, connection.protocol).await;
+                            break 'connection;
+                        }
+                    };
+                    info!("Received request: {:?}", argv);
+
+                    let mut command = match RedisCommandParser::parse_argv(&argv) {
+                        Ok(cmd) => cmd,
+                        Err(e) => {
+                            error!("Invalid command: {:?}", e);
+                            if reply_to_parse_error(&mut stream, &e, connection.protocol).await {
+                                break 'connection;
+                            }
+                            continue;
+                        }
+                    };
+
+                    if let Some(response) =
+                        handle_connection_command(&command, &mut connection).await
+                    {
+                        if let Err(e) = stream
+                            .write_all(&response.encode(connection.protocol))
+                            .await
+                        {
+                            error!("Error writing response: {:?}", e);
+                        }
                         continue;
                     }
-                };
-                info!("Received buffer: {:?}", buffer_str);
 
-                let command = match RedisCommandParser::parse(buffer_str) {
-                    Ok(cmd) => cmd,
-                    Err(e) => {
-                        eprintln!("Invalid command: {:?}", e);
+                    if command.is_blocking() {
+                        let redis = redis_clone.lock().await;
+                        let blocked = redis.base.block(command).await;
+                        let served = match blocked {
+                            Ok(served) => {
+                                if served.executed.is_some() {
+                                    redis.base.serve_blocked_clients().await;
+                                }
+                                drop(redis);
+                                served
+                            }
+                            Err(client) => {
+                                drop(redis);
+                                match wait_for_blocked_client(&stream, client, &blocked_clients)
+                                    .await
+                                {
+                                    Some(served) => served,
+                                    None => break 'connection,
+                                }
+                            }
+                        };
+                        if let Err(e) = served
+                            .response
+                            .write_chunked(&mut stream, connection.protocol, chunk_size)
+                            .await
+                        {
+                            error!("Error writing response: {:?}", e);
+                        }
                         continue;
                     }
-                };
 
-                let response = match redis_clone.lock().await.handle_command(command).await {
-                    Ok(resp) => resp,
-                    Err(e) => {
-                        eprintln!("Error handling command: {:?}", e);
+                    let is_write = command.is_write_operation();
+                    let mut redis = redis_clone.lock().await;
+                    redis.base.resolve_command(&mut command).await;
+                    let response = match redis.handle_command(command).await {
+                        Ok(resp) => resp,
+                        Err(e) => {
+                            error!("Error handling command: {:?}", e);
+                            error_reply(e)
+                        }
+                    };
+                    if is_write {
+                        redis.base.serve_blocked_clients().await;
+                    }
+                    drop(redis);
+                    info!("Sending response: {:?}", response);
+                    if let Err(e) = response
+                        .write_chunked(&mut stream, connection.protocol, chunk_size)
+                        .await
+                    {
+                        error!("Error writing response: {:?}", e);
                         continue;
                     }
-                };
-                info!("Sending response: {:?}", response);
-                if let Err(e) = stream.write_all(response.message.as_bytes()).await {
-                    eprintln!("Error writing response: {:?}", e);
-                    continue;
                 }
-                buffer.fill(0);
             }
+            connected_clients.fetch_sub(1, Ordering::Relaxed);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::time::{Duration, Instant};
+
+    /// Starts a master on a free port, returning its address once it accepts connections.
+    async fn start_server() -> String {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+            .port();
+        let redis = Arc::new(Mutex::new(Master::new("127.0.0.1", &port.to_string())));
+        tokio::spawn(start_master_server(redis));
+        let address = format!("127.0.0.1:{}", port);
+        for _ in 0..100 {
+            if TcpStream::connect(&address).await.is_ok() {
+                return address;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("server didn't start listening on {}", address);
+    }
+
+    /// Sends a request and returns the reply to it.
+    async fn request(stream: &mut TcpStream, argv: &[&str]) -> Vec<u8> {
+        let argv: Vec<Bytes> = argv
+            .iter()
+            .map(|argument| Bytes::copy_from_slice(argument.as_bytes()))
+            .collect();
+        stream.write_all(&encode_request(&argv)).await.unwrap();
+        let mut reply = vec![0; 4096];
+        let n = stream.read(&mut reply).await.unwrap();
+        reply.truncate(n);
+        reply
+    }
+
+    #[tokio::test]
+    async fn debug_sleep_only_blocks_its_connection() {
+        let address = start_server().await;
+        let mut sleeper = TcpStream::connect(&address).await.unwrap();
+        let mut other = TcpStream::connect(&address).await.unwrap();
+
+        let started = Instant::now();
+        let sleep =
+            tokio::spawn(async move { request(&mut sleeper, &["DEBUG", "SLEEP", "0.05"]).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(request(&mut other, &["PING"]).await, b"+PONG\r\n");
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        assert_eq!(sleep.await.unwrap(), b"+OK\r\n");
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "slept {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "slept {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn debug_sleep_rejects_invalid_durations() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        for (seconds, reply) in [
+            ("abc", "-ERR value is not a valid float\r\n"),
+            (
+                "-1",
+                "-ERR DEBUG SLEEP requires a non-negative number of seconds\r\n",
+            ),
+            (
+                "1e20",
+                "-ERR DEBUG SLEEP requires a non-negative number of seconds\r\n",
+            ),
+        ] {
+            let reply = reply.as_bytes();
+            assert_eq!(
+                request(&mut stream, &["DEBUG", "SLEEP", seconds]).await,
+                reply
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn every_failed_request_gets_an_error_reply() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        for argv in [
+            &["XINFO", "FOO"][..],
+            &["DEBUG", "POPULATE", "abc"],
+            &["REPLCONF", "foo"],
+            &["NOSUCHCOMMAND"],
+        ] {
+            let reply = request(&mut stream, argv).await;
+            assert!(reply.starts_with(b"-ERR "), "{:?} got {:?}", argv, reply);
+        }
+        // The connection is still usable after the errors
+        assert_eq!(request(&mut stream, &["PING"]).await, b"+PONG\r\n");
+    }
+
+    #[tokio::test]
+    async fn debug_populate_fills_the_keyspace() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(
+            request(&mut stream, &["DEBUG", "POPULATE", "1000"]).await,
+            b"+OK\r\n"
+        );
+        assert_eq!(request(&mut stream, &["DBSIZE"]).await, b":1000\r\n");
+        assert_eq!(
+            request(&mut stream, &["DEBUG", "POPULATE", "abc"]).await,
+            b"-ERR value is not an integer or out of range\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["DEBUG", "POPULATE", "10", "key", "-1"]).await,
+            b"-ERR value is not an integer or out of range\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["DEBUG", "POPULATE", "100000000000"]).await,
+            b"-ERR OOM in dictTryExpand\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["DEBUG", "POPULATE", "1", "k", "99999999999"]).await,
+            b"-ERR OOM in dictTryExpand\r\n"
+        );
+        request(&mut stream, &["DEBUG", "POPULATE", "1", "padded", "10"]).await;
+        request(&mut stream, &["DEBUG", "POPULATE", "1", "cut", "3"]).await;
+        assert_eq!(
+            request(&mut stream, &["GET", "padded:0"]).await,
+            b"$10\r\nvalue:0\0\0\0\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["GET", "cut:0"]).await,
+            b"$3\r\nval\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn zrandmember_repeats_members_for_a_negative_count() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["ZADD", "z", "1", "a"]).await;
+        assert_eq!(
+            request(&mut stream, &["ZRANDMEMBER", "z", "-3"]).await,
+            b"*3\r\n$1\r\na\r\n$1\r\na\r\n$1\r\na\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZRANDMEMBER", "z", "-9223372036854775808"]).await,
+            b"-ERR value is out of range\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn pushx_only_pushes_onto_existing_lists() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(
+            request(&mut stream, &["LPUSHX", "list", "a"]).await,
+            b":0\r\n"
+        );
+        assert_eq!(request(&mut stream, &["EXISTS", "list"]).await, b":0\r\n");
+        request(&mut stream, &["RPUSH", "list", "b"]).await;
+        assert_eq!(
+            request(&mut stream, &["LPUSHX", "list", "a"]).await,
+            b":2\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["RPUSHX", "list", "c", "d"]).await,
+            b":4\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["LRANGE", "list", "0", "-1"]).await,
+            b"*4\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n$1\r\nd\r\n"
+        );
+        request(&mut stream, &["SET", "string", "value"]).await;
+        assert_eq!(
+            request(&mut stream, &["RPUSHX", "string", "a"]).await,
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn sort_orders_lists_by_value_or_by_pattern() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["RPUSH", "list", "3", "1", "2"]).await;
+        assert_eq!(
+            request(&mut stream, &["SORT", "list", "DESC", "LIMIT", "0", "2"]).await,
+            b"*2\r\n$1\r\n3\r\n$1\r\n2\r\n"
+        );
+        request(
+            &mut stream,
+            &["MSET", "w_1", "30", "w_2", "10", "w_3", "20"],
+        )
+        .await;
+        request(&mut stream, &["HSET", "h_2", "name", "two"]).await;
+        assert_eq!(
+            request(
+                &mut stream,
+                &["SORT", "list", "BY", "w_*", "GET", "#", "GET", "h_*->name"]
+            )
+            .await,
+            b"*6\r\n$1\r\n2\r\n$3\r\ntwo\r\n$1\r\n3\r\n$-1\r\n$1\r\n1\r\n$-1\r\n"
+        );
+        assert_eq!(
+            request(
+                &mut stream,
+                &["SORT", "list", "BY", "nosort", "STORE", "copy"]
+            )
+            .await,
+            b":3\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["LRANGE", "copy", "0", "-1"]).await,
+            b"*3\r\n$1\r\n3\r\n$1\r\n1\r\n$1\r\n2\r\n"
+        );
+        request(&mut stream, &["SADD", "set", "b", "a"]).await;
+        assert_eq!(
+            request(&mut stream, &["SORT", "set"]).await,
+            b"-ERR One or more scores can't be converted into double\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["SORT", "set", "ALPHA"]).await,
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["SORT", "w_1"]).await,
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_internals_counts_blocked_clients() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(
+            request(
+                &mut stream,
+                &["CONFIG", "SET", "enable-debug-internals", "yes"]
+            )
+            .await,
+            b"+OK\r\n"
+        );
+
+        let mut blocked = TcpStream::connect(&address).await.unwrap();
+        let pop = tokio::spawn(async move { request(&mut blocked, &["BLPOP", "list", "0"]).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let internals = request(&mut stream, &["DEBUG", "INTERNALS"]).await;
+        let internals = String::from_utf8_lossy(&internals);
+        assert!(internals.contains("blocked_clients:1\r\n"), "{}", internals);
+
+        request(&mut stream, &["RPUSH", "list", "value"]).await;
+        assert_eq!(pop.await.unwrap(), b"*2\r\n$4\r\nlist\r\n$5\r\nvalue\r\n");
+        let internals = request(&mut stream, &["DEBUG", "INTERNALS"]).await;
+        let internals = String::from_utf8_lossy(&internals);
+        assert!(internals.contains("blocked_clients:0"), "{}", internals);
+    }
+
+    #[tokio::test]
+    async fn a_failing_blocked_client_does_not_starve_the_next_one() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["SET", "str", "x"]).await;
+
+        let mut mover = TcpStream::connect(&address).await.unwrap();
+        let blmove = tokio::spawn(async move {
+            request(&mut mover, &["BLMOVE", "src", "str", "LEFT", "LEFT", "0"]).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut popper = TcpStream::connect(&address).await.unwrap();
+        let blpop = tokio::spawn(async move { request(&mut popper, &["BLPOP", "src", "0"]).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        request(&mut stream, &["RPUSH", "src", "v"]).await;
+        assert_eq!(
+            blmove.await.unwrap(),
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+        assert_eq!(blpop.await.unwrap(), b"*2\r\n$3\r\nsrc\r\n$1\r\nv\r\n");
+        assert_eq!(request(&mut stream, &["LLEN", "src"]).await, b":0\r\n");
+    }
+
+    #[tokio::test]
+    async fn a_blocking_command_served_straight_away_wakes_clients_on_its_writes() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let mut waiter = TcpStream::connect(&address).await.unwrap();
+        let blpop = tokio::spawn(async move { request(&mut waiter, &["BLPOP", "dst", "0"]).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        request(&mut stream, &["RPUSH", "src", "v"]).await;
+        assert_eq!(
+            request(&mut stream, &["BLMOVE", "src", "dst", "LEFT", "LEFT", "0"]).await,
+            b"$1\r\nv\r\n"
+        );
+        assert_eq!(blpop.await.unwrap(), b"*2\r\n$3\r\ndst\r\n$1\r\nv\r\n");
+        assert_eq!(request(&mut stream, &["LLEN", "dst"]).await, b":0\r\n");
+    }
+
+    #[tokio::test]
+    async fn info_is_a_verbatim_string_in_resp3() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert!(request(&mut stream, &["INFO", "server"])
+            .await
+            .starts_with(b"$"));
+        assert_eq!(
+            request(&mut stream, &["LOLWUT"]).await,
+            format!("$17\r\nRedis ver. {}\n\r\n", REDIS_VERSION).as_bytes()
+        );
+
+        request(&mut stream, &["HELLO", "3"]).await;
+        let info = request(&mut stream, &["INFO", "server"]).await;
+        let info = String::from_utf8(info).unwrap();
+        let (length, text) = info
+            .strip_prefix('=')
+            .and_then(|info| info.split_once("\r\n"))
+            .unwrap();
+        assert!(text.starts_with("txt:# Server\r\n"), "{}", info);
+        assert_eq!(length.parse::<usize>().unwrap(), text.len() - 2);
+        assert_eq!(
+            request(&mut stream, &["LOLWUT"]).await,
+            format!("=21\r\ntxt:Redis ver. {}\n\r\n", REDIS_VERSION).as_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn scores_are_doubles_in_resp3_and_bulk_strings_in_resp2() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["ZADD", "z", "1.5", "a", "-inf", "b"]).await;
+        assert_eq!(
+            request(&mut stream, &["ZSCORE", "z", "a"]).await,
+            b"$3\r\n1.5\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZINCRBY", "z", "1", "a"]).await,
+            b"$3\r\n2.5\r\n"
+        );
+
+        request(&mut stream, &["HELLO", "3"]).await;
+        assert_eq!(
+            request(&mut stream, &["ZSCORE", "z", "a"]).await,
+            b",2.5\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZSCORE", "z", "b"]).await,
+            b",-inf\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZINCRBY", "z", "inf", "a"]).await,
+            b",inf\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZADD", "z", "INCR", "1", "c"]).await,
+            b",1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn resp3_pairs_members_with_their_scores() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["ZADD", "z", "1", "a", "2.5", "b"]).await;
+        assert_eq!(
+            request(&mut stream, &["ZRANGE", "z", "0", "-1", "WITHSCORES"]).await,
+            b"*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$3\r\n2.5\r\n"
+        );
+
+        request(&mut stream, &["HELLO", "3"]).await;
+        assert_eq!(
+            request(&mut stream, &["ZRANGE", "z", "0", "-1", "WITHSCORES"]).await,
+            b"*2\r\n*2\r\n$1\r\na\r\n,1\r\n*2\r\n$1\r\nb\r\n,2.5\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZRANK", "z", "b", "WITHSCORE"]).await,
+            b"*2\r\n:1\r\n,2.5\r\n"
+        );
+        let picked = request(&mut stream, &["ZRANDMEMBER", "z", "-1", "WITHSCORES"]).await;
+        assert!(
+            picked == b"*1\r\n*2\r\n$1\r\na\r\n,1\r\n"
+                || picked == b"*1\r\n*2\r\n$1\r\nb\r\n,2.5\r\n",
+            "{:?}",
+            String::from_utf8_lossy(&picked)
+        );
+        assert_eq!(
+            request(&mut stream, &["ZPOPMAX", "z"]).await,
+            b"*2\r\n$1\r\nb\r\n,2.5\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["ZPOPMIN", "z", "1"]).await,
+            b"*1\r\n*2\r\n$1\r\na\r\n,1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn sorted_sets_outgrow_listpack_exactly_past_the_configured_limits() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let listpack = b"$8\r\nlistpack\r\n".to_vec();
+        let skiplist = b"$8\r\nskiplist\r\n".to_vec();
+
+        request(
+            &mut stream,
+            &["CONFIG", "SET", "zset-max-listpack-entries", "3"],
+        )
+        .await;
+        request(&mut stream, &["ZADD", "z", "1", "a", "2", "b", "3", "c"]).await;
+        assert_eq!(
+            request(&mut stream, &["OBJECT", "ENCODING", "z"]).await,
+            listpack
+        );
+        request(&mut stream, &["ZADD", "z", "4", "d"]).await;
+        assert_eq!(
+            request(&mut stream, &["OBJECT", "ENCODING", "z"]).await,
+            skiplist
+        );
+
+        request(
+            &mut stream,
+            &["CONFIG", "SET", "zset-max-listpack-value", "4"],
+        )
+        .await;
+        request(&mut stream, &["ZADD", "v", "1", "abcd"]).await;
+        assert_eq!(
+            request(&mut stream, &["OBJECT", "ENCODING", "v"]).await,
+            listpack
+        );
+        request(&mut stream, &["ZADD", "v", "2", "abcde"]).await;
+        assert_eq!(
+            request(&mut stream, &["OBJECT", "ENCODING", "v"]).await,
+            skiplist
+        );
+    }
+
+    #[tokio::test]
+    async fn set_nx_get_only_writes_a_missing_key_and_returns_the_old_value() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(
+            request(&mut stream, &["SET", "key", "first", "NX", "GET"]).await,
+            b"$-1\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["GET", "key"]).await,
+            b"$5\r\nfirst\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["SET", "key", "second", "NX", "GET"]).await,
+            b"$5\r\nfirst\r\n"
+        );
+        assert_eq!(
+            request(&mut stream, &["GET", "key"]).await,
+            b"$5\r\nfirst\r\n"
+        );
+
+        request(&mut stream, &["LPUSH", "list", "a"]).await;
+        assert_eq!(
+            request(&mut stream, &["SET", "list", "value", "NX", "GET"]).await,
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn get_on_a_list_is_a_wrongtype_error() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        request(&mut stream, &["LPUSH", "list", "a"]).await;
+        assert_eq!(
+            request(&mut stream, &["GET", "list"]).await,
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn expire_jitter_spreads_identical_expiries() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(
+            request(
+                &mut stream,
+                &["CONFIG", "SET", "active-expire-jitter", "1000"]
+            )
+            .await,
+            b"+OK\r\n"
+        );
+        let expiry = crate::utils::now_millis() + 60_000;
+        let mut expiries = Vec::new();
+        for key in ["a", "b", "c", "d", "e"] {
+            let timestamp = expiry.to_string();
+            assert_eq!(
+                request(&mut stream, &["SET", key, "value", "PXAT", &timestamp]).await,
+                b"+OK\r\n"
+            );
+            let reply = request(&mut stream, &["PEXPIRETIME", key]).await;
+            let stored: u64 = String::from_utf8_lossy(&reply)
+                .trim_start_matches(':')
+                .trim_end()
+                .parse()
+                .unwrap();
+            assert!((expiry..=expiry + 1000).contains(&stored), "{}", stored);
+            expiries.push(stored);
+        }
+        // Five draws out of a thousand and one all landing on the same millisecond would
+        // mean the jitter isn't applied
+        assert!(expiries.iter().any(|&stored| stored != expiries[0]));
+    }
+
+    #[tokio::test]
+    async fn oversized_multibulk_header_closes_the_connection() {
+        let address = start_server().await;
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        stream.write_all(b"*999999999\r\n").await.unwrap();
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"-ERR Protocol error: invalid multibulk length\r\n");
+    }
+}
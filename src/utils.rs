@@ -1,4 +1,9 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// Returns the current time in milliseconds.
 pub fn now_millis() -> u64 {
@@ -8,6 +13,14 @@ pub fn now_millis() -> u64 {
         .as_millis() as u64
 }
 
+/// Returns the current time in microseconds.
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_micros() as u64
+}
+
 /// Converts a duration in milliseconds to a timestamp in the future as u64.
 pub fn millis_to_timestamp_from_now(millis: u64) -> Result<u64, anyhow::Error> {
     let now = SystemTime::now()
@@ -38,3 +51,252 @@ pub fn _duration_until_timestamp(timestamp: u64) -> Duration {
         Err(_) => Duration::from_secs(0), // Default to 0 if there's an error
     }
 }
+
+/// Parses a finite float the way Redis does, rejecting NaN, infinities and surrounding spaces.
+pub fn parse_float(value: &str) -> Option<f64> {
+    if value.trim() != value {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// Parses a sorted set score. Unlike `parse_float` this accepts `inf`, `+inf` and `-inf`.
+pub fn parse_score(value: &str) -> Option<f64> {
+    if value.trim() != value {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|value| !value.is_nan())
+}
+
+/// Formats a float the way Redis replies with it: shortest round-trip digits,
+/// no exponent and no trailing zeros.
+pub fn format_float(value: f64) -> String {
+    if value == 0.0 {
+        // Avoid replying with "-0"
+        return "0".to_string();
+    }
+    format!("{}", value)
+}
+
+/// Matches a string against a Redis-style glob pattern.
+/// Supports `*`, `?`, `[...]` character classes (with `^` negation and `a-z` ranges)
+/// and `\` escaping.
+pub fn glob_match(pattern: &str, string: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let string: Vec<char> = string.chars().collect();
+    glob_match_chars(&pattern, &string)
+}
+
+fn glob_match_chars(pattern: &[char], string: &[char]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            '*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == '*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len())
+                    .any(|i| glob_match_chars(&pattern[p + 1..], &string[i..]));
+            }
+            '?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            '[' => {
+                if s >= string.len() {
+                    return false;
+                }
+                p += 1;
+                let negate = p < pattern.len() && pattern[p] == '^';
+                if negate {
+                    p += 1;
+                }
+                let mut matched = false;
+                while p < pattern.len() && pattern[p] != ']' {
+                    if pattern[p] == '\\' && p + 1 < pattern.len() {
+                        p += 1;
+                        matched |= pattern[p] == string[s];
+                    } else if p + 2 < pattern.len()
+                        && pattern[p + 1] == '-'
+                        && pattern[p + 2] != ']'
+                    {
+                        let (start, end) = if pattern[p] <= pattern[p + 2] {
+                            (pattern[p], pattern[p + 2])
+                        } else {
+                            (pattern[p + 2], pattern[p])
+                        };
+                        matched |= start <= string[s] && string[s] <= end;
+                        p += 2;
+                    } else {
+                        matched |= pattern[p] == string[s];
+                    }
+                    p += 1;
+                }
+                if matched == negate {
+                    return false;
+                }
+                s += 1;
+            }
+            '\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if s >= string.len() || pattern[p] != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                if s >= string.len() || c != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    s == string.len()
+}
+
+/// A matching range found by `longest_common_subsequence`, as inclusive byte
+/// offsets into the first and second input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl LcsMatch {
+    pub fn match_len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Computes the longest common subsequence of two byte strings, along with the
+/// contiguous ranges it is made of, ordered from the end of the strings backwards
+/// as LCS IDX reports them.
+pub fn longest_common_subsequence(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<LcsMatch>) {
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut subsequence = Vec::with_capacity(table[a.len() * width + b.len()] as usize);
+    let mut matches = Vec::new();
+    let mut current: Option<LcsMatch> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            subsequence.push(a[i - 1]);
+            match current.as_mut() {
+                // Extend the current range backwards while the match stays contiguous
+                Some(range) if range.a.0 == i && range.b.0 == j => {
+                    range.a.0 -= 1;
+                    range.b.0 -= 1;
+                }
+                Some(range) => {
+                    matches.push(*range);
+                    current = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    });
+                }
+                None => {
+                    current = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                    })
+                }
+            }
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            matches.extend(current.take());
+        }
+    }
+    matches.extend(current);
+    subsequence.reverse();
+    (subsequence, matches)
+}
+
+/// Position of an element in SCAN order. This is a hash of the element rather than where
+/// it sits in its collection, so cursors stay valid while the collection changes.
+fn scan_position(element: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    element.hash(&mut hasher);
+    // 0 is reserved for the cursor that starts and ends a scan
+    hasher.finish().max(1)
+}
+
+/// Returns the next batch of a cursor based scan over `elements`, each given with the
+/// name that decides its position, along with the cursor to continue from (0 once the
+/// scan is complete). A batch holds `count` elements plus any sharing a position with
+/// the last one, so every element present for the whole scan is returned.
+pub fn scan<'a, T>(
+    elements: impl Iterator<Item = (&'a str, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<T>) {
+    let mut positioned: Vec<_> = elements
+        .map(|(name, element)| (scan_position(name), element))
+        .filter(|(position, _)| *position >= cursor)
+        .collect();
+    positioned.sort_by_key(|(position, _)| *position);
+    let mut end = count.min(positioned.len());
+    while end > 0 && end < positioned.len() && positioned[end].0 == positioned[end - 1].0 {
+        end += 1;
+    }
+    let next = positioned.get(end).map_or(0, |(position, _)| *position);
+    positioned.truncate(end);
+    (
+        next,
+        positioned.into_iter().map(|(_, element)| element).collect(),
+    )
+}
+
+/// The most picks a random reply with repeats reserves space for up front, so a huge count
+/// grows the reply as it is built instead of allocating it all at once.
+const MAX_RESERVED_PICKS: usize = 1024;
+
+/// Picks `count` random elements of `items`, allowing the same element more than once, as
+/// HRANDFIELD and ZRANDMEMBER do for a negative count. Picks nothing from an empty slice.
+pub fn choose_with_repeats<T: Clone>(items: &[T], count: u64) -> Vec<T> {
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    let mut rng = rand::thread_rng();
+    let mut picks = Vec::with_capacity(count.min(MAX_RESERVED_PICKS));
+    for _ in 0..count {
+        picks.push(items[rng.gen_range(0..items.len())].clone());
+    }
+    picks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choosing_with_repeats_picks_count_elements() {
+        let picks = choose_with_repeats(&["a", "b"], 5);
+        assert_eq!(picks.len(), 5);
+        assert!(picks.iter().all(|pick| ["a", "b"].contains(pick)));
+        assert!(choose_with_repeats::<&str>(&[], u64::MAX).is_empty());
+    }
+}